@@ -0,0 +1,91 @@
+#![allow(dead_code)]
+// src/acpi.rs
+// Just enough ACPI to read fixed hardware facts out of the FADT, e.g. which
+// CMOS register (if any) holds the RTC century.
+//
+// The RSDP comes from the UEFI loader (EFI configuration table); on a BIOS
+// boot it is found the classic way, by scanning the EBDA and 0xE0000-0xFFFFF.
+// Tables are read in place, so they have to sit in the identity-mapped low
+// 4 GiB; anything above is treated as absent.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use crate::bootinfo;
+
+const SDT_HEADER_LEN: usize = 36;
+/// Offset of the CENTURY byte in the FADT (ACPI 1.0 and later).
+const FADT_CENTURY: usize = 108;
+const IDENTITY_LIMIT: u64 = 1 << 32;
+
+/// 0x100 = not looked up yet; otherwise the register, 0 meaning none.
+static CENTURY: AtomicU16 = AtomicU16::new(0x100);
+
+/// `len` bytes of physical memory at `addr`, if it is mapped.
+fn phys(addr: u64, len: usize) -> Option<&'static [u8]> {
+    if addr == 0 || addr.checked_add(len as u64)? > IDENTITY_LIMIT { return None; }
+    Some(unsafe { core::slice::from_raw_parts(addr as *const u8, len) })
+}
+
+fn checksum_ok(b: &[u8]) -> bool {
+    b.iter().fold(0u8, |s, &x| s.wrapping_add(x)) == 0
+}
+
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn le64(b: &[u8]) -> u64 {
+    u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+}
+
+fn rsdp() -> Option<u64> {
+    bootinfo::acpi_rsdp().or_else(scan_bios_areas)
+}
+
+/// "RSD PTR " on a 16-byte boundary in the first KiB of the EBDA or in the
+/// BIOS ROM area.
+fn scan_bios_areas() -> Option<u64> {
+    let ebda = (u16::from_le_bytes(phys(0x40E, 2)?.try_into().ok()?) as u64) << 4;
+    let mut areas = [(0xE0000u64, 0x20000usize), (0, 0)];
+    if (0x80000..0xA0000).contains(&ebda) { areas[1] = (ebda, 1024); }
+    areas.iter().find_map(|&(start, len)| {
+        let mem = phys(start, len)?;
+        (0..len.saturating_sub(20)).step_by(16)
+            .find(|&i| &mem[i..i + 8] == b"RSD PTR " && checksum_ok(&mem[i..i + 20]))
+            .map(|i| start + i as u64)
+    })
+}
+
+/// A whole system description table, checksum verified.
+fn sdt(addr: u64) -> Option<&'static [u8]> {
+    let len = le32(&phys(addr, SDT_HEADER_LEN)?[4..8]) as usize;
+    if !(SDT_HEADER_LEN..=0x10000).contains(&len) { return None; }
+    let t = phys(addr, len)?;
+    checksum_ok(t).then_some(t)
+}
+
+/// The table with signature `sig`, via the XSDT (ACPI 2.0+) or the RSDT.
+pub fn find_table(sig: &[u8; 4]) -> Option<&'static [u8]> {
+    let r = phys(rsdp()?, 36)?;
+    let (root, width) = if r[15] >= 2 && le64(&r[24..32]) != 0 {
+        (sdt(le64(&r[24..32]))?, 8)
+    } else {
+        (sdt(le32(&r[16..20]) as u64)?, 4)
+    };
+    root[SDT_HEADER_LEN..].chunks_exact(width).find_map(|e| {
+        let t = sdt(if width == 8 { le64(e) } else { le32(e) as u64 })?;
+        (&t[0..4] == sig).then_some(t)
+    })
+}
+
+/// CMOS register of the RTC century, if the FADT names one.
+pub fn rtc_century_register() -> Option<u8> {
+    let mut v = CENTURY.load(Ordering::Relaxed);
+    if v == 0x100 {
+        v = find_table(b"FACP")
+            .and_then(|f| f.get(FADT_CENTURY).copied())
+            .unwrap_or(0) as u16;
+        CENTURY.store(v, Ordering::Relaxed);
+    }
+    (v != 0).then_some(v as u8)
+}
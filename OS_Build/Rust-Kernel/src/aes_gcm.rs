@@ -0,0 +1,194 @@
+#![allow(dead_code)]
+// src/aes_gcm.rs
+// AES-128 (FIPS 197) in GCM mode (NIST SP 800-38D), in place, no heap.
+// Same shape as chacha20poly1305.rs: `seal` returns the tag, `open` checks
+// it before decrypting. Table S-box and bitwise GHASH: small, not
+// constant-time.
+
+use crate::chacha20poly1305::ct_eq;
+
+pub const KEY_LEN: usize = 16;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+// -----------------------------------------------------------------------------
+// AES-128
+// -----------------------------------------------------------------------------
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const RCON: [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+#[inline(always)]
+fn xtime(b: u8) -> u8 {
+    (b << 1) ^ if b & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+/// Expanded AES-128 key: 11 round keys.
+#[derive(Clone)]
+pub struct Aes128 {
+    rk: [[u8; 16]; 11],
+}
+
+impl Aes128 {
+    pub fn new(key: &[u8; 16]) -> Self {
+        let mut w = [[0u8; 4]; 44];
+        for i in 0..4 {
+            w[i].copy_from_slice(&key[i * 4..i * 4 + 4]);
+        }
+        for i in 4..44 {
+            let mut t = w[i - 1];
+            if i % 4 == 0 {
+                t = [SBOX[t[1] as usize] ^ RCON[i / 4 - 1], SBOX[t[2] as usize], SBOX[t[3] as usize], SBOX[t[0] as usize]];
+            }
+            for j in 0..4 {
+                w[i][j] = w[i - 4][j] ^ t[j];
+            }
+        }
+        let mut rk = [[0u8; 16]; 11];
+        for (r, k) in rk.iter_mut().enumerate() {
+            for j in 0..4 {
+                k[j * 4..j * 4 + 4].copy_from_slice(&w[r * 4 + j]);
+            }
+        }
+        Self { rk }
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8; 16]) {
+        let s = block;
+        xor16(s, &self.rk[0]);
+        for round in 1..11 {
+            // SubBytes
+            for b in s.iter_mut() {
+                *b = SBOX[*b as usize];
+            }
+            // ShiftRows (state is column-major: s[col * 4 + row])
+            let t = *s;
+            for col in 0..4 {
+                for row in 0..4 {
+                    s[col * 4 + row] = t[((col + row) % 4) * 4 + row];
+                }
+            }
+            // MixColumns
+            if round != 10 {
+                for col in 0..4 {
+                    let a = [s[col * 4], s[col * 4 + 1], s[col * 4 + 2], s[col * 4 + 3]];
+                    let all = a[0] ^ a[1] ^ a[2] ^ a[3];
+                    for row in 0..4 {
+                        s[col * 4 + row] = a[row] ^ all ^ xtime(a[row] ^ a[(row + 1) % 4]);
+                    }
+                }
+            }
+            xor16(s, &self.rk[round]);
+        }
+    }
+}
+
+#[inline(always)]
+fn xor16(a: &mut [u8; 16], b: &[u8; 16]) {
+    for i in 0..16 {
+        a[i] ^= b[i];
+    }
+}
+
+// -----------------------------------------------------------------------------
+// GCM
+// -----------------------------------------------------------------------------
+
+/// Multiply in GF(2^128) with GCM's bit order (bit 0 = MSB of the block).
+fn gf_mul(x: u128, y: u128) -> u128 {
+    const R: u128 = 0xE1 << 120;
+    let mut z = 0u128;
+    let mut v = y;
+    for i in 0..128 {
+        if (x >> (127 - i)) & 1 == 1 {
+            z ^= v;
+        }
+        v = if v & 1 == 1 { (v >> 1) ^ R } else { v >> 1 };
+    }
+    z
+}
+
+struct Ghash {
+    h: u128,
+    acc: u128,
+}
+
+impl Ghash {
+    fn update_padded(&mut self, data: &[u8]) {
+        for chunk in data.chunks(16) {
+            let mut b = [0u8; 16];
+            b[..chunk.len()].copy_from_slice(chunk);
+            self.acc = gf_mul(self.acc ^ u128::from_be_bytes(b), self.h);
+        }
+    }
+}
+
+fn ctr_xor(aes: &Aes128, j0: &[u8; 16], buf: &mut [u8]) {
+    let mut counter = u32::from_be_bytes([j0[12], j0[13], j0[14], j0[15]]);
+    for chunk in buf.chunks_mut(16) {
+        counter = counter.wrapping_add(1);
+        let mut ks = *j0;
+        ks[12..].copy_from_slice(&counter.to_be_bytes());
+        aes.encrypt_block(&mut ks);
+        for (b, k) in chunk.iter_mut().zip(ks.iter()) {
+            *b ^= *k;
+        }
+    }
+}
+
+fn compute_tag(aes: &Aes128, j0: &[u8; 16], aad: &[u8], ct: &[u8]) -> [u8; 16] {
+    let mut h = [0u8; 16];
+    aes.encrypt_block(&mut h);
+    let mut g = Ghash { h: u128::from_be_bytes(h), acc: 0 };
+    g.update_padded(aad);
+    g.update_padded(ct);
+    let lens = ((aad.len() as u128 * 8) << 64) | (ct.len() as u128 * 8);
+    g.acc = gf_mul(g.acc ^ lens, g.h);
+
+    let mut tag = *j0;
+    aes.encrypt_block(&mut tag);
+    let t = u128::from_be_bytes(tag) ^ g.acc;
+    t.to_be_bytes()
+}
+
+fn j0(nonce: &[u8; 12]) -> [u8; 16] {
+    let mut j = [0u8; 16];
+    j[..12].copy_from_slice(nonce);
+    j[15] = 1;
+    j
+}
+
+/// Encrypt `buf` in place; returns the tag.
+pub fn seal(key: &[u8; 16], nonce: &[u8; 12], aad: &[u8], buf: &mut [u8]) -> [u8; 16] {
+    let aes = Aes128::new(key);
+    let j = j0(nonce);
+    ctr_xor(&aes, &j, buf);
+    compute_tag(&aes, &j, aad, buf)
+}
+
+/// Verify and decrypt `buf` in place. On failure `buf` is left untouched.
+pub fn open(key: &[u8; 16], nonce: &[u8; 12], aad: &[u8], buf: &mut [u8], tag: &[u8]) -> bool {
+    let aes = Aes128::new(key);
+    let j = j0(nonce);
+    if !ct_eq(&compute_tag(&aes, &j, aad, buf), tag) { return false; }
+    ctr_xor(&aes, &j, buf);
+    true
+}
@@ -0,0 +1,235 @@
+#![allow(dead_code)]
+// src/bignum.rs
+// Modular arithmetic on big unsigned integers for signature verification
+// (RSA up to 8192 bits, the NIST P-256/P-384 curves). Numbers are
+// little-endian u32 limbs, all the same length as the modulus; products use
+// Montgomery multiplication (CIOS). Only public data goes through here, so
+// nothing tries to be constant-time.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+pub type Limbs = Vec<u32>;
+
+/// Big-endian bytes into `k` limbs; `None` if the value doesn't fit.
+pub fn from_be(bytes: &[u8], k: usize) -> Option<Limbs> {
+    let mut out = vec![0u32; k];
+    for (i, &b) in bytes.iter().rev().enumerate() {
+        if b == 0 { continue; }
+        let limb = i / 4;
+        if limb >= k { return None; }
+        out[limb] |= (b as u32) << ((i % 4) * 8);
+    }
+    Some(out)
+}
+
+/// `len` big-endian bytes (high limbs beyond `len` must be zero).
+pub fn to_be(a: &[u32], len: usize) -> Vec<u8> {
+    let mut out = vec![0u8; len];
+    for i in 0..len {
+        let limb = i / 4;
+        if limb < a.len() {
+            out[len - 1 - i] = (a[limb] >> ((i % 4) * 8)) as u8;
+        }
+    }
+    out
+}
+
+pub fn cmp(a: &[u32], b: &[u32]) -> Ordering {
+    for i in (0..a.len()).rev() {
+        match a[i].cmp(&b[i]) {
+            Ordering::Equal => {}
+            o => return o,
+        }
+    }
+    Ordering::Equal
+}
+
+pub fn is_zero(a: &[u32]) -> bool {
+    a.iter().all(|&x| x == 0)
+}
+
+/// a -= b; returns the borrow.
+fn sub_in_place(a: &mut [u32], b: &[u32]) -> bool {
+    let mut borrow = 0u64;
+    for i in 0..a.len() {
+        let d = (a[i] as u64).wrapping_sub(b[i] as u64).wrapping_sub(borrow);
+        a[i] = d as u32;
+        borrow = (d >> 63) & 1;
+    }
+    borrow != 0
+}
+
+/// a += b; returns the carry.
+fn add_in_place(a: &mut [u32], b: &[u32]) -> bool {
+    let mut carry = 0u64;
+    for i in 0..a.len() {
+        let s = a[i] as u64 + b[i] as u64 + carry;
+        a[i] = s as u32;
+        carry = s >> 32;
+    }
+    carry != 0
+}
+
+pub fn bit_len(a: &[u32]) -> usize {
+    for i in (0..a.len()).rev() {
+        if a[i] != 0 {
+            return i * 32 + 32 - a[i].leading_zeros() as usize;
+        }
+    }
+    0
+}
+
+pub fn bit(a: &[u32], i: usize) -> bool {
+    (a[i / 32] >> (i % 32)) & 1 == 1
+}
+
+/// An odd modulus with its Montgomery constants (R = 2^(32k)).
+pub struct Modulus {
+    pub m: Limbs,
+    /// -m^-1 mod 2^32
+    n0: u32,
+    /// R^2 mod m
+    r2: Limbs,
+}
+
+impl Modulus {
+    pub fn new(m_be: &[u8]) -> Option<Modulus> {
+        let skip = m_be.iter().take_while(|&&b| b == 0).count();
+        let m_be = &m_be[skip..];
+        let k = m_be.len().div_ceil(4).max(1);
+        let m = from_be(m_be, k)?;
+        if m[0] & 1 == 0 || bit_len(&m) < 2 { return None; }
+
+        // Newton iteration for m[0]^-1 mod 2^32
+        let mut inv: u32 = 1;
+        for _ in 0..5 {
+            inv = inv.wrapping_mul(2u32.wrapping_sub(m[0].wrapping_mul(inv)));
+        }
+
+        let mut md = Modulus { m, n0: inv.wrapping_neg(), r2: Vec::new() };
+        // R^2 mod m by doubling 1, 64k times
+        let mut r = vec![0u32; k];
+        r[0] = 1;
+        for _ in 0..64 * k {
+            r = md.add(&r, &r);
+        }
+        md.r2 = r;
+        Some(md)
+    }
+
+    pub fn limbs(&self) -> usize {
+        self.m.len()
+    }
+
+    pub fn bytes(&self) -> usize {
+        bit_len(&self.m).div_ceil(8)
+    }
+
+    /// Big-endian bytes into a residue. Values up to twice the modulus are
+    /// reduced; larger ones give `None`.
+    pub fn reduce_be(&self, bytes: &[u8]) -> Option<Limbs> {
+        let mut a = from_be(bytes, self.limbs())?;
+        if cmp(&a, &self.m) != Ordering::Less {
+            sub_in_place(&mut a, &self.m);
+            if cmp(&a, &self.m) != Ordering::Less { return None; }
+        }
+        Some(a)
+    }
+
+    pub fn add(&self, a: &[u32], b: &[u32]) -> Limbs {
+        let mut r = a.to_vec();
+        let carry = add_in_place(&mut r, b);
+        if carry || cmp(&r, &self.m) != Ordering::Less {
+            sub_in_place(&mut r, &self.m);
+        }
+        r
+    }
+
+    pub fn sub(&self, a: &[u32], b: &[u32]) -> Limbs {
+        let mut r = a.to_vec();
+        if sub_in_place(&mut r, b) {
+            add_in_place(&mut r, &self.m);
+        }
+        r
+    }
+
+    /// a * b / R mod m.
+    pub fn mont_mul(&self, a: &[u32], b: &[u32]) -> Limbs {
+        let k = self.limbs();
+        let m = &self.m;
+        let mut t = vec![0u32; k + 2];
+        for &bi in b.iter().take(k) {
+            let mut c = 0u64;
+            for j in 0..k {
+                let s = t[j] as u64 + a[j] as u64 * bi as u64 + c;
+                t[j] = s as u32;
+                c = s >> 32;
+            }
+            let s = t[k] as u64 + c;
+            t[k] = s as u32;
+            t[k + 1] = (s >> 32) as u32;
+
+            let q = t[0].wrapping_mul(self.n0);
+            let s = t[0] as u64 + q as u64 * m[0] as u64;
+            let mut c = s >> 32;
+            for j in 1..k {
+                let s = t[j] as u64 + q as u64 * m[j] as u64 + c;
+                t[j - 1] = s as u32;
+                c = s >> 32;
+            }
+            let s = t[k] as u64 + c;
+            t[k - 1] = s as u32;
+            t[k] = t[k + 1] + (s >> 32) as u32;
+        }
+        let mut r = t[..k].to_vec();
+        if t[k] != 0 || cmp(&r, m) != Ordering::Less {
+            sub_in_place(&mut r, m);
+        }
+        r
+    }
+
+    pub fn enter_mont(&self, a: &[u32]) -> Limbs {
+        self.mont_mul(a, &self.r2)
+    }
+
+    pub fn leave_mont(&self, a: &[u32]) -> Limbs {
+        let mut one = vec![0u32; self.limbs()];
+        one[0] = 1;
+        self.mont_mul(a, &one)
+    }
+
+    /// 1 in Montgomery form.
+    pub fn one(&self) -> Limbs {
+        let mut one = vec![0u32; self.limbs()];
+        one[0] = 1;
+        self.enter_mont(&one)
+    }
+
+    /// base^exp with `base` in Montgomery form; the result is too.
+    pub fn pow(&self, base: &[u32], exp: &[u32]) -> Limbs {
+        let mut r = self.one();
+        for i in (0..bit_len(exp)).rev() {
+            r = self.mont_mul(&r, &r);
+            if bit(exp, i) {
+                r = self.mont_mul(&r, base);
+            }
+        }
+        r
+    }
+
+    /// a^-1 for a prime modulus (Fermat), Montgomery form in and out.
+    pub fn inv(&self, a: &[u32]) -> Limbs {
+        let mut e = self.m.clone();
+        let two = {
+            let mut t = vec![0u32; self.limbs()];
+            t[0] = 2;
+            t
+        };
+        sub_in_place(&mut e, &two);
+        self.pow(a, &e)
+    }
+}
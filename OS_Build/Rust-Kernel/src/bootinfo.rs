@@ -32,6 +32,18 @@ pub struct BootKernelMapRaw {
     pub kernel_size: u64,
 }
 
+/// Magic of the ACPI payload at (bootinfo_ptr + 48).
+pub const BOOT_ACPI_MAGIC: u32 = 0x4F54_4841; // 'OTHA'
+
+/// RSDP address from the EFI configuration table, written at (bootinfo_ptr + 48).
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct BootAcpiRaw {
+    pub magic: u32,
+    pub _reserved: u32,
+    pub rsdp: u64,
+}
+
 static mut BOOTINFO_PTR: *const BootVideoInfoRaw = ptr::null();
 
 /// Must be called once at the start of `_start()`.
@@ -61,6 +73,18 @@ pub fn kernel_map() -> Option<BootKernelMapRaw> {
     }
 }
 
+/// ACPI RSDP address passed by the UEFI loader (if present).
+pub fn acpi_rsdp() -> Option<u64> {
+    let base = bootinfo_base_u8();
+    if base.is_null() {
+        return None;
+    }
+    unsafe {
+        let a = ptr::read_unaligned(base.add(48) as *const BootAcpiRaw);
+        if a.magic == BOOT_ACPI_MAGIC && a.rsdp != 0 { Some(a.rsdp) } else { None }
+    }
+}
+
 /// Translate a virtual address to a physical address for DMA.
 ///
 /// - If the UEFI loader provided a kernel map, translate within the kernel's
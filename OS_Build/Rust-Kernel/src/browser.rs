@@ -7,7 +7,7 @@
 //!
 //! Network:
 //! - HTTP via in-kernel DNS+TCP
-//! - HTTPS via optional host-side proxy (see net::http and tools/https_proxy.py)
+//! - HTTPS via the in-kernel TLS 1.3 client (or the optional host-side proxy, see net::http)
 
 extern crate alloc;
 
@@ -327,7 +327,10 @@ fn navigate_current(push_hist: bool) {
                 net::http::HttpError::Parse => "Parse".to_string(),
                 net::http::HttpError::RedirectLoop => "RedirectLoop".to_string(),
                 net::http::HttpError::UnsupportedScheme => "UnsupportedScheme".to_string(),
+                net::http::HttpError::ProxyMethod => "ProxyMethod".to_string(),
+                net::http::HttpError::Decode => "Decode".to_string(),
                 net::http::HttpError::Tcp(te) => format!("Tcp ({:?})", te),
+                net::http::HttpError::Tls(te) => format!("Tls ({:?})", te),
             };
             with_state(|st| {
                 st.status = "Fetch failed".to_string();
@@ -0,0 +1,247 @@
+#![allow(dead_code)]
+// src/chacha20poly1305.rs
+// ChaCha20-Poly1305 AEAD (RFC 8439), in place, no heap.
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+// -----------------------------------------------------------------------------
+// ChaCha20
+// -----------------------------------------------------------------------------
+
+#[inline(always)]
+fn qr(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]); s[d] ^= s[a]; s[d] = s[d].rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]); s[b] ^= s[c]; s[b] = s[b].rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]); s[d] ^= s[a]; s[d] = s[d].rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]); s[b] ^= s[c]; s[b] = s[b].rotate_left(7);
+}
+
+#[inline(always)]
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn chacha_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let mut init = [0u32; 16];
+    init[0] = 0x6170_7865;
+    init[1] = 0x3320_646e;
+    init[2] = 0x7962_2d32;
+    init[3] = 0x6b20_6574;
+    for i in 0..8 {
+        init[4 + i] = le32(&key[i * 4..]);
+    }
+    init[12] = counter;
+    for i in 0..3 {
+        init[13 + i] = le32(&nonce[i * 4..]);
+    }
+
+    let mut s = init;
+    for _ in 0..10 {
+        qr(&mut s, 0, 4, 8, 12);
+        qr(&mut s, 1, 5, 9, 13);
+        qr(&mut s, 2, 6, 10, 14);
+        qr(&mut s, 3, 7, 11, 15);
+        qr(&mut s, 0, 5, 10, 15);
+        qr(&mut s, 1, 6, 11, 12);
+        qr(&mut s, 2, 7, 8, 13);
+        qr(&mut s, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; 64];
+    for i in 0..16 {
+        out[i * 4..i * 4 + 4].copy_from_slice(&s[i].wrapping_add(init[i]).to_le_bytes());
+    }
+    out
+}
+
+/// XOR `buf` with the keystream starting at block `counter`.
+pub fn chacha20_xor(key: &[u8; 32], counter: u32, nonce: &[u8; 12], buf: &mut [u8]) {
+    for (i, chunk) in buf.chunks_mut(64).enumerate() {
+        let ks = chacha_block(key, counter.wrapping_add(i as u32), nonce);
+        for (b, k) in chunk.iter_mut().zip(ks.iter()) {
+            *b ^= *k;
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Poly1305 (26-bit limbs)
+// -----------------------------------------------------------------------------
+
+struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+    buf: [u8; 16],
+    buf_len: usize,
+}
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Self {
+        Self {
+            r: [
+                le32(&key[0..]) & 0x03ff_ffff,
+                (le32(&key[3..]) >> 2) & 0x03ff_ff03,
+                (le32(&key[6..]) >> 4) & 0x03ff_c0ff,
+                (le32(&key[9..]) >> 6) & 0x03f0_3fff,
+                (le32(&key[12..]) >> 8) & 0x000f_ffff,
+            ],
+            h: [0; 5],
+            pad: [le32(&key[16..]), le32(&key[20..]), le32(&key[24..]), le32(&key[28..])],
+            buf: [0; 16],
+            buf_len: 0,
+        }
+    }
+
+    fn block(&mut self, m: &[u8; 16], hibit: u32) {
+        const M: u32 = 0x03ff_ffff;
+        let [r0, r1, r2, r3, r4] = self.r.map(|x| x as u64);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+
+        let h0 = (self.h[0] + (le32(&m[0..]) & M)) as u64;
+        let h1 = (self.h[1] + ((le32(&m[3..]) >> 2) & M)) as u64;
+        let h2 = (self.h[2] + ((le32(&m[6..]) >> 4) & M)) as u64;
+        let h3 = (self.h[3] + ((le32(&m[9..]) >> 6) & M)) as u64;
+        let h4 = (self.h[4] + ((le32(&m[12..]) >> 8) | hibit)) as u64;
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        let mut c = d0 >> 26;
+        let mut n0 = (d0 as u32) & M;
+        d1 += c; c = d1 >> 26; let mut n1 = (d1 as u32) & M;
+        d2 += c; c = d2 >> 26; let n2 = (d2 as u32) & M;
+        d3 += c; c = d3 >> 26; let n3 = (d3 as u32) & M;
+        d4 += c; c = d4 >> 26; let n4 = (d4 as u32) & M;
+        n0 += (c as u32) * 5;
+        let c2 = n0 >> 26;
+        n0 &= M;
+        n1 += c2;
+
+        self.h = [n0, n1, n2, n3, n4];
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        if self.buf_len > 0 {
+            let take = (16 - self.buf_len).min(data.len());
+            self.buf[self.buf_len..self.buf_len + take].copy_from_slice(&data[..take]);
+            self.buf_len += take;
+            data = &data[take..];
+            if self.buf_len < 16 { return; }
+            let b = self.buf;
+            self.block(&b, 1 << 24);
+            self.buf_len = 0;
+        }
+        while data.len() >= 16 {
+            let mut b = [0u8; 16];
+            b.copy_from_slice(&data[..16]);
+            self.block(&b, 1 << 24);
+            data = &data[16..];
+        }
+        self.buf[..data.len()].copy_from_slice(data);
+        self.buf_len = data.len();
+    }
+
+    /// Zero-pad the pending input to a 16-byte boundary (AEAD framing).
+    fn pad16(&mut self) {
+        if self.buf_len > 0 {
+            for b in self.buf[self.buf_len..].iter_mut() { *b = 0; }
+            let b = self.buf;
+            self.block(&b, 1 << 24);
+            self.buf_len = 0;
+        }
+    }
+
+    fn finish(mut self) -> [u8; 16] {
+        const M: u32 = 0x03ff_ffff;
+        if self.buf_len > 0 {
+            let mut b = [0u8; 16];
+            b[..self.buf_len].copy_from_slice(&self.buf[..self.buf_len]);
+            b[self.buf_len] = 1;
+            self.block(&b, 0);
+        }
+
+        let [mut h0, mut h1, mut h2, mut h3, mut h4] = self.h;
+        let mut c;
+        c = h1 >> 26; h1 &= M; h2 += c;
+        c = h2 >> 26; h2 &= M; h3 += c;
+        c = h3 >> 26; h3 &= M; h4 += c;
+        c = h4 >> 26; h4 &= M; h0 += c * 5;
+        c = h0 >> 26; h0 &= M; h1 += c;
+
+        // g = h + 5 - 2^130; keep h if g is negative
+        let mut g0 = h0.wrapping_add(5); c = g0 >> 26; g0 &= M;
+        let mut g1 = h1.wrapping_add(c); c = g1 >> 26; g1 &= M;
+        let mut g2 = h2.wrapping_add(c); c = g2 >> 26; g2 &= M;
+        let mut g3 = h3.wrapping_add(c); c = g3 >> 26; g3 &= M;
+        let g4 = h4.wrapping_add(c).wrapping_sub(1 << 26);
+
+        let mask = (g4 >> 31).wrapping_sub(1);
+        h0 = (h0 & !mask) | (g0 & mask);
+        h1 = (h1 & !mask) | (g1 & mask);
+        h2 = (h2 & !mask) | (g2 & mask);
+        h3 = (h3 & !mask) | (g3 & mask);
+        h4 = (h4 & !mask) | (g4 & mask);
+
+        let w0 = h0 | (h1 << 26);
+        let w1 = (h1 >> 6) | (h2 << 20);
+        let w2 = (h2 >> 12) | (h3 << 14);
+        let w3 = (h3 >> 18) | (h4 << 8);
+
+        let mut out = [0u8; 16];
+        let mut f: u64 = 0;
+        for (i, (w, p)) in [w0, w1, w2, w3].iter().zip(self.pad.iter()).enumerate() {
+            f = (f >> 32) + *w as u64 + *p as u64;
+            out[i * 4..i * 4 + 4].copy_from_slice(&(f as u32).to_le_bytes());
+        }
+        out
+    }
+}
+
+// -----------------------------------------------------------------------------
+// AEAD
+// -----------------------------------------------------------------------------
+
+fn compute_tag(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], ct: &[u8]) -> [u8; 16] {
+    let block0 = chacha_block(key, 0, nonce);
+    let mut otk = [0u8; 32];
+    otk.copy_from_slice(&block0[..32]);
+
+    let mut p = Poly1305::new(&otk);
+    p.update(aad);
+    p.pad16();
+    p.update(ct);
+    p.pad16();
+    p.update(&(aad.len() as u64).to_le_bytes());
+    p.update(&(ct.len() as u64).to_le_bytes());
+    p.finish()
+}
+
+/// Encrypt `buf` in place; returns the tag.
+pub fn seal(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], buf: &mut [u8]) -> [u8; 16] {
+    chacha20_xor(key, 1, nonce, buf);
+    compute_tag(key, nonce, aad, buf)
+}
+
+/// Verify and decrypt `buf` in place. On failure `buf` is left untouched.
+pub fn open(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], buf: &mut [u8], tag: &[u8]) -> bool {
+    let calc = compute_tag(key, nonce, aad, buf);
+    if !ct_eq(&calc, tag) { return false; }
+    chacha20_xor(key, 1, nonce, buf);
+    true
+}
+
+/// Constant-time comparison.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() { return false; }
+    let mut d = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        d |= x ^ y;
+    }
+    d == 0
+}
@@ -18,8 +18,14 @@ pub enum FsError {
     Exists,
     InvalidPath,
     ReadOnly,
+    QuotaExceeded,
 }
 
+/// Owner of everything created before anyone logs in.
+pub const ROOT_OWNER: &str = "root";
+/// Per-user quotas live here so they persist like any other file.
+pub const QUOTA_FILE: &str = "/etc/quota";
+
 pub type FsResult<T> = core::result::Result<T, FsError>;
 
 /// Minimal spinlock for no_std
@@ -64,6 +70,9 @@ enum NodeKind {
 struct Node {
     name: String,
     parent: Option<usize>,
+    owner: String,
+    // account charged for a file's bytes: whoever last wrote it
+    payer: String,
     kind: NodeKind,
 }
 
@@ -75,6 +84,11 @@ pub struct RamFs {
     // persistence tracking
     dirty_puts: BTreeMap<String, bool>,
     dirty_dels: BTreeMap<String, bool>,
+
+    // accounting: new nodes belong to `owner`; bytes are charged to the writer
+    owner: String,
+    usage: BTreeMap<String, u64>,
+    quotas: BTreeMap<String, u64>,
 }
 
 impl RamFs {
@@ -83,6 +97,8 @@ impl RamFs {
         nodes.push(Node {
             name: "/".to_string(),
             parent: None,
+            owner: ROOT_OWNER.to_string(),
+            payer: ROOT_OWNER.to_string(),
             kind: NodeKind::Dir { children: BTreeMap::new() },
         });
         Self {
@@ -90,6 +106,9 @@ impl RamFs {
             root: 0,
             dirty_puts: BTreeMap::new(),
             dirty_dels: BTreeMap::new(),
+            owner: ROOT_OWNER.to_string(),
+            usage: BTreeMap::new(),
+            quotas: BTreeMap::new(),
         }
     }
 
@@ -101,6 +120,174 @@ impl RamFs {
         (puts, dels)
     }
 
+    /// Drop every node and all pending dirty state (the current owner stays).
+    pub fn reset(&mut self) {
+        let owner = core::mem::take(&mut self.owner);
+        *self = RamFs::new();
+        self.owner = owner;
+    }
+
+    // ---- ownership, usage and quotas ----
+
+    /// Owner for nodes created from now on; returns the previous one.
+    pub fn set_owner(&mut self, owner: &str) -> String {
+        core::mem::replace(&mut self.owner, owner.to_string())
+    }
+
+    pub fn current_owner(&self) -> &str { &self.owner }
+
+    /// Is the current owner allowed to change quotas? Root, and the account
+    /// whose login created the encrypted persist store.
+    pub fn owner_is_admin(&self) -> bool {
+        self.owner == ROOT_OWNER || crate::persist::is_store_owner(&self.owner)
+    }
+
+    pub fn owner_of(&self, abs_path: &str) -> FsResult<String> {
+        let idx = self.resolve_abs(abs_path)?;
+        Ok(self.nodes[idx].owner.clone())
+    }
+
+    /// Account charged for the file at `abs_path`.
+    pub fn payer_of(&self, abs_path: &str) -> FsResult<String> {
+        let idx = self.resolve_abs(abs_path)?;
+        Ok(self.nodes[idx].payer.clone())
+    }
+
+    /// Bytes stored under `abs_path` (a file's size, or a whole tree).
+    pub fn du(&self, abs_path: &str) -> FsResult<u64> {
+        let idx = self.resolve_abs(abs_path)?;
+        Ok(self.tree_bytes(idx))
+    }
+
+    fn tree_bytes(&self, idx: usize) -> u64 {
+        match &self.nodes[idx].kind {
+            NodeKind::File { data } => data.len() as u64,
+            NodeKind::Dir { children } => children.values().map(|&c| self.tree_bytes(c)).sum(),
+        }
+    }
+
+    pub fn usage_of(&self, owner: &str) -> u64 {
+        self.usage.get(owner).copied().unwrap_or(0)
+    }
+
+    /// (owner, bytes) for everyone who owns at least one byte.
+    pub fn usage_by_owner(&self) -> Vec<(String, u64)> {
+        self.usage.iter().filter(|(_, &b)| b > 0).map(|(o, &b)| (o.clone(), b)).collect()
+    }
+
+    pub fn quota(&self, owner: &str) -> Option<u64> {
+        self.quotas.get(owner).copied()
+    }
+
+    pub fn quotas(&self) -> Vec<(String, u64)> {
+        self.quotas.iter().map(|(o, &b)| (o.clone(), b)).collect()
+    }
+
+    /// Set (Some) or clear (None) a user's quota and save the table.
+    pub fn set_quota(&mut self, owner: &str, limit: Option<u64>) -> FsResult<()> {
+        match limit {
+            Some(b) => { self.quotas.insert(owner.to_string(), b); }
+            None => { self.quotas.remove(owner); }
+        }
+        let mut text = String::new();
+        for (o, b) in &self.quotas {
+            text.push_str(&alloc::format!("{o} {b}\n"));
+        }
+        let prev = self.set_owner(ROOT_OWNER);
+        let _ = self.mkdir_p_inner("/etc", true);
+        let res = self.write_all_inner(QUOTA_FILE, text.as_bytes(), true);
+        self.owner = prev;
+        res
+    }
+
+    /// Re-read the quota table from QUOTA_FILE (after a log replay).
+    pub fn reload_quotas(&mut self) {
+        self.quotas.clear();
+        let Ok(bytes) = self.read_all(QUOTA_FILE) else { return; };
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        for line in text.lines() {
+            let mut it = line.split_whitespace();
+            if let (Some(o), Some(b)) = (it.next(), it.next()) {
+                if let Ok(b) = b.parse::<u64>() {
+                    self.quotas.insert(o.to_string(), b);
+                }
+            }
+        }
+    }
+
+    /// Would `owner` leaving a file at `new` bytes (of which `old` are
+    /// already charged to them) break its quota? Each sync appends the whole
+    /// file to the log, which never gives space back, so the log bytes
+    /// already written for `owner` count as well.
+    fn check_quota(&self, owner: &str, old: usize, new: usize) -> FsResult<()> {
+        if new == 0 || owner == ROOT_OWNER { return Ok(()); }
+        if let Some(limit) = self.quota(owner) {
+            let live = self.usage_of(owner).saturating_sub(old as u64) + new as u64;
+            let logged = crate::persist::log_usage_of(owner) + new as u64;
+            if (new > old && live > limit) || logged > limit {
+                return Err(FsError::QuotaExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    fn charge(&mut self, owner: &str, old: usize, new: usize) {
+        let e = self.usage.entry(owner.to_string()).or_insert(0);
+        *e = (*e + new as u64).saturating_sub(old as u64);
+    }
+
+    /// (payer, current size) of the file at `abs_path`, or of a new file there.
+    fn file_payer_len(&self, abs_path: &str) -> (String, usize) {
+        match self.resolve_abs(abs_path) {
+            Ok(idx) => {
+                let len = match &self.nodes[idx].kind {
+                    NodeKind::File { data } => data.len(),
+                    _ => 0,
+                };
+                (self.nodes[idx].payer.clone(), len)
+            }
+            Err(_) => (self.owner.clone(), 0),
+        }
+    }
+
+    /// The quota table may only be changed by an admin; anyone else could
+    /// simply raise their own limit.
+    fn check_writable(&self, abs_path: &str) -> FsResult<()> {
+        if abs_path == QUOTA_FILE && !self.owner_is_admin() {
+            return Err(FsError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Mark every file for rewrite (used when the persist log is rebuilt).
+    pub fn mark_all_dirty(&mut self) {
+        for path in self.file_paths() {
+            self.mark_put(&path);
+        }
+    }
+
+    /// Absolute path of every file.
+    pub fn file_paths(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut stack = alloc::vec![(self.root, String::new())];
+        while let Some((idx, path)) = stack.pop() {
+            match &self.nodes[idx].kind {
+                NodeKind::Dir { children } => {
+                    for (name, &child) in children {
+                        stack.push((child, alloc::format!("{path}/{name}")));
+                    }
+                }
+                NodeKind::File { .. } => out.push(path),
+            }
+        }
+        out
+    }
+
+    /// Record `abs_path` as deleted for the next sync unless it exists.
+    pub fn mark_gone(&mut self, abs_path: &str) {
+        if !self.exists(abs_path) { self.mark_del(abs_path); }
+    }
+
     pub fn exists(&self, abs_path: &str) -> bool {
         self.resolve_abs(abs_path).is_ok()
     }
@@ -173,7 +360,8 @@ impl RamFs {
         }
     }
 
-    fn mkdir_p_inner(&mut self, abs_path: &str, _dirty: bool) -> FsResult<()> {
+    fn mkdir_p_inner(&mut self, abs_path: &str, dirty: bool) -> FsResult<()> {
+        if dirty { self.check_writable(abs_path)?; }
         let comps = split_abs(abs_path)?;
         let mut cur = self.root;
 
@@ -194,6 +382,8 @@ impl RamFs {
             self.nodes.push(Node {
                 name: name.clone(),
                 parent: Some(cur),
+                owner: self.owner.clone(),
+                payer: self.owner.clone(),
                 kind: NodeKind::Dir { children: BTreeMap::new() },
             });
 
@@ -212,6 +402,7 @@ impl RamFs {
     }
 
     fn touch_inner(&mut self, abs_path: &str, dirty: bool) -> FsResult<()> {
+        if dirty { self.check_writable(abs_path)?; }
         let (parent, leaf) = parent_leaf(abs_path)?;
         let pidx = self.resolve_abs(&parent)?;
 
@@ -228,6 +419,8 @@ impl RamFs {
         self.nodes.push(Node {
             name: leaf.clone(),
             parent: Some(pidx),
+            owner: self.owner.clone(),
+            payer: self.owner.clone(),
             kind: NodeKind::File { data: Vec::new() },
         });
 
@@ -243,6 +436,13 @@ impl RamFs {
     }
 
     fn write_all_inner(&mut self, abs_path: &str, bytes: &[u8], dirty: bool) -> FsResult<()> {
+        // replay (dirty == false) must never be refused, or the log would diverge
+        let (payer, old) = self.file_payer_len(abs_path);
+        let writer = self.owner.clone();
+        if dirty {
+            self.check_writable(abs_path)?;
+            self.check_quota(&writer, if payer == writer { old } else { 0 }, bytes.len())?;
+        }
         if self.resolve_abs(abs_path).is_err() {
             self.touch_inner(abs_path, dirty)?;
         }
@@ -251,6 +451,9 @@ impl RamFs {
             NodeKind::File { data } => {
                 data.clear();
                 data.extend_from_slice(bytes);
+                self.charge(&payer, old, 0);
+                self.charge(&writer, 0, bytes.len());
+                self.nodes[idx].payer = writer;
                 if dirty { self.mark_put(abs_path); }
                 Ok(())
             }
@@ -259,6 +462,12 @@ impl RamFs {
     }
 
     fn append_all_inner(&mut self, abs_path: &str, bytes: &[u8], dirty: bool) -> FsResult<()> {
+        let (payer, old) = self.file_payer_len(abs_path);
+        let writer = self.owner.clone();
+        if dirty {
+            self.check_writable(abs_path)?;
+            self.check_quota(&writer, if payer == writer { old } else { 0 }, old + bytes.len())?;
+        }
         if self.resolve_abs(abs_path).is_err() {
             self.touch_inner(abs_path, dirty)?;
         }
@@ -266,6 +475,9 @@ impl RamFs {
         match &mut self.nodes[idx].kind {
             NodeKind::File { data } => {
                 data.extend_from_slice(bytes);
+                self.charge(&payer, old, 0);
+                self.charge(&writer, 0, old + bytes.len());
+                self.nodes[idx].payer = writer;
                 if dirty { self.mark_put(abs_path); }
                 Ok(())
             }
@@ -275,6 +487,7 @@ impl RamFs {
 
     fn rm_inner(&mut self, abs_path: &str, dirty: bool) -> FsResult<()> {
         if abs_path == "/" { return Err(FsError::InvalidPath); }
+        if dirty { self.check_writable(abs_path)?; }
         let idx = self.resolve_abs(abs_path)?;
         // Can't remove non-empty dirs
         if let NodeKind::Dir { children } = &self.nodes[idx].kind {
@@ -286,6 +499,13 @@ impl RamFs {
             NodeKind::Dir { children } => { children.remove(&name); }
             _ => return Err(FsError::NotDir),
         }
+        if let NodeKind::File { data } = &mut self.nodes[idx].kind {
+            let len = data.len();
+            // the node slot is never reused; drop its bytes now
+            *data = Vec::new();
+            let payer = self.nodes[idx].payer.clone();
+            self.charge(&payer, len, 0);
+        }
         if dirty { self.mark_del(abs_path); }
         Ok(())
     }
@@ -354,6 +574,7 @@ pub fn init_default_layout() {
     let _ = fs.mkdir_p_nodirty("/bin");
     let _ = fs.write_all_nodirty("/etc/motd", b"Welcome to Othello OS!\nType: help, ls, cat, write, mkdir, touch, cd, pwd, sync\n");
     let _ = fs.write_all_nodirty("/home/user/readme.txt", b"This is your home directory.\n");
+    let _ = fs.write_all_nodirty(crate::net::netconf::PATH, crate::net::netconf::DEFAULT.as_bytes());
 }
 
 /// Convert (cwd, path) -> normalized absolute path
@@ -5,7 +5,9 @@
 extern crate alloc;
 
 use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use crate::fs::{self, FS, FsError, SpinLock};
+use crate::p9::{self, P9Error};
 use crate::persist;
 
 static CWD: SpinLock<String> = SpinLock::new(String::new());
@@ -32,6 +34,11 @@ pub fn try_handle(cmd: &str, args: &[&str]) -> Option<String> {
         "append" => Some(cmd_write(args, true)),
         "sync" => Some(cmd_sync()),
         "persist" => Some(cmd_persist(args)),
+        "du" => Some(cmd_du(args)),
+        "df" => Some(cmd_df()),
+        "quota" => Some(cmd_quota(args)),
+        "mount" => Some(cmd_mount(args)),
+        "umount" => Some(cmd_umount()),
         _ => None,
     }
 }
@@ -45,6 +52,15 @@ fn cmd_cd(args: &[&str]) -> String {
         Ok(p) => p,
         Err(e) => return alloc::format!("cd: {e:?}"),
     };
+    if p9::owns(&abs) {
+        match p9::stat(&abs) {
+            Ok(a) if a.is_dir() => {}
+            Ok(_) => return "cd: not a directory".to_string(),
+            Err(e) => return p9_err("cd", e),
+        }
+        *CWD.lock() = abs;
+        return String::new();
+    }
     let fsg = FS.lock();
     if !fsg.exists(&abs) { return "cd: not found".to_string(); }
     if !fsg.is_dir(&abs) { return "cd: not a directory".to_string(); }
@@ -60,6 +76,19 @@ fn cmd_ls(args: &[&str]) -> String {
         Ok(p) => p,
         Err(e) => return alloc::format!("ls: {e:?}"),
     };
+    if p9::owns(&abs) {
+        return match p9::list(&abs) {
+            Ok(items) => {
+                let mut out = String::new();
+                for it in items {
+                    out.push_str(&it.name);
+                    out.push('\n');
+                }
+                out
+            }
+            Err(e) => p9_err("ls", e),
+        };
+    }
     let fsg = FS.lock();
     match fsg.ls(&abs) {
         Ok(items) => {
@@ -77,18 +106,58 @@ fn cmd_ls(args: &[&str]) -> String {
     }
 }
 
+/// Whole file at `path` (relative to the shell's cwd, ramfs or 9p).
+/// Errors come back as "`cmd`: ..." messages.
+pub fn read_file(cmd: &str, path: &str) -> Result<Vec<u8>, String> {
+    let abs = fs::normalize_path(&cwd(), path).map_err(|e| alloc::format!("{cmd}: {e:?}"))?;
+    if p9::owns(&abs) {
+        p9::read_all(&abs).map_err(|e| p9_err(cmd, e))
+    } else {
+        match FS.lock().read_all(&abs) {
+            Ok(b) => Ok(b),
+            Err(FsError::NotFile) => Err(alloc::format!("{cmd}: not a file")),
+            Err(FsError::NotFound) => Err(alloc::format!("{cmd}: not found")),
+            Err(e) => Err(alloc::format!("{cmd}: {e:?}")),
+        }
+    }
+}
+
+/// Create or replace the file at `path` with `data`, making missing parent
+/// directories on the ramfs. Errors come back as "`cmd`: ..." messages.
+pub fn write_file(cmd: &str, path: &str, data: &[u8]) -> Result<(), String> {
+    store_file(cmd, path, data, false)
+}
+
+/// Like `write_file`, but adds `data` to the end of the file.
+pub fn append_file(cmd: &str, path: &str, data: &[u8]) -> Result<(), String> {
+    store_file(cmd, path, data, true)
+}
+
+fn store_file(cmd: &str, path: &str, data: &[u8], append: bool) -> Result<(), String> {
+    let abs = fs::normalize_path(&cwd(), path).map_err(|e| alloc::format!("{cmd}: {e:?}"))?;
+    if p9::owns(&abs) {
+        let res = if append { p9::append_all(&abs, data) } else { p9::write_all(&abs, data) };
+        return res.map_err(|e| p9_err(cmd, e));
+    }
+    let mut fsg = FS.lock();
+    if let Some(i) = abs.rfind('/').filter(|&i| i > 0) {
+        fsg.mkdir_p(&abs[..i]).map_err(|e| alloc::format!("{cmd}: {e:?}"))?;
+    }
+    let res = if append { fsg.append_all(&abs, data) } else { fsg.write_all(&abs, data) };
+    match res {
+        Ok(()) => Ok(()),
+        Err(FsError::QuotaExceeded) => Err(alloc::format!("{cmd}: quota exceeded")),
+        Err(FsError::NotFile) => Err(alloc::format!("{cmd}: not a file")),
+        Err(e) => Err(alloc::format!("{cmd}: {e:?}")),
+    }
+}
+
 fn cmd_cat(args: &[&str]) -> String {
     let path = match args.get(0) {
         Some(p) => *p,
         None => return "cat: missing path".to_string(),
     };
-    let cur = cwd();
-    let abs = match fs::normalize_path(&cur, path) {
-        Ok(p) => p,
-        Err(e) => return alloc::format!("cat: {e:?}"),
-    };
-    let fsg = FS.lock();
-    match fsg.read_all(&abs) {
+    match read_file("cat", path) {
         Ok(bytes) => {
             match core::str::from_utf8(&bytes) {
                 Ok(s) => s.to_string(),
@@ -101,9 +170,7 @@ fn cmd_cat(args: &[&str]) -> String {
                 }
             }
         }
-        Err(FsError::NotFile) => "cat: not a file".to_string(),
-        Err(FsError::NotFound) => "cat: not found".to_string(),
-        Err(e) => alloc::format!("cat: {e:?}"),
+        Err(msg) => msg,
     }
 }
 
@@ -114,6 +181,9 @@ fn cmd_mkdir(args: &[&str]) -> String {
         Ok(p) => p,
         Err(e) => return alloc::format!("mkdir: {e:?}"),
     };
+    if p9::owns(&abs) {
+        return p9::mkdir_p(&abs).map_or_else(|e| p9_err("mkdir", e), |_| String::new());
+    }
     let mut fsg = FS.lock();
     match fsg.mkdir_p(&abs) {
         Ok(()) => String::new(),
@@ -129,6 +199,9 @@ fn cmd_touch(args: &[&str]) -> String {
         Ok(p) => p,
         Err(e) => return alloc::format!("touch: {e:?}"),
     };
+    if p9::owns(&abs) {
+        return p9::touch(&abs).map_or_else(|e| p9_err("touch", e), |_| String::new());
+    }
     let mut fsg = FS.lock();
     match fsg.touch(&abs) {
         Ok(()) => String::new(),
@@ -143,6 +216,9 @@ fn cmd_rm(args: &[&str]) -> String {
         Ok(p) => p,
         Err(e) => return alloc::format!("rm: {e:?}"),
     };
+    if p9::owns(&abs) {
+        return p9::remove(&abs).map_or_else(|e| p9_err("rm", e), |_| String::new());
+    }
     let mut fsg = FS.lock();
     match fsg.rm(&abs) {
         Ok(()) => String::new(),
@@ -165,11 +241,21 @@ fn cmd_write(args: &[&str], append: bool) -> String {
         Err(e) => return alloc::format!("write: {e:?}"),
     };
 
+    if p9::owns(&abs) {
+        let res = if append { p9::append_all(&abs, text.as_bytes()) } else { p9::write_all(&abs, text.as_bytes()) };
+        return res.map_or_else(|e| p9_err("write", e), |_| String::new());
+    }
+
     let mut fsg = FS.lock();
     let bytes = text.as_bytes();
     let res = if append { fsg.append_all(&abs, bytes) } else { fsg.write_all(&abs, bytes) };
     match res {
         Ok(()) => String::new(),
+        Err(FsError::QuotaExceeded) => {
+            let owner = fsg.owner_of(&abs).unwrap_or_else(|_| fsg.current_owner().to_string());
+            alloc::format!("write: quota exceeded for {owner} ({} of {})",
+                fmt_size(fsg.usage_of(&owner)), fmt_size(fsg.quota(&owner).unwrap_or(0)))
+        }
         Err(e) => alloc::format!("write: {e:?}"),
     }
 }
@@ -180,15 +266,31 @@ fn cmd_sync() -> String {
     }
     match persist::sync_dirty() {
         Ok(n) => alloc::format!("sync: wrote {n} record(s)"),
+        Err(persist::PersistError::Locked) => "sync: store is locked (log in first)".to_string(),
         Err(e) => alloc::format!("sync: error {e:?}"),
     }
 }
 
 fn cmd_persist(args: &[&str]) -> String {
-    let sub = args.get(0).copied().unwrap_or("status");
+    let sub = args.first().copied().unwrap_or("status");
     match sub {
         "status" => {
-            if persist::enabled() { "persist: enabled".to_string() } else { "persist: disabled".to_string() }
+            if !persist::enabled() { return "persist: disabled".to_string(); }
+            let st = persist::stats();
+            let crypt = match (persist::encrypted(), persist::unlocked()) {
+                (true, true) => alloc::format!("encrypted (unlocked, {} key slot(s))", persist::key_slots_used()),
+                (true, false) => "encrypted (locked)".to_string(),
+                _ => "plaintext".to_string(),
+            };
+            let mut out = alloc::format!(
+                "persist: enabled, {crypt}, compress={}, hash={}",
+                on_off(persist::compression_enabled()),
+                if persist::strong_hash_enabled() { "crc32+sha256" } else { "crc32" },
+            );
+            if st.raw_bytes > 0 {
+                out.push_str(&alloc::format!("\nwritten since boot: {} bytes -> {} on disk", st.raw_bytes, st.stored_bytes));
+            }
+            out
         }
         "format" => {
             match persist::format() {
@@ -196,7 +298,233 @@ fn cmd_persist(args: &[&str]) -> String {
                 Err(e) => alloc::format!("persist: format failed {e:?}"),
             }
         }
-        _ => "persist: usage: persist [status|format]".to_string()
+        "snapshot" => {
+            let Some(name) = args.get(1) else { return "persist: usage: persist snapshot <name>".to_string() };
+            match persist::snapshot(name) {
+                Ok(()) => alloc::format!("persist: snapshot '{name}' recorded"),
+                Err(e) => persist_err(e),
+            }
+        }
+        "list" => {
+            match persist::list_snapshots() {
+                Ok(snaps) if snaps.is_empty() => "persist: no snapshots".to_string(),
+                Ok(snaps) => {
+                    let mut out = String::new();
+                    for s in snaps {
+                        out.push_str(&alloc::format!("{:<16} {}  ({} KiB of log)\n", s.name, s.created, s.sectors / 2));
+                    }
+                    out
+                }
+                Err(e) => persist_err(e),
+            }
+        }
+        "rollback" => {
+            let Some(name) = args.get(1) else { return "persist: usage: persist rollback <name>".to_string() };
+            match persist::rollback(name) {
+                Ok(()) => {
+                    after_fs_rebuild();
+                    alloc::format!("persist: rolled back to '{name}'")
+                }
+                Err(e) => {
+                    after_fs_rebuild();
+                    persist_err(e)
+                }
+            }
+        }
+        "compress" | "hash" => {
+            let on = match args.get(1).copied() {
+                Some("on") => true,
+                Some("off") => false,
+                _ => return alloc::format!("persist: usage: persist {sub} on|off"),
+            };
+            let res = if sub == "compress" { persist::set_compression(on) } else { persist::set_strong_hash(on) };
+            match res {
+                Ok(()) => alloc::format!("persist: {sub} {}", on_off(on)),
+                Err(e) => alloc::format!("persist: {e:?}"),
+            }
+        }
+        _ => "persist: usage: persist [status|format|snapshot <name>|list|rollback <name>|compress on|off|hash on|off]".to_string()
+    }
+}
+
+fn persist_err(e: persist::PersistError) -> String {
+    match e {
+        persist::PersistError::Locked => "persist: store is locked (log in first)".to_string(),
+        persist::PersistError::NoSuchSnapshot => "persist: no such snapshot".to_string(),
+        persist::PersistError::SnapshotExists => "persist: snapshot already exists".to_string(),
+        persist::PersistError::InvalidName => "persist: invalid snapshot name".to_string(),
+        e => alloc::format!("persist: {e:?}"),
+    }
+}
+
+/// RamFs was rebuilt from the log: re-graft the host share and make sure the
+/// shell's cwd still exists.
+fn after_fs_rebuild() {
+    if let Some((mp, _)) = p9::mount_info() {
+        let _ = FS.lock().mkdir_p_nodirty(&mp);
+    }
+    let cur = cwd();
+    if !p9::owns(&cur) && !FS.lock().is_dir(&cur) {
+        *CWD.lock() = "/".to_string();
+    }
+}
+
+fn on_off(b: bool) -> &'static str { if b { "on" } else { "off" } }
+
+fn cmd_du(args: &[&str]) -> String {
+    let path = args.first().copied().unwrap_or(".");
+    let abs = match fs::normalize_path(&cwd(), path) {
+        Ok(p) => p,
+        Err(e) => return alloc::format!("du: {e:?}"),
+    };
+    if p9::owns(&abs) { return "du: not supported on the host share".to_string(); }
+
+    let fsg = FS.lock();
+    let total = match fsg.du(&abs) {
+        Ok(t) => t,
+        Err(FsError::NotFound) => return "du: not found".to_string(),
+        Err(e) => return alloc::format!("du: {e:?}"),
+    };
+    let mut out = String::new();
+    if let Ok(children) = fsg.ls(&abs) {
+        for name in children {
+            let child = if abs == "/" { alloc::format!("/{name}") } else { alloc::format!("{abs}/{name}") };
+            let size = fsg.du(&child).unwrap_or(0);
+            let owner = fsg.owner_of(&child).unwrap_or_default();
+            out.push_str(&alloc::format!("{:>9}  {:<10} {}\n", fmt_size(size), owner, child));
+        }
+    }
+    out.push_str(&alloc::format!("{:>9}  total {}", fmt_size(total), abs));
+    out
+}
+
+fn cmd_df() -> String {
+    let fsg = FS.lock();
+    let mut out = String::new();
+    match persist::disk_usage() {
+        Some((used, cap)) => out.push_str(&alloc::format!(
+            "persist log: {} used of {} ({} free)\n",
+            fmt_size(used), fmt_size(cap), fmt_size(cap.saturating_sub(used)),
+        )),
+        None => out.push_str("persist log: disabled\n"),
+    }
+    out.push_str(&alloc::format!("ramfs: {} in files\n", fmt_size(fsg.du("/").unwrap_or(0))));
+    out.push_str(&quota_table(&fsg));
+    out
+}
+
+/// quota                      show usage/limits per owner
+/// quota set <user> <size>    size accepts K/M suffixes
+/// quota clear <user>
+fn cmd_quota(args: &[&str]) -> String {
+    let sub = args.first().copied();
+    if matches!(sub, Some("set" | "clear")) && !FS.lock().owner_is_admin() {
+        return "quota: only root or the store owner can change quotas".to_string();
+    }
+    match sub {
+        None => quota_table(&FS.lock()),
+        Some("set") => {
+            let (Some(user), Some(size)) = (args.get(1), args.get(2)) else {
+                return "quota: usage: quota set <user> <bytes|NK|NM>".to_string();
+            };
+            let Some(limit) = parse_size(size) else { return "quota: bad size".to_string() };
+            match FS.lock().set_quota(user, Some(limit)) {
+                Ok(()) => alloc::format!("quota: {user} limited to {}", fmt_size(limit)),
+                Err(e) => alloc::format!("quota: {e:?}"),
+            }
+        }
+        Some("clear") => {
+            let Some(user) = args.get(1) else { return "quota: usage: quota clear <user>".to_string() };
+            match FS.lock().set_quota(user, None) {
+                Ok(()) => alloc::format!("quota: {user} unlimited"),
+                Err(e) => alloc::format!("quota: {e:?}"),
+            }
+        }
+        _ => "quota: usage: quota [set <user> <size>|clear <user>]".to_string(),
+    }
+}
+
+fn quota_table(fsg: &fs::RamFs) -> String {
+    let mut owners: alloc::vec::Vec<String> = fsg.usage_by_owner().into_iter().map(|(o, _)| o).collect();
+    for (o, _) in fsg.quotas() {
+        if !owners.contains(&o) { owners.push(o); }
+    }
+    if owners.is_empty() { return "no files".to_string(); }
+
+    let mut out = alloc::format!("{:<12} {:>9} {:>9} {:>9}\n", "owner", "used", "logged", "quota");
+    for o in owners {
+        let limit = fsg.quota(&o).map(fmt_size).unwrap_or_else(|| "-".to_string());
+        let logged = fmt_size(persist::log_usage_of(&o));
+        out.push_str(&alloc::format!("{:<12} {:>9} {:>9} {:>9}\n", o, fmt_size(fsg.usage_of(&o)), logged, limit));
+    }
+    out
+}
+
+fn fmt_size(b: u64) -> String {
+    if b >= 1024 * 1024 {
+        alloc::format!("{}.{}M", b / (1024 * 1024), (b % (1024 * 1024)) * 10 / (1024 * 1024))
+    } else if b >= 1024 {
+        alloc::format!("{}.{}K", b / 1024, (b % 1024) * 10 / 1024)
+    } else {
+        alloc::format!("{b}B")
+    }
+}
+
+fn parse_size(s: &str) -> Option<u64> {
+    let (num, mul) = match s.as_bytes().last()? {
+        b'K' | b'k' => (&s[..s.len() - 1], 1024),
+        b'M' | b'm' => (&s[..s.len() - 1], 1024 * 1024),
+        _ => (s, 1),
+    };
+    num.parse::<u64>().ok()?.checked_mul(mul)
+}
+
+fn cmd_mount(args: &[&str]) -> String {
+    if args.is_empty() {
+        return match p9::mount_info() {
+            Some((mp, tag)) => alloc::format!("{tag} on {mp} type 9p"),
+            None => "mount: nothing mounted".to_string(),
+        };
+    }
+    let cur = cwd();
+    let abs = match fs::normalize_path(&cur, args[0]) {
+        Ok(p) => p,
+        Err(e) => return alloc::format!("mount: {e:?}"),
+    };
+    match p9::mount(&abs) {
+        Ok(()) => alloc::format!("mount: 9p share on {abs}"),
+        Err(P9Error::NoDevice) => "mount: no virtio-9p device (start QEMU with -virtfs)".to_string(),
+        Err(e) => p9_err("mount", e),
+    }
+}
+
+fn cmd_umount() -> String {
+    let mp = p9::mount_info().map(|(mp, _)| mp);
+    match p9::unmount() {
+        Ok(()) => {
+            // don't leave the shell inside a path that no longer resolves
+            if let Some(mp) = mp {
+                let mut g = CWD.lock();
+                if *g == mp || g.starts_with(&(mp + "/")) { *g = "/".to_string(); }
+            }
+            String::new()
+        }
+        Err(e) => p9_err("umount", e),
+    }
+}
+
+fn p9_err(cmd: &str, e: P9Error) -> String {
+    match e {
+        P9Error::NotFound => alloc::format!("{cmd}: not found"),
+        P9Error::NotDir => alloc::format!("{cmd}: not a directory"),
+        P9Error::NotMounted => alloc::format!("{cmd}: not mounted"),
+        P9Error::TooLarge => alloc::format!("{cmd}: file too large"),
+        P9Error::AlreadyMounted => match p9::mount_info() {
+            Some((mp, _)) => alloc::format!("{cmd}: share already mounted on {mp} (umount first)"),
+            None => alloc::format!("{cmd}: already mounted"),
+        },
+        P9Error::Remote(errno) => alloc::format!("{cmd}: host error (errno {errno})"),
+        e => alloc::format!("{cmd}: {e:?}"),
     }
 }
 
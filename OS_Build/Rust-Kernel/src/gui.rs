@@ -636,7 +636,7 @@ fn paint_taskbar_and_dock() {
         }
 
         // Clock (bottom-right)
-        let dt = time::rtc_now();
+        let dt = time::now();
         let mut buf = [0u8; 32];
         let n = time::format_datetime(&mut buf, dt);
         let s = core::str::from_utf8_unchecked(&buf[..n]);
@@ -1,56 +1,64 @@
 #![allow(dead_code)]
 // src/heap.rs
-// Minimal bump allocator to enable `alloc` in this no_std kernel.
+// Kernel heap: a first-fit free-list allocator (linked_list_allocator) over a
+// static arena, so `alloc` memory is reused once dropped. Syncs, file
+// transfers and packet handling allocate and free per operation, which a bump
+// allocator can't sustain.
 //
-// This is intentionally simple: no free(), no reuse, just linear allocation.
-// Enough to support Strings/Vectors for early FS + persistence features.
+// The arena is handed to the allocator on the first allocation; nothing has to
+// run before `alloc` is used.
 
 extern crate alloc;
 
 use core::alloc::{GlobalAlloc, Layout};
-use core::ptr::null_mut;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicBool, Ordering};
 
-const HEAP_SIZE: usize = 4 * 1024 * 1024; // 4 MiB bump heap (tune as needed)
+use linked_list_allocator::LockedHeap;
+
+const HEAP_SIZE: usize = 4 * 1024 * 1024; // 4 MiB arena (tune as needed)
 
 #[repr(align(16))]
 struct Heap([u8; HEAP_SIZE]);
 
 static mut HEAP: Heap = Heap([0u8; HEAP_SIZE]);
-static NEXT: AtomicUsize = AtomicUsize::new(0);
+static READY: AtomicBool = AtomicBool::new(false);
 
-pub struct BumpAlloc;
+pub struct KernelHeap(LockedHeap);
 
-unsafe impl GlobalAlloc for BumpAlloc {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let align = layout.align().max(16);
-        let size = layout.size();
-
-        let base = HEAP.0.as_ptr() as usize;
-
-        loop {
-            let cur = NEXT.load(Ordering::Relaxed);
-            let aligned = (cur + (align - 1)) & !(align - 1);
-            let new = aligned.saturating_add(size);
-            if new > HEAP_SIZE {
-                return null_mut();
-            }
-            if NEXT.compare_exchange(cur, new, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
-                return (base + aligned) as *mut u8;
-            }
+impl KernelHeap {
+    fn ensure_ready(&self) {
+        if READY.load(Ordering::Acquire) { return; }
+        let mut h = self.0.lock();
+        if !READY.load(Ordering::Relaxed) {
+            unsafe { h.init(core::ptr::addr_of_mut!(HEAP.0) as *mut u8, HEAP_SIZE); }
+            READY.store(true, Ordering::Release);
         }
     }
+}
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        // bump allocator: no dealloc
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.ensure_ready();
+        self.0.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout)
     }
 }
 
 #[global_allocator]
-static ALLOC: BumpAlloc = BumpAlloc;
+static ALLOC: KernelHeap = KernelHeap(LockedHeap::empty());
+
+/// (bytes in use, arena size)
+pub fn usage() -> (usize, usize) {
+    ALLOC.ensure_ready();
+    let h = ALLOC.0.lock();
+    (h.used(), h.size())
+}
 
 // Some older builds of `alloc` look for this name.
 #[no_mangle]
 pub extern "Rust" fn rust_oom(_layout: Layout) -> ! {
     panic!("allocation failed");
-}
\ No newline at end of file
+}
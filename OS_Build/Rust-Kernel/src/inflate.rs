@@ -0,0 +1,337 @@
+#![allow(dead_code)]
+// src/inflate.rs
+// DEFLATE decoder (RFC 1951) plus the zlib (RFC 1950) and gzip (RFC 1952)
+// wrappers, for HTTP Content-Encoding.
+//
+// Canonical Huffman decoding one bit at a time, after zlib's puff.c: not
+// fast, but small, and every table lives on the stack. Output stops at
+// `max_out` bytes; a stream cut short there isn't an error, but then the
+// trailer checksum can't be checked either.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InflateError {
+    /// Input ended inside the stream.
+    Truncated,
+    /// Reserved block type, bad stored length or bad code length set.
+    BadBlock,
+    /// Bit pattern that isn't a code in the current table.
+    BadCode,
+    /// Distance before the start of the output.
+    BadDistance,
+    /// Not a zlib/gzip header we can handle.
+    BadHeader,
+    /// CRC-32 / Adler-32 or length in the trailer doesn't match.
+    Checksum,
+}
+
+const MAX_BITS: usize = 15;
+const MAX_LIT: usize = 288;
+const MAX_DIST: usize = 30;
+
+const LEN_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LEN_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// Order code length code lengths are sent in.
+const CL_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    cnt: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0, buf: 0, cnt: 0 }
+    }
+
+    /// Next `n` bits (n <= 16), least significant first.
+    fn bits(&mut self, n: u32) -> Result<u32, InflateError> {
+        let mut val = self.buf as u64;
+        while self.cnt < n {
+            let b = *self.data.get(self.pos).ok_or(InflateError::Truncated)?;
+            self.pos += 1;
+            val |= (b as u64) << self.cnt;
+            self.cnt += 8;
+        }
+        self.buf = (val >> n) as u32;
+        self.cnt -= n;
+        Ok((val & ((1u64 << n) - 1)) as u32)
+    }
+
+    /// Drop the rest of the current byte (stored blocks start aligned).
+    fn align(&mut self) {
+        self.buf = 0;
+        self.cnt = 0;
+    }
+}
+
+/// Canonical Huffman table: how many codes of each length, and the symbols
+/// ordered by code.
+struct Huffman<const N: usize> {
+    count: [u16; MAX_BITS + 1],
+    symbol: [u16; N],
+}
+
+impl<const N: usize> Huffman<N> {
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut h = Huffman { count: [0; MAX_BITS + 1], symbol: [0; N] };
+        for &l in lengths {
+            h.count[l as usize] += 1;
+        }
+        // over-subscribed sets can't be decoded; incomplete ones are fine
+        // (a distance table with a single code is legal)
+        let mut left: i32 = 1;
+        for len in 1..=MAX_BITS {
+            left <<= 1;
+            left -= h.count[len] as i32;
+            if left < 0 { return Err(InflateError::BadBlock); }
+        }
+
+        let mut offs = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offs[len + 1] = offs[len] + h.count[len];
+        }
+        for (sym, &l) in lengths.iter().enumerate() {
+            if l != 0 {
+                h.symbol[offs[l as usize] as usize] = sym as u16;
+                offs[l as usize] += 1;
+            }
+        }
+        Ok(h)
+    }
+
+    fn decode(&self, s: &mut Bits) -> Result<u16, InflateError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= s.bits(1)? as i32;
+            let count = self.count[len] as i32;
+            if code - count < first {
+                return Ok(self.symbol[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::BadCode)
+    }
+}
+
+/// Decode a raw DEFLATE stream. Returns the output and how many input bytes
+/// it used (None if it stopped at `max_out`).
+fn run(data: &[u8], max_out: usize) -> Result<(Vec<u8>, Option<usize>), InflateError> {
+    let mut s = Bits::new(data);
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let last = s.bits(1)? == 1;
+        let full = match s.bits(2)? {
+            0 => stored(&mut s, &mut out, max_out)?,
+            1 => {
+                let (lit, dist) = fixed_tables()?;
+                codes(&mut s, &mut out, &lit, &dist, max_out)?
+            }
+            2 => {
+                let (lit, dist) = dynamic_tables(&mut s)?;
+                codes(&mut s, &mut out, &lit, &dist, max_out)?
+            }
+            _ => return Err(InflateError::BadBlock),
+        };
+        if full { return Ok((out, None)); }
+        if last { return Ok((out, Some(s.pos))); }
+    }
+}
+
+/// Copy a stored block. Returns true if `max_out` was reached.
+fn stored(s: &mut Bits, out: &mut Vec<u8>, max_out: usize) -> Result<bool, InflateError> {
+    s.align();
+    let hdr = s.data.get(s.pos..s.pos + 4).ok_or(InflateError::Truncated)?;
+    let len = u16::from_le_bytes([hdr[0], hdr[1]]);
+    let nlen = u16::from_le_bytes([hdr[2], hdr[3]]);
+    if len != !nlen { return Err(InflateError::BadBlock); }
+    s.pos += 4;
+    let body = s.data.get(s.pos..s.pos + len as usize).ok_or(InflateError::Truncated)?;
+    s.pos += len as usize;
+    let take = body.len().min(max_out - out.len());
+    out.extend_from_slice(&body[..take]);
+    Ok(out.len() >= max_out)
+}
+
+fn fixed_tables() -> Result<(Huffman<MAX_LIT>, Huffman<MAX_DIST>), InflateError> {
+    let mut lengths = [0u8; MAX_LIT];
+    for (i, l) in lengths.iter_mut().enumerate() {
+        *l = match i {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5u8; MAX_DIST])?))
+}
+
+fn dynamic_tables(s: &mut Bits) -> Result<(Huffman<MAX_LIT>, Huffman<MAX_DIST>), InflateError> {
+    let nlen = s.bits(5)? as usize + 257;
+    let ndist = s.bits(5)? as usize + 1;
+    let ncode = s.bits(4)? as usize + 4;
+    if nlen > 286 || ndist > MAX_DIST { return Err(InflateError::BadBlock); }
+
+    let mut cl = [0u8; 19];
+    for &i in CL_ORDER.iter().take(ncode) {
+        cl[i] = s.bits(3)? as u8;
+    }
+    let clcode: Huffman<19> = Huffman::new(&cl)?;
+
+    let mut lengths = [0u8; MAX_LIT + MAX_DIST];
+    let mut i = 0usize;
+    while i < nlen + ndist {
+        let sym = clcode.decode(s)?;
+        if sym < 16 {
+            lengths[i] = sym as u8;
+            i += 1;
+            continue;
+        }
+        let (value, repeat) = match sym {
+            16 => {
+                if i == 0 { return Err(InflateError::BadBlock); }
+                (lengths[i - 1], 3 + s.bits(2)? as usize)
+            }
+            17 => (0, 3 + s.bits(3)? as usize),
+            _ => (0, 11 + s.bits(7)? as usize),
+        };
+        if i + repeat > nlen + ndist { return Err(InflateError::BadBlock); }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    // no end-of-block code: the block could never finish
+    if lengths[256] == 0 { return Err(InflateError::BadBlock); }
+
+    Ok((Huffman::new(&lengths[..nlen])?, Huffman::new(&lengths[nlen..nlen + ndist])?))
+}
+
+/// Decode literals and matches up to end-of-block. Returns true if
+/// `max_out` was reached.
+fn codes(
+    s: &mut Bits,
+    out: &mut Vec<u8>,
+    lit: &Huffman<MAX_LIT>,
+    dist: &Huffman<MAX_DIST>,
+    max_out: usize,
+) -> Result<bool, InflateError> {
+    loop {
+        if out.len() >= max_out { return Ok(true); }
+        let sym = lit.decode(s)? as usize;
+        if sym < 256 {
+            out.push(sym as u8);
+            continue;
+        }
+        if sym == 256 { return Ok(false); }
+
+        let i = sym - 257;
+        if i >= LEN_BASE.len() { return Err(InflateError::BadCode); }
+        let len = LEN_BASE[i] as usize + s.bits(LEN_EXTRA[i] as u32)? as usize;
+        let d = dist.decode(s)? as usize;
+        if d >= DIST_BASE.len() { return Err(InflateError::BadDistance); }
+        let back = DIST_BASE[d] as usize + s.bits(DIST_EXTRA[d] as u32)? as usize;
+        if back > out.len() { return Err(InflateError::BadDistance); }
+
+        // byte by byte: the source may overlap what is being written
+        let len = len.min(max_out - out.len());
+        let start = out.len() - back;
+        for k in 0..len {
+            let b = out[start + k];
+            out.push(b);
+        }
+    }
+}
+
+/// Raw DEFLATE, no header or trailer.
+pub fn inflate(data: &[u8], max_out: usize) -> Result<Vec<u8>, InflateError> {
+    run(data, max_out).map(|(out, _)| out)
+}
+
+/// zlib stream: CMF/FLG header, DEFLATE, Adler-32.
+pub fn zlib(data: &[u8], max_out: usize) -> Result<Vec<u8>, InflateError> {
+    let [cmf, flg, ..] = *data else { return Err(InflateError::Truncated) };
+    // method 8 (deflate), window <= 32K, header check, no preset dictionary
+    if cmf & 0x0F != 8 || cmf >> 4 > 7 || !((cmf as u16) << 8 | flg as u16).is_multiple_of(31) || flg & 0x20 != 0 {
+        return Err(InflateError::BadHeader);
+    }
+    let (out, used) = run(&data[2..], max_out)?;
+    let Some(used) = used else { return Ok(out) };
+    let t = data.get(2 + used..2 + used + 4).ok_or(InflateError::Truncated)?;
+    if u32::from_be_bytes([t[0], t[1], t[2], t[3]]) != adler32(&out) {
+        return Err(InflateError::Checksum);
+    }
+    Ok(out)
+}
+
+/// gzip member: header (optional extra, name, comment, header CRC),
+/// DEFLATE, CRC-32 and length. Anything after the first member is ignored.
+pub fn gunzip(data: &[u8], max_out: usize) -> Result<Vec<u8>, InflateError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    if data.len() < 10 { return Err(InflateError::Truncated); }
+    if data[0] != 0x1F || data[1] != 0x8B || data[2] != 8 || data[3] & 0xE0 != 0 {
+        return Err(InflateError::BadHeader);
+    }
+    let flg = data[3];
+    let mut p = 10usize;
+    if flg & FEXTRA != 0 {
+        let x = data.get(p..p + 2).ok_or(InflateError::Truncated)?;
+        p += 2 + u16::from_le_bytes([x[0], x[1]]) as usize;
+    }
+    for bit in [FNAME, FCOMMENT] {
+        if flg & bit != 0 {
+            let z = data.get(p..).and_then(|r| r.iter().position(|&b| b == 0)).ok_or(InflateError::Truncated)?;
+            p += z + 1;
+        }
+    }
+    if flg & FHCRC != 0 { p += 2; }
+    let body = data.get(p..).ok_or(InflateError::Truncated)?;
+
+    let (out, used) = run(body, max_out)?;
+    let Some(used) = used else { return Ok(out) };
+    let t = body.get(used..used + 8).ok_or(InflateError::Truncated)?;
+    let crc = u32::from_le_bytes([t[0], t[1], t[2], t[3]]);
+    let size = u32::from_le_bytes([t[4], t[5], t[6], t[7]]);
+    if crc != crate::crc32::crc32(&out) || size != out.len() as u32 {
+        return Err(InflateError::Checksum);
+    }
+    Ok(out)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before b could overflow
+    for chunk in data.chunks(5552) {
+        for &x in chunk {
+            a += x as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,96 @@
+#![allow(dead_code)]
+// src/kdf.rs
+// HMAC-SHA256 (RFC 2104), PBKDF2-HMAC-SHA256 (RFC 8018) and HKDF-SHA256
+// (RFC 5869).
+
+use crate::sha256::{Sha256, BLOCK_LEN, DIGEST_LEN};
+
+#[derive(Clone)]
+pub struct HmacSha256 {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl HmacSha256 {
+    pub fn new(key: &[u8]) -> Self {
+        let mut k = [0u8; BLOCK_LEN];
+        if key.len() > BLOCK_LEN {
+            k[..DIGEST_LEN].copy_from_slice(&crate::sha256::sha256(key));
+        } else {
+            k[..key.len()].copy_from_slice(key);
+        }
+
+        let mut ipad = [0x36u8; BLOCK_LEN];
+        let mut opad = [0x5Cu8; BLOCK_LEN];
+        for i in 0..BLOCK_LEN {
+            ipad[i] ^= k[i];
+            opad[i] ^= k[i];
+        }
+
+        let mut inner = Sha256::new();
+        inner.update(&ipad);
+        let mut outer = Sha256::new();
+        outer.update(&opad);
+        Self { inner, outer }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finalize(self) -> [u8; 32] {
+        let ih = self.inner.finalize();
+        let mut o = self.outer;
+        o.update(&ih);
+        o.finalize()
+    }
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut h = HmacSha256::new(key);
+    h.update(data);
+    h.finalize()
+}
+
+/// PBKDF2-HMAC-SHA256, filling `out` (any length).
+pub fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    // keyed once; every iteration clones the precomputed pad states
+    let prf = HmacSha256::new(password);
+
+    for (block, chunk) in out.chunks_mut(DIGEST_LEN).enumerate() {
+        let mut h = prf.clone();
+        h.update(salt);
+        h.update(&(block as u32 + 1).to_be_bytes());
+        let mut u = h.finalize();
+        let mut t = u;
+
+        for _ in 1..iterations.max(1) {
+            let mut h = prf.clone();
+            h.update(&u);
+            u = h.finalize();
+            for (a, b) in t.iter_mut().zip(u.iter()) {
+                *a ^= *b;
+            }
+        }
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+}
+
+/// HKDF-Extract: PRK = HMAC(salt, IKM).
+pub fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; 32] {
+    hmac_sha256(salt, ikm)
+}
+
+/// HKDF-Expand `prk` with `info`, filling `out` (at most 255 * 32 bytes).
+pub fn hkdf_expand(prk: &[u8], info: &[u8], out: &mut [u8]) {
+    let prf = HmacSha256::new(prk);
+    let mut t = [0u8; DIGEST_LEN];
+    for (i, chunk) in out.chunks_mut(DIGEST_LEN).enumerate() {
+        let mut h = prf.clone();
+        if i > 0 { h.update(&t); }
+        h.update(info);
+        h.update(&[i as u8 + 1]);
+        t = h.finalize();
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+}
@@ -6,7 +6,7 @@
 //! - Passwords are stored as salted hashes in the in-memory registry
 //! - Rendered full-screen (Windows-11-ish) when gui::UiMode::Login is active
 
-use crate::{gui, registry, time};
+use crate::{fs, gui, persist, registry, serial, time};
 use crate::framebuffer_driver as fb;
 
 const FG: u32 = 0xE5E7EB;
@@ -70,6 +70,10 @@ static MSG_ERR_INVALID: [u8; 28] = *b"Invalid username or password";
 static MSG_ERR_MATCH: [u8; 22] = *b"Passwords do not match";
 #[link_section = ".data"]
 static MSG_OK_CREATED: [u8; 27] = *b"Account created & logged in";
+#[link_section = ".data"]
+static MSG_ERR_STORE: [u8; 43] = *b"Password does not unlock the encrypted disk";
+#[link_section = ".data"]
+static MSG_ERR_NO_SLOT: [u8; 52] = *b"No disk key for this user: log in as an enrolled one";
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum Mode { Login, Create }
@@ -286,7 +290,9 @@ fn try_submit() -> bool {
                     return false;
                 }
                 if registry::validate_login(username, password) {
+                    if !unlock_store(username, password) { return false; }
                     LOGGED_IN = true;
+                    fs::FS.lock().set_owner(username);
                     ACTIVE_USER[..ulen].copy_from_slice(&USER[..ulen]);
                     ACTIVE_USER_LEN = ulen;
                     set_status(&MSG_OK_LOGIN[..], OK);
@@ -304,9 +310,13 @@ fn try_submit() -> bool {
                     set_status(&MSG_ERR_MATCH[..], ERR);
                     return false;
                 }
+                // on an encrypted store the account needs a key slot (or gets
+                // one, if an enrolled user already unlocked it this boot)
+                if !unlock_store(username, password) { return false; }
                 match registry::create_user(username, password) {
                     Ok(()) => {
                         LOGGED_IN = true;
+                        fs::FS.lock().set_owner(username);
                         ACTIVE_USER[..ulen].copy_from_slice(&USER[..ulen]);
                         ACTIVE_USER_LEN = ulen;
                         set_status(&MSG_OK_CREATED[..], OK);
@@ -323,6 +333,31 @@ fn try_submit() -> bool {
     }
 }
 
+/// Unlock persistent storage with the login credentials. A wrong password or
+/// an account without a key slot blocks the login (with a status message);
+/// disk errors are logged and the session runs from RAM.
+fn unlock_store(username: &str, password: &str) -> bool {
+    match persist::unlock(username, password) {
+        Ok(()) => true,
+        Err(persist::PersistError::BadPassword) => {
+            set_status(&MSG_ERR_STORE[..], ERR);
+            false
+        }
+        Err(persist::PersistError::NoKeySlot) => {
+            set_status(&MSG_ERR_NO_SLOT[..], ERR);
+            false
+        }
+        Err(persist::PersistError::KeySlotsFull) => {
+            serial::serial_write_str("LOGIN: no free key slot; this account can't unlock the disk by itself.\n");
+            true
+        }
+        Err(_) => {
+            serial::serial_write_str("LOGIN: persistent store unavailable.\n");
+            true
+        }
+    }
+}
+
 pub fn render() {
     // If we're in login UI mode, use the full-screen renderer.
     if gui::ui_mode() == gui::UiMode::Login {
@@ -429,9 +464,9 @@ pub fn render_fullscreen() {
         };
         draw_str(px + 24, py + 18 + CH_H, sub, DIM, BG);
 
-    // Current date/time (from CMOS RTC) in header (top-right of card)
+    // Current date/time (RTC, or NTP-corrected) in header (top-right of card)
     {
-        let dt = time::rtc_now();
+        let dt = time::now();
         let mut tbuf = [0u8; 32];
         let n = time::format_datetime(&mut tbuf, dt);
         let tw = (n as i32) * CH_W;
@@ -0,0 +1,138 @@
+#![allow(dead_code)]
+// src/lz4.rs
+// LZ4 block format (no frame header), greedy single-pass compressor.
+//
+// Compatible with the reference decoder: the last 5 bytes are always
+// literals and no match starts within the last 12 bytes of the input.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+const MIN_MATCH: usize = 4;
+const LAST_LITERALS: usize = 5;
+const MFLIMIT: usize = 12;
+const MAX_OFFSET: usize = 65535;
+const HASH_BITS: u32 = 12;
+
+#[inline(always)]
+fn read_u32(b: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]])
+}
+
+#[inline(always)]
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+fn push_len(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 255 {
+        out.push(255);
+        n -= 255;
+    }
+    out.push(n as u8);
+}
+
+fn emit(out: &mut Vec<u8>, literals: &[u8], m: Option<(u16, usize)>) {
+    let lit = literals.len();
+    let lit_tok = if lit >= 15 { 15 } else { lit as u8 };
+    let ml = m.map(|(_, len)| len - MIN_MATCH).unwrap_or(0);
+    let ml_tok = if ml >= 15 { 15 } else { ml as u8 };
+
+    out.push((lit_tok << 4) | ml_tok);
+    if lit >= 15 { push_len(out, lit - 15); }
+    out.extend_from_slice(literals);
+
+    if let Some((off, _)) = m {
+        out.extend_from_slice(&off.to_le_bytes());
+        if ml >= 15 { push_len(out, ml - 15); }
+    }
+}
+
+/// Compress `input` into a raw LZ4 block.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let n = input.len();
+    let mut out = Vec::with_capacity(n + n / 255 + 16);
+    let mut table = vec![0u32; 1 << HASH_BITS]; // position + 1, 0 = empty
+
+    let mut anchor = 0usize;
+    let mut i = 0usize;
+
+    if n > MFLIMIT {
+        let limit = n - MFLIMIT;
+        while i < limit {
+            let seq = read_u32(input, i);
+            let h = hash(seq);
+            let cand = table[h] as usize;
+            table[h] = (i + 1) as u32;
+
+            if cand != 0 {
+                let c = cand - 1;
+                if i - c <= MAX_OFFSET && read_u32(input, c) == seq {
+                    let max = n - LAST_LITERALS - i;
+                    let mut m = MIN_MATCH;
+                    while m < max && input[c + m] == input[i + m] { m += 1; }
+
+                    emit(&mut out, &input[anchor..i], Some(((i - c) as u16, m)));
+                    i += m;
+                    anchor = i;
+                    continue;
+                }
+            }
+            i += 1;
+        }
+    }
+
+    emit(&mut out, &input[anchor..], None);
+    out
+}
+
+/// Decompress a raw LZ4 block that must expand to exactly `raw_len` bytes.
+/// Returns None on any malformed input.
+pub fn decompress(input: &[u8], raw_len: usize) -> Option<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(raw_len);
+    let mut i = 0usize;
+
+    let read_len = |i: &mut usize, mut n: usize| -> Option<usize> {
+        loop {
+            let b = *input.get(*i)?;
+            *i += 1;
+            n = n.checked_add(b as usize)?;
+            if b != 255 { return Some(n); }
+        }
+    };
+
+    loop {
+        let token = *input.get(i)?;
+        i += 1;
+
+        let mut lit = (token >> 4) as usize;
+        if lit == 15 { lit = read_len(&mut i, lit)?; }
+        let end = i.checked_add(lit)?;
+        if end > input.len() || out.len() + lit > raw_len { return None; }
+        out.extend_from_slice(&input[i..end]);
+        i = end;
+
+        if i == input.len() { break; }
+
+        if i + 2 > input.len() { return None; }
+        let off = u16::from_le_bytes([input[i], input[i + 1]]) as usize;
+        i += 2;
+        if off == 0 || off > out.len() { return None; }
+
+        let mut ml = (token & 0x0F) as usize;
+        if ml == 15 { ml = read_len(&mut i, ml)?; }
+        ml += MIN_MATCH;
+        if out.len() + ml > raw_len { return None; }
+
+        // byte-wise: matches may overlap their own output
+        let start = out.len() - off;
+        for k in 0..ml {
+            let b = out[start + k];
+            out.push(b);
+        }
+    }
+
+    if out.len() == raw_len { Some(out) } else { None }
+}
@@ -1,28 +1,55 @@
 #![allow(dead_code)]
 // src/net.rs
 //
-// RTL8139 + minimal Ethernet/ARP/IPv4/UDP/DHCP + ICMP ping (polling).
+// Interface table + minimal Ethernet/ARP/IPv4/UDP/DHCP + ICMP ping (polling).
 //
-// IMPORTANT (DMA):
-// - RBSTART / TSAD* must be PHYSICAL addresses.
-// - This code assumes your kernel & these statics are identity-mapped below 4GiB.
-//   If your kernel is higher-half, you MUST translate virt->phys for these buffers.
+// NIC drivers (net/rtl8139.rs, net/e1000.rs, net/virtio_net.rs) implement
+// `iface::NetDevice` and are registered as eth0, eth1, ... Frames go out on
+// the primary interface (the one `NET.cfg` describes) via `send_frame`.
+// `lo` (net/loopback.rs) is always registered last and is never the primary;
+// ipv4.rs/ipv6.rs hand it local traffic through `loopback_send`.
 //
-// IMPORTANT (RX ring):
-// - The RTL8139 receive ring "wraps" at 8 KiB, but hardware can write into an
-//   "overflow" area appended after the ring. We allocate RX_RING + 16 + 2048,
-//   and read packets linearly using that overflow area (no modulo indexing).
+// Receiving goes through one path: `poll` drains every interface into
+// net/demux.rs, which parses each frame once and queues it for the socket
+// that owns it (ICMP ident, UDP port, TCP 4-tuple). Protocol code opens a
+// queue, sends through net/ipv4.rs, then alternates `poll()` and `recv()`.
 
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::str;
 
-use crate::portio::{inb, inl, inw, outb, outl, outw};
+use crate::fs::SpinLock;
 use crate::time;
 use core::ptr;
 
+pub mod arp;
+pub mod demux;
+pub mod dhcp;
 pub mod dns;
+pub mod e1000;
+pub mod iface;
+pub mod ip;
+pub mod ipv4;
+pub mod ipv6;
+pub mod loopback;
+pub mod ndp;
+pub mod netconf;
+pub mod ntp;
+pub mod pcap;
+pub mod route;
+pub mod rtl8139;
 pub mod tcp;
+pub mod udp;
 pub mod http;
 pub mod tls;
+pub mod x509;
+pub mod virtio_net;
+
+use iface::{IfaceInfo, Interface, NetDevice};
 
 // -----------------------------------------------------------------------------
 // Public surface
@@ -44,6 +71,8 @@ pub struct NetConfig {
     pub dns: [u8; 4],
     pub server_id: [u8; 4],
     pub lease_seconds: u32,
+    /// IPv4 MTU from DHCP; 0 = the interface MTU.
+    pub mtu: u16,
 }
 
 impl NetConfig {
@@ -58,6 +87,7 @@ impl NetConfig {
             dns: [0; 4],
             server_id: [0; 4],
             lease_seconds: 0,
+            mtu: 0,
         }
     }
 }
@@ -75,6 +105,10 @@ pub enum DhcpError {
     Timeout,
     Malformed,
     Nack,
+    /// Another DHCP exchange already owns UDP port 68.
+    Busy,
+    /// Renew without a lease.
+    NotBound,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -83,373 +117,605 @@ pub enum PingError {
     NotConfigured,
     ArpTimeout,
     Timeout,
+    /// Payload larger than `PING_MAX_SIZE` (or the IPv6 link MTU).
+    TooLarge,
     TxFail,
 }
 
-#[derive(Copy, Clone)]
-pub struct PingReply {
-    pub seq: u16,
+/// Largest echo payload: a full IPv4 datagram minus the ICMP header.
+pub const PING_MAX_SIZE: usize = ipv4::MAX_PAYLOAD - 8;
+
+#[derive(Copy, Clone, Debug)]
+pub struct PingOptions {
+    /// Echo payload bytes after the 8-byte ICMP header.
+    pub size: usize,
+    /// TTL / hop limit for the request; 0 uses the stack default.
     pub ttl: u8,
-    pub rtt_tsc: u64,
+    pub timeout_ms: u64,
 }
 
-// -----------------------------------------------------------------------------
-// RTL8139 definitions
-// -----------------------------------------------------------------------------
-
-const PCI_CONFIG_ADDR: u16 = 0xCF8;
-const PCI_CONFIG_DATA: u16 = 0xCFC;
+impl Default for PingOptions {
+    fn default() -> Self {
+        PingOptions { size: 56, ttl: 0, timeout_ms: 2000 }
+    }
+}
 
-const RTL_VENDOR_ID: u16 = 0x10ec;
-const RTL_DEVICE_ID: u16 = 0x8139;
+/// What answered an echo request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PingKind {
+    Echo,
+    /// A router on the path ran the TTL / hop limit down to zero.
+    TimeExceeded,
+    /// Destination Unreachable with this ICMP code (see `unreachable_text`).
+    Unreachable(u8),
+}
 
-// RTL8139 registers (I/O base + offset)
-const IDR0: u16 = 0x00;
-const TSD0: u16 = 0x10;
-const TSAD0: u16 = 0x20;
-const RBSTART: u16 = 0x30;
-const CAPR: u16 = 0x38;
-const CBR: u16 = 0x3A;
-const IMR: u16 = 0x3C;
-const ISR: u16 = 0x3E;
-const TCR: u16 = 0x40;
-const RCR: u16 = 0x44;
-const CR: u16 = 0x37;
+#[derive(Copy, Clone)]
+pub struct PingReply {
+    pub seq: u16,
+    pub ttl: u8,
+    /// Who answered: the target, or a router for errors.
+    pub from: ip::IpAddr,
+    pub kind: PingKind,
+    /// ICMP message size, header included.
+    pub bytes: usize,
+    pub rtt_us: u64,
+}
 
-const CR_RESET: u8 = 0x10;
-const CR_RX_ENABLE: u8 = 0x08;
-const CR_TX_ENABLE: u8 = 0x04;
+/// Length of `frame` with trailing pad/FCS trimmed, using the ARP size or the
+/// IPv4/IPv6 length fields when they can be read. Shared by every driver's RX path.
+fn trimmed_len(frame: &[u8]) -> usize {
+    let len = frame.len();
+    if len < 14 { return len; }
 
-// RX ring (wraps at 8 KiB)
-const RX_RING_LEN: usize = 8192;
-const RX_OVERFLOW: usize = 16 + 2048;
-const RX_BUFFER_SIZE: usize = RX_RING_LEN + RX_OVERFLOW;
+    let mut l2 = 14usize;
+    let mut ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+    if ethertype == 0x8100 && len >= 18 {
+        ethertype = u16::from_be_bytes([frame[16], frame[17]]);
+        l2 = 18;
+    }
 
-const TX_BUF_SIZE: usize = 1600;
-const NUM_TX: usize = 4;
+    if ethertype == 0x0806 {
+        // ARP header is 28 bytes after Ethernet/VLAN header.
+        let want = l2 + 28;
+        if want <= len { return want; }
+    } else if ethertype == 0x0800 && len >= l2 + 20 {
+        // IPv4: use Total Length field to trim trailing pad/CRC.
+        let ver_ihl = frame[l2];
+        if (ver_ihl >> 4) == 4 {
+            let ihl = ((ver_ihl & 0x0F) as usize) * 4;
+            if ihl >= 20 && len >= l2 + ihl + 4 {
+                let total = u16::from_be_bytes([frame[l2 + 2], frame[l2 + 3]]) as usize;
+                let want = l2 + total;
+                if (14..=len).contains(&want) { return want; }
+            }
+        }
+    } else if ethertype == 0x86DD && len >= l2 + 40 {
+        // IPv6: fixed header plus Payload Length.
+        let want = l2 + 40 + u16::from_be_bytes([frame[l2 + 4], frame[l2 + 5]]) as usize;
+        if want <= len { return want; }
+    }
+    len
+}
 
-#[repr(align(16))]
-struct AlignedRx([u8; RX_BUFFER_SIZE]);
+// -----------------------------------------------------------------------------
+// Global net state
+// -----------------------------------------------------------------------------
 
-#[link_section = ".nic_rx_buffer"]
-static mut RX_BUFFER: AlignedRx = AlignedRx([0u8; RX_BUFFER_SIZE]);
+struct NetState {
+    ifaces: Vec<Interface>,
+    primary: usize,
+    probed: bool,
+    cfg: NetConfig,
+}
 
-#[link_section = ".nic_tx_buffers"]
-static mut TX_BUFFERS: [[u8; TX_BUF_SIZE]; NUM_TX] = [[0u8; TX_BUF_SIZE]; NUM_TX];
+static mut NET: NetState = NetState {
+    ifaces: Vec::new(),
+    primary: 0,
+    probed: false,
+    cfg: NetConfig::empty(),
+};
 
-static mut RX_SCRATCH: [u8; 2048] = [0u8; 2048];
-const RX_SCRATCH_LEN: usize = 2048;
+// net_scan pretty strings (no alloc)
+static mut SCAN_BUFS: [[u8; 96]; 8] = [[0; 96]; 8];
+static mut SCAN_STRS: [&'static str; 8] = [""; 8];
+const SCAN_SLOTS: usize = 8;
 
-struct Rtl8139 {
-    io: u16,
-    mac: [u8; 6],
-    rx_off: usize, // 0..RX_RING_LEN
-    tx_cur: usize,
+// Take the interface table out of `NET` for the duration of `f`, so drivers
+// run without a live `&mut` borrow of the `static mut` (Rust 2024 compatibility).
+fn with_ifaces<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Vec<Interface>) -> R,
+{
+    unsafe {
+        let p: *mut Vec<Interface> = ptr::addr_of_mut!(NET.ifaces);
+        let mut owned = ptr::replace(p, Vec::new());
+        let r = f(&mut owned);
+        // keep anything registered while the table was out
+        let added = ptr::replace(p, owned);
+        (*p).extend(added);
+        r
+    }
 }
 
-impl Rtl8139 {
-    fn init(io: u16) -> Self {
-        let mut mac = [0u8; 6];
+fn with_primary<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut Interface) -> R,
+{
+    let i = unsafe { NET.primary };
+    with_ifaces(|t| t.get_mut(i).filter(|x| !x.is_loopback()).map(f))
+}
 
-        unsafe {
-            // reset
-            outb(io + CR, CR_RESET);
-            while (inb(io + CR) & CR_RESET) != 0 {
-                time::cpu_pause();
-            }
+/// Any interface at all, `lo` included: enough for sockets.
+fn have_iface() -> bool {
+    with_ifaces(|t| !t.is_empty())
+}
 
-            // read MAC
-            for i in 0..6 {
-                mac[i] = inb(io + IDR0 + i as u16);
-            }
+/// A real NIC to reach anything beyond this machine.
+fn have_nic() -> bool {
+    with_primary(|_| ()).is_some()
+}
 
-            // program RX buffer physical address (DMA needs physical addresses)
-            // Use a raw const pointer to the static to avoid creating a shared
-            // reference to a `static mut` (Rust 2024 compatibility).
-            let rx_virt = &raw const RX_BUFFER as *const _ as u64;
-            let rx_phys = crate::bootinfo::virt_to_phys_u32(rx_virt).unwrap_or(0);
-            outl(io + RBSTART, rx_phys);
+/// Set an interface's administrative state by name. Returns whether it is
+/// the primary NIC, or None if there is no such interface.
+fn set_iface_up(name: &str, up: bool) -> Option<bool> {
+    let primary = unsafe { NET.primary };
+    with_ifaces(|t| {
+        let (i, x) = t.iter_mut().enumerate().find(|(_, x)| x.name == name)?;
+        x.up = up;
+        Some(i == primary && !x.is_loopback())
+    })
+}
 
-            // RX config:
-            // - accept physical match (bit1) + broadcast (bit3)
-            // - wrap (bit7)
-            // - MXDMA = unlimited (bits 10:8 = 111)
-            let rcr = (1u32 << 0) | (1u32 << 1) | (1u32 << 3) | (1u32 << 7) | (0x7u32 << 8);
-            outl(io + RCR, rcr);
+fn iface_is_primary(name: &str) -> Option<bool> {
+    let primary = unsafe { NET.primary };
+    with_ifaces(|t| {
+        let i = t.iter().position(|x| x.name == name)?;
+        Some(i == primary && !t[i].is_loopback())
+    })
+}
 
-            // TX config: MXDMA unlimited
-            outl(io + TCR, 0x7u32 << 8);
+/// Hand one packet to `lo`; it comes back in through `poll`.
+fn loopback_send(ethertype: u16, payload: &[u8]) -> bool {
+    with_ifaces(|t| {
+        t.iter_mut()
+            .find(|i| i.is_loopback())
+            .is_some_and(|i| i.send_frame([0; 6], ethertype, payload))
+    })
+}
 
-            // Enable RX OK in IMR (we poll, but some QEMU models behave better with it enabled)
-            outw(io + IMR, 0x0001);
-            outw(io + ISR, 0xFFFF);
+/// Send one Ethernet frame on the primary interface.
+fn send_frame(dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool {
+    with_primary(|i| i.send_frame(dst, ethertype, payload)).unwrap_or(false)
+}
 
-            // enable RX/TX
-            outb(io + CR, CR_RX_ENABLE | CR_TX_ENABLE);
+/// Frames handled per `poll` call at most, so a flooded link can't stall callers.
+const POLL_BUDGET: usize = 32;
 
-            // CAPR is "read pointer - 16"
-            outw(io + CAPR, 0xFFF0);
+/// Drain received frames from every interface into the demultiplexer.
+/// Returns how many frames were processed.
+pub fn poll() -> usize {
+    let mut n = 0usize;
+    while n < POLL_BUDGET {
+        // the frame slice is only valid until the driver's next poll_recv,
+        // so hand it to the demux before touching the table again
+        let got = with_ifaces(|t| {
+            for i in t.iter_mut() {
+                if let Some(f) = i.poll_recv() {
+                    return Some((f, i.is_loopback()));
+                }
+            }
+            None
+        });
+        match got {
+            Some((f, lo)) => demux::input(f, lo),
+            None => break,
         }
+        n += 1;
+    }
+    dhcp::tick();
+    ipv6::tick();
+    tcp::tick();
+    ntp::tick();
+    n
+}
 
-        Self { io, mac, rx_off: 0, tx_cur: 0 }
+/// Answer an ICMP echo request (`req` is the whole ICMP message) sent to
+/// `our_ip`. Local requests go back through `lo`, the rest straight to the
+/// sender's MAC.
+fn send_echo_reply(dst_mac: [u8; 6], our_ip: [u8; 4], dst_ip: [u8; 4], req: &[u8]) {
+    let mut icmp = req.to_vec();
+    icmp[0] = 0; // echo reply
+    icmp[2..4].copy_from_slice(&[0, 0]);
+    let c = checksum16(&icmp);
+    icmp[2..4].copy_from_slice(&c.to_be_bytes());
+
+    if ipv4::is_local(dst_ip) {
+        let _ = ipv4::send(our_ip, dst_ip, ipv4::PROTO_ICMP, &icmp);
+        return;
     }
 
-    fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool {
-        // Ethernet minimum frame size is 60 bytes (excluding 4-byte CRC).
-        // If we transmit runt frames, some backends/emulations will drop them,
-        // which breaks ARP and small TCP packets (e.g. SYN is 58 bytes).
-        let total = 14 + payload.len();
-        let tx_total = core::cmp::max(total, 60);
-        if tx_total > TX_BUF_SIZE {
-            return false;
-        }
+    let ip_len = 20 + icmp.len();
+    if ip_len > 1500 { return; }
+    let mut ip = alloc::vec![0u8; 20];
+    ip[0] = 0x45;
+    ip[2..4].copy_from_slice(&(ip_len as u16).to_be_bytes());
+    ip[8] = 64;
+    ip[9] = 1;
+    ip[12..16].copy_from_slice(&our_ip);
+    ip[16..20].copy_from_slice(&dst_ip);
+    let c = checksum16(&ip[0..20]);
+    ip[10..12].copy_from_slice(&c.to_be_bytes());
+    ip.extend_from_slice(&icmp);
 
-        // Pick a TX descriptor that looks idle. Some emulations will drop frames if we
-// stomp an in-flight descriptor.
-let mut idx = self.tx_cur % NUM_TX;
-for _ in 0..NUM_TX {
-    let tsd = unsafe { inl(self.io + TSD0 + (idx as u16) * 4) };
-    // OWN (bit13) is typically set while the NIC owns the descriptor.
-    // If an emulation uses different semantics, this just becomes a best-effort heuristic.
-    let own = (tsd & (1u32 << 13)) != 0;
-    if !own { break; }
-    idx = (idx + 1) % NUM_TX;
+    send_frame(dst_mac, 0x0800, &ip);
 }
-self.tx_cur = (idx + 1) % NUM_TX;
 
-        unsafe {
-            let buf = &mut TX_BUFFERS[idx];
-            buf[0..6].copy_from_slice(&dst);
-            buf[6..12].copy_from_slice(&self.mac);
-            buf[12..14].copy_from_slice(&ethertype.to_be_bytes());
-            buf[14..14 + payload.len()].copy_from_slice(payload);
-            if tx_total > total {
-                // Zero padding to satisfy minimum frame size.
-                for b in buf[14 + payload.len()..tx_total].iter_mut() { *b = 0; }
-            }
+// -----------------------------------------------------------------------------
+// Public API
+// -----------------------------------------------------------------------------
 
-            let tx_virt = &TX_BUFFERS[idx] as *const _ as u64;
-            let phys = crate::bootinfo::virt_to_phys_u32(tx_virt).unwrap_or(0);
-            outl(self.io + TSAD0 + (idx as u16) * 4, phys);
-            outl(self.io + TSD0 + (idx as u16) * 4, tx_total as u32);
+/// Add a device to the interface table as `<prefix>N`; returns its index.
+pub fn register(prefix: &str, dev: Box<dyn NetDevice>) -> usize {
+    with_ifaces(|t| {
+        let n = t.iter().filter(|i| i.name.starts_with(prefix)).count();
+        t.push(Interface::new(format!("{}{}", prefix, n), dev));
+        t.len() - 1
+    })
+}
+
+/// Probe every supported NIC once. Order decides eth0 (the primary):
+/// virtio-net (fastest under QEMU), RTL8139, then e1000/e1000e.
+pub fn init() {
+    unsafe {
+        if NET.probed {
+            return;
         }
+        NET.probed = true;
+    }
 
-        unsafe { NET.stats.tx_packets = NET.stats.tx_packets.wrapping_add(1); }
-        true
+    if let Ok(d) = virtio_net::VirtioNet::probe() {
+        register("eth", Box::new(d));
+    }
+    if let Some(d) = rtl8139::Rtl8139::probe() {
+        register("eth", Box::new(d));
+    }
+    if let Ok(d) = e1000::E1000::probe() {
+        register("eth", Box::new(d));
     }
 
-    fn poll_recv(&mut self) -> Option<&'static [u8]> {
-        // Poll ring write pointer (CBR) instead of gating on ISR bits.
-        let cbr = unsafe { inw(self.io + CBR) as usize } & (RX_RING_LEN - 1);
-        if cbr == self.rx_off {
-            return None;
-        }
+    with_ifaces(|t| t.push(Interface::new(String::from("lo"), Box::new(loopback::Loopback::new()))));
 
+    if let Some(mac) = with_primary(|i| i.mac()) {
         unsafe {
-            // Obtain a raw pointer to the RX buffer contents without creating a
-            // shared reference to the `static mut`.
-            let ring_ptr = &raw const RX_BUFFER.0 as *const u8;
-
-            // Header is 4 bytes at rx_off (status + length), potentially in the overflow region.
-            let off = self.rx_off;
-            let status = u16::from_le_bytes([
-                ptr::read_volatile(ring_ptr.add(off)),
-                ptr::read_volatile(ring_ptr.add(off + 1)),
-            ]);
-            let len_raw = u16::from_le_bytes([
-                ptr::read_volatile(ring_ptr.add(off + 2)),
-                ptr::read_volatile(ring_ptr.add(off + 3)),
-            ]) as usize;
-
-            // Length is the whole frame bytes (usually includes CRC). Keep it conservative.
-            if len_raw < 14 || len_raw > 2048 {
-                NET.stats.rx_dropped = NET.stats.rx_dropped.wrapping_add(1);
-                self.advance_rx(align4(4 + len_raw));
-                outw(self.io + ISR, 0xFFFF);
-                return None;
-            }
-
-            // Some emulators/NICs report the length including the 4-byte Ethernet FCS (CRC),
-            // others do not. If we blindly subtract 4 we can truncate ARP/IP packets and then
-            // everything (ARP, DHCP, TCP) mysteriously times out.
-            //
-            // Strategy:
-            // 1) Copy exactly `len_raw` bytes into RX_SCRATCH (bounded).
-            // 2) Trim the returned slice using L2 ethertype + IPv4 total length when possible.
-            let start = off + 4;
-
-            let copy_len = len_raw.min(RX_SCRATCH_LEN);
-            if start + copy_len <= RX_BUFFER_SIZE {
-                // Fast path: contiguous region available in the backing buffer.
-                // Copy using volatile reads to avoid creating shared refs.
-                let mut j = 0usize;
-                while j < copy_len {
-                    RX_SCRATCH[j] = ptr::read_volatile(ring_ptr.add(start + j));
-                    j += 1;
-                }
-            } else {
-                // Extremely rare fallback (shouldn't happen with our overflow size)
-                let mut j = 0usize;
-                while j < copy_len {
-                    let src = (start + j) & (RX_RING_LEN - 1);
-                    RX_SCRATCH[j] = ptr::read_volatile(ring_ptr.add(src));
-                    j += 1;
-                }
-            }
-
-            // Advance rx ptr: header(4) + len_raw, aligned to dword, wrap at 8KiB.
-            self.advance_rx(align4(4 + len_raw));
+            NET.cfg.nic_present = true;
+            NET.cfg.mac = mac;
+        }
+        ipv6::start(mac);
+    }
+}
 
-            // ack everything (we poll)
-            outw(self.io + ISR, 0xFFFF);
+pub fn interfaces() -> Vec<IfaceInfo> {
+    with_ifaces(|t| t.iter().map(|i| i.info()).collect())
+}
 
-            if (status & 0x0001) == 0 {
-                NET.stats.rx_dropped = NET.stats.rx_dropped.wrapping_add(1);
-                return None;
-            }
+pub fn mac() -> Option<[u8; 6]> {
+    unsafe {
+        if NET.cfg.nic_present { Some(NET.cfg.mac) } else { None }
+    }
+}
 
-            // Trim padding/FCS for the consumer.
-            let mut out_len = copy_len.max(14);
 
-            if copy_len >= 14 {
-                let mut l2 = 14usize;
-                let mut ethertype = u16::from_be_bytes([RX_SCRATCH[12], RX_SCRATCH[13]]);
-                if ethertype == 0x8100 && copy_len >= 18 {
-                    ethertype = u16::from_be_bytes([RX_SCRATCH[16], RX_SCRATCH[17]]);
-                    l2 = 18;
-                }
+pub fn config() -> NetConfig {
+    unsafe { NET.cfg }
+}
 
-                if ethertype == 0x0806 {
-                    // ARP header is 28 bytes after Ethernet/VLAN header.
-                    let want = l2 + 28;
-                    if want <= copy_len { out_len = want; }
-                } else if ethertype == 0x0800 && copy_len >= l2 + 20 {
-                    // IPv4: use Total Length field to trim trailing pad/CRC.
-                    let ip = l2;
-                    let ver_ihl = RX_SCRATCH[ip];
-                    if (ver_ihl >> 4) == 4 {
-                        let ihl = ((ver_ihl & 0x0F) as usize) * 4;
-                        if ihl >= 20 && copy_len >= l2 + ihl + 4 {
-                            let total = u16::from_be_bytes([RX_SCRATCH[ip + 2], RX_SCRATCH[ip + 3]]) as usize;
-                            let want = l2 + total;
-                            if want >= 14 && want <= copy_len { out_len = want; }
-                        }
-                    }
-                }
-            }
+/// Counters summed over every interface.
+pub fn stats() -> NetStats {
+    with_ifaces(|t| {
+        let mut s = NetStats { rx_packets: 0, tx_packets: 0, rx_dropped: 0 };
+        for i in t.iter() {
+            let x = i.stats();
+            s.rx_packets = s.rx_packets.wrapping_add(x.rx_packets);
+            s.tx_packets = s.tx_packets.wrapping_add(x.tx_packets);
+            s.rx_dropped = s.rx_dropped.wrapping_add(x.rx_dropped);
+        }
+        s
+    })
+}
 
-            NET.stats.rx_packets = NET.stats.rx_packets.wrapping_add(1);
-            Some(&RX_SCRATCH[..out_len.min(copy_len)])
+/// Receive-path counters from the demultiplexer, one line per layer:
+/// frames delivered, then drops broken down by reason.
+pub fn demux_report() -> String {
+    let st = demux::stats();
+    let mut out = String::new();
+    out.push_str("layer  delivered  dropped (reason=count)\n");
+    for (li, layer) in demux::LAYERS.iter().enumerate() {
+        let name = match layer {
+            demux::Layer::Eth => "eth",
+            demux::Layer::Arp => "arp",
+            demux::Layer::Ipv4 => "ipv4",
+            demux::Layer::Icmp => "icmp",
+            demux::Layer::Ipv6 => "ipv6",
+            demux::Layer::Icmpv6 => "icmp6",
+            demux::Layer::Udp => "udp",
+            demux::Layer::Tcp => "tcp",
+        };
+        out.push_str(&format!("{:<6} {:>9}", name, st.delivered[li]));
+        for (ri, reason) in demux::REASONS.iter().enumerate() {
+            let n = st.dropped[li][ri];
+            if n == 0 { continue; }
+            let why = match reason {
+                demux::DropReason::Malformed => "malformed",
+                demux::DropReason::Unsupported => "unsupported",
+                demux::DropReason::NotForUs => "not-for-us",
+                demux::DropReason::NoSocket => "no-socket",
+                demux::DropReason::QueueFull => "queue-full",
+            };
+            out.push_str(&format!("  {}={}", why, n));
         }
+        out.push('\n');
     }
+    out.push_str(&format!("total dropped: {}", st.dropped_total()));
+    let f = ipv4::frag_stats();
+    out.push_str(&format!("\nipv4 fragments: reassembled={} timed-out={} dropped={} sent-fragmented={}",
+        f.reassembled, f.timed_out, f.dropped, f.fragmented));
+    out
+}
 
-    fn advance_rx(&mut self, bytes: usize) {
-        self.rx_off = (self.rx_off + bytes) & (RX_RING_LEN - 1);
+/// "a.b.c.d:port", or "[v6]:port".
+fn endpoint(a: ip::IpAddr, port: u16) -> String {
+    let mut b = [0u8; 40];
+    let n = ip::write(&mut b, a);
+    let text = str::from_utf8(&b[..n]).unwrap_or("?");
+    if a.is_v6() { format!("[{}]:{}", text, port) } else { format!("{}:{}", text, port) }
+}
 
-        // CAPR wants (rx_off - 16) modulo ring length.
-        let capr = (self.rx_off.wrapping_sub(16) & (RX_RING_LEN - 1)) as u16;
-        unsafe { outw(self.io + CAPR, capr); }
+/// TCP connection table as text, one line per socket.
+pub fn tcp_report() -> String {
+    let conns = tcp::connections();
+    if conns.is_empty() {
+        return String::from("tcp: no sockets");
+    }
+    let mut out = String::new();
+    out.push_str("local                  remote                 state        rx-q    tx-q    cwnd    rto   retx");
+    for c in conns.iter() {
+        let local = endpoint(c.local_ip, c.local_port);
+        let remote = if c.state == tcp::TcpState::Listen {
+            String::from("*:*")
+        } else {
+            endpoint(c.remote_ip, c.remote_port)
+        };
+        out.push_str(&format!("\n{:<22} {:<22} {:<12} {:<7} {:<7} {:<7} {:<5} {}",
+            local, remote, c.state.name(), c.rx_queued, c.tx_queued, c.cwnd, c.rto_ms, c.retransmits));
     }
+    out
 }
 
-#[inline(always)]
-fn align4(x: usize) -> usize { (x + 3) & !3 }
-
-// -----------------------------------------------------------------------------
-// Global net state
-// -----------------------------------------------------------------------------
-
-struct NetState {
-    rtl: Option<Rtl8139>,
-    cfg: NetConfig,
-    stats: NetStats,
-
-    // tiny ARP cache (1 entry)
-    arp_valid: bool,
-    arp_ip: [u8; 4],
-    arp_mac: [u8; 6],
+/// ARP table as text: one line per entry with its age in seconds.
+pub fn arp_report() -> String {
+    let entries = arp::entries();
+    if entries.is_empty() {
+        return String::from("arp: table is empty");
+    }
+    let now = time::uptime_secs();
+    let mut out = String::new();
+    out.push_str("address          hwaddress           age(s)");
+    for e in entries.iter() {
+        let mut ip = [0u8; 16];
+        let ni = write_ipv4(&mut ip, e.ip);
+        let mut mac = [0u8; 17];
+        let nm = write_mac(&mut mac, e.mac);
+        out.push_str(&format!(
+            "\n{:<16} {:<19} {}",
+            str::from_utf8(&ip[..ni]).unwrap_or("?"),
+            str::from_utf8(&mac[..nm]).unwrap_or("?"),
+            now.saturating_sub(e.updated),
+        ));
+    }
+    out
 }
 
-static mut NET: NetState = NetState {
-    rtl: None,
-    cfg: NetConfig::empty(),
-    stats: NetStats { rx_packets: 0, tx_packets: 0, rx_dropped: 0 },
-
-    arp_valid: false,
-    arp_ip: [0; 4],
-    arp_mac: [0; 6],
-};
+/// Routing table as text, most specific route first.
+pub fn route_report() -> String {
+    let routes = route::routes();
+    if routes.is_empty() {
+        return String::from("route: table is empty");
+    }
+    let mut out = String::new();
+    out.push_str("destination         gateway          flags");
+    for r in routes.iter() {
+        let mut d = [0u8; 16];
+        let nd = write_ipv4(&mut d, r.dest);
+        let mut g = [0u8; 16];
+        let ng = write_ipv4(&mut g, r.gateway);
+        let dest = if r.mask == [0, 0, 0, 0] {
+            String::from("default")
+        } else {
+            format!("{}/{}", str::from_utf8(&d[..nd]).unwrap_or("?"), route::prefix_len(r.mask).unwrap_or(0))
+        };
+        let gw = if r.kind == route::RouteKind::Loopback {
+            "lo"
+        } else if r.gateway == [0, 0, 0, 0] {
+            "on-link"
+        } else {
+            str::from_utf8(&g[..ng]).unwrap_or("?")
+        };
+        let flags = match r.kind {
+            route::RouteKind::Loopback => "loopback",
+            route::RouteKind::Connected => "connected",
+            route::RouteKind::Default => "default (config)",
+            route::RouteKind::Static => "static",
+        };
+        out.push_str(&format!("\n{:<19} {:<16} {}", dest, gw, flags));
+    }
+    out
+}
 
-// net_scan pretty strings (no alloc)
-static mut SCAN_BUFS: [[u8; 96]; 4] = [[0; 96]; 4];
-static mut SCAN_STRS: [&'static str; 4] = ["", "", "", ""];
-const SCAN_SLOTS: usize = 4;
+/// IPv6 neighbor cache as text.
+pub fn ndp_report() -> String {
+    let entries = ndp::entries();
+    if entries.is_empty() {
+        return String::from("ndp: neighbor cache is empty");
+    }
+    let now = time::uptime_secs();
+    let mut out = String::new();
+    out.push_str("address                                  hwaddress           age(s) router");
+    for e in entries.iter() {
+        let mut ip = [0u8; 40];
+        let ni = ipv6::write_addr(&mut ip, e.ip);
+        let mut mac = [0u8; 17];
+        let nm = write_mac(&mut mac, e.mac);
+        out.push_str(&format!(
+            "\n{:<40} {:<19} {:<6} {}",
+            str::from_utf8(&ip[..ni]).unwrap_or("?"),
+            str::from_utf8(&mac[..nm]).unwrap_or("?"),
+            now.saturating_sub(e.updated),
+            if e.router { "yes" } else { "" },
+        ));
+    }
+    out
+}
 
-// Helpers to safely inspect / temporarily mutate the `NET.rtl` Option without
-// creating `&`/`&mut` borrows to a `static mut` (Rust 2024 compatibility).
-fn net_rtl_exists() -> bool {
-    unsafe {
-        let p: *mut Option<Rtl8139> = ptr::addr_of_mut!(NET.rtl);
-        let owned = ptr::replace(p, None);
-        let exists = owned.is_some();
-        ptr::replace(p, owned);
-        exists
+pub fn pcap_error_text(e: pcap::PcapError) -> &'static str {
+    match e {
+        pcap::PcapError::Busy => "a capture is already running (pcap stop)",
+        pcap::PcapError::NotRunning => "no capture running",
+        pcap::PcapError::BadFilter => "bad filter expression",
+        pcap::PcapError::Dns => "cannot resolve filter host",
     }
 }
 
-fn with_rtl_owned<F, R>(f: F) -> Option<R>
-where
-    F: FnOnce(&mut Rtl8139) -> R,
-{
-    unsafe {
-        let p: *mut Option<Rtl8139> = ptr::addr_of_mut!(NET.rtl);
-        let owned = ptr::replace(p, None);
-        if let Some(mut rtl) = owned {
-            let r = f(&mut rtl);
-            ptr::replace(p, Some(rtl));
-            Some(r)
-        } else {
-            ptr::replace(p, None);
-            None
-        }
+pub fn netconf_error_text(e: netconf::ConfError) -> String {
+    match e {
+        netconf::ConfError::Syntax(n) => format!("{}:{}: syntax error", netconf::PATH, n),
+        netconf::ConfError::Incomplete(n) => format!("{}:{}: static needs address and netmask", netconf::PATH, n),
+        netconf::ConfError::NotText => format!("{}: not a text file", netconf::PATH),
+        netconf::ConfError::NoSuchIface => String::from("no such interface"),
+        netconf::ConfError::Dhcp(e) => format!("DHCP: {}", dhcp_error_text(e)),
     }
 }
 
-// -----------------------------------------------------------------------------
-// Public API
-// -----------------------------------------------------------------------------
+/// One `ifup`/`ifdown` result line.
+pub fn ifstate_text(name: &str, st: netconf::IfState) -> String {
+    let ip = |a: [u8; 4]| format!("{}.{}.{}.{}", a[0], a[1], a[2], a[3]);
+    match st {
+        netconf::IfState::Dhcp(a) => format!("{}: up, dhcp {}", name, ip(a)),
+        netconf::IfState::Static(a) => format!("{}: up, static {}", name, ip(a)),
+        netconf::IfState::Link => format!("{}: up", name),
+        netconf::IfState::Down => format!("{}: down", name),
+        netconf::IfState::Absent => format!("{}: not present, skipped", name),
+    }
+}
 
-pub fn init() {
-    unsafe {
-        if net_rtl_exists() {
-            return;
-        }
+/// `pcap status`: whether a capture runs, where it goes and its counters.
+pub fn pcap_report() -> String {
+    let Some((sink, filter, st)) = pcap::status() else {
+        return String::from("pcap: no capture running");
+    };
+    let to = match sink {
+        pcap::Sink::File(p) => format!("{} ({} bytes buffered, saved on stop)", p, st.file_bytes),
+        pcap::Sink::Serial => String::from("COM1 (libpcap stream)"),
+        pcap::Sink::None => String::from("live view only"),
+    };
+    format!("pcap: capturing to {}\nfilter: {}\n{} packets, {} dropped",
+        to, if filter.is_empty() { "(all)" } else { &filter }, st.packets, st.dropped)
+}
 
-        if let Some(io) = pci_find_rtl8139_io() {
-            let rtl = Rtl8139::init(io);
-            NET.cfg.nic_present = true;
-            NET.cfg.mac = rtl.mac;
-            NET.rtl = Some(rtl);
-        }
+pub fn dns_error_text(e: dns::DnsError) -> &'static str {
+    match e {
+        dns::DnsError::NxDomain => "NXDOMAIN (no such name)",
+        dns::DnsError::NoAnswer => "no records of this type",
+        dns::DnsError::ServerFailure => "server failure",
+        dns::DnsError::Timeout => "timed out",
+        dns::DnsError::NotConfigured => "no DNS server configured",
+        dns::DnsError::NoNic => "no NIC",
+        dns::DnsError::CnameLoop => "CNAME chain too long",
+        dns::DnsError::Malformed => "malformed name or reply",
+        dns::DnsError::TxFail => "send failed",
     }
 }
 
-pub fn mac() -> Option<[u8; 6]> {
-    unsafe {
-        if NET.cfg.nic_present { Some(NET.cfg.mac) } else { None }
+/// `nslookup`/`dig` answer: where it came from, then one line per record.
+pub fn dns_report(name: &str, qtypes: &[u16]) -> String {
+    let mut out = String::new();
+    for &qtype in qtypes {
+        if !out.is_empty() { out.push('\n'); }
+        let a = match dns::lookup(name, qtype) {
+            Ok(a) => a,
+            Err(e) => {
+                let why = dns_error_text(e);
+                out.push_str(&format!(";; {} {}: {}", name, dns::type_name(qtype), why));
+                continue;
+            }
+        };
+        match a.server {
+            Some(srv) => {
+                let mut b = [0u8; 40];
+                let n = ip::write(&mut b, srv);
+                out.push_str(&format!(";; {} {} from {}#53 ({}{})", name, dns::type_name(qtype),
+                    str::from_utf8(&b[..n]).unwrap_or("?"),
+                    if a.via_tcp { "tcp" } else { "udp" },
+                    if a.authoritative { ", authoritative" } else { "" }));
+            }
+            None => out.push_str(&format!(";; {} {} from cache", name, dns::type_name(qtype))),
+        }
+        for r in a.records.iter() {
+            let data = match &r.data {
+                dns::RData::A(v) => {
+                    let mut b = [0u8; 16];
+                    let n = write_ipv4(&mut b, *v);
+                    String::from(str::from_utf8(&b[..n]).unwrap_or("?"))
+                }
+                dns::RData::Aaaa(v) => {
+                    let mut b = [0u8; 40];
+                    let n = ipv6::write_addr(&mut b, *v);
+                    String::from(str::from_utf8(&b[..n]).unwrap_or("?"))
+                }
+                dns::RData::Name(n) => format!("{}.", n),
+                dns::RData::Mx { preference, exchange } => format!("{} {}.", preference, exchange),
+                dns::RData::Txt(parts) => {
+                    let mut t = String::new();
+                    for p in parts.iter() {
+                        if !t.is_empty() { t.push(' '); }
+                        t.push('"');
+                        t.push_str(&String::from_utf8_lossy(p));
+                        t.push('"');
+                    }
+                    t
+                }
+                // RFC 3597 unknown-type form
+                dns::RData::Other(raw) => {
+                    let mut t = format!("\\# {}", raw.len());
+                    for b in raw.iter() {
+                        t.push_str(&format!(" {:02x}", b));
+                    }
+                    t
+                }
+            };
+            out.push_str(&format!("\n{:<32} {:<7} IN {:<5} {}", format!("{}.", r.name), r.ttl, dns::type_name(r.rtype), data));
+        }
     }
+    out
 }
 
+/// Sent in DHCP requests until /etc/network.conf says otherwise.
+pub const DEFAULT_HOSTNAME: &str = "othello";
 
-pub fn config() -> NetConfig {
-    unsafe { NET.cfg }
+static HOSTNAME: SpinLock<String> = SpinLock::new(String::new());
+
+pub fn hostname() -> String {
+    let h = HOSTNAME.lock();
+    if h.is_empty() { String::from(DEFAULT_HOSTNAME) } else { h.clone() }
 }
 
-pub fn stats() -> NetStats {
-    unsafe { NET.stats }
+pub fn set_hostname(name: &str) {
+    *HOSTNAME.lock() = String::from(name);
 }
 
 pub fn set_static_config(ip: [u8; 4], mask: [u8; 4], gateway: [u8; 4], dns: [u8; 4]) {
+    // otherwise the lease timers would put the DHCP address back at T1
+    dhcp::forget();
     unsafe {
         NET.cfg.dhcp_bound = false;
         NET.cfg.ip = ip;
@@ -458,8 +724,10 @@ pub fn set_static_config(ip: [u8; 4], mask: [u8; 4], gateway: [u8; 4], dns: [u8;
         NET.cfg.dns = dns;
         NET.cfg.server_id = [0, 0, 0, 0];
         NET.cfg.lease_seconds = 0;
-        NET.arp_valid = false;
+        NET.cfg.mtu = 0;
     }
+    arp::flush();
+    arp::announce(unsafe { NET.cfg.mac }, ip);
 }
 
 pub fn net_scan() -> NetScanResult {
@@ -469,21 +737,28 @@ pub fn net_scan() -> NetScanResult {
     unsafe {
         let mut n = 0usize;
 
-        if !net_rtl_exists() {
-            n += write_line_from_buf(n, b"rtl8139: not found");
-            return NetScanResult { devices: &SCAN_STRS[..n] };
+        let ifaces = interfaces();
+        if !have_nic() {
+            n += write_line_from_buf(n, b"nic: not found (virtio-net/rtl8139/e1000), loopback only");
         }
 
-        // line 0: mac
-        {
+        // one line per interface: name, driver, state, mac, mtu
+        for ifc in ifaces.iter().take(SCAN_SLOTS - 2 - n) {
             let mut buf = [0u8; 96];
             let mut k = 0usize;
-            k += copy_bytes(&mut buf[k..], b"rtl8139: up  mac=");
-            k += write_mac(&mut buf[k..], NET.cfg.mac);
+            k += copy_bytes(&mut buf[k..], ifc.name.as_bytes());
+            k += copy_bytes(&mut buf[k..], b" (");
+            k += copy_bytes(&mut buf[k..], ifc.driver.as_bytes());
+            k += copy_bytes(&mut buf[k..], b"): ");
+            k += copy_bytes(&mut buf[k..], if !ifc.up { b"down" } else if ifc.link { b"up" } else { b"no-link" });
+            k += copy_bytes(&mut buf[k..], b"  mac=");
+            k += write_mac(&mut buf[k..], ifc.mac);
+            k += copy_bytes(&mut buf[k..], b" mtu=");
+            k += write_u32_dec(&mut buf[k..], ifc.mtu as u32);
             n += write_line_from_buf(n, &buf[..k]);
         }
 
-        // line 1: ipv4
+        // ipv4 (primary interface)
         {
             let mut buf = [0u8; 96];
             let mut k = 0usize;
@@ -493,16 +768,17 @@ pub fn net_scan() -> NetScanResult {
             n += write_line_from_buf(n, &buf[..k]);
         }
 
-        // line 2: stats
+        // stats (all interfaces)
         {
+            let st = stats();
             let mut buf = [0u8; 96];
             let mut k = 0usize;
             k += copy_bytes(&mut buf[k..], b"stats: rx=");
-            k += write_u32_dec(&mut buf[k..], NET.stats.rx_packets);
+            k += write_u32_dec(&mut buf[k..], st.rx_packets);
             k += copy_bytes(&mut buf[k..], b" tx=");
-            k += write_u32_dec(&mut buf[k..], NET.stats.tx_packets);
+            k += write_u32_dec(&mut buf[k..], st.tx_packets);
             k += copy_bytes(&mut buf[k..], b" drop=");
-            k += write_u32_dec(&mut buf[k..], NET.stats.rx_dropped);
+            k += write_u32_dec(&mut buf[k..], st.rx_dropped);
             n += write_line_from_buf(n, &buf[..k]);
         }
 
@@ -511,526 +787,178 @@ pub fn net_scan() -> NetScanResult {
 }
 
 // -----------------------------------------------------------------------------
-// DHCP (blocking, polled)
+// DHCP (net/dhcp.rs)
 // -----------------------------------------------------------------------------
 
 pub fn dhcp_acquire() -> Result<(), DhcpError> {
-    init();
-
-    // Take temporary ownership of NET.rtl so we can call RTL methods without
-    // creating `&mut` borrows to a `static mut`.
-    if let Some(res) = with_rtl_owned(|rtl| {
-        unsafe {
-            // clear prior
-            NET.cfg.dhcp_bound = false;
-            NET.cfg.ip = [0, 0, 0, 0];
-            NET.cfg.mask = [0, 0, 0, 0];
-            NET.cfg.gateway = [0, 0, 0, 0];
-            NET.cfg.dns = [0, 0, 0, 0];
-            NET.cfg.server_id = [0, 0, 0, 0];
-            NET.cfg.lease_seconds = 0;
-        }
-
-        let xid = (time::rdtsc() as u32) ^ 0xA5A5_1234;
-
-        // Run DHCP sequence using the owned rtl reference.
-        (|| -> Result<(), DhcpError> {
-            // DISCOVER
-            send_dhcp(rtl, xid, 1, [0, 0, 0, 0], [0, 0, 0, 0])?;
-
-            // OFFER
-            let offer = wait_dhcp(rtl, xid, 2)?;
-            let offered_ip = offer.yiaddr;
-            let server = offer.server_id;
-
-            // REQUEST
-            send_dhcp(rtl, xid, 3, offered_ip, server)?;
-
-            // ACK (or NACK)
-            let ack = wait_dhcp(rtl, xid, 5)?;
-            if ack.msg_type == 6 {
-                return Err(DhcpError::Nack);
-            }
-
-            unsafe {
-                NET.cfg.dhcp_bound = true;
-                NET.cfg.ip = offered_ip;
-                NET.cfg.mask = ack.subnet_mask;
-                NET.cfg.gateway = ack.router;
-                NET.cfg.dns = ack.dns1;
-                NET.cfg.server_id = server;
-                NET.cfg.lease_seconds = ack.lease_time;
-
-                // clear ARP cache
-                NET.arp_valid = false;
-            }
-
-            Ok(())
-        })()
-    }) {
-        res
-    } else {
-        Err(DhcpError::NoNic)
-    }
-}
-
-fn send_dhcp(
-    rtl: &mut Rtl8139,
-    xid: u32,
-    msg_type: u8,
-    req_ip: [u8; 4],
-    server_id: [u8; 4],
-) -> Result<(), DhcpError> {
-    // build DHCP payload
-    let mut dhcp = [0u8; 300];
-    let dhcp_len = build_dhcp(&mut dhcp, rtl.mac, xid, msg_type, req_ip, server_id);
-
-    // build UDP + IPv4
-    let mut pkt = [0u8; 420];
-    let udp_len = 8 + dhcp_len;
-    let ip_len = 20 + udp_len;
-
-    // IPv4 header
-    pkt[0] = 0x45;
-    pkt[1] = 0;
-    pkt[2..4].copy_from_slice(&(ip_len as u16).to_be_bytes());
-    pkt[4..6].copy_from_slice(&0u16.to_be_bytes());
-    pkt[6..8].copy_from_slice(&0u16.to_be_bytes());
-    pkt[8] = 64;
-    pkt[9] = 17; // UDP
-    pkt[10..12].copy_from_slice(&0u16.to_be_bytes());
-    pkt[12..16].copy_from_slice(&[0, 0, 0, 0]); // src 0.0.0.0
-    pkt[16..20].copy_from_slice(&[255, 255, 255, 255]); // dst broadcast
-    let csum = checksum16(&pkt[0..20]);
-    pkt[10..12].copy_from_slice(&csum.to_be_bytes());
-
-    // UDP header
-    let u = 20;
-    pkt[u + 0..u + 2].copy_from_slice(&68u16.to_be_bytes());
-    pkt[u + 2..u + 4].copy_from_slice(&67u16.to_be_bytes());
-    pkt[u + 4..u + 6].copy_from_slice(&(udp_len as u16).to_be_bytes());
-    pkt[u + 6..u + 8].copy_from_slice(&0u16.to_be_bytes()); // UDP checksum 0 OK for IPv4
-
-    // DHCP
-    pkt[u + 8..u + 8 + dhcp_len].copy_from_slice(&dhcp[..dhcp_len]);
-
-    let ok = rtl.send_frame([0xFF; 6], 0x0800, &pkt[..ip_len]);
-    if ok { Ok(()) } else { Err(DhcpError::Malformed) }
+    dhcp::acquire()
 }
 
-#[derive(Copy, Clone)]
-struct DhcpParsed {
-    msg_type: u8,
-    yiaddr: [u8; 4],
-    subnet_mask: [u8; 4],
-    router: [u8; 4],
-    dns1: [u8; 4],
-    server_id: [u8; 4],
-    lease_time: u32,
-}
-
-fn wait_dhcp(rtl: &mut Rtl8139, xid: u32, want_type: u8) -> Result<DhcpParsed, DhcpError> {
-    let mut spins: u32 = 0;
-    while spins < 12_000_000 {
-        if let Some(frame) = rtl.poll_recv() {
-            if let Some(p) = parse_dhcp_frame(frame, xid) {
-                if p.msg_type == want_type || (want_type == 5 && p.msg_type == 6) {
-                    return Ok(p);
-                }
-            }
-        }
-        spins = spins.wrapping_add(1);
-        if (spins & 0x3FF) == 0 { time::cpu_pause(); }
+pub fn dhcp_error_text(e: DhcpError) -> &'static str {
+    match e {
+        DhcpError::NoNic => "no NIC detected",
+        DhcpError::Timeout => "timeout (no offer/ack)",
+        DhcpError::Malformed => "malformed reply",
+        DhcpError::Nack => "NACK (request denied)",
+        DhcpError::Busy => "already in progress",
+        DhcpError::NotBound => "no lease (run dhcp first)",
     }
-    Err(DhcpError::Timeout)
 }
 
-fn parse_dhcp_frame(frame: &[u8], xid: u32) -> Option<DhcpParsed> {
-    // Ethernet(14) + IPv4(20+) + UDP(8) + DHCP(240+)
-    if frame.len() < 14 + 20 + 8 + 240 { return None; }
-
-    // VLAN-aware L2 header
-    let mut l2 = 14usize;
-    let mut ethertype = u16::from_be_bytes([frame[12], frame[13]]);
-    if ethertype == 0x8100 && frame.len() >= 18 {
-        ethertype = u16::from_be_bytes([frame[16], frame[17]]);
-        l2 = 18;
-    }
-    if ethertype != 0x0800 { return None; }
-
-    let ip = l2;
-    let ver_ihl = frame[ip];
-    if (ver_ihl >> 4) != 4 { return None; }
-    let ihl = (ver_ihl & 0x0F) as usize * 4;
-    if frame.len() < l2 + ihl + 8 + 240 { return None; }
-    if frame[ip + 9] != 17 { return None; }
-
-    let udp = l2 + ihl;
-    let src_port = u16::from_be_bytes([frame[udp], frame[udp + 1]]);
-    let dst_port = u16::from_be_bytes([frame[udp + 2], frame[udp + 3]]);
-    if !(src_port == 67 && dst_port == 68) { return None; }
-
-    let dh = udp + 8;
-    if frame.len() < dh + 240 { return None; }
-
-    let got_xid = u32::from_be_bytes([frame[dh + 4], frame[dh + 5], frame[dh + 6], frame[dh + 7]]);
-    if got_xid != xid { return None; }
-
-    // magic cookie
-    if frame[dh + 236..dh + 240] != [99, 130, 83, 99] { return None; }
-
-    let mut out = DhcpParsed {
-        msg_type: 0,
-        yiaddr: [frame[dh + 16], frame[dh + 17], frame[dh + 18], frame[dh + 19]],
-        subnet_mask: [0, 0, 0, 0],
-        router: [0, 0, 0, 0],
-        dns1: [0, 0, 0, 0],
-        server_id: [0, 0, 0, 0],
-        lease_time: 0,
-    };
-
-    let mut i = dh + 240;
-    while i < frame.len() {
-        let opt = frame[i];
-        i += 1;
-        if opt == 0 { continue; }
-        if opt == 255 { break; }
-        if i >= frame.len() { break; }
-        let len = frame[i] as usize;
-        i += 1;
-        if i + len > frame.len() { break; }
-
-        match opt {
-            53 if len >= 1 => out.msg_type = frame[i],
-            1 if len == 4 => out.subnet_mask.copy_from_slice(&frame[i..i + 4]),
-            3 if len >= 4 => out.router.copy_from_slice(&frame[i..i + 4]),
-            6 if len >= 4 => out.dns1.copy_from_slice(&frame[i..i + 4]),
-            51 if len == 4 => out.lease_time = u32::from_be_bytes([frame[i], frame[i + 1], frame[i + 2], frame[i + 3]]),
-            54 if len == 4 => out.server_id.copy_from_slice(&frame[i..i + 4]),
-            _ => {}
-        }
-        i += len;
+/// Install the IPv4 side of a DHCP lease.
+fn set_dhcp_config(l: &dhcp::Lease) {
+    unsafe {
+        NET.cfg.dhcp_bound = true;
+        NET.cfg.ip = l.ip;
+        NET.cfg.mask = l.mask;
+        NET.cfg.gateway = l.router;
+        NET.cfg.dns = l.dns.first().copied().unwrap_or([0, 0, 0, 0]);
+        NET.cfg.server_id = l.server_id;
+        NET.cfg.lease_seconds = l.lease_secs;
+        NET.cfg.mtu = l.mtu;
     }
-
-    if out.msg_type == 0 { None } else { Some(out) }
 }
 
-fn build_dhcp(
-    buf: &mut [u8],
-    mac: [u8; 6],
-    xid: u32,
-    msg_type: u8,
-    req_ip: [u8; 4],
-    server_id: [u8; 4],
-) -> usize {
-    buf.fill(0);
-
-    buf[0] = 1; // BOOTREQUEST
-    buf[1] = 1; // ethernet
-    buf[2] = 6; // mac len
-    buf[3] = 0; // hops
-    buf[4..8].copy_from_slice(&xid.to_be_bytes());
-    buf[10..12].copy_from_slice(&0x8000u16.to_be_bytes()); // broadcast flag
-    buf[28..34].copy_from_slice(&mac); // chaddr
-
-    // magic cookie
-    buf[236..240].copy_from_slice(&[99, 130, 83, 99]);
-
-    let mut i = 240usize;
-
-    // 53: message type
-    i = push_opt(buf, i, 53, &[msg_type]);
-
-    // 61: client identifier (type 1 + mac)
-    let mut cid = [0u8; 7];
-    cid[0] = 1;
-    cid[1..7].copy_from_slice(&mac);
-    i = push_opt(buf, i, 61, &cid);
-
-    // 55: parameter request list (subnet, router, dns, lease, server id)
-    i = push_opt(buf, i, 55, &[1, 3, 6, 51, 54]);
-
-    // 12: hostname
-    i = push_opt(buf, i, 12, b"othello");
-
-    if msg_type == 3 {
-        i = push_opt(buf, i, 50, &req_ip);     // requested ip
-        i = push_opt(buf, i, 54, &server_id);  // server identifier
-    }
-
-    if i < buf.len() {
-        buf[i] = 255;
-        i += 1;
+/// Forget the IPv4 address (lease released or expired, or a new `dhcp` run).
+fn clear_ipv4_config() {
+    unsafe {
+        NET.cfg.dhcp_bound = false;
+        NET.cfg.ip = [0, 0, 0, 0];
+        NET.cfg.mask = [0, 0, 0, 0];
+        NET.cfg.gateway = [0, 0, 0, 0];
+        NET.cfg.dns = [0, 0, 0, 0];
+        NET.cfg.server_id = [0, 0, 0, 0];
+        NET.cfg.lease_seconds = 0;
+        NET.cfg.mtu = 0;
     }
-    i
 }
 
-fn push_opt(buf: &mut [u8], mut i: usize, code: u8, data: &[u8]) -> usize {
-    if i + 2 + data.len() > buf.len() { return i; }
-    buf[i] = code; i += 1;
-    buf[i] = data.len() as u8; i += 1;
-    buf[i..i + data.len()].copy_from_slice(data);
-    i + data.len()
+/// Before power-off: hand the DHCP lease back.
+pub fn shutdown() {
+    dhcp::release();
 }
 
 // -----------------------------------------------------------------------------
 // Ping (ARP + ICMP echo)
 // -----------------------------------------------------------------------------
 
-pub fn ping_once(dst_ip: [u8; 4], seq: u16) -> Result<PingReply, PingError> {
+/// Send one ICMP echo request to `dst` and wait for whatever answers it: the
+/// echo reply, or a Time Exceeded / Destination Unreachable from a router
+/// quoting our request (that is what traceroute looks for).
+pub fn ping(dst: ip::IpAddr, seq: u16, opts: &PingOptions) -> Result<PingReply, PingError> {
     init();
 
-    let (src_ip, mask, gateway, mac) = unsafe {
-        // Check NIC presence without creating a borrow to the `static mut`.
-        if !net_rtl_exists() { return Err(PingError::NoNic); }
-        if NET.cfg.ip == [0, 0, 0, 0] { return Err(PingError::NotConfigured); }
-        (NET.cfg.ip, NET.cfg.mask, NET.cfg.gateway, NET.cfg.mac)
-    };
-
-    let next_hop = if same_subnet(src_ip, dst_ip, mask) || gateway == [0, 0, 0, 0] {
-        dst_ip
-    } else {
-        gateway
-    };
+    if !have_iface() { return Err(PingError::NoNic); }
+    if opts.size > PING_MAX_SIZE { return Err(PingError::TooLarge); }
 
     // Resolve next-hop MAC
-    let nh_mac = arp_resolve(next_hop, mac, src_ip)?;
-
-    // Build ICMP echo request
-    let ident: u16 = 0x4F54; // 'OT'
-    let mut icmp = [0u8; 8 + 32];
-    icmp[0] = 8; // type = echo request
-    icmp[1] = 0; // code
-    icmp[2..4].copy_from_slice(&0u16.to_be_bytes()); // checksum placeholder
-    icmp[4..6].copy_from_slice(&ident.to_be_bytes());
+    ip::resolve(dst).map_err(ping_err)?;
+
+    // The ident picks our reply queue ('OT', or the next free one if another
+    // ping is in flight)
+    let q = (0..16u16)
+        .find_map(|k| demux::icmp_open(0x4F54u16.wrapping_add(k)))
+        .ok_or(PingError::TxFail)?;
+    let mut icmp = alloc::vec![0u8; 8 + opts.size];
+    icmp[4..6].copy_from_slice(&q.ident().to_be_bytes());
     icmp[6..8].copy_from_slice(&seq.to_be_bytes());
-    for i in 0..32 { icmp[8 + i] = i as u8; }
-    let csum = checksum16(&icmp);
-    icmp[2..4].copy_from_slice(&csum.to_be_bytes());
-
-    // Build IPv4 packet
-    let ip_len = 20 + icmp.len();
-    let mut ip = [0u8; 20 + 8 + 32];
-    ip[0] = 0x45;
-    ip[1] = 0;
-    ip[2..4].copy_from_slice(&(ip_len as u16).to_be_bytes());
-    ip[4..6].copy_from_slice(&seq.to_be_bytes()); // id
-    ip[6..8].copy_from_slice(&0u16.to_be_bytes()); // flags/frag
-    ip[8] = 64; // ttl
-    ip[9] = 1;  // ICMP
-    ip[10..12].copy_from_slice(&0u16.to_be_bytes());
-    ip[12..16].copy_from_slice(&src_ip);
-    ip[16..20].copy_from_slice(&dst_ip);
-    let ip_csum = checksum16(&ip[0..20]);
-    ip[10..12].copy_from_slice(&ip_csum.to_be_bytes());
-    ip[20..20 + icmp.len()].copy_from_slice(&icmp);
-
-    // Send Ethernet frame
-    let ok = match with_rtl_owned(|rtl| rtl.send_frame(nh_mac, 0x0800, &ip[..ip_len])) {
-        Some(v) => v,
-        None => return Err(PingError::NoNic),
-    };
-    if !ok { return Err(PingError::TxFail); }
+    for (i, b) in icmp[8..].iter_mut().enumerate() { *b = i as u8; }
 
     let start = time::rdtsc();
-
-    // Wait for echo reply
-    let mut spins: u32 = 0;
-    while spins < 12_000_000 {
-        let frame_option = match with_rtl_owned(|rtl| rtl.poll_recv()) {
-            Some(f) => f,
-            None => return Err(PingError::NoNic),
-        };
-        if let Some(f) = frame_option {
-            if let Some((ttl, got_seq)) = parse_icmp_echo_reply(f, src_ip, dst_ip, ident) {
-                if got_seq == seq {
-                    let end = time::rdtsc();
-                    return Ok(PingReply { seq, ttl, rtt_tsc: end.wrapping_sub(start) });
-                }
-            }
-
-            // harvest ARP replies while we're here
-            if let Some((sip, sha)) = parse_arp_reply_for_us(f, src_ip) {
-                unsafe {
-                    NET.arp_valid = true;
-                    NET.arp_ip = sip;
-                    NET.arp_mac = sha;
-                }
-            }
+    match dst {
+        ip::IpAddr::V4(d) => {
+            let src = ipv4::source_for(d);
+            icmp[0] = 8; // echo request
+            let csum = checksum16(&icmp);
+            icmp[2..4].copy_from_slice(&csum.to_be_bytes());
+            let ttl = if opts.ttl == 0 { ipv4::DEFAULT_TTL } else { opts.ttl };
+            ipv4::send_with_ttl(src, d, ipv4::PROTO_ICMP, ttl, &icmp).map_err(ping_err)?;
         }
-        spins = spins.wrapping_add(1);
-        if (spins & 0x3FF) == 0 { time::cpu_pause(); }
-    }
-
-    Err(PingError::Timeout)
-}
-
-fn same_subnet(a: [u8; 4], b: [u8; 4], m: [u8; 4]) -> bool {
-    ((a[0] & m[0]) == (b[0] & m[0])) &&
-    ((a[1] & m[1]) == (b[1] & m[1])) &&
-    ((a[2] & m[2]) == (b[2] & m[2])) &&
-    ((a[3] & m[3]) == (b[3] & m[3]))
-}
-
-fn arp_resolve(target_ip: [u8; 4], our_mac: [u8; 6], our_ip: [u8; 4]) -> Result<[u8; 6], PingError> {
-    unsafe {
-        if NET.arp_valid && NET.arp_ip == target_ip {
-            return Ok(NET.arp_mac);
+        ip::IpAddr::V6(d) => {
+            // from our global address (or link-local for link-local targets)
+            let src = ipv6::source_for(d);
+            icmp[0] = 128; // echo request
+            let csum = ipv6::pseudo_checksum(src, d, ipv6::PROTO_ICMPV6, &icmp);
+            icmp[2..4].copy_from_slice(&csum.to_be_bytes());
+            let r = if opts.ttl == 0 {
+                ipv6::send(src, d, ipv6::PROTO_ICMPV6, &icmp)
+            } else {
+                ipv6::send_with_hop_limit(src, d, ipv6::PROTO_ICMPV6, opts.ttl, &icmp)
+            };
+            r.map_err(ping_err)?;
         }
     }
 
-    // Build ARP request
-    let mut arp = [0u8; 28];
-    arp[0..2].copy_from_slice(&1u16.to_be_bytes());      // htype ethernet
-    arp[2..4].copy_from_slice(&0x0800u16.to_be_bytes()); // ptype ipv4
-    arp[4] = 6; // hlen
-    arp[5] = 4; // plen
-    arp[6..8].copy_from_slice(&1u16.to_be_bytes());      // opcode request
-    arp[8..14].copy_from_slice(&our_mac);
-    arp[14..18].copy_from_slice(&our_ip);
-    arp[18..24].copy_from_slice(&[0u8; 6]);              // target mac
-    arp[24..28].copy_from_slice(&target_ip);
-
-    let ok = match with_rtl_owned(|rtl| rtl.send_frame([0xFF; 6], 0x0806, &arp)) {
-        Some(v) => v,
-        None => return Err(PingError::NoNic),
-    };
-    if !ok { return Err(PingError::TxFail); }
+    wait_echo(&q, dst, seq, start, opts.timeout_ms)
+}
 
-    // Wait for ARP reply
+/// Wait up to `timeout_ms` for the reply to echo `seq` sent to `dst`.
+fn wait_echo(q: &demux::IcmpQueue, dst: ip::IpAddr, seq: u16, start: u64, timeout_ms: u64) -> Result<PingReply, PingError> {
+    let deadline = time::uptime_ms().saturating_add(timeout_ms);
     let mut spins: u32 = 0;
-    while spins < 6_000_000 {
-        let frame_option = match with_rtl_owned(|rtl| rtl.poll_recv()) {
-            Some(f) => f,
-            None => return Err(PingError::NoNic),
-        };
-        if let Some(f) = frame_option {
-            if let Some((sip, sha)) = parse_arp_reply_for_us(f, our_ip) {
-                if sip == target_ip {
-                    unsafe {
-                        NET.arp_valid = true;
-                        NET.arp_ip = sip;
-                        NET.arp_mac = sha;
-                    }
-                    return Ok(sha);
-                }
+    loop {
+        poll();
+        while let Some(m) = q.recv() {
+            if let Some(kind) = match_echo(&m.data, m.src_ip, dst, seq) {
+                let rtt_us = time::tsc_to_us(time::rdtsc().wrapping_sub(start));
+                return Ok(PingReply { seq, ttl: m.ttl, from: m.src_ip, kind, bytes: m.data.len(), rtt_us });
             }
         }
         spins = spins.wrapping_add(1);
-        if (spins & 0x3FF) == 0 { time::cpu_pause(); }
+        if (spins & 0x3FF) == 0 {
+            if time::uptime_ms() >= deadline { return Err(PingError::Timeout); }
+            time::cpu_pause();
+        }
     }
-
-    Err(PingError::ArpTimeout)
-}
-
-fn parse_arp_reply_for_us(frame: &[u8], our_ip: [u8; 4]) -> Option<([u8; 4], [u8; 6])> {
-    if frame.len() < 14 + 28 { return None; }
-    let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
-    if ethertype != 0x0806 { return None; }
-
-    let a = 14;
-    let opcode = u16::from_be_bytes([frame[a + 6], frame[a + 7]]);
-    if opcode != 2 { return None; }
-
-    let sha = [frame[a + 8], frame[a + 9], frame[a + 10], frame[a + 11], frame[a + 12], frame[a + 13]];
-    let spa = [frame[a + 14], frame[a + 15], frame[a + 16], frame[a + 17]];
-    let tpa = [frame[a + 24], frame[a + 25], frame[a + 26], frame[a + 27]];
-
-    if tpa != our_ip { return None; }
-    Some((spa, sha))
 }
 
-fn parse_icmp_echo_reply(frame: &[u8], our_ip: [u8; 4], dst_ip: [u8; 4], ident: u16) -> Option<(u8, u16)> {
-    if frame.len() < 14 + 20 + 8 { return None; }
-
-    let mut l2 = 14usize;
-    let mut ethertype = u16::from_be_bytes([frame[12], frame[13]]);
-    if ethertype == 0x8100 && frame.len() >= 18 {
-        ethertype = u16::from_be_bytes([frame[16], frame[17]]);
-        l2 = 18;
-    }
-    if ethertype != 0x0800 { return None; }
-
-    let ip = l2;
-    let ver_ihl = frame[ip];
-    if (ver_ihl >> 4) != 4 { return None; }
-    let ihl = (ver_ihl & 0x0F) as usize * 4;
-    if frame.len() < l2 + ihl + 8 { return None; }
-    if frame[ip + 9] != 1 { return None; } // ICMP
-
-    let ttl = frame[ip + 8];
-    let src = [frame[ip + 12], frame[ip + 13], frame[ip + 14], frame[ip + 15]];
-    let dst = [frame[ip + 16], frame[ip + 17], frame[ip + 18], frame[ip + 19]];
-    if dst != our_ip { return None; }
-    if src != dst_ip { return None; }
-
-    let icmp = l2 + ihl;
-    let typ = frame[icmp];
-    let code = frame[icmp + 1];
-    if typ != 0 || code != 0 { return None; } // echo reply
-
-    let got_ident = u16::from_be_bytes([frame[icmp + 4], frame[icmp + 5]]);
-    if got_ident != ident { return None; }
-
-    let seq = u16::from_be_bytes([frame[icmp + 6], frame[icmp + 7]]);
-    Some((ttl, seq))
-}
-
-// -----------------------------------------------------------------------------
-// PCI helper
-// -----------------------------------------------------------------------------
-
-fn pci_cfg_addr(bus: u8, dev: u8, func: u8, off: u8) -> u32 {
-    0x8000_0000u32
-        | ((bus as u32) << 16)
-        | ((dev as u32) << 11)
-        | ((func as u32) << 8)
-        | ((off as u32) & 0xFC)
-}
-
-fn pci_read_u32(bus: u8, dev: u8, func: u8, off: u8) -> u32 {
-    unsafe {
-        outl(PCI_CONFIG_ADDR, pci_cfg_addr(bus, dev, func, off));
-        inl(PCI_CONFIG_DATA)
-    }
+/// Classify an ICMP/ICMPv6 message from `from` as the answer to echo `seq`
+/// sent to `dst`, if it is one. Errors are matched on the request they quote.
+fn match_echo(m: &[u8], from: ip::IpAddr, dst: ip::IpAddr, seq: u16) -> Option<PingKind> {
+    let quoted = |inner: &[u8], hdr: usize, dst_at: usize, dst: &[u8]| {
+        inner.len() >= hdr + 8
+            && &inner[dst_at..dst_at + dst.len()] == dst
+            && u16::from_be_bytes([inner[hdr + 6], inner[hdr + 7]]) == seq
+    };
+    let inner = &m[8..];
+    let kind = match (dst, m[0]) {
+        (ip::IpAddr::V4(_), 0) | (ip::IpAddr::V6(_), 129) => PingKind::Echo,
+        (ip::IpAddr::V4(_), 11) | (ip::IpAddr::V6(_), 3) => PingKind::TimeExceeded,
+        (ip::IpAddr::V4(_), 3) | (ip::IpAddr::V6(_), 1) => PingKind::Unreachable(m[1]),
+        _ => return None,
+    };
+    let ok = match (kind, dst) {
+        (PingKind::Echo, _) => from == dst && u16::from_be_bytes([m[6], m[7]]) == seq,
+        (_, ip::IpAddr::V4(d)) => {
+            let ihl = inner.first().map_or(0, |b| ((b & 0x0F) as usize) * 4);
+            ihl >= 20 && quoted(inner, ihl, 16, &d)
+        }
+        (_, ip::IpAddr::V6(d)) => quoted(inner, 40, 24, &d),
+    };
+    if ok { Some(kind) } else { None }
 }
 
-fn pci_write_u32(bus: u8, dev: u8, func: u8, off: u8, val: u32) {
-    unsafe {
-        outl(PCI_CONFIG_ADDR, pci_cfg_addr(bus, dev, func, off));
-        outl(PCI_CONFIG_DATA, val);
+/// Short description of an ICMP Destination Unreachable code.
+pub fn unreachable_text(v6: bool, code: u8) -> &'static str {
+    match (v6, code) {
+        (false, 0) | (true, 0) => "Network unreachable",
+        (false, 1) | (true, 3) => "Host unreachable",
+        (false, 2) => "Protocol unreachable",
+        (false, 3) | (true, 4) => "Port unreachable",
+        (false, 4) => "Fragmentation needed",
+        (false, 9) | (false, 10) | (false, 13) | (true, 1) => "Communication administratively prohibited",
+        _ => "Destination unreachable",
     }
 }
 
-fn pci_find_rtl8139_io() -> Option<u16> {
-    for bus in 0u8..=255 {
-        for dev in 0u8..32 {
-            for func in 0u8..8 {
-                let vd = pci_read_u32(bus, dev, func, 0x00);
-                let vendor = (vd & 0xFFFF) as u16;
-                if vendor == 0xFFFF { continue; }
-                let device = (vd >> 16) as u16;
-                if vendor == RTL_VENDOR_ID && device == RTL_DEVICE_ID {
-                    // BAR0
-                    let bar0 = pci_read_u32(bus, dev, func, 0x10);
-                    if (bar0 & 0x1) == 0 { continue; } // not I/O bar
-                    let io = (bar0 & 0xFFFC) as u16;
-
-                    // enable I/O + bus master
-                    let cmdsts = pci_read_u32(bus, dev, func, 0x04);
-                    let cmd = (cmdsts & 0xFFFF) as u16;
-                    let sts = (cmdsts >> 16) as u16;
-
-                    let new_cmd = cmd | 0x0001 | 0x0004;
-                    let new_val = ((sts as u32) << 16) | (new_cmd as u32);
-                    pci_write_u32(bus, dev, func, 0x04, new_val);
-
-                    return Some(io);
-                }
-            }
-        }
+fn ping_err(e: ipv4::IpError) -> PingError {
+    match e {
+        ipv4::IpError::NoNic => PingError::NoNic,
+        ipv4::IpError::NotConfigured => PingError::NotConfigured,
+        ipv4::IpError::ArpTimeout => PingError::ArpTimeout,
+        ipv4::IpError::TooLarge => PingError::TooLarge,
+        ipv4::IpError::TxFail => PingError::TxFail,
     }
-    None
 }
 
 // -----------------------------------------------------------------------------
@@ -1137,3 +1065,211 @@ fn write_mac(out: &mut [u8], mac: [u8; 6]) -> usize {
     }
     n
 }
+
+fn cert_error_text(e: x509::CertError) -> &'static str {
+    match e {
+        x509::CertError::Malformed => "malformed certificate",
+        x509::CertError::Unsupported => "unsupported key or signature algorithm",
+        x509::CertError::BadSignature => "bad certificate signature",
+        x509::CertError::Expired => "certificate expired",
+        x509::CertError::NotYetValid => "certificate not yet valid (check the clock)",
+        x509::CertError::UnknownIssuer => "issuer not trusted (see `tls trust`)",
+        x509::CertError::NameMismatch => "certificate is for a different host",
+        x509::CertError::NotCa => "issuer is not a CA",
+        x509::CertError::BadUsage => "certificate not valid for TLS servers",
+        x509::CertError::UnknownCritical => "unknown critical extension",
+        x509::CertError::ChainTooLong => "chain too long",
+    }
+}
+
+pub fn tls_error_text(e: tls::TlsError) -> String {
+    match e {
+        tls::TlsError::Tcp(t) => format!("tcp: {}", tcp_error_text(t)),
+        tls::TlsError::Alert(a) => format!("server alert {} ({})", a, tls::alert_name(a)),
+        tls::TlsError::Protocol => String::from("protocol error"),
+        tls::TlsError::BadRecord => String::from("record failed authentication"),
+        tls::TlsError::Negotiation => String::from("no common TLS 1.3 parameters"),
+        tls::TlsError::Certificate(c) => String::from(cert_error_text(c)),
+        tls::TlsError::BadSignature => String::from("handshake signature check failed"),
+        tls::TlsError::Closed => String::from("connection closed during handshake"),
+    }
+}
+
+pub fn tcp_error_text(e: tcp::TcpError) -> &'static str {
+    match e {
+        tcp::TcpError::NoNic => "no NIC",
+        tcp::TcpError::NotConfigured => "no IPv4 address (run dhcp or ifup)",
+        tcp::TcpError::ArpTimeout => "no ARP reply from the next hop",
+        tcp::TcpError::Timeout => "timed out",
+        tcp::TcpError::TxFail => "send failed",
+        tcp::TcpError::Reset => "connection reset by peer",
+        tcp::TcpError::Proto => "connection refused or protocol error",
+        tcp::TcpError::AddrInUse => "no free local port",
+        tcp::TcpError::Closed => "connection closed",
+    }
+}
+
+pub fn http_error_text(e: http::HttpError) -> String {
+    match e {
+        http::HttpError::Dns => String::from("cannot resolve host name"),
+        http::HttpError::Tcp(t) => String::from(tcp_error_text(t)),
+        http::HttpError::Tls(t) => format!("TLS: {}", tls_error_text(t)),
+        http::HttpError::Parse => String::from("malformed URL or HTTP response"),
+        http::HttpError::RedirectLoop => format!("more than {} redirects", http::MAX_REDIRECTS),
+        http::HttpError::UnsupportedScheme => String::from("only http:// and https:// URLs are supported"),
+        http::HttpError::ProxyMethod => String::from("the HTTPS proxy only does GET (tls proxy off)"),
+        http::HttpError::Decode => String::from("compressed body failed to decode"),
+    }
+}
+
+/// `tls` status: what the client offers and where `https://` goes.
+pub fn tls_report() -> String {
+    let mut out = String::new();
+    out.push_str("TLS 1.3 client: x25519, ");
+    out.push_str(tls::suite_name(tls::TLS_AES_128_GCM_SHA256));
+    out.push_str(", ");
+    out.push_str(tls::suite_name(tls::TLS_CHACHA20_POLY1305_SHA256));
+    out.push_str(&format!(
+        "\nTrusted roots:  {} built in, {} added",
+        x509::builtin_root_count(),
+        x509::extra_root_count()
+    ));
+    out.push_str(if http::https_proxy_enabled() {
+        "\nhttps:// via:   host proxy at 10.0.2.2:8000"
+    } else {
+        "\nhttps:// via:   native TLS"
+    });
+    out
+}
+
+/// `tls connect`: handshake with `host:port` and describe the result.
+pub fn tls_connect_report(host: &str, port: u16) -> String {
+    let ip = match ip::parse(host) {
+        Some(a) => a,
+        None => match dns::resolve_a(host) {
+            Ok(a) => ip::IpAddr::V4(a),
+            Err(_) => return format!("tls: cannot resolve {}", host),
+        },
+    };
+    let mut s = match tls::TlsStream::connect(ip, port, host) {
+        Ok(s) => s,
+        Err(e) => return format!("tls: {}:{}: {}", host, port, tls_error_text(e)),
+    };
+    let mut out = format!("Connected to {}:{}", host, port);
+    if let Some(i) = s.info() {
+        let sig = match i.sig_scheme {
+            0x0403 => "ecdsa_secp256r1_sha256",
+            0x0503 => "ecdsa_secp384r1_sha384",
+            0x0804 => "rsa_pss_rsae_sha256",
+            0x0805 => "rsa_pss_rsae_sha384",
+            0x0806 => "rsa_pss_rsae_sha512",
+            _ => "?",
+        };
+        let na = i.not_after;
+        out.push_str(&format!("\n  cipher:      {} (x25519)", tls::suite_name(i.suite)));
+        out.push_str(&format!("\n  signature:   {}", sig));
+        out.push_str(&format!("\n  subject:     {}", i.subject));
+        out.push_str(&format!("\n  issuer:      {}", i.issuer));
+        out.push_str(&format!("\n  root:        {} ({} certificate(s) below it)", i.root, i.chain_len));
+        out.push_str(&format!(
+            "\n  expires:     {:04}-{:02}-{:02} {:02}:{:02} UTC",
+            na / 10_000_000_000,
+            na / 100_000_000 % 100,
+            na / 1_000_000 % 100,
+            na / 10_000 % 100,
+            na / 100 % 100
+        ));
+    }
+    let _ = s.close();
+    out
+}
+
+/// `tls roots`: subject of every trusted root.
+pub fn tls_roots_report() -> String {
+    let mut out = String::new();
+    for r in x509::roots().iter() {
+        if !out.is_empty() { out.push('\n'); }
+        out.push_str(&r.subject_name());
+    }
+    out
+}
+
+fn ntp_error_text(e: ntp::NtpError) -> String {
+    match e {
+        ntp::NtpError::NoNic => String::from("no NIC"),
+        ntp::NtpError::NotConfigured => String::from("no IPv4 address or route"),
+        ntp::NtpError::Dns => String::from("no NTP server (DHCP gave none and the name didn't resolve)"),
+        ntp::NtpError::Timeout => String::from("no answer from any server"),
+        ntp::NtpError::Malformed => String::from("malformed reply"),
+        ntp::NtpError::KissOfDeath(code) => {
+            format!("server refused (kiss code {})", str::from_utf8(&code).unwrap_or("?"))
+        }
+        ntp::NtpError::Unsynchronized => String::from("server clock is not synchronized"),
+        ntp::NtpError::TxFail => String::from("send failed"),
+    }
+}
+
+/// Milliseconds as "+1.234 s".
+fn signed_secs(ms: i64) -> String {
+    let sign = if ms < 0 { '-' } else { '+' };
+    let a = ms.unsigned_abs();
+    format!("{}{}.{:03} s", sign, a / 1000, a % 1000)
+}
+
+fn ntp_sample_lines(out: &mut String, s: &ntp::Sample) {
+    let mut b = [0u8; 40];
+    let n = ip::write(&mut b, s.server);
+    // stratum 1 reference IDs are ASCII source names ("GPS", "PPS")
+    let refid = if s.stratum == 1 {
+        String::from(str::from_utf8(&s.ref_id).unwrap_or("?").trim_end_matches('\0'))
+    } else {
+        let mut r = [0u8; 16];
+        let m = write_ipv4(&mut r, s.ref_id);
+        String::from(str::from_utf8(&r[..m]).unwrap_or("?"))
+    };
+    out.push_str(&format!("\nServer:      {} (stratum {}, ref {})", str::from_utf8(&b[..n]).unwrap_or("?"), s.stratum, refid));
+    out.push_str(&format!("\nOffset:      {}", signed_secs(s.offset_ms)));
+    out.push_str(&format!("\nDelay:       {} ms", s.delay_ms));
+    out.push_str(&format!("\nRoot delay:  {} ms, dispersion {} ms", s.root_delay_ms, s.root_dispersion_ms));
+    if s.leap == 1 || s.leap == 2 {
+        out.push_str(&format!("\nLeap second: {} at the end of the month", if s.leap == 1 { "inserted" } else { "deleted" }));
+    }
+}
+
+/// `ntp` status: current wall time and the last sync.
+pub fn ntp_report() -> String {
+    let dt = time::now();
+    let mut out = format!(
+        "Clock:       {:04}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+        dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second,
+        if time::wall_clock_set() { "UTC (NTP)" } else { "(RTC, not synced)" }
+    );
+    match ntp::server() {
+        Some(s) => out.push_str(&format!("\nConfigured:  {}", s)),
+        None => out.push_str("\nConfigured:  DHCP, then pool.ntp.org"),
+    }
+    match ntp::last_sync() {
+        Some(l) => {
+            ntp_sample_lines(&mut out, &l.sample);
+            out.push_str(&format!(
+                "\nLast sync:   {} s ago ({} so far)",
+                time::uptime_ms().saturating_sub(l.at_ms) / 1000,
+                l.count
+            ));
+        }
+        None => out.push_str("\nLast sync:   never (run `ntp sync`)"),
+    }
+    out
+}
+
+/// `ntp sync`: query now and report what changed.
+pub fn ntp_sync_report() -> String {
+    match ntp::sync() {
+        Ok(s) => {
+            let mut out = String::from("ntp: clock set");
+            ntp_sample_lines(&mut out, &s);
+            out
+        }
+        Err(e) => format!("ntp: {}", ntp_error_text(e)),
+    }
+}
@@ -0,0 +1,155 @@
+#![allow(dead_code)]
+// src/net/arp.rs
+//
+// ARP table (IPv4 -> MAC) with aging, plus the ARP side of the receive path:
+// - answers requests for our address,
+// - learns/refreshes senders as RFC 826 describes (update if known, add if
+//   the packet was aimed at us),
+// - announces our address with a gratuitous ARP when it is assigned.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::demux::DropReason;
+use crate::fs::SpinLock;
+use crate::time;
+
+/// Entries not refreshed for this long are dropped on lookup.
+pub const ARP_TIMEOUT_SECS: u64 = 120;
+const MAX_ENTRIES: usize = 64;
+
+const OP_REQUEST: u16 = 1;
+const OP_REPLY: u16 = 2;
+
+#[derive(Copy, Clone)]
+pub struct ArpEntry {
+    pub ip: [u8; 4],
+    pub mac: [u8; 6],
+    /// `time::uptime_secs()` when the entry was last confirmed.
+    pub updated: u64,
+}
+
+static TABLE: SpinLock<Vec<ArpEntry>> = SpinLock::new(Vec::new());
+
+fn expired(e: &ArpEntry, now: u64) -> bool {
+    now.saturating_sub(e.updated) >= ARP_TIMEOUT_SECS
+}
+
+/// MAC for `ip` if we have a fresh entry.
+pub fn lookup(ip: [u8; 4]) -> Option<[u8; 6]> {
+    let now = time::uptime_secs();
+    let mut t = TABLE.lock();
+    t.retain(|e| !expired(e, now));
+    t.iter().find(|e| e.ip == ip).map(|e| e.mac)
+}
+
+/// Add or refresh an entry. When the table is full the stalest entry goes.
+pub fn insert(ip: [u8; 4], mac: [u8; 6]) {
+    let now = time::uptime_secs();
+    let mut t = TABLE.lock();
+    if let Some(e) = t.iter_mut().find(|e| e.ip == ip) {
+        e.mac = mac;
+        e.updated = now;
+        return;
+    }
+    if t.len() >= MAX_ENTRIES {
+        if let Some(oldest) = t.iter().enumerate().min_by_key(|(_, e)| e.updated).map(|(i, _)| i) {
+            t.swap_remove(oldest);
+        }
+    }
+    t.push(ArpEntry { ip, mac, updated: now });
+}
+
+/// Refresh an entry only if it already exists (returns whether it did).
+fn update(ip: [u8; 4], mac: [u8; 6]) -> bool {
+    let now = time::uptime_secs();
+    let mut t = TABLE.lock();
+    match t.iter_mut().find(|e| e.ip == ip) {
+        Some(e) => {
+            e.mac = mac;
+            e.updated = now;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Unexpired entries.
+pub fn entries() -> Vec<ArpEntry> {
+    let now = time::uptime_secs();
+    let mut t = TABLE.lock();
+    t.retain(|e| !expired(e, now));
+    t.clone()
+}
+
+pub fn flush() {
+    TABLE.lock().clear();
+}
+
+fn build(op: u16, sha: [u8; 6], spa: [u8; 4], tha: [u8; 6], tpa: [u8; 4]) -> [u8; 28] {
+    let mut arp = [0u8; 28];
+    arp[0..2].copy_from_slice(&1u16.to_be_bytes());      // htype ethernet
+    arp[2..4].copy_from_slice(&0x0800u16.to_be_bytes()); // ptype ipv4
+    arp[4] = 6; // hlen
+    arp[5] = 4; // plen
+    arp[6..8].copy_from_slice(&op.to_be_bytes());
+    arp[8..14].copy_from_slice(&sha);
+    arp[14..18].copy_from_slice(&spa);
+    arp[18..24].copy_from_slice(&tha);
+    arp[24..28].copy_from_slice(&tpa);
+    arp
+}
+
+/// Broadcast a who-has for `target_ip`.
+pub fn send_request(target_ip: [u8; 4], our_mac: [u8; 6], our_ip: [u8; 4]) -> bool {
+    let arp = build(OP_REQUEST, our_mac, our_ip, [0; 6], target_ip);
+    super::send_frame([0xFF; 6], 0x0806, &arp)
+}
+
+/// Gratuitous ARP: a broadcast request for our own address, so neighbours
+/// refresh their caches (and anyone else using it notices).
+pub fn announce(our_mac: [u8; 6], our_ip: [u8; 4]) -> bool {
+    if our_ip == [0, 0, 0, 0] { return false; }
+    let arp = build(OP_REQUEST, our_mac, our_ip, [0; 6], our_ip);
+    super::send_frame([0xFF; 6], 0x0806, &arp)
+}
+
+/// ARP packet from the demultiplexer (Ethernet header already stripped).
+pub fn input(arp: &[u8]) -> Result<(), DropReason> {
+    if arp.len() < 28 { return Err(DropReason::Malformed); }
+    let htype = u16::from_be_bytes([arp[0], arp[1]]);
+    let ptype = u16::from_be_bytes([arp[2], arp[3]]);
+    if htype != 1 || ptype != 0x0800 || arp[4] != 6 || arp[5] != 4 {
+        return Err(DropReason::Unsupported);
+    }
+    let op = u16::from_be_bytes([arp[6], arp[7]]);
+    let sha = [arp[8], arp[9], arp[10], arp[11], arp[12], arp[13]];
+    let spa = [arp[14], arp[15], arp[16], arp[17]];
+    let tpa = [arp[24], arp[25], arp[26], arp[27]];
+
+    let cfg = super::config();
+    let for_us = cfg.ip != [0, 0, 0, 0] && tpa == cfg.ip;
+    // someone else claiming our address: don't cache ourselves
+    if cfg.ip != [0, 0, 0, 0] && spa == cfg.ip { return Err(DropReason::Unsupported); }
+
+    // RFC 826: refresh a known sender regardless of target, add it if the
+    // packet was meant for us (covers gratuitous ARP from known hosts too)
+    let known = spa != [0, 0, 0, 0] && update(spa, sha);
+    if !for_us {
+        return if known { Ok(()) } else { Err(DropReason::NotForUs) };
+    }
+    if !known && spa != [0, 0, 0, 0] {
+        insert(spa, sha);
+    }
+
+    match op {
+        OP_REQUEST => {
+            let reply = build(OP_REPLY, cfg.mac, cfg.ip, sha, spa);
+            super::send_frame(sha, 0x0806, &reply);
+            Ok(())
+        }
+        OP_REPLY => Ok(()),
+        _ => Err(DropReason::Unsupported),
+    }
+}
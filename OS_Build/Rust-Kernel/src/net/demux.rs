@@ -0,0 +1,522 @@
+#![allow(dead_code)]
+// src/net/demux.rs
+//
+// Single receive path: `net::poll` hands every frame from every interface to
+// `input`, which parses Ethernet/IPv4/IPv6 once and dispatches to ARP, ICMP,
+// ICMPv6 (echo, NDP), UDP or TCP. ICMP and UDP payloads are queued per socket (echo ident, UDP port);
+// TCP segments go straight to the connection table in tcp.rs, which buffers
+// per connection. Concurrent flows no longer steal each other's frames.
+//
+// Every frame that isn't delivered is counted by layer and reason.
+
+extern crate alloc;
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+
+use super::ip::IpAddr;
+use super::{ipv4, ipv6};
+use crate::fs::SpinLock;
+
+/// Per-socket queue depth; further packets are dropped as `QueueFull`.
+const MAX_QUEUE: usize = 128;
+
+// -----------------------------------------------------------------------------
+// Stats
+// -----------------------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layer {
+    Eth,
+    Arp,
+    Ipv4,
+    Icmp,
+    Ipv6,
+    Icmpv6,
+    Udp,
+    Tcp,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DropReason {
+    Malformed,
+    Unsupported,
+    NotForUs,
+    NoSocket,
+    QueueFull,
+}
+
+pub const LAYERS: [Layer; 8] = [
+    Layer::Eth,
+    Layer::Arp,
+    Layer::Ipv4,
+    Layer::Icmp,
+    Layer::Ipv6,
+    Layer::Icmpv6,
+    Layer::Udp,
+    Layer::Tcp,
+];
+pub const REASONS: [DropReason; 5] = [
+    DropReason::Malformed,
+    DropReason::Unsupported,
+    DropReason::NotForUs,
+    DropReason::NoSocket,
+    DropReason::QueueFull,
+];
+
+#[derive(Copy, Clone)]
+pub struct DemuxStats {
+    /// Frames accepted at each layer.
+    pub delivered: [u32; 8],
+    /// Frames dropped, indexed by layer then reason.
+    pub dropped: [[u32; 5]; 8],
+}
+
+impl DemuxStats {
+    const fn new() -> Self {
+        Self { delivered: [0; 8], dropped: [[0; 5]; 8] }
+    }
+
+    pub fn dropped_total(&self) -> u32 {
+        self.dropped.iter().flatten().fold(0u32, |a, b| a.wrapping_add(*b))
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Queued packets
+// -----------------------------------------------------------------------------
+
+pub struct IcmpMessage {
+    pub src_ip: IpAddr,
+    /// TTL or hop limit.
+    pub ttl: u8,
+    /// Whole ICMP message (header included).
+    pub data: Vec<u8>,
+}
+
+pub struct UdpDatagram {
+    pub src_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_ip: IpAddr,
+    pub data: Vec<u8>,
+}
+
+// -----------------------------------------------------------------------------
+// Socket table
+// -----------------------------------------------------------------------------
+
+struct Demux {
+    icmp: BTreeMap<u16, VecDeque<IcmpMessage>>,
+    udp: BTreeMap<u16, VecDeque<UdpDatagram>>,
+    stats: DemuxStats,
+}
+
+static DEMUX: SpinLock<Demux> = SpinLock::new(Demux {
+    icmp: BTreeMap::new(),
+    udp: BTreeMap::new(),
+    stats: DemuxStats::new(),
+});
+
+fn layer_idx(l: Layer) -> usize {
+    LAYERS.iter().position(|x| *x == l).unwrap_or(0)
+}
+
+fn reason_idx(r: DropReason) -> usize {
+    REASONS.iter().position(|x| *x == r).unwrap_or(0)
+}
+
+fn drop_at(d: &mut Demux, l: Layer, r: DropReason) {
+    let c = &mut d.stats.dropped[layer_idx(l)][reason_idx(r)];
+    *c = c.wrapping_add(1);
+}
+
+fn deliver_at(d: &mut Demux, l: Layer) {
+    let c = &mut d.stats.delivered[layer_idx(l)];
+    *c = c.wrapping_add(1);
+}
+
+/// Count a drop from outside the demultiplexer (e.g. a protocol handler).
+pub fn count_drop(l: Layer, r: DropReason) {
+    drop_at(&mut DEMUX.lock(), l, r);
+}
+
+pub fn stats() -> DemuxStats {
+    DEMUX.lock().stats
+}
+
+fn push<T>(q: &mut VecDeque<T>, item: T) -> bool {
+    if q.len() >= MAX_QUEUE { return false; }
+    q.push_back(item);
+    true
+}
+
+// Receive handles: the queue lives as long as the handle.
+
+pub struct IcmpQueue { ident: u16 }
+pub struct UdpQueue { port: u16 }
+
+/// Queue ICMP echo replies (and errors quoting our echoes) for `ident`.
+pub fn icmp_open(ident: u16) -> Option<IcmpQueue> {
+    let mut d = DEMUX.lock();
+    if d.icmp.contains_key(&ident) { return None; }
+    d.icmp.insert(ident, VecDeque::new());
+    Some(IcmpQueue { ident })
+}
+
+pub fn udp_open(port: u16) -> Option<UdpQueue> {
+    let mut d = DEMUX.lock();
+    if d.udp.contains_key(&port) { return None; }
+    d.udp.insert(port, VecDeque::new());
+    Some(UdpQueue { port })
+}
+
+pub fn udp_port_in_use(port: u16) -> bool {
+    DEMUX.lock().udp.contains_key(&port)
+}
+
+impl IcmpQueue {
+    pub fn ident(&self) -> u16 { self.ident }
+    pub fn recv(&self) -> Option<IcmpMessage> {
+        DEMUX.lock().icmp.get_mut(&self.ident)?.pop_front()
+    }
+}
+
+impl UdpQueue {
+    pub fn port(&self) -> u16 { self.port }
+    pub fn recv(&self) -> Option<UdpDatagram> {
+        DEMUX.lock().udp.get_mut(&self.port)?.pop_front()
+    }
+}
+
+impl Drop for IcmpQueue {
+    fn drop(&mut self) { DEMUX.lock().icmp.remove(&self.ident); }
+}
+
+impl Drop for UdpQueue {
+    fn drop(&mut self) { DEMUX.lock().udp.remove(&self.port); }
+}
+
+// -----------------------------------------------------------------------------
+// Input path
+// -----------------------------------------------------------------------------
+
+/// Parse and dispatch one received Ethernet frame.
+pub fn input(frame: &[u8], lo: bool) {
+    let mut d = DEMUX.lock();
+
+    if frame.len() < 14 {
+        drop_at(&mut d, Layer::Eth, DropReason::Malformed);
+        return;
+    }
+
+    let our_mac = super::config().mac;
+    let dst_mac = &frame[0..6];
+    // unicast to someone else (group bit clear, not our address)
+    if !lo && (dst_mac[0] & 1) == 0 && dst_mac != our_mac {
+        drop_at(&mut d, Layer::Eth, DropReason::NotForUs);
+        return;
+    }
+
+    let mut l2 = 14usize;
+    let mut ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+    if ethertype == 0x8100 && frame.len() >= 18 {
+        ethertype = u16::from_be_bytes([frame[16], frame[17]]);
+        l2 = 18;
+    }
+    let mut src_mac = [0u8; 6];
+    src_mac.copy_from_slice(&frame[6..12]);
+
+    match ethertype {
+        0x0806 => {
+            deliver_at(&mut d, Layer::Eth);
+            drop(d);
+            let r = super::arp::input(&frame[l2..]);
+            let mut d = DEMUX.lock();
+            match r {
+                Ok(()) => deliver_at(&mut d, Layer::Arp),
+                Err(e) => drop_at(&mut d, Layer::Arp, e),
+            }
+        }
+        0x0800 => {
+            deliver_at(&mut d, Layer::Eth);
+            ipv4_input(d, src_mac, &frame[l2..], lo);
+        }
+        0x86DD => {
+            deliver_at(&mut d, Layer::Eth);
+            ipv6_input(d, &frame[l2..], lo);
+        }
+        _ => drop_at(&mut d, Layer::Eth, DropReason::Unsupported),
+    }
+}
+
+fn ipv4_input(mut d: crate::fs::SpinGuard<'_, Demux>, src_mac: [u8; 6], pkt: &[u8], lo: bool) {
+    if pkt.len() < 20 || (pkt[0] >> 4) != 4 {
+        drop_at(&mut d, Layer::Ipv4, DropReason::Malformed);
+        return;
+    }
+    let ihl = ((pkt[0] & 0x0F) as usize) * 4;
+    let total = u16::from_be_bytes([pkt[2], pkt[3]]) as usize;
+    if ihl < 20 || total < ihl || total > pkt.len() || super::checksum16(&pkt[..ihl]) != 0 {
+        drop_at(&mut d, Layer::Ipv4, DropReason::Malformed);
+        return;
+    }
+
+    let src = [pkt[12], pkt[13], pkt[14], pkt[15]];
+    let dst = [pkt[16], pkt[17], pkt[18], pkt[19]];
+    let cfg = super::config();
+    let bcast = [
+        cfg.ip[0] | !cfg.mask[0],
+        cfg.ip[1] | !cfg.mask[1],
+        cfg.ip[2] | !cfg.mask[2],
+        cfg.ip[3] | !cfg.mask[3],
+    ];
+    // while unconfigured (DHCP in progress) anything addressed to this MAC is
+    // ours; everything on `lo` is, and 127/8 never comes in from a NIC
+    let for_us = if lo {
+        true
+    } else {
+        !ipv4::is_loopback(src) && !ipv4::is_loopback(dst)
+            && (cfg.ip == [0, 0, 0, 0] || dst == cfg.ip || dst == [255, 255, 255, 255] || dst == bcast)
+    };
+    if !for_us {
+        drop_at(&mut d, Layer::Ipv4, DropReason::NotForUs);
+        return;
+    }
+
+    // MF set or non-zero offset: hold it until the datagram is complete
+    let frag = u16::from_be_bytes([pkt[6], pkt[7]]);
+    let more = (frag & 0x2000) != 0;
+    let offset = ((frag & 0x1FFF) as usize) * 8;
+    let whole;
+    let body = if more || offset != 0 {
+        let id = u16::from_be_bytes([pkt[4], pkt[5]]);
+        match ipv4::reassemble(src, dst, pkt[9], id, offset, more, &pkt[ihl..total]) {
+            Ok(Some(v)) => {
+                whole = v;
+                &whole[..]
+            }
+            Ok(None) => return,
+            Err(r) => {
+                drop_at(&mut d, Layer::Ipv4, r);
+                return;
+            }
+        }
+    } else {
+        &pkt[ihl..total]
+    };
+
+    deliver_at(&mut d, Layer::Ipv4);
+    let ttl = pkt[8];
+
+    match pkt[9] {
+        1 => icmp_input(d, src_mac, src, dst, ttl, body, lo),
+        17 => udp_input(d, IpAddr::V4(src), IpAddr::V4(dst), body),
+        6 => tcp_input(d, IpAddr::V4(src), IpAddr::V4(dst), body),
+        _ => drop_at(&mut d, Layer::Ipv4, DropReason::Unsupported),
+    }
+}
+
+fn ipv6_input(mut d: crate::fs::SpinGuard<'_, Demux>, pkt: &[u8], lo: bool) {
+    if pkt.len() < 40 || (pkt[0] >> 4) != 6 {
+        drop_at(&mut d, Layer::Ipv6, DropReason::Malformed);
+        return;
+    }
+    let end = 40 + u16::from_be_bytes([pkt[4], pkt[5]]) as usize;
+    if end > pkt.len() {
+        drop_at(&mut d, Layer::Ipv6, DropReason::Malformed);
+        return;
+    }
+    let mut src = [0u8; 16];
+    src.copy_from_slice(&pkt[8..24]);
+    let mut dst = [0u8; 16];
+    dst.copy_from_slice(&pkt[24..40]);
+    let for_us = if lo { true } else { src != ipv6::LOOPBACK && dst != ipv6::LOOPBACK && ipv6::accepts(dst) };
+    if !for_us {
+        drop_at(&mut d, Layer::Ipv6, DropReason::NotForUs);
+        return;
+    }
+
+    // skip hop-by-hop, routing and destination options headers
+    let mut next = pkt[6];
+    let mut off = 40usize;
+    loop {
+        match next {
+            0 | 43 | 60 => {
+                if off + 8 > end {
+                    drop_at(&mut d, Layer::Ipv6, DropReason::Malformed);
+                    return;
+                }
+                next = pkt[off];
+                off += (pkt[off + 1] as usize + 1) * 8;
+                if off > end {
+                    drop_at(&mut d, Layer::Ipv6, DropReason::Malformed);
+                    return;
+                }
+            }
+            // fragment header: no IPv6 reassembly
+            44 => {
+                drop_at(&mut d, Layer::Ipv6, DropReason::Unsupported);
+                return;
+            }
+            _ => break,
+        }
+    }
+
+    deliver_at(&mut d, Layer::Ipv6);
+    let hop_limit = pkt[7];
+    let body = &pkt[off..end];
+
+    match next {
+        58 => icmpv6_input(d, src, dst, hop_limit, body, lo),
+        17 => udp_input(d, IpAddr::V6(src), IpAddr::V6(dst), body),
+        6 => tcp_input(d, IpAddr::V6(src), IpAddr::V6(dst), body),
+        _ => drop_at(&mut d, Layer::Ipv6, DropReason::Unsupported),
+    }
+}
+
+fn icmp_input(mut d: crate::fs::SpinGuard<'_, Demux>, src_mac: [u8; 6], src: [u8; 4], dst: [u8; 4], ttl: u8, msg: &[u8], lo: bool) {
+    if msg.len() < 8 || super::checksum16(msg) != 0 {
+        drop_at(&mut d, Layer::Icmp, DropReason::Malformed);
+        return;
+    }
+
+    let ident = match msg[0] {
+        0 => Some(u16::from_be_bytes([msg[4], msg[5]])),
+        // destination unreachable / time exceeded quoting one of our echoes
+        3 | 11 => {
+            let inner = &msg[8..];
+            if inner.len() >= 20 && inner[9] == 1 {
+                let ihl = ((inner[0] & 0x0F) as usize) * 4;
+                if inner.len() >= ihl + 8 && inner[ihl] == 8 {
+                    Some(u16::from_be_bytes([inner[ihl + 4], inner[ihl + 5]]))
+                } else {
+                    None
+                }
+            } else {
+                None
+            }
+        }
+        8 => {
+            // echo request: answer it (only when addressed to us directly)
+            deliver_at(&mut d, Layer::Icmp);
+            drop(d);
+            if lo || dst == super::config().ip {
+                super::send_echo_reply(src_mac, dst, src, msg);
+            }
+            return;
+        }
+        _ => None,
+    };
+
+    let Some(ident) = ident else {
+        drop_at(&mut d, Layer::Icmp, DropReason::Unsupported);
+        return;
+    };
+    let item = IcmpMessage { src_ip: IpAddr::V4(src), ttl, data: msg.to_vec() };
+    let ok = match d.icmp.get_mut(&ident) {
+        None => { drop_at(&mut d, Layer::Icmp, DropReason::NoSocket); return; }
+        Some(q) => push(q, item),
+    };
+    if ok { deliver_at(&mut d, Layer::Icmp) } else { drop_at(&mut d, Layer::Icmp, DropReason::QueueFull) }
+}
+
+fn icmpv6_input(mut d: crate::fs::SpinGuard<'_, Demux>, src: [u8; 16], dst: [u8; 16], hop_limit: u8, msg: &[u8], lo: bool) {
+    if msg.len() < 8 || ipv6::pseudo_checksum(src, dst, ipv6::PROTO_ICMPV6, msg) != 0 {
+        drop_at(&mut d, Layer::Icmpv6, DropReason::Malformed);
+        return;
+    }
+
+    let ident = match msg[0] {
+        129 => Some(u16::from_be_bytes([msg[4], msg[5]])),
+        // destination unreachable / time exceeded quoting one of our echoes
+        1 | 3 => {
+            let inner = &msg[8..];
+            if inner.len() >= 48 && inner[6] == ipv6::PROTO_ICMPV6 && inner[40] == 128 {
+                Some(u16::from_be_bytes([inner[44], inner[45]]))
+            } else {
+                None
+            }
+        }
+        128 => {
+            // echo request: answer it (only when addressed to us directly)
+            deliver_at(&mut d, Layer::Icmpv6);
+            drop(d);
+            if lo || ipv6::is_ours(dst) {
+                ipv6::send_echo_reply(dst, src, msg);
+            }
+            return;
+        }
+        133..=137 => {
+            drop(d);
+            let r = super::ndp::input(src, dst, hop_limit, msg);
+            let mut d = DEMUX.lock();
+            match r {
+                Ok(()) => deliver_at(&mut d, Layer::Icmpv6),
+                Err(e) => drop_at(&mut d, Layer::Icmpv6, e),
+            }
+            return;
+        }
+        _ => None,
+    };
+
+    let Some(ident) = ident else {
+        drop_at(&mut d, Layer::Icmpv6, DropReason::Unsupported);
+        return;
+    };
+    let item = IcmpMessage { src_ip: IpAddr::V6(src), ttl: hop_limit, data: msg.to_vec() };
+    let ok = match d.icmp.get_mut(&ident) {
+        None => { drop_at(&mut d, Layer::Icmpv6, DropReason::NoSocket); return; }
+        Some(q) => push(q, item),
+    };
+    if ok { deliver_at(&mut d, Layer::Icmpv6) } else { drop_at(&mut d, Layer::Icmpv6, DropReason::QueueFull) }
+}
+
+fn udp_input(mut d: crate::fs::SpinGuard<'_, Demux>, src: IpAddr, dst: IpAddr, dgram: &[u8]) {
+    if dgram.len() < 8 {
+        drop_at(&mut d, Layer::Udp, DropReason::Malformed);
+        return;
+    }
+    let sport = u16::from_be_bytes([dgram[0], dgram[1]]);
+    let dport = u16::from_be_bytes([dgram[2], dgram[3]]);
+    let ulen = u16::from_be_bytes([dgram[4], dgram[5]]) as usize;
+    if ulen < 8 || ulen > dgram.len() {
+        drop_at(&mut d, Layer::Udp, DropReason::Malformed);
+        return;
+    }
+    // a zero checksum field means the sender didn't compute one (IPv4 only;
+    // it is mandatory over IPv6)
+    let csum = u16::from_be_bytes([dgram[6], dgram[7]]);
+    let unchecked = csum == 0 && !src.is_v6();
+    if !unchecked && super::ip::pseudo_checksum(src, dst, super::ipv4::PROTO_UDP, &dgram[..ulen]) != 0 {
+        drop_at(&mut d, Layer::Udp, DropReason::Malformed);
+        return;
+    }
+
+    let item = UdpDatagram { src_ip: src, src_port: sport, dst_ip: dst, data: dgram[8..ulen].to_vec() };
+    let ok = match d.udp.get_mut(&dport) {
+        None => { drop_at(&mut d, Layer::Udp, DropReason::NoSocket); return; }
+        Some(q) => push(q, item),
+    };
+    if ok { deliver_at(&mut d, Layer::Udp) } else { drop_at(&mut d, Layer::Udp, DropReason::QueueFull) }
+}
+
+fn tcp_input(mut d: crate::fs::SpinGuard<'_, Demux>, src: IpAddr, dst: IpAddr, seg: &[u8]) {
+    if seg.len() < 20 {
+        drop_at(&mut d, Layer::Tcp, DropReason::Malformed);
+        return;
+    }
+    let data_off = ((seg[12] >> 4) as usize) * 4;
+    if data_off < 20 || data_off > seg.len() {
+        drop_at(&mut d, Layer::Tcp, DropReason::Malformed);
+        return;
+    }
+
+    // the state machine may transmit (ACK, RST, SYN/ACK): run it unlocked
+    drop(d);
+    let r = super::tcp::input(src, dst, seg);
+    let mut d = DEMUX.lock();
+    match r {
+        Ok(()) => deliver_at(&mut d, Layer::Tcp),
+        Err(e) => drop_at(&mut d, Layer::Tcp, e),
+    }
+}
@@ -0,0 +1,602 @@
+#![allow(dead_code)]
+// src/net/dhcp.rs
+//
+// DHCP client (RFC 2131) for the primary interface:
+//
+//   INIT -> SELECTING -> REQUESTING -> BOUND -> RENEWING (T1) -> REBINDING (T2)
+//   INIT-REBOOT -> REBOOTING -> BOUND          (ask for the last address first)
+//
+// `acquire` runs the blocking part up to BOUND, retransmitting DISCOVER and
+// REQUEST with exponential backoff. After that `tick`, called
+// from `net::poll`, renews at T1 (unicast to the server) and rebinds at T2
+// (broadcast), and drops the address if the lease runs out. It never polls,
+// so it only uses `try_send_to` and `recv_queued`. `release` hands the lease
+// back (the `shutdown` command does this).
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::ip::IpAddr;
+use super::ipv4;
+use super::udp::{UdpError, UdpSocket};
+use super::DhcpError;
+use crate::fs::SpinLock;
+use crate::{rng, time};
+
+const CLIENT_PORT: u16 = 68;
+const SERVER_PORT: u16 = 67;
+
+/// First wait for a server reply; doubled on each retransmission up to
+/// BACKOFF_MAX_MS, with +/- 1 s of jitter (RFC 2131 4.1).
+const REPLY_TIMEOUT_MS: u64 = 4_000;
+const BACKOFF_MAX_MS: u64 = 64_000;
+/// DISCOVER / REQUEST attempts while acquiring: 4 + 8 + 16 + 32 s.
+const MAX_TRIES: u32 = 4;
+/// INIT-REBOOT and `dhcp renew` give up sooner; the first falls back to
+/// DISCOVER, the second keeps the lease running.
+const SHORT_TRIES: u32 = 2;
+/// Least time between retransmissions while renewing/rebinding (RFC 2131 4.4.5).
+const RETRY_MIN_MS: u64 = 60_000;
+const INFINITE: u32 = 0xFFFF_FFFF;
+
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+const NAK: u8 = 6;
+const RELEASE: u8 = 7;
+
+const OPT_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_HOSTNAME: u8 = 12;
+const OPT_DOMAIN: u8 = 15;
+const OPT_MTU: u8 = 26;
+const OPT_NTP: u8 = 42;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE: u8 = 51;
+const OPT_MSG_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_PARAMS: u8 = 55;
+const OPT_T1: u8 = 58;
+const OPT_T2: u8 = 59;
+const OPT_CLIENT_ID: u8 = 61;
+const OPT_SEARCH: u8 = 119;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DhcpState {
+    Init,
+    Selecting,
+    Requesting,
+    InitReboot,
+    Rebooting,
+    Bound,
+    Renewing,
+    Rebinding,
+}
+
+impl DhcpState {
+    pub fn name(self) -> &'static str {
+        match self {
+            DhcpState::Init => "INIT",
+            DhcpState::Selecting => "SELECTING",
+            DhcpState::Requesting => "REQUESTING",
+            DhcpState::InitReboot => "INIT-REBOOT",
+            DhcpState::Rebooting => "REBOOTING",
+            DhcpState::Bound => "BOUND",
+            DhcpState::Renewing => "RENEWING",
+            DhcpState::Rebinding => "REBINDING",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Lease {
+    pub ip: [u8; 4],
+    pub mask: [u8; 4],
+    pub router: [u8; 4],
+    pub server_id: [u8; 4],
+    pub dns: Vec<[u8; 4]>,
+    pub ntp: Vec<[u8; 4]>,
+    pub domain: String,
+    pub search: Vec<String>,
+    pub hostname: String,
+    /// Interface MTU (option 26); 0 when not given.
+    pub mtu: u16,
+    /// Seconds; `0xFFFF_FFFF` = infinite.
+    pub lease_secs: u32,
+    pub t1_secs: u32,
+    pub t2_secs: u32,
+    /// `time::uptime_ms()` when the lease was (re)acknowledged.
+    pub obtained_ms: u64,
+}
+
+impl Lease {
+    fn at(&self, secs: u32) -> u64 {
+        self.obtained_ms + secs as u64 * 1000
+    }
+}
+
+struct Client {
+    state: DhcpState,
+    lease: Option<Lease>,
+    /// Address to ask for in INIT-REBOOT.
+    last_ip: [u8; 4],
+    xid: u32,
+    /// Port 68, held while renewing or rebinding.
+    sock: Option<UdpSocket>,
+    next_tx: u64,
+}
+
+static CLIENT: SpinLock<Client> = SpinLock::new(Client {
+    state: DhcpState::Init,
+    lease: None,
+    last_ip: [0; 4],
+    xid: 0,
+    sock: None,
+    next_tx: 0,
+});
+
+pub fn state() -> DhcpState {
+    CLIENT.lock().state
+}
+
+pub fn lease() -> Option<Lease> {
+    CLIENT.lock().lease.clone()
+}
+
+fn new_xid() -> u32 {
+    (time::rdtsc() as u32) ^ 0xA5A5_1234
+}
+
+fn set_state(s: DhcpState) {
+    CLIENT.lock().state = s;
+}
+
+fn open_socket() -> Result<UdpSocket, DhcpError> {
+    let mut sock = UdpSocket::bind(CLIENT_PORT).map_err(|e| match e {
+        UdpError::AddrInUse => DhcpError::Busy,
+        _ => DhcpError::NoNic,
+    })?;
+    sock.set_broadcast(true);
+    sock.set_read_timeout(Some(REPLY_TIMEOUT_MS));
+    Ok(sock)
+}
+
+/// Run DHCP until bound: INIT-REBOOT with the last address if there is one
+/// (falling back to INIT on NAK or silence), otherwise DISCOVER/OFFER/
+/// REQUEST/ACK.
+pub fn acquire() -> Result<(), DhcpError> {
+    super::init();
+    if !super::have_nic() { return Err(DhcpError::NoNic); }
+
+    // stop background renewal (it holds port 68) and forget the address
+    let last = {
+        let mut c = CLIENT.lock();
+        c.sock = None;
+        c.state = DhcpState::Init;
+        if let Some(l) = c.lease.take() { c.last_ip = l.ip; }
+        c.last_ip
+    };
+    super::clear_ipv4_config();
+
+    let mut sock = open_socket()?;
+    let mac = super::config().mac;
+
+    if last != [0, 0, 0, 0] {
+        set_state(DhcpState::InitReboot);
+        let xid = new_xid();
+        let msg = build(mac, xid, REQUEST, [0; 4], Some(last), None);
+        set_state(DhcpState::Rebooting);
+        match exchange(&mut sock, &msg, ipv4::BROADCAST, xid, ACK, SHORT_TRIES) {
+            Ok(ack) if ack.msg_type == ACK => {
+                bind(ack, xid);
+                return Ok(());
+            }
+            // NAK (wrong network now) or no answer: start over
+            _ => {}
+        }
+    }
+
+    set_state(DhcpState::Selecting);
+    let xid = new_xid();
+    let msg = build(mac, xid, DISCOVER, [0; 4], None, None);
+    let offer = match exchange(&mut sock, &msg, ipv4::BROADCAST, xid, OFFER, MAX_TRIES) {
+        Ok(o) => o,
+        Err(e) => {
+            set_state(DhcpState::Init);
+            return Err(e);
+        }
+    };
+
+    set_state(DhcpState::Requesting);
+    let msg = build(mac, xid, REQUEST, [0; 4], Some(offer.lease.ip), Some(offer.lease.server_id));
+    let ack = match exchange(&mut sock, &msg, ipv4::BROADCAST, xid, ACK, MAX_TRIES) {
+        Ok(a) => a,
+        Err(e) => {
+            set_state(DhcpState::Init);
+            return Err(e);
+        }
+    };
+    if ack.msg_type == NAK {
+        set_state(DhcpState::Init);
+        return Err(DhcpError::Nack);
+    }
+    bind(ack, xid);
+    Ok(())
+}
+
+/// Renew now instead of waiting for T1 (unicast to the server, blocking).
+pub fn renew() -> Result<(), DhcpError> {
+    let (lease, xid) = {
+        let mut c = CLIENT.lock();
+        if !matches!(c.state, DhcpState::Bound | DhcpState::Renewing | DhcpState::Rebinding) {
+            return Err(DhcpError::NotBound);
+        }
+        c.sock = None;
+        c.xid = new_xid();
+        (c.lease.clone().ok_or(DhcpError::NotBound)?, c.xid)
+    };
+
+    let mut sock = open_socket()?;
+    let msg = build(super::config().mac, xid, REQUEST, lease.ip, None, None);
+    let server = if lease.server_id != [0, 0, 0, 0] { lease.server_id } else { ipv4::BROADCAST };
+    let ack = exchange(&mut sock, &msg, server, xid, ACK, SHORT_TRIES)?;
+    if ack.msg_type == NAK {
+        expire();
+        return Err(DhcpError::Nack);
+    }
+    bind(ack, xid);
+    Ok(())
+}
+
+/// Give the lease back to the server and drop the address. Returns whether
+/// there was a lease to release.
+pub fn release() -> bool {
+    let lease = {
+        let mut c = CLIENT.lock();
+        if !matches!(c.state, DhcpState::Bound | DhcpState::Renewing | DhcpState::Rebinding) {
+            return false;
+        }
+        c.sock = None;
+        c.state = DhcpState::Init;
+        let Some(l) = c.lease.take() else { return false };
+        c.last_ip = l.ip;
+        l
+    };
+
+    // sent while the address is still configured: the server wants ciaddr
+    if let Ok(sock) = open_socket() {
+        let msg = build(super::config().mac, new_xid(), RELEASE, lease.ip, None, Some(lease.server_id));
+        let _ = sock.send_to(&msg, IpAddr::V4(lease.server_id), SERVER_PORT);
+    }
+    super::clear_ipv4_config();
+    true
+}
+
+/// Stop managing the lease without telling the server (a static address
+/// was configured over it).
+pub fn forget() {
+    let mut c = CLIENT.lock();
+    c.sock = None;
+    c.state = DhcpState::Init;
+    c.lease = None;
+}
+
+/// Lease timers; run from `net::poll`.
+pub fn tick() {
+    let now = time::uptime_ms();
+    let mut c = CLIENT.lock();
+    if !matches!(c.state, DhcpState::Bound | DhcpState::Renewing | DhcpState::Rebinding) { return; }
+    let Some(l) = c.lease.as_ref() else { return };
+    if l.lease_secs == INFINITE { return; }
+    let (t1, t2, end) = (l.at(l.t1_secs), l.at(l.t2_secs), l.at(l.lease_secs));
+
+    // answers to an earlier renew/rebind request
+    let xid = c.xid;
+    let mac = super::config().mac;
+    let reply = c.sock.as_ref().and_then(|sock| {
+        let mut buf = [0u8; 1500];
+        while let Some((n, _, port)) = sock.recv_queued(&mut buf) {
+            if port != SERVER_PORT { continue; }
+            match parse(&buf[..n], xid, mac) {
+                Some(p) if p.msg_type == ACK || p.msg_type == NAK => return Some(p),
+                _ => {}
+            }
+        }
+        None
+    });
+    if let Some(p) = reply {
+        drop(c);
+        if p.msg_type == ACK { bind(p, xid) } else { expire() }
+        return;
+    }
+
+    if now >= end {
+        drop(c);
+        expire();
+        return;
+    }
+    if now >= t2 && c.state != DhcpState::Rebinding {
+        c.state = DhcpState::Rebinding;
+        c.next_tx = now;
+    } else if now >= t1 && c.state == DhcpState::Bound {
+        c.state = DhcpState::Renewing;
+        c.next_tx = now;
+        c.xid = new_xid();
+    }
+    if c.state == DhcpState::Bound || now < c.next_tx { return; }
+
+    if c.sock.is_none() {
+        // port 68 busy (someone is running `dhcp`): try again next tick
+        let Ok(sock) = open_socket() else { return };
+        c.sock = Some(sock);
+    }
+    let (ip, server) = match c.lease.as_ref() {
+        Some(l) => (l.ip, l.server_id),
+        None => return,
+    };
+    let (dst, deadline) = if c.state == DhcpState::Renewing && server != [0, 0, 0, 0] {
+        (server, t2)
+    } else {
+        (ipv4::BROADCAST, end)
+    };
+    let msg = build(mac, c.xid, REQUEST, ip, None, None);
+    if let Some(sock) = c.sock.as_ref() {
+        let _ = sock.try_send_to(&msg, IpAddr::V4(dst), SERVER_PORT);
+    }
+    // RFC 2131: wait half the time left, but at least a minute
+    c.next_tx = now + (deadline.saturating_sub(now) / 2).max(RETRY_MIN_MS);
+}
+
+/// Lease gone (ran out or NAKed): drop the address, remember it for INIT-REBOOT.
+fn expire() {
+    {
+        let mut c = CLIENT.lock();
+        c.sock = None;
+        c.state = DhcpState::Init;
+        if let Some(l) = c.lease.take() { c.last_ip = l.ip; }
+    }
+    super::clear_ipv4_config();
+}
+
+/// ACK received: install the lease.
+fn bind(ack: Parsed, xid: u32) {
+    let mut l = ack.lease;
+    l.obtained_ms = time::uptime_ms();
+    if l.lease_secs == 0 { l.lease_secs = INFINITE; }
+    if l.lease_secs != INFINITE {
+        if l.t1_secs == 0 || l.t1_secs >= l.lease_secs { l.t1_secs = l.lease_secs / 2; }
+        if l.t2_secs == 0 || l.t2_secs >= l.lease_secs || l.t2_secs < l.t1_secs {
+            l.t2_secs = (l.lease_secs as u64 * 7 / 8) as u32;
+        }
+    }
+
+    let changed = super::config().ip != l.ip;
+    super::set_dhcp_config(&l);
+    if changed {
+        let mac = super::config().mac;
+        super::arp::flush();
+        super::arp::announce(mac, l.ip);
+    }
+
+    let mut c = CLIENT.lock();
+    c.state = DhcpState::Bound;
+    c.last_ip = l.ip;
+    c.xid = xid;
+    c.sock = None;
+    c.lease = Some(l);
+}
+
+// -----------------------------------------------------------------------------
+// Messages
+// -----------------------------------------------------------------------------
+
+fn send(sock: &UdpSocket, msg: &[u8], dst: [u8; 4]) -> Result<(), DhcpError> {
+    // from 0.0.0.0:68 to 255.255.255.255:67 until we have an address
+    match sock.send_to(msg, IpAddr::V4(dst), SERVER_PORT) {
+        Ok(_) => Ok(()),
+        Err(UdpError::NoNic) => Err(DhcpError::NoNic),
+        Err(_) => Err(DhcpError::Malformed),
+    }
+}
+
+struct Parsed {
+    msg_type: u8,
+    lease: Lease,
+}
+
+/// Send `msg` and wait for the reply, retransmitting with exponential
+/// backoff up to `tries` times (same xid throughout).
+fn exchange(sock: &mut UdpSocket, msg: &[u8], dst: [u8; 4], xid: u32, want_type: u8, tries: u32) -> Result<Parsed, DhcpError> {
+    let mut timeout = REPLY_TIMEOUT_MS;
+    for _ in 0..tries {
+        send(sock, msg, dst)?;
+        let mut r = [0u8; 2];
+        rng::fill(&mut r);
+        let jitter = u16::from_le_bytes(r) as u64 % 2_001;
+        match wait(sock, xid, want_type, timeout + jitter - 1_000) {
+            Err(DhcpError::Timeout) => {}
+            other => return other,
+        }
+        timeout = (timeout * 2).min(BACKOFF_MAX_MS);
+    }
+    Err(DhcpError::Timeout)
+}
+
+fn wait(sock: &mut UdpSocket, xid: u32, want_type: u8, timeout_ms: u64) -> Result<Parsed, DhcpError> {
+    let deadline = time::uptime_ms() + timeout_ms;
+    let mac = super::config().mac;
+    let mut buf = [0u8; 1500];
+    loop {
+        let now = time::uptime_ms();
+        if now >= deadline { break; }
+        sock.set_read_timeout(Some(deadline - now));
+        let (n, _, src_port) = match sock.recv_from(&mut buf) {
+            Ok(r) => r,
+            Err(_) => break,
+        };
+        if src_port != SERVER_PORT { continue; }
+        if let Some(p) = parse(&buf[..n], xid, mac) {
+            if p.msg_type == want_type || (want_type == ACK && p.msg_type == NAK) {
+                return Ok(p);
+            }
+        }
+    }
+    Err(DhcpError::Timeout)
+}
+
+fn be32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn addrs(b: &[u8]) -> Vec<[u8; 4]> {
+    b.chunks_exact(4).map(|a| [a[0], a[1], a[2], a[3]]).collect()
+}
+
+fn text(b: &[u8]) -> String {
+    // trailing NULs are common
+    let end = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+    String::from_utf8_lossy(&b[..end]).into_owned()
+}
+
+/// Domain search list (option 119): DNS-encoded names, compression
+/// pointers relative to the start of the option data (RFC 3397).
+fn search_list(data: &[u8]) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut off = 0usize;
+    while off < data.len() {
+        let mut name = String::new();
+        let mut pos = off;
+        let mut next = None;
+        let mut jumps = 0;
+        loop {
+            let Some(&len) = data.get(pos) else { return out };
+            if len == 0 {
+                pos += 1;
+                break;
+            }
+            if len & 0xC0 == 0xC0 {
+                let Some(&lo) = data.get(pos + 1) else { return out };
+                next.get_or_insert(pos + 2);
+                pos = (((len & 0x3F) as usize) << 8) | lo as usize;
+                jumps += 1;
+                if jumps > 16 { return out; }
+                continue;
+            }
+            let l = len as usize;
+            let Some(label) = data.get(pos + 1..pos + 1 + l) else { return out };
+            if !name.is_empty() { name.push('.'); }
+            name.push_str(&String::from_utf8_lossy(label));
+            pos += 1 + l;
+        }
+        off = next.unwrap_or(pos);
+        if !name.is_empty() { out.push(name); }
+    }
+    out
+}
+
+fn parse(frame: &[u8], xid: u32, mac: [u8; 6]) -> Option<Parsed> {
+    // BOOTP fixed part (236) + magic cookie
+    if frame.len() < 240 || frame[0] != 2 { return None; }
+    if be32(&frame[4..8]) != xid { return None; }
+    // replies are broadcast: skip the ones meant for other clients
+    if frame[28..34] != mac { return None; }
+    if frame[236..240] != [99, 130, 83, 99] { return None; }
+
+    let mut msg_type = 0;
+    let mut l = Lease {
+        ip: [frame[16], frame[17], frame[18], frame[19]],
+        mask: [0; 4],
+        router: [0; 4],
+        server_id: [0; 4],
+        dns: Vec::new(),
+        ntp: Vec::new(),
+        domain: String::new(),
+        search: Vec::new(),
+        hostname: String::new(),
+        mtu: 0,
+        lease_secs: 0,
+        t1_secs: 0,
+        t2_secs: 0,
+        obtained_ms: 0,
+    };
+
+    let mut i = 240;
+    while i < frame.len() {
+        let opt = frame[i];
+        i += 1;
+        if opt == 0 { continue; }
+        if opt == 255 { break; }
+        if i >= frame.len() { break; }
+        let len = frame[i] as usize;
+        i += 1;
+        if i + len > frame.len() { break; }
+        let v = &frame[i..i + len];
+
+        match opt {
+            OPT_MSG_TYPE if len >= 1 => msg_type = v[0],
+            OPT_MASK if len == 4 => l.mask.copy_from_slice(v),
+            OPT_ROUTER if len >= 4 => l.router.copy_from_slice(&v[..4]),
+            OPT_DNS => l.dns = addrs(v),
+            OPT_NTP => l.ntp = addrs(v),
+            OPT_HOSTNAME => l.hostname = text(v),
+            OPT_DOMAIN => l.domain = text(v),
+            OPT_SEARCH => l.search = search_list(v),
+            OPT_MTU if len == 2 => l.mtu = u16::from_be_bytes([v[0], v[1]]),
+            OPT_LEASE if len == 4 => l.lease_secs = be32(v),
+            OPT_T1 if len == 4 => l.t1_secs = be32(v),
+            OPT_T2 if len == 4 => l.t2_secs = be32(v),
+            OPT_SERVER_ID if len == 4 => l.server_id.copy_from_slice(v),
+            _ => {}
+        }
+        i += len;
+    }
+
+    if msg_type == 0 { None } else { Some(Parsed { msg_type, lease: l }) }
+}
+
+/// A client message. `ciaddr` is our address when renewing, rebinding or
+/// releasing; `requested` / `server` become options 50 / 54.
+fn build(mac: [u8; 6], xid: u32, msg_type: u8, ciaddr: [u8; 4], requested: Option<[u8; 4]>, server: Option<[u8; 4]>) -> Vec<u8> {
+    let mut buf = alloc::vec![0u8; 240];
+    buf[0] = 1; // BOOTREQUEST
+    buf[1] = 1; // ethernet
+    buf[2] = 6; // mac len
+    buf[4..8].copy_from_slice(&xid.to_be_bytes());
+    // ask for broadcast replies until we can receive unicast
+    if ciaddr == [0, 0, 0, 0] {
+        buf[10..12].copy_from_slice(&0x8000u16.to_be_bytes());
+    }
+    buf[12..16].copy_from_slice(&ciaddr);
+    buf[28..34].copy_from_slice(&mac); // chaddr
+    buf[236..240].copy_from_slice(&[99, 130, 83, 99]);
+
+    push_opt(&mut buf, OPT_MSG_TYPE, &[msg_type]);
+    let mut cid = [0u8; 7];
+    cid[0] = 1;
+    cid[1..7].copy_from_slice(&mac);
+    push_opt(&mut buf, OPT_CLIENT_ID, &cid);
+    if msg_type != RELEASE {
+        push_opt(&mut buf, OPT_PARAMS, &[
+            OPT_MASK, OPT_ROUTER, OPT_DNS, OPT_HOSTNAME, OPT_DOMAIN, OPT_MTU, OPT_NTP,
+            OPT_LEASE, OPT_SERVER_ID, OPT_T1, OPT_T2, OPT_SEARCH,
+        ]);
+        push_opt(&mut buf, OPT_HOSTNAME, super::hostname().as_bytes());
+    }
+    if let Some(ip) = requested { push_opt(&mut buf, OPT_REQUESTED_IP, &ip); }
+    if let Some(id) = server { push_opt(&mut buf, OPT_SERVER_ID, &id); }
+    buf.push(255);
+
+    // some servers drop anything shorter than a BOOTP packet (300 bytes)
+    if buf.len() < 300 { buf.resize(300, 0); }
+    buf
+}
+
+fn push_opt(buf: &mut Vec<u8>, code: u8, data: &[u8]) {
+    buf.push(code);
+    buf.push(data.len() as u8);
+    buf.extend_from_slice(data);
+}
@@ -1,26 +1,128 @@
 #![allow(dead_code)]
 
-//! Minimal DNS A record resolver over UDP.
+//! DNS stub resolver (RFC 1035).
+//!
+//! - Answers are cached for their TTL; NXDOMAIN and "no such record" are
+//!   cached too, for the SOA minimum (RFC 2308).
+//! - CNAME chains are followed, asking again for the target when the server
+//!   didn't include the final records.
+//! - Every known server is tried in turn (DHCP list, static config, IPv6
+//!   RDNSS), twice round, before giving up.
+//! - A truncated UDP answer (TC bit) is asked again over TCP.
+//! - A, AAAA, MX, TXT and PTR have helpers; `lookup` takes any type.
 //!
 //! Blocking/polling implementation intended for early-boot/OS-dev use.
 
 extern crate alloc;
 
+use alloc::string::String;
 use alloc::vec::Vec;
 
+use super::ip::IpAddr;
+use super::tcp::{TcpError, TcpStream};
+use super::udp::{UdpError, UdpSocket};
+use crate::fs::SpinLock;
 use crate::time;
 
+/// Per server and attempt.
+const QUERY_TIMEOUT_MS: u64 = 2_000;
+/// Times round the server list.
+const ROUNDS: usize = 2;
+const TCP_CONNECT_SPINS: u32 = 5_000_000;
+const MAX_CNAME_HOPS: usize = 8;
+
+const CACHE_MAX: usize = 128;
+const MAX_TTL: u32 = 86_400;
+/// Negative answers without an SOA to take the TTL from.
+const NEG_TTL: u32 = 60;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_MX: u16 = 15;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+const TYPE_OPT: u16 = 41;
+
+const CLASS_IN: u16 = 1;
+
+const RCODE_FORMERR: u8 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+
+/// Advertised in the EDNS0 OPT record; larger answers arrive as IPv4 fragments.
+const EDNS_UDP_SIZE: u16 = 4096;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DnsError {
     NoNic,
     NotConfigured,
     Timeout,
     Malformed,
+    /// The name exists but has no record of the asked type.
     NoAnswer,
+    /// The name doesn't exist.
+    NxDomain,
+    /// Every server answered SERVFAIL/REFUSED.
+    ServerFailure,
+    /// CNAME chain longer than `MAX_CNAME_HOPS`.
+    CnameLoop,
     TxFail,
 }
 
-fn parse_ipv4_literal(host: &str) -> Option<[u8; 4]> {
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RData {
+    A([u8; 4]),
+    Aaaa([u8; 16]),
+    /// CNAME, NS and PTR targets.
+    Name(String),
+    Mx { preference: u16, exchange: String },
+    /// Character strings, as sent.
+    Txt(Vec<Vec<u8>>),
+    Other(Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub name: String,
+    pub rtype: u16,
+    pub ttl: u32,
+    pub data: RData,
+}
+
+#[derive(Clone, Debug)]
+pub struct Answer {
+    /// The CNAMEs that were followed, then the records of the asked type.
+    pub records: Vec<Record>,
+    /// Server that gave the last part of the answer; `None` = all from cache.
+    pub server: Option<IpAddr>,
+    pub via_tcp: bool,
+    pub authoritative: bool,
+}
+
+pub fn type_name(t: u16) -> &'static str {
+    match t {
+        TYPE_A => "A",
+        TYPE_NS => "NS",
+        TYPE_CNAME => "CNAME",
+        TYPE_SOA => "SOA",
+        TYPE_PTR => "PTR",
+        TYPE_MX => "MX",
+        TYPE_TXT => "TXT",
+        TYPE_AAAA => "AAAA",
+        _ => "?",
+    }
+}
+
+/// Record type from its mnemonic (any case).
+pub fn type_from_name(s: &str) -> Option<u16> {
+    [TYPE_A, TYPE_NS, TYPE_CNAME, TYPE_SOA, TYPE_PTR, TYPE_MX, TYPE_TXT, TYPE_AAAA]
+        .into_iter()
+        .find(|&t| s.eq_ignore_ascii_case(type_name(t)))
+}
+
+pub fn parse_ipv4_literal(host: &str) -> Option<[u8; 4]> {
     let mut out = [0u8; 4];
     let mut idx = 0usize;
     let mut acc: u16 = 0;
@@ -48,30 +150,402 @@ fn parse_ipv4_literal(host: &str) -> Option<[u8; 4]> {
     Some(out)
 }
 
+fn udp_err(e: UdpError) -> DnsError {
+    match e {
+        UdpError::NoNic => DnsError::NoNic,
+        UdpError::NotConfigured => DnsError::NotConfigured,
+        UdpError::ArpTimeout | UdpError::Timeout => DnsError::Timeout,
+        _ => DnsError::TxFail,
+    }
+}
+
+fn tcp_err(e: TcpError) -> DnsError {
+    match e {
+        TcpError::NoNic => DnsError::NoNic,
+        TcpError::NotConfigured => DnsError::NotConfigured,
+        TcpError::ArpTimeout | TcpError::Timeout => DnsError::Timeout,
+        _ => DnsError::TxFail,
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Public lookups
+// -----------------------------------------------------------------------------
+
+/// "localhost" (RFC 6761 6.3): answered here, never sent to a server.
+fn is_localhost(host: &str) -> bool {
+    let h = host.strip_suffix('.').unwrap_or(host);
+    h.eq_ignore_ascii_case("localhost") || (h.len() > 10 && h.as_bytes()[h.len() - 10..].eq_ignore_ascii_case(b".localhost"))
+}
+
+pub fn resolve_a(host: &str) -> Result<[u8; 4], DnsError> {
+    if let Some(ip) = parse_ipv4_literal(host) {
+        return Ok(ip);
+    }
+    if is_localhost(host) {
+        return Ok(super::ipv4::LOOPBACK);
+    }
+    lookup(host, TYPE_A)?
+        .records
+        .iter()
+        .find_map(|r| match r.data {
+            RData::A(ip) => Some(ip),
+            _ => None,
+        })
+        .ok_or(DnsError::NoAnswer)
+}
+
+pub fn resolve_aaaa(host: &str) -> Result<[u8; 16], DnsError> {
+    if let Some(ip) = super::ipv6::parse_addr(host) {
+        return Ok(ip);
+    }
+    if is_localhost(host) {
+        return Ok(super::ipv6::LOOPBACK);
+    }
+    lookup(host, TYPE_AAAA)?
+        .records
+        .iter()
+        .find_map(|r| match r.data {
+            RData::Aaaa(ip) => Some(ip),
+            _ => None,
+        })
+        .ok_or(DnsError::NoAnswer)
+}
+
+/// Mail exchangers, most preferred first.
+pub fn resolve_mx(host: &str) -> Result<Vec<(u16, String)>, DnsError> {
+    let mut out: Vec<(u16, String)> = lookup(host, TYPE_MX)?
+        .records
+        .into_iter()
+        .filter_map(|r| match r.data {
+            RData::Mx { preference, exchange } => Some((preference, exchange)),
+            _ => None,
+        })
+        .collect();
+    out.sort_by_key(|m| m.0);
+    Ok(out)
+}
+
+/// TXT records, each one's strings joined.
+pub fn resolve_txt(host: &str) -> Result<Vec<String>, DnsError> {
+    Ok(lookup(host, TYPE_TXT)?
+        .records
+        .into_iter()
+        .filter_map(|r| match r.data {
+            RData::Txt(parts) => Some(parts.iter().map(|p| String::from_utf8_lossy(p)).collect()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Name for an address (in-addr.arpa / ip6.arpa).
+pub fn resolve_ptr(ip: IpAddr) -> Result<String, DnsError> {
+    lookup(&reverse_name(ip), TYPE_PTR)?
+        .records
+        .into_iter()
+        .find_map(|r| match r.data {
+            RData::Name(n) if r.rtype == TYPE_PTR => Some(n),
+            _ => None,
+        })
+        .ok_or(DnsError::NoAnswer)
+}
+
+/// `4.3.2.1.in-addr.arpa` / nibble-reversed `ip6.arpa` name of an address.
+pub fn reverse_name(ip: IpAddr) -> String {
+    let mut s = String::new();
+    match ip {
+        IpAddr::V4(a) => {
+            for b in a.iter().rev() {
+                s.push_str(&alloc::format!("{}.", b));
+            }
+            s.push_str("in-addr.arpa");
+        }
+        IpAddr::V6(a) => {
+            const HEX: &[u8; 16] = b"0123456789abcdef";
+            for b in a.iter().rev() {
+                s.push(HEX[(b & 0xF) as usize] as char);
+                s.push('.');
+                s.push(HEX[(b >> 4) as usize] as char);
+                s.push('.');
+            }
+            s.push_str("ip6.arpa");
+        }
+    }
+    s
+}
+
+/// Records of type `qtype` for `name`, following CNAMEs. Served from the
+/// cache where possible.
+pub fn lookup(name: &str, qtype: u16) -> Result<Answer, DnsError> {
+    let mut out = Answer { records: Vec::new(), server: None, via_tcp: false, authoritative: false };
+    let mut cur = normalize(name);
+    if cur.is_empty() || cur.len() > 253 { return Err(DnsError::Malformed); }
+
+    let mut hops = 0usize;
+    loop {
+        let records = match cached(&cur, qtype) {
+            Some(r) => r?,
+            None => {
+                let (records, server, via_tcp, aa) = ask(&cur, qtype)?;
+                out.server = Some(server);
+                out.via_tcp = via_tcp;
+                out.authoritative = aa;
+                records
+            }
+        };
+        if qtype == TYPE_CNAME {
+            out.records.extend(records.into_iter().filter(|r| r.rtype == TYPE_CNAME));
+            return Ok(out);
+        }
+
+        // walk the chain as far as this answer goes
+        let mut target = cur.clone();
+        while let Some(r) = records.iter().find(|r| r.rtype == TYPE_CNAME && r.name.eq_ignore_ascii_case(&target)) {
+            hops += 1;
+            if hops > MAX_CNAME_HOPS { return Err(DnsError::CnameLoop); }
+            out.records.push(r.clone());
+            if let RData::Name(n) = &r.data { target = normalize(n); }
+        }
+        let before = out.records.len();
+        out.records.extend(records.into_iter().filter(|r| r.rtype == qtype && r.name.eq_ignore_ascii_case(&target)));
+        if out.records.len() > before { return Ok(out); }
+        if target == cur { return Err(DnsError::NoAnswer); }
+        // the server stopped at a CNAME: ask for its target
+        cur = target;
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// -----------------------------------------------------------------------------
+// Cache
+// -----------------------------------------------------------------------------
+
+struct CacheEntry {
+    name: String,
+    qtype: u16,
+    /// Empty with `negative` set for NXDOMAIN / no data.
+    records: Vec<Record>,
+    negative: Option<DnsError>,
+    stored_ms: u64,
+    expires_ms: u64,
+}
+
+static CACHE: SpinLock<Vec<CacheEntry>> = SpinLock::new(Vec::new());
+
+/// Cached result with TTLs counted down, if still fresh.
+fn cached(name: &str, qtype: u16) -> Option<Result<Vec<Record>, DnsError>> {
+    let now = time::uptime_ms();
+    let mut c = CACHE.lock();
+    c.retain(|e| e.expires_ms > now);
+    let e = c.iter().find(|e| e.qtype == qtype && e.name == name)?;
+    if let Some(err) = e.negative { return Some(Err(err)); }
+    let elapsed = ((now - e.stored_ms) / 1000) as u32;
+    Some(Ok(e.records.iter().cloned().map(|mut r| {
+        r.ttl = r.ttl.saturating_sub(elapsed);
+        r
+    }).collect()))
+}
+
+fn store(name: &str, qtype: u16, records: Vec<Record>, negative: Option<DnsError>, ttl: u32) {
+    let ttl = ttl.min(MAX_TTL);
+    if ttl == 0 { return; }
+    let now = time::uptime_ms();
+    let mut c = CACHE.lock();
+    c.retain(|e| e.expires_ms > now && !(e.qtype == qtype && e.name == name));
+    if c.len() >= CACHE_MAX {
+        if let Some(i) = c.iter().enumerate().min_by_key(|(_, e)| e.expires_ms).map(|(i, _)| i) {
+            c.swap_remove(i);
+        }
+    }
+    c.push(CacheEntry {
+        name: String::from(name),
+        qtype,
+        records,
+        negative,
+        stored_ms: now,
+        expires_ms: now + ttl as u64 * 1000,
+    });
+}
+
+/// Number of live cache entries.
+pub fn cache_len() -> usize {
+    let now = time::uptime_ms();
+    let mut c = CACHE.lock();
+    c.retain(|e| e.expires_ms > now);
+    c.len()
+}
+
+// -----------------------------------------------------------------------------
+// Servers and transport
+// -----------------------------------------------------------------------------
+
+/// Servers from /etc/network.conf; asked before any learned ones.
+static CONFIGURED: SpinLock<Vec<IpAddr>> = SpinLock::new(Vec::new());
+
+pub fn set_servers(list: Vec<IpAddr>) {
+    *CONFIGURED.lock() = list;
+}
+
+/// Servers to ask, in order: configured, DHCP-supplied, static (or the
+/// gateway), then one learned from an IPv6 Router Advertisement.
+pub fn servers() -> Vec<IpAddr> {
+    let cfg = super::config();
+    let mut out: Vec<IpAddr> = CONFIGURED.lock().clone();
+    if cfg.ip != [0, 0, 0, 0] {
+        if cfg.dhcp_bound {
+            if let Some(l) = super::dhcp::lease() {
+                out.extend(l.dns.iter().map(|&d| IpAddr::V4(d)));
+            }
+        }
+        if cfg.dns != [0, 0, 0, 0] {
+            out.push(IpAddr::V4(cfg.dns));
+        } else if out.is_empty() && cfg.gateway != [0, 0, 0, 0] {
+            out.push(IpAddr::V4(cfg.gateway));
+        }
+    }
+    let v6 = super::ipv6::config().dns;
+    if v6 != [0; 16] { out.push(IpAddr::V6(v6)); }
+
+    let mut uniq: Vec<IpAddr> = Vec::new();
+    for s in out {
+        if !uniq.contains(&s) { uniq.push(s); }
+    }
+    uniq
+}
+
+/// Ask the servers for `name`/`qtype` and cache the outcome. Returns the
+/// answer records, the server, whether TCP was used, and the AA bit.
+fn ask(name: &str, qtype: u16) -> Result<(Vec<Record>, IpAddr, bool, bool), DnsError> {
+    let servers = servers();
+    if servers.is_empty() { return Err(DnsError::NotConfigured); }
+
+    let mut last = DnsError::Timeout;
+    for _ in 0..ROUNDS {
+        for &server in servers.iter() {
+            let (reply, via_tcp) = match exchange(server, name, qtype) {
+                Ok(r) => r,
+                Err(DnsError::NoNic) => return Err(DnsError::NoNic),
+                Err(e) => {
+                    last = e;
+                    continue;
+                }
+            };
+            match reply.rcode {
+                0 if reply.answers.is_empty() => {
+                    store(name, qtype, Vec::new(), Some(DnsError::NoAnswer), reply.neg_ttl);
+                    return Err(DnsError::NoAnswer);
+                }
+                0 => {
+                    let ttl = reply.answers.iter().map(|r| r.ttl).min().unwrap_or(0);
+                    store(name, qtype, reply.answers.clone(), None, ttl);
+                    return Ok((reply.answers, server, via_tcp, reply.authoritative));
+                }
+                RCODE_NXDOMAIN => {
+                    store(name, qtype, Vec::new(), Some(DnsError::NxDomain), reply.neg_ttl);
+                    return Err(DnsError::NxDomain);
+                }
+                // SERVFAIL, REFUSED, ...: someone else may know
+                _ => last = DnsError::ServerFailure,
+            }
+        }
+    }
+    Err(last)
+}
+
+/// One query to one server: UDP (with EDNS0, without if the server chokes
+/// on it), then TCP if the answer was truncated.
+fn exchange(server: IpAddr, name: &str, qtype: u16) -> Result<(Reply, bool), DnsError> {
+    let id = (time::rdtsc() as u16) ^ 0xBEEF;
+    let mut reply = udp_exchange(server, id, name, qtype, true)?;
+    if reply.rcode == RCODE_FORMERR {
+        reply = udp_exchange(server, id, name, qtype, false)?;
+    }
+    if !reply.truncated {
+        return Ok((reply, false));
+    }
+    let q = build_dns_query(id, name, qtype, false);
+    let msg = tcp_exchange(server, &q)?;
+    Ok((parse_reply(&msg, id, qtype)?, true))
+}
+
+fn udp_exchange(server: IpAddr, id: u16, name: &str, qtype: u16, edns: bool) -> Result<Reply, DnsError> {
+    let mut sock = UdpSocket::bind(0).map_err(udp_err)?;
+    sock.set_read_timeout(Some(QUERY_TIMEOUT_MS));
+    let q = build_dns_query(id, name, qtype, edns);
+    sock.send_to(&q, server, 53).map_err(udp_err)?;
+
+    let mut buf = alloc::vec![0u8; EDNS_UDP_SIZE as usize];
+    let deadline = time::uptime_ms() + QUERY_TIMEOUT_MS;
+    while time::uptime_ms() < deadline {
+        let (n, src_ip, src_port) = sock.recv_from(&mut buf).map_err(udp_err)?;
+        if src_ip != server || src_port != 53 { continue; }
+        match parse_reply(&buf[..n], id, qtype) {
+            Ok(r) => return Ok(r),
+            // someone else's reply, or garbage: keep waiting for ours
+            Err(_) => continue,
+        }
+    }
+    Err(DnsError::Timeout)
+}
+
+/// DNS over TCP: two-byte length prefix each way (RFC 1035 4.2.2).
+fn tcp_exchange(server: IpAddr, q: &[u8]) -> Result<Vec<u8>, DnsError> {
+    let mut s = TcpStream::connect(server, 53, TCP_CONNECT_SPINS).map_err(tcp_err)?;
+    let mut framed = Vec::with_capacity(q.len() + 2);
+    framed.extend_from_slice(&(q.len() as u16).to_be_bytes());
+    framed.extend_from_slice(q);
+    s.write_all(&framed).map_err(tcp_err)?;
+
+    let deadline = time::uptime_ms() + QUERY_TIMEOUT_MS * 2;
+    let mut len = [0u8; 2];
+    read_exact(&mut s, &mut len, deadline)?;
+    let mut msg = alloc::vec![0u8; u16::from_be_bytes(len) as usize];
+    read_exact(&mut s, &mut msg, deadline)?;
+    let _ = s.close();
+    Ok(msg)
+}
+
+fn read_exact(s: &mut TcpStream, buf: &mut [u8], deadline: u64) -> Result<(), DnsError> {
+    let mut got = 0usize;
+    while got < buf.len() {
+        if time::uptime_ms() >= deadline { return Err(DnsError::Timeout); }
+        match s.read(&mut buf[got..], 100_000) {
+            Ok(0) => return Err(DnsError::Malformed),
+            Ok(n) => got += n,
+            Err(TcpError::Timeout) => {}
+            Err(e) => return Err(tcp_err(e)),
+        }
+    }
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+// Wire format
+// -----------------------------------------------------------------------------
+
 fn write_u16_be(buf: &mut [u8], off: usize, v: u16) {
     buf[off..off + 2].copy_from_slice(&v.to_be_bytes());
 }
 
-fn udp_checksum(src_ip: [u8; 4], dst_ip: [u8; 4], udp_hdr_and_payload: &[u8]) -> u16 {
-    // Pseudoheader: src(4), dst(4), zero(1), proto(1), len(2)
-    let len = udp_hdr_and_payload.len() as u16;
-    let mut tmp = Vec::with_capacity(12 + udp_hdr_and_payload.len());
-    tmp.extend_from_slice(&src_ip);
-    tmp.extend_from_slice(&dst_ip);
-    tmp.push(0);
-    tmp.push(17);
-    tmp.extend_from_slice(&len.to_be_bytes());
-    tmp.extend_from_slice(udp_hdr_and_payload);
-    super::checksum16(&tmp)
+fn be16(msg: &[u8], off: usize) -> u16 {
+    u16::from_be_bytes([msg[off], msg[off + 1]])
 }
 
-fn build_dns_query(id: u16, host: &str) -> Vec<u8> {
+fn be32(msg: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes([msg[off], msg[off + 1], msg[off + 2], msg[off + 3]])
+}
+
+fn build_dns_query(id: u16, host: &str, qtype: u16, edns: bool) -> Vec<u8> {
     let mut q = Vec::new();
     q.resize(12, 0);
 
     write_u16_be(&mut q, 0, id);
     write_u16_be(&mut q, 2, 0x0100); // RD
     write_u16_be(&mut q, 4, 1);      // QDCOUNT
+    if edns { write_u16_be(&mut q, 10, 1); } // ARCOUNT
 
     // QNAME
     for label in host.split('.') {
@@ -82,9 +556,17 @@ fn build_dns_query(id: u16, host: &str) -> Vec<u8> {
     }
     q.push(0);
 
-    // QTYPE=A, QCLASS=IN
-    q.extend_from_slice(&1u16.to_be_bytes());
-    q.extend_from_slice(&1u16.to_be_bytes());
+    // QTYPE, QCLASS=IN
+    q.extend_from_slice(&qtype.to_be_bytes());
+    q.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    if edns {
+        // OPT pseudo-RR: root name, TYPE=41, CLASS=UDP payload size, TTL=0, RDLEN=0
+        q.push(0);
+        q.extend_from_slice(&TYPE_OPT.to_be_bytes());
+        q.extend_from_slice(&EDNS_UDP_SIZE.to_be_bytes());
+        q.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    }
 
     q
 }
@@ -111,144 +593,121 @@ fn skip_name(msg: &[u8], mut off: usize) -> Option<usize> {
     }
 }
 
-pub fn resolve_a(host: &str) -> Result<[u8; 4], DnsError> {
-    if let Some(ip) = parse_ipv4_literal(host) {
-        return Ok(ip);
+/// Name at `off`, following compression pointers.
+fn read_name(msg: &[u8], mut off: usize) -> Option<String> {
+    let mut name = String::new();
+    let mut jumps = 0usize;
+    loop {
+        if off >= msg.len() { return None; }
+        let b = msg[off];
+        if b & 0xC0 == 0xC0 {
+            if off + 1 >= msg.len() { return None; }
+            off = (be16(msg, off) & 0x3FFF) as usize;
+            jumps += 1;
+            if jumps > 32 { return None; }
+            continue;
+        }
+        if b == 0 { break; }
+        let len = b as usize;
+        off += 1;
+        if off + len > msg.len() || name.len() + len > 255 { return None; }
+        if !name.is_empty() { name.push('.'); }
+        name.extend(msg[off..off + len].iter().map(|&c| c as char));
+        off += len;
     }
+    Some(name)
+}
 
-    super::init();
-
-    let (src_ip, mask, gateway, our_mac, dns_ip) = unsafe {
-        if super::NET.rtl.is_none() { return Err(DnsError::NoNic); }
-        if super::NET.cfg.ip == [0, 0, 0, 0] { return Err(DnsError::NotConfigured); }
-        (super::NET.cfg.ip, super::NET.cfg.mask, super::NET.cfg.gateway, super::NET.cfg.mac, super::NET.cfg.dns)
-    };
-
-    let dst_ip = if dns_ip == [0, 0, 0, 0] { gateway } else { dns_ip };
-    if dst_ip == [0, 0, 0, 0] { return Err(DnsError::NotConfigured); }
-
-    let next_hop = if super::same_subnet(src_ip, dst_ip, mask) || gateway == [0, 0, 0, 0] {
-        dst_ip
-    } else {
-        gateway
-    };
+/// What a reply says, before the CNAME walk.
+struct Reply {
+    rcode: u8,
+    truncated: bool,
+    authoritative: bool,
+    /// Answer-section records of class IN.
+    answers: Vec<Record>,
+    /// How long to remember a negative answer.
+    neg_ttl: u32,
+}
 
-    let dst_mac = match super::arp_resolve(next_hop, our_mac, src_ip) {
-        Ok(m) => m,
-        Err(super::PingError::ArpTimeout) => return Err(DnsError::Timeout),
-        Err(super::PingError::NoNic) => return Err(DnsError::NoNic),
-        Err(super::PingError::NotConfigured) => return Err(DnsError::NotConfigured),
-        _ => return Err(DnsError::Timeout),
+fn parse_reply(msg: &[u8], id: u16, qtype: u16) -> Result<Reply, DnsError> {
+    if msg.len() < 12 || be16(msg, 0) != id { return Err(DnsError::Malformed); }
+    let flags = be16(msg, 2);
+    if (flags & 0x8000) == 0 { return Err(DnsError::Malformed); }
+
+    let mut reply = Reply {
+        rcode: (flags & 0x000F) as u8,
+        truncated: (flags & 0x0200) != 0,
+        authoritative: (flags & 0x0400) != 0,
+        answers: Vec::new(),
+        neg_ttl: NEG_TTL,
     };
+    if reply.truncated { return Ok(reply); }
+
+    let qd = be16(msg, 4) as usize;
+    let an = be16(msg, 6) as usize;
+    let ns = be16(msg, 8) as usize;
+
+    let mut off = 12usize;
+    for _ in 0..qd {
+        off = skip_name(msg, off).ok_or(DnsError::Malformed)?;
+        if off + 4 > msg.len() { return Err(DnsError::Malformed); }
+        let t = be16(msg, off);
+        if t != qtype { return Err(DnsError::Malformed); }
+        off += 4;
+    }
 
-    let id = (time::rdtsc() as u16) ^ 0xBEEF;
-    let q = build_dns_query(id, host);
-
-    let src_port: u16 = 53000u16.wrapping_add((time::rdtsc() as u16) & 0x0FFF);
-    let dst_port: u16 = 53;
-    let udp_len = (8 + q.len()) as u16;
-
-    let mut udp = Vec::with_capacity(8 + q.len());
-    udp.resize(8, 0);
-    write_u16_be(&mut udp, 0, src_port);
-    write_u16_be(&mut udp, 2, dst_port);
-    write_u16_be(&mut udp, 4, udp_len);
-    write_u16_be(&mut udp, 6, 0);
-    udp.extend_from_slice(&q);
-
-    let csum = udp_checksum(src_ip, dst_ip, &udp);
-    write_u16_be(&mut udp, 6, csum);
-
-    let ip_len = 20 + udp.len();
-    let mut ip = Vec::with_capacity(ip_len);
-    ip.resize(20, 0);
-    ip[0] = 0x45;
-    ip[1] = 0;
-    write_u16_be(&mut ip, 2, ip_len as u16);
-    write_u16_be(&mut ip, 4, id);
-    write_u16_be(&mut ip, 6, 0);
-    ip[8] = 64;
-    ip[9] = 17;
-    write_u16_be(&mut ip, 10, 0);
-    ip[12..16].copy_from_slice(&src_ip);
-    ip[16..20].copy_from_slice(&dst_ip);
-    let ip_csum = super::checksum16(&ip[..20]);
-    write_u16_be(&mut ip, 10, ip_csum);
-    ip.extend_from_slice(&udp);
-
-    let sent = unsafe {
-        let rtl = super::NET.rtl.as_mut().unwrap();
-        rtl.send_frame(dst_mac, 0x0800, &ip)
-    };
-    if !sent { return Err(DnsError::TxFail); }
-
-    let mut spins: u32 = 0;
-    while spins < 18_000_000 {
-        spins = spins.wrapping_add(1);
-        time::cpu_pause();
-
-        let frame_opt = unsafe { super::NET.rtl.as_mut().unwrap().poll_recv() };
-        let Some(frame) = frame_opt else { continue; };
-
-        if frame.len() < 14 + 20 { continue; }
-        let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
-        if ethertype != 0x0800 { continue; }
-
-        let ip_off = 14;
-        let ver_ihl = frame[ip_off];
-        if (ver_ihl >> 4) != 4 { continue; }
-        let ihl = ((ver_ihl & 0x0F) as usize) * 4;
-        if frame.len() < ip_off + ihl + 8 { continue; }
-        if frame[ip_off + 9] != 17 { continue; }
-
-        let src = [frame[ip_off + 12], frame[ip_off + 13], frame[ip_off + 14], frame[ip_off + 15]];
-        let dst = [frame[ip_off + 16], frame[ip_off + 17], frame[ip_off + 18], frame[ip_off + 19]];
-        if src != dst_ip || dst != src_ip { continue; }
-
-        let udp_off = ip_off + ihl;
-        let got_src_port = u16::from_be_bytes([frame[udp_off], frame[udp_off + 1]]);
-        let got_dst_port = u16::from_be_bytes([frame[udp_off + 2], frame[udp_off + 3]]);
-        if got_src_port != 53 || got_dst_port != src_port { continue; }
-
-        let dns_off = udp_off + 8;
-        if frame.len() < dns_off + 12 { continue; }
-        let got_id = u16::from_be_bytes([frame[dns_off], frame[dns_off + 1]]);
-        if got_id != id { continue; }
-
-        let flags = u16::from_be_bytes([frame[dns_off + 2], frame[dns_off + 3]]);
-        if (flags & 0x8000) == 0 { continue; }
-        if (flags & 0x000F) != 0 { return Err(DnsError::NoAnswer); }
-
-        let qd = u16::from_be_bytes([frame[dns_off + 4], frame[dns_off + 5]]) as usize;
-        let an = u16::from_be_bytes([frame[dns_off + 6], frame[dns_off + 7]]) as usize;
-        if an == 0 { return Err(DnsError::NoAnswer); }
-
-        let msg = &frame[dns_off..];
-        let mut off = 12usize;
-
-        for _ in 0..qd {
-            off = skip_name(msg, off).ok_or(DnsError::Malformed)?;
-            if off + 4 > msg.len() { return Err(DnsError::Malformed); }
-            off += 4;
-        }
-
-        for _ in 0..an {
-            off = skip_name(msg, off).ok_or(DnsError::Malformed)?;
-            if off + 10 > msg.len() { return Err(DnsError::Malformed); }
-            let typ = u16::from_be_bytes([msg[off], msg[off + 1]]); off += 2;
-            let cls = u16::from_be_bytes([msg[off], msg[off + 1]]); off += 2;
-            off += 4;
-            let rdlen = u16::from_be_bytes([msg[off], msg[off + 1]]) as usize; off += 2;
-            if off + rdlen > msg.len() { return Err(DnsError::Malformed); }
-
-            if typ == 1 && cls == 1 && rdlen == 4 {
-                return Ok([msg[off], msg[off + 1], msg[off + 2], msg[off + 3]]);
+    for i in 0..an + ns {
+        let name_off = off;
+        off = skip_name(msg, off).ok_or(DnsError::Malformed)?;
+        if off + 10 > msg.len() { return Err(DnsError::Malformed); }
+        let typ = be16(msg, off);
+        let cls = be16(msg, off + 2);
+        let ttl = be32(msg, off + 4);
+        let rdlen = be16(msg, off + 8) as usize;
+        off += 10;
+        if off + rdlen > msg.len() { return Err(DnsError::Malformed); }
+
+        if cls == CLASS_IN {
+            if i < an {
+                let name = read_name(msg, name_off).ok_or(DnsError::Malformed)?;
+                let data = rdata(msg, off, rdlen, typ).ok_or(DnsError::Malformed)?;
+                reply.answers.push(Record { name, rtype: typ, ttl, data });
+            } else if typ == TYPE_SOA {
+                // negative TTL = min(SOA TTL, SOA MINIMUM)
+                let min_off = skip_name(msg, off).and_then(|o| skip_name(msg, o)).map(|o| o + 16);
+                if let Some(m) = min_off.filter(|&m| m + 4 <= off + rdlen) {
+                    reply.neg_ttl = ttl.min(be32(msg, m));
+                }
             }
-            off += rdlen;
         }
-
-        return Err(DnsError::NoAnswer);
+        off += rdlen;
     }
+    Ok(reply)
+}
 
-    Err(DnsError::Timeout)
+fn rdata(msg: &[u8], off: usize, len: usize, typ: u16) -> Option<RData> {
+    let raw = &msg[off..off + len];
+    Some(match typ {
+        TYPE_A if len == 4 => RData::A([raw[0], raw[1], raw[2], raw[3]]),
+        TYPE_AAAA if len == 16 => {
+            let mut a = [0u8; 16];
+            a.copy_from_slice(raw);
+            RData::Aaaa(a)
+        }
+        TYPE_CNAME | TYPE_NS | TYPE_PTR => RData::Name(read_name(msg, off)?),
+        TYPE_MX if len >= 3 => RData::Mx { preference: be16(msg, off), exchange: read_name(msg, off + 2)? },
+        TYPE_TXT => {
+            let mut parts = Vec::new();
+            let mut p = 0usize;
+            while p < len {
+                let n = raw[p] as usize;
+                if p + 1 + n > len { return None; }
+                parts.push(raw[p + 1..p + 1 + n].to_vec());
+                p += 1 + n;
+            }
+            RData::Txt(parts)
+        }
+        TYPE_A | TYPE_AAAA | TYPE_MX => return None,
+        _ => RData::Other(raw.to_vec()),
+    })
 }
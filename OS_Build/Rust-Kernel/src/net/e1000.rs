@@ -0,0 +1,369 @@
+#![allow(dead_code)]
+// src/net/e1000.rs
+//
+// Intel 8254x / 82574 (e1000 / e1000e) driver: legacy descriptors, polled.
+//
+// - Registers live in the BAR0 MMIO window. The loader identity-maps 0..4GiB,
+//   so the BAR's physical address is used directly.
+// - RX/TX rings and packet buffers come from the heap; the NIC is handed their
+//   physical addresses via bootinfo::virt_to_phys.
+// - Interrupts stay masked; `poll_recv` walks the RX ring like the RTL8139 path.
+
+extern crate alloc;
+
+use alloc::alloc::{alloc_zeroed, Layout};
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
+
+use crate::time;
+
+pub const PCI_IDS: &[(u16, u16)] = &[
+    (0x8086, 0x100E), // 82540EM (QEMU "e1000")
+    (0x8086, 0x100F), // 82545EM
+    (0x8086, 0x10D3), // 82574L (QEMU "e1000e")
+    (0x8086, 0x10EA), // 82577LM
+    (0x8086, 0x153A), // I217-LM
+];
+
+// Registers (BAR0 + offset)
+const CTRL: usize = 0x0000;
+const STATUS: usize = 0x0008;
+const EERD: usize = 0x0014;
+const ICR: usize = 0x00C0;
+const IMC: usize = 0x00D8;
+const RCTL: usize = 0x0100;
+const TCTL: usize = 0x0400;
+const TIPG: usize = 0x0410;
+const RDBAL: usize = 0x2800;
+const RDBAH: usize = 0x2804;
+const RDLEN: usize = 0x2808;
+const RDH: usize = 0x2810;
+const RDT: usize = 0x2818;
+const TDBAL: usize = 0x3800;
+const TDBAH: usize = 0x3804;
+const TDLEN: usize = 0x3808;
+const TDH: usize = 0x3810;
+const TDT: usize = 0x3818;
+const MTA: usize = 0x5200;
+const RAL0: usize = 0x5400;
+const RAH0: usize = 0x5404;
+
+const CTRL_ASDE: u32 = 1 << 5;
+const CTRL_SLU: u32 = 1 << 6;
+const CTRL_RST: u32 = 1 << 26;
+
+const STATUS_LU: u32 = 1 << 1;
+
+const RCTL_EN: u32 = 1 << 1;
+const RCTL_MPE: u32 = 1 << 4;
+const RCTL_BAM: u32 = 1 << 15;
+const RCTL_BSIZE_2048: u32 = 0;
+const RCTL_SECRC: u32 = 1 << 26;
+
+const TCTL_EN: u32 = 1 << 1;
+const TCTL_PSP: u32 = 1 << 3;
+const TCTL_CT: u32 = 0x0F << 4;
+const TCTL_COLD: u32 = 0x40 << 12;
+
+const RAH_AV: u32 = 1 << 31;
+
+// descriptor bits
+const RX_DD: u8 = 1 << 0;
+const RX_EOP: u8 = 1 << 1;
+const TX_CMD_EOP: u8 = 1 << 0;
+const TX_CMD_IFCS: u8 = 1 << 1;
+const TX_CMD_RS: u8 = 1 << 3;
+const TX_DD: u8 = 1 << 0;
+
+// ring sizes: RDLEN/TDLEN must be multiples of 128 bytes (8 descriptors)
+const NUM_RX: usize = 32;
+const NUM_TX: usize = 8;
+const BUF_SIZE: usize = 2048;
+const RING_ALIGN: usize = 128;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct RxDesc {
+    addr: u64,
+    length: u16,
+    checksum: u16,
+    status: u8,
+    errors: u8,
+    special: u16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TxDesc {
+    addr: u64,
+    length: u16,
+    cso: u8,
+    cmd: u8,
+    status: u8,
+    css: u8,
+    special: u16,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum E1000Error {
+    NoDevice,
+    NoBar,
+    NoMemory,
+    BadAddress,
+}
+
+static mut RX_SCRATCH: [u8; BUF_SIZE] = [0u8; BUF_SIZE];
+
+pub struct E1000 {
+    mmio: usize,
+    pub mac: [u8; 6],
+    device_id: u16,
+
+    rx_ring: *mut RxDesc,
+    rx_bufs: *mut u8,
+    rx_cur: usize,
+
+    tx_ring: *mut TxDesc,
+    tx_bufs: *mut u8,
+    tx_cur: usize,
+
+    rx_dropped: u32,
+}
+
+// The rings are only ever touched through the single NET owner.
+unsafe impl Send for E1000 {}
+
+fn alloc_dma(bytes: usize) -> Result<(*mut u8, u64), E1000Error> {
+    let layout = Layout::from_size_align(bytes, RING_ALIGN).map_err(|_| E1000Error::NoMemory)?;
+    let p = unsafe { alloc_zeroed(layout) };
+    if p.is_null() { return Err(E1000Error::NoMemory); }
+    let phys = crate::bootinfo::virt_to_phys(p as u64);
+    Ok((p, phys))
+}
+
+impl E1000 {
+    /// Probe PCI for a supported controller and bring it up.
+    pub fn probe() -> Result<Self, E1000Error> {
+        let dev = crate::pci::find_any(PCI_IDS).ok_or(E1000Error::NoDevice)?;
+        let bar = dev.mem_bar(0).ok_or(E1000Error::NoBar)?;
+        if bar > u32::MAX as u64 { return Err(E1000Error::BadAddress); }
+        dev.enable(crate::pci::CMD_MEM | crate::pci::CMD_BUS_MASTER);
+        Self::init(bar as usize, dev.device)
+    }
+
+    fn init(mmio: usize, device_id: u16) -> Result<Self, E1000Error> {
+        let (rx_ring, rx_ring_phys) = alloc_dma(NUM_RX * 16)?;
+        let (rx_bufs, rx_bufs_phys) = alloc_dma(NUM_RX * BUF_SIZE)?;
+        let (tx_ring, tx_ring_phys) = alloc_dma(NUM_TX * 16)?;
+        let (tx_bufs, tx_bufs_phys) = alloc_dma(NUM_TX * BUF_SIZE)?;
+
+        let mut nic = Self {
+            mmio,
+            mac: [0; 6],
+            device_id,
+            rx_ring: rx_ring as *mut RxDesc,
+            rx_bufs,
+            rx_cur: 0,
+            tx_ring: tx_ring as *mut TxDesc,
+            tx_bufs,
+            tx_cur: 0,
+            rx_dropped: 0,
+        };
+
+        // reset, then mask everything (we poll)
+        nic.write(IMC, 0xFFFF_FFFF);
+        nic.write(CTRL, nic.read(CTRL) | CTRL_RST);
+        time::spin(100_000);
+        let mut spins = 0u32;
+        while (nic.read(CTRL) & CTRL_RST) != 0 && spins < 1_000_000 {
+            time::cpu_pause();
+            spins += 1;
+        }
+        nic.write(IMC, 0xFFFF_FFFF);
+        let _ = nic.read(ICR);
+
+        nic.write(CTRL, nic.read(CTRL) | CTRL_SLU | CTRL_ASDE);
+
+        nic.mac = nic.read_mac();
+        let ral = u32::from_le_bytes([nic.mac[0], nic.mac[1], nic.mac[2], nic.mac[3]]);
+        let rah = u16::from_le_bytes([nic.mac[4], nic.mac[5]]) as u32 | RAH_AV;
+        nic.write(RAL0, ral);
+        nic.write(RAH0, rah);
+
+        for i in 0..128 {
+            nic.write(MTA + i * 4, 0);
+        }
+
+        // RX ring: every descriptor owns one 2 KiB buffer
+        for i in 0..NUM_RX {
+            let d = RxDesc {
+                addr: rx_bufs_phys + (i * BUF_SIZE) as u64,
+                length: 0,
+                checksum: 0,
+                status: 0,
+                errors: 0,
+                special: 0,
+            };
+            unsafe { ptr::write_volatile(nic.rx_ring.add(i), d); }
+        }
+        nic.write(RDBAL, rx_ring_phys as u32);
+        nic.write(RDBAH, (rx_ring_phys >> 32) as u32);
+        nic.write(RDLEN, (NUM_RX * 16) as u32);
+        nic.write(RDH, 0);
+        nic.write(RDT, (NUM_RX - 1) as u32);
+        // multicast promiscuous: IPv6 neighbor discovery uses solicited-node groups
+        nic.write(RCTL, RCTL_EN | RCTL_MPE | RCTL_BAM | RCTL_BSIZE_2048 | RCTL_SECRC);
+
+        // TX ring: descriptors start "done" so the first lap finds them free
+        for i in 0..NUM_TX {
+            let d = TxDesc {
+                addr: tx_bufs_phys + (i * BUF_SIZE) as u64,
+                length: 0,
+                cso: 0,
+                cmd: 0,
+                status: TX_DD,
+                css: 0,
+                special: 0,
+            };
+            unsafe { ptr::write_volatile(nic.tx_ring.add(i), d); }
+        }
+        nic.write(TDBAL, tx_ring_phys as u32);
+        nic.write(TDBAH, (tx_ring_phys >> 32) as u32);
+        nic.write(TDLEN, (NUM_TX * 16) as u32);
+        nic.write(TDH, 0);
+        nic.write(TDT, 0);
+        nic.write(TIPG, 10 | (8 << 10) | (6 << 20));
+        nic.write(TCTL, TCTL_EN | TCTL_PSP | TCTL_CT | TCTL_COLD);
+
+        Ok(nic)
+    }
+
+    #[inline(always)]
+    fn read(&self, reg: usize) -> u32 {
+        unsafe { ptr::read_volatile((self.mmio + reg) as *const u32) }
+    }
+
+    #[inline(always)]
+    fn write(&self, reg: usize, val: u32) {
+        unsafe { ptr::write_volatile((self.mmio + reg) as *mut u32, val) }
+    }
+
+    pub fn link_up(&self) -> bool {
+        (self.read(STATUS) & STATUS_LU) != 0
+    }
+
+    /// MAC from RAL0/RAH0 (auto-loaded from the EEPROM at reset); falls back to EERD.
+    fn read_mac(&self) -> [u8; 6] {
+        let ral = self.read(RAL0);
+        let rah = self.read(RAH0);
+        if ral != 0 || (rah & 0xFFFF) != 0 {
+            let l = ral.to_le_bytes();
+            let h = (rah as u16).to_le_bytes();
+            return [l[0], l[1], l[2], l[3], h[0], h[1]];
+        }
+
+        let mut mac = [0u8; 6];
+        for w in 0..3 {
+            let v = self.eeprom_read(w as u8).unwrap_or(0).to_le_bytes();
+            mac[w * 2] = v[0];
+            mac[w * 2 + 1] = v[1];
+        }
+        mac
+    }
+
+    fn eeprom_read(&self, word: u8) -> Option<u16> {
+        // 8254x: address at bit 8, DONE at bit 4; 82574 and later: bit 2 / bit 1
+        let (shift, done) = if matches!(self.device_id, 0x100E | 0x100F) { (8, 1 << 4) } else { (2, 1 << 1) };
+        self.write(EERD, 1 | ((word as u32) << shift));
+        for _ in 0..100_000 {
+            let v = self.read(EERD);
+            if (v & done) != 0 {
+                return Some((v >> 16) as u16);
+            }
+            time::cpu_pause();
+        }
+        None
+    }
+
+    pub fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool {
+        // same runt padding rule as the RTL8139 path
+        let total = 14 + payload.len();
+        let tx_total = core::cmp::max(total, 60);
+        if tx_total > BUF_SIZE { return false; }
+
+        let idx = self.tx_cur;
+        unsafe {
+            let dp = self.tx_ring.add(idx);
+
+            // wait for the NIC to release this slot
+            let mut spins = 0u32;
+            while (ptr::read_volatile(ptr::addr_of!((*dp).status)) & TX_DD) == 0 {
+                if spins > 2_000_000 { return false; }
+                time::cpu_pause();
+                spins += 1;
+            }
+
+            let buf = core::slice::from_raw_parts_mut(self.tx_bufs.add(idx * BUF_SIZE), BUF_SIZE);
+            buf[0..6].copy_from_slice(&dst);
+            buf[6..12].copy_from_slice(&self.mac);
+            buf[12..14].copy_from_slice(&ethertype.to_be_bytes());
+            buf[14..total].copy_from_slice(payload);
+            for b in buf[total..tx_total].iter_mut() { *b = 0; }
+
+            ptr::write_volatile(ptr::addr_of_mut!((*dp).length), tx_total as u16);
+            ptr::write_volatile(ptr::addr_of_mut!((*dp).cmd), TX_CMD_EOP | TX_CMD_IFCS | TX_CMD_RS);
+            ptr::write_volatile(ptr::addr_of_mut!((*dp).status), 0);
+        }
+
+        self.tx_cur = (idx + 1) % NUM_TX;
+        fence(Ordering::SeqCst);
+        self.write(TDT, self.tx_cur as u32);
+
+        true
+    }
+
+    pub fn poll_recv(&mut self) -> Option<&'static [u8]> {
+        let idx = self.rx_cur;
+        unsafe {
+            let dp = self.rx_ring.add(idx);
+            let d = ptr::read_volatile(dp);
+            if (d.status & RX_DD) == 0 {
+                return None;
+            }
+            fence(Ordering::SeqCst);
+
+            let len = (d.length as usize).min(BUF_SIZE);
+            let good = (d.status & RX_EOP) != 0 && d.errors == 0 && len >= 14;
+
+            let scratch = &raw mut RX_SCRATCH as *mut u8;
+            if good {
+                ptr::copy_nonoverlapping(self.rx_bufs.add(idx * BUF_SIZE), scratch, len);
+            }
+
+            // hand the descriptor back
+            ptr::write_volatile(ptr::addr_of_mut!((*dp).status), 0);
+            self.rx_cur = (idx + 1) % NUM_RX;
+            fence(Ordering::SeqCst);
+            self.write(RDT, idx as u32);
+
+            if !good {
+                self.rx_dropped = self.rx_dropped.wrapping_add(1);
+                return None;
+            }
+
+            let frame = core::slice::from_raw_parts(scratch as *const u8, len);
+            Some(&frame[..super::trimmed_len(frame)])
+        }
+    }
+}
+
+impl super::iface::NetDevice for E1000 {
+    fn driver(&self) -> &'static str { "e1000" }
+    fn mac(&self) -> [u8; 6] { self.mac }
+    fn link_up(&self) -> bool { E1000::link_up(self) }
+    fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool {
+        E1000::send_frame(self, dst, ethertype, payload)
+    }
+    fn poll_recv(&mut self) -> Option<&'static [u8]> { E1000::poll_recv(self) }
+    fn rx_dropped(&self) -> u32 { self.rx_dropped }
+}
@@ -1,47 +1,147 @@
 #![allow(dead_code)]
 
-//! Minimal HTTP/1.1 client.
+//! HTTP/1.1 client.
 //!
-//! - Uses DNS (A queries) and a minimal TCP client.
-//! - Supports basic redirects (Location) and chunked transfer decoding.
+//! - Uses DNS (A, then AAAA) and the TCP/TLS streams.
+//! - `request` sends any method with extra headers and a body, follows
+//!   redirects, and returns the whole response: every header field, and the
+//!   body un-chunked and, for `gzip`/`deflate`, decompressed (crate::inflate).
+//! - `open` does the same up to the response head and returns a
+//!   `BodyReader` that pulls the body off the connection as it is read. It
+//!   asks for identity encoding, since there is no streaming inflater.
+//! - Connections are kept alive and reused per scheme/host/port for up to
+//!   30 s idle. An idempotent request on a reused connection the server
+//!   already dropped is retried once on a fresh one.
+//! - `get`, `head`, `post` and `put` are one-call shorthands.
 //!
 //! HTTPS:
-//! - Native TLS is not implemented in-kernel yet.
-//! - For now, `https://` URLs are fetched via the optional host-side HTTPS proxy
-//!   at 10.0.2.2:8000 (QEMU user networking default). See tools/https_proxy.py.
+//! - `https://` URLs go over the in-kernel TLS 1.3 client (net::tls), with
+//!   the server certificate checked against the root store in net::x509.
+//! - The old host-side HTTPS proxy at 10.0.2.2:8000 (QEMU user networking
+//!   default, see tools/https_proxy.py) can still be switched on with
+//!   `set_https_proxy(true)` (`tls proxy on`), e.g. for TLS 1.2-only sites.
+//!   It only does GET.
 
 extern crate alloc;
 
+use alloc::boxed::Box;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
+use core::mem;
 
-use super::{dns, tcp};
+use crate::fs::SpinLock;
+use crate::{inflate, time};
 
-fn parse_ipv4(host: &str) -> Option<[u8; 4]> {
-    let mut out = [0u8; 4];
-    let mut i = 0usize;
-    for part in host.split('.') {
-        if i >= 4 { return None; }
-        let n: u16 = part.parse().ok()?;
-        if n > 255 { return None; }
-        out[i] = n as u8;
-        i += 1;
-    }
-    if i == 4 { Some(out) } else { None }
-}
+use super::ip::{self, IpAddr};
+use super::tcp::{TcpError, TcpState};
+use super::{dns, tcp, tls};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum HttpError {
     Dns,
     Tcp(super::tcp::TcpError),
+    Tls(super::tls::TlsError),
     Parse,
     RedirectLoop,
     UnsupportedScheme,
+    /// The HTTPS proxy only forwards GET.
+    ProxyMethod,
+    /// A gzip/deflate body that doesn't decompress.
+    Decode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Method {
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+
+    /// Safe to send twice (RFC 9110 9.2.2).
+    pub fn idempotent(self) -> bool {
+        self != Method::Post
+    }
+
+    pub fn parse(s: &str) -> Option<Method> {
+        [Method::Get, Method::Head, Method::Post, Method::Put, Method::Delete]
+            .into_iter()
+            .find(|m| m.name().eq_ignore_ascii_case(s))
+    }
+}
+
+/// Header fields in the order they arrived; lookups ignore case.
+#[derive(Clone, Debug, Default)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// First value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// Every value of `name` (e.g. several Set-Cookie lines).
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields.iter().filter(move |(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Whether a comma-separated field such as Connection lists `token`.
+    fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name).any(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+    }
+}
+
+/// What to send. Fields in `headers` replace the defaults of the same name
+/// (Host, User-Agent, Accept, Accept-Encoding, Connection, Content-Length).
+#[derive(Clone, Copy)]
+pub struct Request<'a> {
+    pub method: Method,
+    pub url: &'a str,
+    pub headers: &'a [(&'a str, &'a str)],
+    pub body: &'a [u8],
+    /// Follow up to `MAX_REDIRECTS` Location hops.
+    pub follow_redirects: bool,
+}
+
+impl<'a> Request<'a> {
+    pub fn new(method: Method, url: &'a str) -> Self {
+        Request { method, url, headers: &[], body: &[], follow_redirects: true }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct HttpResponse {
     pub status: u16,
+    pub reason: String,
+    /// URL the response came from, after redirects.
+    pub url: String,
+    pub headers: Headers,
     pub content_type: Option<String>,
     pub location: Option<String>,
     pub body: Vec<u8>,
@@ -49,47 +149,91 @@ pub struct HttpResponse {
 
 const HTTPS_PROXY_IP: [u8; 4] = [10, 0, 2, 2];
 const HTTPS_PROXY_PORT: u16 = 8000;
+/// `max` passed to the proxy when streaming (it buffers the page itself).
+const PROXY_MAX: usize = 2_000_000;
+
+/// Route `https://` through the host-side proxy instead of native TLS.
+static USE_HTTPS_PROXY: SpinLock<bool> = SpinLock::new(false);
+
+pub fn set_https_proxy(on: bool) {
+    *USE_HTTPS_PROXY.lock() = on;
+}
 
-const IO_TIMEOUT_SPINS: u32 = 200_000_000; // big busy-wait budget (QEMU can be slow)
+pub fn https_proxy_enabled() -> bool {
+    *USE_HTTPS_PROXY.lock()
+}
+
+const IO_TIMEOUT_SPINS: u32 = 10_000_000; // per read/connect (QEMU can be slow)
+const USER_AGENT: &str = "OthelloBrowser/0.1";
+pub const MAX_REDIRECTS: usize = 5;
+/// Largest response head we'll buffer.
+const MAX_HEAD: usize = 64 * 1024;
+/// Longest chunk-size or trailer line.
+const MAX_LINE: usize = 4096;
+
+// -----------------------------------------------------------------------------
+// URLs
+// -----------------------------------------------------------------------------
 
 #[derive(Clone, Debug)]
 struct UrlParts {
     scheme: String,
     host: String,
     port: u16,
+    /// Path and query, always starting with '/'.
     path: String,
     original: String,
 }
 
-fn parse_url(url: &str) -> Result<UrlParts, HttpError> {
-    let original = url.to_string();
-    let mut rest = url;
+impl UrlParts {
+    fn tls(&self) -> bool {
+        self.scheme == "https"
+    }
 
-    let mut scheme = "http".to_string();
-    if let Some(i) = url.find("://") {
-        scheme = url[..i].to_string();
-        rest = &url[i + 3..];
+    /// `host[:port]` the way the Host header and absolute URLs want it.
+    fn authority(&self) -> String {
+        let host = if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        let default = if self.tls() { 443 } else { 80 };
+        if self.port == default { host } else { format!("{}:{}", host, self.port) }
     }
 
-    let mut host_port = rest;
-    let mut path = "/";
-    if let Some(slash) = rest.find('/') {
-        host_port = &rest[..slash];
-        path = &rest[slash..];
+    fn origin(&self) -> Origin {
+        Origin { tls: self.tls(), host: self.host.clone(), port: self.port }
     }
+}
 
-    let mut host = host_port;
+fn parse_url(url: &str) -> Result<UrlParts, HttpError> {
+    let original = url.to_string();
+    let (scheme, rest) = match url.find("://") {
+        Some(i) => (url[..i].to_ascii_lowercase(), &url[i + 3..]),
+        None => ("http".to_string(), url),
+    };
     let mut port: u16 = match scheme.as_str() {
         "http" => 80,
         "https" => 443,
         _ => return Err(HttpError::UnsupportedScheme),
     };
 
-    if let Some(col) = host_port.rfind(':') {
-        if let Ok(p) = host_port[col + 1..].parse::<u16>() {
-            host = &host_port[..col];
-            port = p;
+    // the authority ends at the first '/', '?' or '#'; the fragment never
+    // goes on the wire
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (host_port, tail) = rest.split_at(end);
+    let mut path = tail.split('#').next().unwrap_or("").to_string();
+    if !path.starts_with('/') { path.insert(0, '/'); }
+
+    // [v6]:port, host:port or host
+    let (host, port_str) = match host_port.strip_prefix('[') {
+        Some(r) => {
+            let (h, after) = r.split_once(']').ok_or(HttpError::Parse)?;
+            (h, after.strip_prefix(':'))
         }
+        None => match host_port.rsplit_once(':') {
+            Some((h, p)) => (h, Some(p)),
+            None => (host_port, None),
+        },
+    };
+    if let Some(p) = port_str {
+        port = p.parse::<u16>().map_err(|_| HttpError::Parse)?;
     }
     if host.is_empty() { return Err(HttpError::Parse); }
 
@@ -97,22 +241,30 @@ fn parse_url(url: &str) -> Result<UrlParts, HttpError> {
         scheme,
         host: host.to_string(),
         port,
-        path: path.to_string(),
+        path,
         original,
     })
 }
 
-fn find_header<'a>(headers: &'a str, name: &str) -> Option<&'a str> {
-    let needle = name.to_ascii_lowercase();
-    for line in headers.split("\r\n") {
-        if let Some(col) = line.find(':') {
-            let (k, v) = line.split_at(col);
-            if k.trim().to_ascii_lowercase() == needle {
-                return Some(v[1..].trim());
-            }
-        }
+/// Where a Location header points, relative to the URL that returned it.
+fn resolve_location(cur: &UrlParts, loc: &str) -> String {
+    if loc.starts_with("http://") || loc.starts_with("https://") {
+        return loc.to_string();
+    }
+    if let Some(rest) = loc.strip_prefix("//") {
+        return format!("{}://{}", cur.scheme, rest);
+    }
+    let base = format!("{}://{}", cur.scheme, cur.authority());
+    let path = cur.path.split('?').next().unwrap_or("/");
+    if loc.starts_with('/') {
+        format!("{}{}", base, loc)
+    } else if loc.starts_with('?') {
+        format!("{}{}{}", base, path, loc)
+    } else {
+        // relative to the current directory
+        let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+        format!("{}{}{}", base, dir, loc)
     }
-    None
 }
 
 fn url_encode(s: &str) -> String {
@@ -131,167 +283,573 @@ fn url_encode(s: &str) -> String {
     out
 }
 
-fn decode_chunked(body: &[u8]) -> Result<Vec<u8>, HttpError> {
-    let mut out = Vec::new();
-    let mut i = 0usize;
+fn resolve_host(host: &str) -> Result<IpAddr, HttpError> {
+    // literals (e.g. 10.0.2.2 or [fe80::2]) skip DNS
+    if let Some(ip) = ip::parse(host) {
+        return Ok(ip);
+    }
+    match dns::resolve_a(host) {
+        Ok(a) => Ok(IpAddr::V4(a)),
+        Err(_) => dns::resolve_aaaa(host).map(IpAddr::V6).map_err(|_| HttpError::Dns),
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Connections and the keep-alive pool
+// -----------------------------------------------------------------------------
 
-    while i < body.len() {
-        let mut j = i;
-        while j + 1 < body.len() && !(body[j] == b'\r' && body[j + 1] == b'\n') {
-            j += 1;
+enum Conn {
+    Plain(tcp::TcpStream),
+    Tls(Box<tls::TlsStream>),
+}
+
+impl Conn {
+    fn open(o: &Origin) -> Result<Conn, HttpError> {
+        let ip = resolve_host(&o.host)?;
+        if o.tls {
+            tls::TlsStream::connect(ip, o.port, &o.host).map(|s| Conn::Tls(Box::new(s))).map_err(HttpError::Tls)
+        } else {
+            tcp::TcpStream::connect(ip, o.port, IO_TIMEOUT_SPINS).map(Conn::Plain).map_err(HttpError::Tcp)
         }
-        if j + 1 >= body.len() { return Err(HttpError::Parse); }
+    }
 
-        let line = &body[i..j];
-        let line_str = core::str::from_utf8(line).map_err(|_| HttpError::Parse)?;
-        let size_str = line_str.split(';').next().unwrap_or("").trim();
-        let size = usize::from_str_radix(size_str, 16).map_err(|_| HttpError::Parse)?;
+    fn write_all(&mut self, data: &[u8]) -> Result<(), HttpError> {
+        match self {
+            Conn::Plain(s) => s.write_all(data).map_err(HttpError::Tcp),
+            Conn::Tls(s) => s.write_all(data).map_err(HttpError::Tls),
+        }
+    }
 
-        i = j + 2;
-        if size == 0 { break; }
-        if i + size > body.len() { return Err(HttpError::Parse); }
+    /// `Ok(0)` once the server has closed its side.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, HttpError> {
+        match self {
+            Conn::Plain(s) => s.read(buf, IO_TIMEOUT_SPINS).map_err(HttpError::Tcp),
+            Conn::Tls(s) => s.read(buf, IO_TIMEOUT_SPINS).map_err(HttpError::Tls),
+        }
+    }
+
+    fn close(&mut self) {
+        match self {
+            Conn::Plain(s) => { let _ = s.close(); }
+            Conn::Tls(s) => { let _ = s.close(); }
+        }
+    }
+
+    /// Still established in both directions (the server hasn't sent FIN).
+    fn usable(&self) -> bool {
+        let st = match self {
+            Conn::Plain(s) => s.state(),
+            Conn::Tls(s) => s.tcp_state(),
+        };
+        st == TcpState::Established
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Origin {
+    tls: bool,
+    host: String,
+    port: u16,
+}
+
+struct Idle {
+    origin: Origin,
+    conn: Conn,
+    since_ms: u64,
+}
+
+/// Idle keep-alive connections, oldest first.
+static POOL: SpinLock<Vec<Idle>> = SpinLock::new(Vec::new());
+const POOL_MAX: usize = 4;
+const POOL_IDLE_MS: u64 = 30_000;
+
+fn checkout(o: &Origin) -> Option<Conn> {
+    let now = time::uptime_ms();
+    let mut pool = POOL.lock();
+    // dropping a stream closes it
+    pool.retain(|i| now.saturating_sub(i.since_ms) < POOL_IDLE_MS && i.conn.usable());
+    let i = pool.iter().position(|i| i.origin == *o)?;
+    Some(pool.remove(i).conn)
+}
 
-        out.extend_from_slice(&body[i..i + size]);
-        i += size;
+fn checkin(origin: Origin, conn: Conn) {
+    let mut pool = POOL.lock();
+    if pool.len() >= POOL_MAX { pool.remove(0); }
+    pool.push(Idle { origin, conn, since_ms: time::uptime_ms() });
+}
 
-        if i + 1 >= body.len() { return Err(HttpError::Parse); }
-        if body[i] != b'\r' || body[i + 1] != b'\n' { return Err(HttpError::Parse); }
-        i += 2;
+/// Number of idle keep-alive connections.
+pub fn idle_connections() -> usize {
+    POOL.lock().len()
+}
+
+/// Close every idle keep-alive connection.
+pub fn close_idle() {
+    let idle = mem::take(&mut *POOL.lock());
+    for mut i in idle {
+        i.conn.close();
     }
+}
 
-    Ok(out)
+// -----------------------------------------------------------------------------
+// Response head
+// -----------------------------------------------------------------------------
+
+struct Head {
+    /// As sent, e.g. "HTTP/1.1".
+    version: String,
+    status: u16,
+    reason: String,
+    headers: Headers,
+    /// HTTP/1.1 or later (keep-alive by default).
+    http11: bool,
 }
 
-fn parse_http_response(raw: &[u8]) -> Result<HttpResponse, HttpError> {
-    // Prefer CRLF delimiter per RFC, but tolerate LF-only servers (or buggy stacks).
-    let mut hdr_end: Option<(usize, usize)> = None; // (index, delimiter_len)
-    for i in 0..raw.len().saturating_sub(3) {
-        if raw[i] == b'\r' && raw[i + 1] == b'\n' && raw[i + 2] == b'\r' && raw[i + 3] == b'\n' {
-            hdr_end = Some((i, 4));
-            break;
+/// Read more from `conn` into `buf`; false at end of stream.
+fn fill(conn: &mut Conn, buf: &mut Vec<u8>) -> Result<bool, HttpError> {
+    let mut tmp = [0u8; 2048];
+    let n = conn.read(&mut tmp)?;
+    buf.extend_from_slice(&tmp[..n]);
+    Ok(n > 0)
+}
+
+/// End of the head and the length of the blank line. CRLF per RFC, but
+/// LF-only servers (or buggy stacks) are tolerated.
+fn head_end(raw: &[u8]) -> Option<(usize, usize)> {
+    if let Some(i) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+        return Some((i, 4));
+    }
+    raw.windows(2).position(|w| w == b"\n\n").map(|i| (i, 2))
+}
+
+fn parse_head(raw: &[u8]) -> Result<Head, HttpError> {
+    let text = core::str::from_utf8(raw).map_err(|_| HttpError::Parse)?;
+    let mut lines = text.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l));
+
+    let status_line = lines.next().ok_or(HttpError::Parse)?;
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().filter(|v| v.starts_with("HTTP/")).ok_or(HttpError::Parse)?;
+    let status = parts.next().ok_or(HttpError::Parse)?.parse::<u16>().map_err(|_| HttpError::Parse)?;
+    let reason = parts.next().unwrap_or("").trim().to_string();
+
+    let mut headers = Headers::default();
+    for line in lines {
+        // obsolete line folding continues the previous value
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, v)) = headers.fields.last_mut() {
+                v.push(' ');
+                v.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((k, v)) = line.split_once(':') {
+            headers.fields.push((k.trim().to_string(), v.trim().to_string()));
         }
     }
-    if hdr_end.is_none() {
-        for i in 0..raw.len().saturating_sub(1) {
-            if raw[i] == b'\n' && raw[i + 1] == b'\n' {
-                hdr_end = Some((i, 2));
-                break;
+
+    Ok(Head { version: version.to_string(), status, reason, headers, http11: version != "HTTP/1.0" })
+}
+
+/// Read up to and including the final response head; interim 1xx
+/// responses are skipped. What follows the head stays in `buf`.
+fn read_head(conn: &mut Conn, buf: &mut Vec<u8>) -> Result<Head, HttpError> {
+    loop {
+        if let Some((end, delim)) = head_end(buf) {
+            let head = parse_head(&buf[..end])?;
+            buf.drain(..end + delim);
+            if (100..200).contains(&head.status) && head.status != 101 { continue; }
+            return Ok(head);
+        }
+        if buf.len() > MAX_HEAD { return Err(HttpError::Parse); }
+        if !fill(conn, buf)? {
+            return Err(if buf.is_empty() { HttpError::Tcp(TcpError::Closed) } else { HttpError::Parse });
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Body
+// -----------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Chunk {
+    Size,
+    Data(u64),
+    /// CRLF after a chunk's data.
+    DataEnd,
+    Trailer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Framing {
+    Length(u64),
+    Chunked(Chunk),
+    /// Until the server closes the connection.
+    Close,
+    Done,
+}
+
+/// A response whose body is read from the connection on demand. Once the
+/// body has been read to the end the connection goes back to the pool;
+/// dropping the reader earlier closes it.
+pub struct BodyReader {
+    /// Protocol version from the status line, e.g. "HTTP/1.1".
+    pub version: String,
+    pub status: u16,
+    pub reason: String,
+    /// URL the response came from, after redirects.
+    pub url: String,
+    pub headers: Headers,
+    origin: Origin,
+    conn: Option<Conn>,
+    /// Received, not yet handed out.
+    buf: Vec<u8>,
+    framing: Framing,
+    keep_alive: bool,
+}
+
+impl BodyReader {
+    fn new(head: Head, url: &UrlParts, method: Method, conn: Conn, buf: Vec<u8>, close: bool) -> Self {
+        let h = &head.headers;
+        let framing = if method == Method::Head || matches!(head.status, 204 | 304) {
+            Framing::Done
+        } else if h.has_token("transfer-encoding", "chunked") {
+            Framing::Chunked(Chunk::Size)
+        } else if let Some(n) = h.get("content-length").and_then(|v| v.parse::<u64>().ok()) {
+            if n == 0 { Framing::Done } else { Framing::Length(n) }
+        } else {
+            Framing::Close
+        };
+        let keep_alive = !close
+            && framing != Framing::Close
+            && !h.has_token("connection", "close")
+            && (head.http11 || h.has_token("connection", "keep-alive"));
+
+        let mut r = BodyReader {
+            version: head.version,
+            status: head.status,
+            reason: head.reason,
+            url: url.original.clone(),
+            headers: head.headers,
+            origin: url.origin(),
+            conn: Some(conn),
+            buf,
+            framing,
+            keep_alive,
+        };
+        if framing == Framing::Done { r.finish(); }
+        r
+    }
+
+    /// Content-Length, if the server sent one.
+    pub fn content_length(&self) -> Option<u64> {
+        self.headers.get("content-length")?.parse().ok()
+    }
+
+    /// Next piece of the body (transfer coding removed); `Ok(0)` at the end.
+    pub fn read(&mut self, out: &mut [u8]) -> Result<usize, HttpError> {
+        if out.is_empty() { return Ok(0); }
+        loop {
+            match self.framing {
+                Framing::Done => return Ok(0),
+                Framing::Length(left) => {
+                    self.need_data()?;
+                    let n = self.take(left, out);
+                    self.framing = Framing::Length(left - n as u64);
+                    if left == n as u64 { self.finish(); }
+                    return Ok(n);
+                }
+                Framing::Close => {
+                    let more = match self.conn.as_mut() {
+                        Some(c) if self.buf.is_empty() => match fill(c, &mut self.buf) {
+                            // plenty of servers just reset once they're done
+                            Err(HttpError::Tcp(TcpError::Reset)) | Err(HttpError::Tls(tls::TlsError::Tcp(TcpError::Reset))) => false,
+                            r => r?,
+                        },
+                        _ => !self.buf.is_empty(),
+                    };
+                    if !more {
+                        self.finish();
+                        return Ok(0);
+                    }
+                    return Ok(self.take(u64::MAX, out));
+                }
+                Framing::Chunked(Chunk::Size) => {
+                    let line = self.line()?;
+                    let size = u64::from_str_radix(line.split(';').next().unwrap_or("").trim(), 16)
+                        .map_err(|_| HttpError::Parse)?;
+                    self.framing = Framing::Chunked(if size == 0 { Chunk::Trailer } else { Chunk::Data(size) });
+                }
+                Framing::Chunked(Chunk::Data(left)) => {
+                    self.need_data()?;
+                    let n = self.take(left, out) as u64;
+                    self.framing = Framing::Chunked(if n == left { Chunk::DataEnd } else { Chunk::Data(left - n) });
+                    return Ok(n as usize);
+                }
+                Framing::Chunked(Chunk::DataEnd) => {
+                    if !self.line()?.is_empty() { return Err(HttpError::Parse); }
+                    self.framing = Framing::Chunked(Chunk::Size);
+                }
+                Framing::Chunked(Chunk::Trailer) => {
+                    // trailer fields are dropped
+                    if self.line()?.is_empty() {
+                        self.finish();
+                        return Ok(0);
+                    }
+                }
             }
         }
     }
 
-    let Some((hdr_end, delim_len)) = hdr_end else { return Err(HttpError::Parse); };
-    let hdrs = &raw[..hdr_end];
-    let body = &raw[hdr_end + delim_len..];
+    /// The rest of the body, at most `max_bytes` (the connection is closed
+    /// if that cuts it short).
+    pub fn read_to_end(&mut self, max_bytes: usize) -> Result<Vec<u8>, HttpError> {
+        let mut out = Vec::new();
+        let mut chunk = [0u8; 2048];
+        while out.len() < max_bytes {
+            let want = chunk.len().min(max_bytes - out.len());
+            let n = self.read(&mut chunk[..want])?;
+            if n == 0 { break; }
+            out.extend_from_slice(&chunk[..n]);
+        }
+        Ok(out)
+    }
 
-    let hdr_str = core::str::from_utf8(hdrs).map_err(|_| HttpError::Parse)?;
+    /// Make sure `buf` isn't empty; the body ending early is an error.
+    fn need_data(&mut self) -> Result<(), HttpError> {
+        if !self.buf.is_empty() { return Ok(()); }
+        let c = self.conn.as_mut().ok_or(HttpError::Parse)?;
+        if fill(c, &mut self.buf)? { Ok(()) } else { Err(HttpError::Parse) }
+    }
 
-    // Split lines on either CRLF or LF; trim trailing CR to be safe.
-    let mut lines_iter = hdr_str
-        .split('\n')
-        .map(|l| l.strip_suffix('\r').unwrap_or(l));
+    /// Move up to `max` buffered bytes into `out`.
+    fn take(&mut self, max: u64, out: &mut [u8]) -> usize {
+        let n = (self.buf.len() as u64).min(max).min(out.len() as u64) as usize;
+        out[..n].copy_from_slice(&self.buf[..n]);
+        self.buf.drain(..n);
+        n
+    }
 
-    let status_line = lines_iter.next().ok_or(HttpError::Parse)?;
-    let mut status_parts = status_line.split_whitespace();
-    let _http = status_parts.next().ok_or(HttpError::Parse)?;
-    let status = status_parts.next().ok_or(HttpError::Parse)?.parse::<u16>().map_err(|_| HttpError::Parse)?;
+    /// One line of chunked framing, without its line ending.
+    fn line(&mut self) -> Result<String, HttpError> {
+        loop {
+            if let Some(i) = self.buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buf.drain(..=i).collect();
+                let s = core::str::from_utf8(&line).map_err(|_| HttpError::Parse)?;
+                return Ok(s.trim_end().to_string());
+            }
+            if self.buf.len() > MAX_LINE { return Err(HttpError::Parse); }
+            self.need_data()?;
+        }
+    }
 
-    let content_type = find_header(hdr_str, "content-type").map(|s| s.to_string());
-    let location = find_header(hdr_str, "location").map(|s| s.to_string());
-    let transfer = find_header(hdr_str, "transfer-encoding").unwrap_or("");
-    let cl = find_header(hdr_str, "content-length").and_then(|s| s.parse::<usize>().ok());
+    /// Body complete: pool the connection if it can carry another request.
+    fn finish(&mut self) {
+        self.framing = Framing::Done;
+        let Some(mut c) = self.conn.take() else { return };
+        // bytes past the end of the body would desync the next response
+        if self.keep_alive && self.buf.is_empty() && c.usable() {
+            checkin(self.origin.clone(), c);
+        } else {
+            c.close();
+        }
+    }
+}
 
-    let mut out_body = if transfer.to_ascii_lowercase().contains("chunked") {
-        decode_chunked(body)?
-    } else if let Some(n) = cl {
-        body.get(..n).unwrap_or(body).to_vec()
-    } else {
-        body.to_vec()
-    };
+impl Drop for BodyReader {
+    fn drop(&mut self) {
+        if let Some(mut c) = self.conn.take() {
+            c.close();
+        }
+    }
+}
 
-    if out_body.len() > 2_000_000 {
-        out_body.truncate(2_000_000);
+// -----------------------------------------------------------------------------
+// Requests
+// -----------------------------------------------------------------------------
+
+fn request_bytes(req: &Request, url: &UrlParts, compressed: bool) -> Vec<u8> {
+    let has = |name: &str| req.headers.iter().any(|(k, _)| k.eq_ignore_ascii_case(name));
+    let mut h = format!("{} {} HTTP/1.1\r\n", req.method.name(), url.path);
+    if !has("host") { h.push_str(&format!("Host: {}\r\n", url.authority())); }
+    if !has("user-agent") { h.push_str(&format!("User-Agent: {}\r\n", USER_AGENT)); }
+    if !has("accept") { h.push_str("Accept: text/html, text/plain, */*\r\n"); }
+    if !has("accept-encoding") {
+        h.push_str(if compressed { "Accept-Encoding: gzip, deflate\r\n" } else { "Accept-Encoding: identity\r\n" });
+    }
+    if !has("connection") { h.push_str("Connection: keep-alive\r\n"); }
+    for (k, v) in req.headers.iter() {
+        h.push_str(&format!("{}: {}\r\n", k, v));
     }
+    let wants_length = !req.body.is_empty() || matches!(req.method, Method::Post | Method::Put);
+    if wants_length && !has("content-length") {
+        h.push_str(&format!("Content-Length: {}\r\n", req.body.len()));
+    }
+    h.push_str("\r\n");
 
-    Ok(HttpResponse {
-        status,
-        content_type,
-        location,
-        body: out_body,
-    })
+    let mut out = h.into_bytes();
+    out.extend_from_slice(req.body);
+    out
 }
 
-fn http_get_direct(parts: &UrlParts, max_bytes: usize) -> Result<HttpResponse, HttpError> {
-    // If the host is already an IPv4 literal (e.g. 10.0.2.2), skip DNS.
-    let ip = if let Some(ip) = parse_ipv4(&parts.host) {
-        ip
-    } else {
-        dns::resolve_a(&parts.host).map_err(|_| HttpError::Dns)?
-    };
-    let mut s = tcp::TcpStream::connect(ip, parts.port, 10_000_000).map_err(HttpError::Tcp)?;
+/// One request/response on a pooled or new connection, up to the head.
+fn exchange(req: &Request, url: &UrlParts, compressed: bool) -> Result<BodyReader, HttpError> {
+    let origin = url.origin();
+    let msg = request_bytes(req, url, compressed);
+    let close = req.headers.iter().any(|(k, v)| k.eq_ignore_ascii_case("connection") && v.eq_ignore_ascii_case("close"));
+
+    if let Some(mut conn) = checkout(&origin) {
+        let mut buf = Vec::new();
+        match conn.write_all(&msg).and_then(|_| read_head(&mut conn, &mut buf)) {
+            Ok(head) => return Ok(BodyReader::new(head, url, req.method, conn, buf, close)),
+            // the server timed the connection out before it got our
+            // request: try again on a fresh one, if sending twice is harmless
+            Err(e) if buf.is_empty() && req.method.idempotent() && dropped(e) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut conn = Conn::open(&origin)?;
+    conn.write_all(&msg)?;
+    let mut buf = Vec::new();
+    let head = read_head(&mut conn, &mut buf)?;
+    Ok(BodyReader::new(head, url, req.method, conn, buf, close))
+}
 
-    let req = format!(
-        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: OthelloBrowser/0.1\r\nAccept: text/html, text/plain, */*\r\nConnection: close\r\n\r\n",
-        parts.path,
-        parts.host
-    );
-    s.write_all(req.as_bytes()).map_err(HttpError::Tcp)?;
-    let raw = s.read_to_end(max_bytes, 10_000_000).map_err(HttpError::Tcp)?;
-    let _ = s.close();
+fn is_any(name: &str, names: &[&str]) -> bool {
+    names.iter().any(|n| name.eq_ignore_ascii_case(n))
+}
 
-    parse_http_response(&raw)
+/// The peer closed or reset the connection (as opposed to timing out).
+fn dropped(e: HttpError) -> bool {
+    matches!(
+        e,
+        HttpError::Tcp(TcpError::Closed | TcpError::Reset)
+            | HttpError::Tls(tls::TlsError::Closed | tls::TlsError::Tcp(TcpError::Closed | TcpError::Reset))
+    )
 }
 
-fn http_get_via_https_proxy(url: &str, max_bytes: usize) -> Result<HttpResponse, HttpError> {
+/// GET through the host-side proxy, which fetches `url` itself.
+fn via_https_proxy(req: &Request, url: &UrlParts, max_bytes: usize) -> Result<BodyReader, HttpError> {
+    if req.method != Method::Get { return Err(HttpError::ProxyMethod); }
     let ip = HTTPS_PROXY_IP;
-    let mut s = tcp::TcpStream::connect(ip, HTTPS_PROXY_PORT, 10_000_000).map_err(HttpError::Tcp)?;
+    let proxy = UrlParts {
+        scheme: "http".to_string(),
+        host: format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]),
+        port: HTTPS_PROXY_PORT,
+        path: format!("/fetch?url={}&max={}", url_encode(&url.original), max_bytes),
+        original: url.original.clone(),
+    };
+    let r = Request {
+        headers: &[("Connection", "close"), ("Accept", "*/*")],
+        body: &[],
+        ..*req
+    };
+    exchange(&r, &proxy, false)
+}
+
+/// Send `req`, following redirects if asked, and stop after the response
+/// head. `compressed` offers gzip/deflate.
+fn send(req: &Request, compressed: bool, max_bytes: usize) -> Result<BodyReader, HttpError> {
+    let mut url = parse_url(req.url)?;
+    let mut method = req.method;
+    let mut body = req.body;
+    let mut headers: Vec<(&str, &str)> = req.headers.to_vec();
+
+    for _ in 0..=MAX_REDIRECTS {
+        let r = Request { method, body, headers: &headers, ..*req };
+        let mut resp = if url.tls() && https_proxy_enabled() {
+            via_https_proxy(&r, &url, max_bytes)?
+        } else {
+            exchange(&r, &url, compressed)?
+        };
+
+        let status = resp.status;
+        let redirect = req.follow_redirects && matches!(status, 301 | 302 | 303 | 307 | 308);
+        let Some(loc) = resp.headers.get("location").filter(|_| redirect) else { return Ok(resp) };
+        let next = resolve_location(&url, loc);
+        // read the (usually tiny) redirect body so the connection is reusable
+        let _ = resp.read_to_end(64 * 1024);
+
+        // 303, and 301/302 after a POST, continue as GET (what browsers do);
+        // 307/308 repeat the request as it was
+        if (status == 303 && method != Method::Head) || (matches!(status, 301 | 302) && method == Method::Post) {
+            method = Method::Get;
+            body = &[];
+            headers.retain(|(k, _)| !is_any(k, &["content-type", "content-length", "content-encoding"]));
+        }
+        let next = parse_url(&next)?;
+        // credentials (and a forced Host) belong to the origin they were meant for
+        if next.origin() != url.origin() {
+            headers.retain(|(k, _)| !is_any(k, &["authorization", "proxy-authorization", "cookie", "host"]));
+        }
+        url = next;
+    }
+    Err(HttpError::RedirectLoop)
+}
 
-    let q = url_encode(url);
-    let path = format!("/fetch?url={}&max={}", q, max_bytes);
+/// Send `req` and return the response with its body still on the wire.
+pub fn open(req: &Request) -> Result<BodyReader, HttpError> {
+    send(req, false, PROXY_MAX)
+}
 
-    let host = format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]);
-    let req = format!(
-        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: OthelloBrowser/0.1\r\nAccept: */*\r\nConnection: close\r\n\r\n",
-        path,
-        host
-    );
+/// Send `req` and read the whole response, with at most `max_bytes` of
+/// (decoded) body.
+pub fn request(req: &Request, max_bytes: usize) -> Result<HttpResponse, HttpError> {
+    let mut r = send(req, true, max_bytes)?;
+    // Only the decoded body is capped: the decoder stops cleanly at
+    // `max_bytes`, but needs enough input to get there. DEFLATE grows data by
+    // at most 1/8 (9-bit fixed-Huffman literals) plus block headers, so this
+    // much compressed input always decodes to the limit or to the end.
+    let raw_max = match r.headers.get("content-encoding") {
+        Some(_) => max_bytes.saturating_add(max_bytes / 8 + 1024),
+        None => max_bytes,
+    };
+    let raw = r.read_to_end(raw_max)?;
+    let body = decode_body(r.headers.get("content-encoding"), raw, max_bytes)?;
 
-    s.write_all(req.as_bytes()).map_err(HttpError::Tcp)?;
-    let raw = s.read_to_end(max_bytes, 10_000_000).map_err(HttpError::Tcp)?;
-    let _ = s.close();
+    let headers = mem::take(&mut r.headers);
+    Ok(HttpResponse {
+        status: r.status,
+        reason: mem::take(&mut r.reason),
+        url: mem::take(&mut r.url),
+        content_type: headers.get("content-type").map(|s| s.to_string()),
+        location: headers.get("location").map(|s| s.to_string()),
+        headers,
+        body,
+    })
+}
 
-    parse_http_response(&raw)
+fn decode_body(encoding: Option<&str>, mut raw: Vec<u8>, max_bytes: usize) -> Result<Vec<u8>, HttpError> {
+    // HEAD, 204 and 304 carry the header without a body
+    if raw.is_empty() { return Ok(raw); }
+    match encoding.map(|e| e.trim().to_ascii_lowercase()).as_deref() {
+        Some("gzip") | Some("x-gzip") => inflate::gunzip(&raw, max_bytes).map_err(|_| HttpError::Decode),
+        // meant to be zlib-wrapped, but some servers send raw DEFLATE
+        Some("deflate") => inflate::zlib(&raw, max_bytes)
+            .or_else(|_| inflate::inflate(&raw, max_bytes))
+            .map_err(|_| HttpError::Decode),
+        _ => {
+            raw.truncate(max_bytes);
+            Ok(raw)
+        }
+    }
 }
 
 pub fn get(url: &str, max_bytes: usize) -> Result<HttpResponse, HttpError> {
-    let mut cur = parse_url(url)?;
-    let mut redirects = 0usize;
+    request(&Request::new(Method::Get, url), max_bytes)
+}
 
-    loop {
-        let resp = match cur.scheme.as_str() {
-            "http" => http_get_direct(&cur, max_bytes),
-            "https" => http_get_via_https_proxy(&cur.original, max_bytes),
-            _ => Err(HttpError::UnsupportedScheme),
-        }?;
-
-        if matches!(resp.status, 301 | 302 | 303 | 307 | 308) {
-            if redirects >= 5 { return Err(HttpError::RedirectLoop); }
-            if let Some(loc) = resp.location.clone() {
-                redirects += 1;
-                let next = if loc.starts_with("http://") || loc.starts_with("https://") {
-                    loc
-                } else if loc.starts_with('/') {
-                    format!("{}://{}{}", cur.scheme, cur.host, loc)
-                } else {
-                    // relative
-                    let base = cur.path.rsplitn(2, '/').nth(1).unwrap_or("");
-                    format!("{}://{}/{}/{}", cur.scheme, cur.host, base, loc)
-                };
-                cur = parse_url(&next)?;
-                continue;
-            }
-        }
+pub fn head(url: &str) -> Result<HttpResponse, HttpError> {
+    request(&Request::new(Method::Head, url), 0)
+}
 
-        return Ok(resp);
-    }
+pub fn post(url: &str, content_type: &str, body: &[u8], max_bytes: usize) -> Result<HttpResponse, HttpError> {
+    let headers = [("Content-Type", content_type)];
+    request(&Request { headers: &headers, body, ..Request::new(Method::Post, url) }, max_bytes)
+}
+
+pub fn put(url: &str, content_type: &str, body: &[u8], max_bytes: usize) -> Result<HttpResponse, HttpError> {
+    let headers = [("Content-Type", content_type)];
+    request(&Request { headers: &headers, body, ..Request::new(Method::Put, url) }, max_bytes)
 }
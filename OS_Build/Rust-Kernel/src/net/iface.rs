@@ -0,0 +1,108 @@
+#![allow(dead_code)]
+// src/net/iface.rs
+//
+// Driver-facing side of the stack: the `NetDevice` trait every NIC driver
+// implements, and the `Interface` wrapper the interface table stores
+// (name, admin state, counters, the pcap capture hooks). Protocol code only
+// ever sees `Interface`.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+
+use super::NetStats;
+
+pub const DEFAULT_MTU: usize = 1500;
+
+pub trait NetDevice {
+    /// Short driver name ("rtl8139", "e1000", ...).
+    fn driver(&self) -> &'static str;
+    fn mac(&self) -> [u8; 6];
+    fn link_up(&self) -> bool;
+    /// Largest L3 payload per frame.
+    fn mtu(&self) -> usize { DEFAULT_MTU }
+
+    /// Send one Ethernet frame; the driver fills in the source MAC and pads runts.
+    fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool;
+
+    /// Next received frame (trimmed of pad/FCS). The slice stays valid until
+    /// the next call on the same device.
+    fn poll_recv(&mut self) -> Option<&'static [u8]>;
+
+    /// Frames the driver discarded (bad status, runts, ...).
+    fn rx_dropped(&self) -> u32 { 0 }
+
+    /// `lo`: never the primary interface, and what it receives is always ours.
+    fn is_loopback(&self) -> bool { false }
+}
+
+pub struct Interface {
+    pub name: String,
+    pub dev: Box<dyn NetDevice>,
+    pub up: bool,
+    rx_packets: u32,
+    tx_packets: u32,
+}
+
+/// Snapshot of one interface for display.
+#[derive(Clone)]
+pub struct IfaceInfo {
+    pub name: String,
+    pub driver: &'static str,
+    pub mac: [u8; 6],
+    pub up: bool,
+    pub link: bool,
+    pub mtu: usize,
+    pub stats: NetStats,
+}
+
+impl Interface {
+    pub fn new(name: String, dev: Box<dyn NetDevice>) -> Self {
+        Self { name, dev, up: true, rx_packets: 0, tx_packets: 0 }
+    }
+
+    pub fn mac(&self) -> [u8; 6] { self.dev.mac() }
+
+    pub fn mtu(&self) -> usize { self.dev.mtu() }
+
+    pub fn is_loopback(&self) -> bool { self.dev.is_loopback() }
+
+    pub fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool {
+        if !self.up { return false; }
+        let ok = self.dev.send_frame(dst, ethertype, payload);
+        if ok {
+            self.tx_packets = self.tx_packets.wrapping_add(1);
+            super::pcap::capture_tx(dst, self.dev.mac(), ethertype, payload);
+        }
+        ok
+    }
+
+    pub fn poll_recv(&mut self) -> Option<&'static [u8]> {
+        if !self.up { return None; }
+        let f = self.dev.poll_recv()?;
+        self.rx_packets = self.rx_packets.wrapping_add(1);
+        super::pcap::capture_rx(f);
+        Some(f)
+    }
+
+    pub fn stats(&self) -> NetStats {
+        NetStats {
+            rx_packets: self.rx_packets,
+            tx_packets: self.tx_packets,
+            rx_dropped: self.dev.rx_dropped(),
+        }
+    }
+
+    pub fn info(&self) -> IfaceInfo {
+        IfaceInfo {
+            name: self.name.clone(),
+            driver: self.dev.driver(),
+            mac: self.dev.mac(),
+            up: self.up,
+            link: self.dev.link_up(),
+            mtu: self.dev.mtu(),
+            stats: self.stats(),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+#![allow(dead_code)]
+// src/net/ip.rs
+//
+// Address type shared by the transports, and the handful of network-layer
+// operations UDP and TCP need without caring about the family: pick a
+// source address, resolve the next hop, send, and the pseudo-header
+// checksum. Each call goes to ipv4.rs or ipv6.rs.
+
+use super::ipv4::{self, IpError};
+use super::ipv6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpAddr {
+    V4([u8; 4]),
+    V6([u8; 16]),
+}
+
+impl IpAddr {
+    pub fn is_unspecified(&self) -> bool {
+        match self {
+            IpAddr::V4(a) => *a == [0; 4],
+            IpAddr::V6(a) => *a == [0; 16],
+        }
+    }
+
+    pub fn is_v6(&self) -> bool {
+        matches!(self, IpAddr::V6(_))
+    }
+}
+
+/// Bytes of network-layer header in front of a transport segment.
+pub fn header_len(a: IpAddr) -> usize {
+    match a {
+        IpAddr::V4(_) => 20,
+        IpAddr::V6(_) => 40,
+    }
+}
+
+/// Our address to use when talking to `dst`.
+pub fn source_for(dst: IpAddr) -> IpAddr {
+    match dst {
+        IpAddr::V4(d) => IpAddr::V4(ipv4::source_for(d)),
+        IpAddr::V6(d) => IpAddr::V6(ipv6::source_for(d)),
+    }
+}
+
+pub fn is_broadcast(dst: IpAddr) -> bool {
+    match dst {
+        IpAddr::V4(d) => ipv4::is_broadcast(d),
+        IpAddr::V6(_) => false,
+    }
+}
+
+/// Link-layer address of the next hop for `dst`. Polls; see `ipv4::resolve`.
+pub fn resolve(dst: IpAddr) -> Result<[u8; 6], IpError> {
+    match dst {
+        IpAddr::V4(d) => ipv4::resolve(d),
+        IpAddr::V6(d) => ipv6::resolve(d),
+    }
+}
+
+/// Send one packet (`proto` is the IPv4 protocol / IPv6 next header). Never polls.
+pub fn send(src: IpAddr, dst: IpAddr, proto: u8, payload: &[u8]) -> Result<(), IpError> {
+    match (src, dst) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => ipv4::send(s, d, proto, payload),
+        (IpAddr::V6(s), IpAddr::V6(d)) => ipv6::send(s, d, proto, payload),
+        _ => Err(IpError::NotConfigured),
+    }
+}
+
+/// Pseudo-header checksum for either family. Mixed families never verify.
+pub fn pseudo_checksum(src: IpAddr, dst: IpAddr, proto: u8, seg: &[u8]) -> u16 {
+    match (src, dst) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => ipv4::pseudo_checksum(s, d, proto, seg),
+        (IpAddr::V6(s), IpAddr::V6(d)) => ipv6::pseudo_checksum(s, d, proto, seg),
+        _ => 0xFFFF,
+    }
+}
+
+/// Dotted quad or IPv6 text.
+pub fn parse(s: &str) -> Option<IpAddr> {
+    if s.contains(':') {
+        ipv6::parse_addr(s).map(IpAddr::V6)
+    } else {
+        super::dns::parse_ipv4_literal(s).map(IpAddr::V4)
+    }
+}
+
+/// Text form into `out`; returns the length (at most 39).
+pub fn write(out: &mut [u8], a: IpAddr) -> usize {
+    match a {
+        IpAddr::V4(v) => super::write_ipv4(out, v),
+        IpAddr::V6(v) => ipv6::write_addr(out, v),
+    }
+}
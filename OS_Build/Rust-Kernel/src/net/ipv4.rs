@@ -0,0 +1,344 @@
+#![allow(dead_code)]
+// src/net/ipv4.rs
+//
+// IPv4 output shared by ICMP, UDP and TCP: next-hop choice (route.rs),
+// link-layer address from the ARP table, header construction and
+// fragmentation, and the pseudo-header checksum UDP and TCP both need.
+// Reassembly of incoming fragments lives here too; the demultiplexer hands
+// over anything with MF set or a non-zero offset.
+//
+// `send` never polls, so it is safe to call with a protocol lock held (TCP
+// transmits from inside its table). Callers that can wait call `resolve`
+// first so the next hop is in the ARP table.
+//
+// Packets for 127.0.0.0/8 and for our own address skip routing and ARP and
+// go to `lo`; they work without a NIC or an address.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use super::arp;
+use super::demux::DropReason;
+use super::route;
+use crate::fs::SpinLock;
+use crate::time;
+
+pub const PROTO_ICMP: u8 = 1;
+pub const PROTO_TCP: u8 = 6;
+pub const PROTO_UDP: u8 = 17;
+
+pub const DEFAULT_TTL: u8 = 64;
+pub const BROADCAST: [u8; 4] = [255, 255, 255, 255];
+pub const LOOPBACK: [u8; 4] = [127, 0, 0, 1];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpError {
+    NoNic,
+    NotConfigured,
+    /// No ARP reply (or, over IPv6, no neighbor advertisement).
+    ArpTimeout,
+    /// Over the 64 KiB IPv4 datagram limit.
+    TooLarge,
+    TxFail,
+}
+
+/// Largest payload after a 20-byte header.
+pub const MAX_PAYLOAD: usize = 65535 - 20;
+
+/// Incomplete datagrams are dropped after this long.
+const REASM_TIMEOUT_MS: u64 = 30_000;
+const REASM_MAX_DATAGRAMS: usize = 16;
+/// Buffer memory across all datagrams being reassembled.
+const REASM_MAX_BYTES: usize = 256 * 1024;
+
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
+#[derive(Clone, Copy, Default)]
+pub struct FragStats {
+    /// Datagrams put back together and delivered.
+    pub reassembled: u32,
+    /// Incomplete datagrams expired by the timeout.
+    pub timed_out: u32,
+    /// Datagrams abandoned for memory/slot limits or bad fragments.
+    pub dropped: u32,
+    /// Outgoing datagrams that had to be fragmented.
+    pub fragmented: u32,
+}
+
+/// One datagram being reassembled, keyed by (src, dst, proto, id).
+struct Partial {
+    src: [u8; 4],
+    dst: [u8; 4],
+    proto: u8,
+    id: u16,
+    started: u64,
+    data: Vec<u8>,
+    /// Received byte ranges, sorted and merged.
+    have: Vec<(usize, usize)>,
+    /// Known once the last fragment (MF clear) arrives.
+    total: Option<usize>,
+}
+
+struct Reasm {
+    list: Vec<Partial>,
+    bytes: usize,
+    stats: FragStats,
+}
+
+static REASM: SpinLock<Reasm> = SpinLock::new(Reasm {
+    list: Vec::new(),
+    bytes: 0,
+    stats: FragStats { reassembled: 0, timed_out: 0, dropped: 0, fragmented: 0 },
+});
+
+/// Limited broadcast, or the directed broadcast of our subnet.
+pub fn is_broadcast(dst: [u8; 4]) -> bool {
+    if dst == BROADCAST { return true; }
+    let cfg = super::config();
+    if cfg.ip == [0, 0, 0, 0] || cfg.mask == [0, 0, 0, 0] { return false; }
+    (0..4).all(|i| dst[i] == cfg.ip[i] | !cfg.mask[i])
+}
+
+/// 127.0.0.0/8.
+pub fn is_loopback(a: [u8; 4]) -> bool {
+    a[0] == 127
+}
+
+/// Delivered through `lo` instead of a NIC: loopback or our own address.
+pub fn is_local(dst: [u8; 4]) -> bool {
+    let ip = super::config().ip;
+    is_loopback(dst) || (ip != [0, 0, 0, 0] && dst == ip)
+}
+
+/// Our address to use when talking to `dst`: 127.0.0.1 for loopback
+/// destinations, the interface address otherwise.
+pub fn source_for(dst: [u8; 4]) -> [u8; 4] {
+    if is_loopback(dst) { LOOPBACK } else { super::config().ip }
+}
+
+/// Where a packet for `dst` goes on the wire: the gateway of the best route,
+/// or `dst` itself when the route is on-link (or there is no route at all).
+pub fn next_hop(dst: [u8; 4]) -> [u8; 4] {
+    match route::lookup(dst) {
+        Some(r) if r.gateway != [0, 0, 0, 0] => r.gateway,
+        _ => dst,
+    }
+}
+
+fn check_source(dst: [u8; 4]) -> Result<(), IpError> {
+    if !super::have_nic() { return Err(IpError::NoNic); }
+    // unconfigured hosts may only broadcast (DHCP)
+    if super::config().ip == [0, 0, 0, 0] && dst != BROADCAST {
+        return Err(IpError::NotConfigured);
+    }
+    Ok(())
+}
+
+/// Link-layer address for `dst`, asking with ARP and polling until the next
+/// hop answers. Never call it with a protocol lock held.
+pub fn resolve(dst: [u8; 4]) -> Result<[u8; 6], IpError> {
+    if is_local(dst) { return Ok([0; 6]); }
+    check_source(dst)?;
+    if is_broadcast(dst) { return Ok([0xFF; 6]); }
+
+    let hop = next_hop(dst);
+    if let Some(mac) = arp::lookup(hop) {
+        return Ok(mac);
+    }
+    let cfg = super::config();
+
+    // up to three requests; replies land in the table via arp::input
+    for _ in 0..3 {
+        if !arp::send_request(hop, cfg.mac, cfg.ip) { return Err(IpError::TxFail); }
+
+        let mut spins: u32 = 0;
+        while spins < 2_000_000 {
+            if super::poll() > 0 {
+                if let Some(mac) = arp::lookup(hop) {
+                    return Ok(mac);
+                }
+            }
+            spins = spins.wrapping_add(1);
+            if (spins & 0x3FF) == 0 { time::cpu_pause(); }
+        }
+    }
+
+    Err(IpError::ArpTimeout)
+}
+
+/// Send one IPv4 packet. If the next hop isn't in the ARP table yet, an ARP
+/// request goes out instead and the caller gets `ArpTimeout` to retry later.
+pub fn send(src: [u8; 4], dst: [u8; 4], proto: u8, payload: &[u8]) -> Result<(), IpError> {
+    send_with_ttl(src, dst, proto, DEFAULT_TTL, payload)
+}
+
+pub fn send_with_ttl(src: [u8; 4], dst: [u8; 4], proto: u8, ttl: u8, payload: &[u8]) -> Result<(), IpError> {
+    if payload.len() > MAX_PAYLOAD { return Err(IpError::TooLarge); }
+    if is_local(dst) {
+        // `lo` takes a whole datagram, so there is nothing to fragment
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let ip = packet(src, dst, proto, ttl, id, 0, payload);
+        return if super::loopback_send(0x0800, &ip) { Ok(()) } else { Err(IpError::TxFail) };
+    }
+    check_source(dst)?;
+
+    let dst_mac = if is_broadcast(dst) {
+        [0xFF; 6]
+    } else {
+        let hop = next_hop(dst);
+        match arp::lookup(hop) {
+            Some(mac) => mac,
+            None => {
+                let cfg = super::config();
+                arp::send_request(hop, cfg.mac, cfg.ip);
+                return Err(IpError::ArpTimeout);
+            }
+        }
+    };
+
+    let link_mtu = super::with_primary(|i| i.mtu()).unwrap_or(super::iface::DEFAULT_MTU);
+    // a DHCP-supplied MTU can only lower it (and 576 is the IPv4 minimum)
+    let mtu = match super::config().mtu as usize {
+        m if m >= 576 => m.min(link_mtu),
+        _ => link_mtu,
+    };
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    if 20 + payload.len() <= mtu {
+        let ip = packet(src, dst, proto, ttl, id, 0, payload);
+        return if super::send_frame(dst_mac, 0x0800, &ip) { Ok(()) } else { Err(IpError::TxFail) };
+    }
+
+    // fragment offsets count 8-byte units, so every piece but the last is a multiple of 8
+    let chunk = (mtu - 20) & !7;
+    {
+        let mut r = REASM.lock();
+        r.stats.fragmented = r.stats.fragmented.wrapping_add(1);
+    }
+    let mut off = 0;
+    while off < payload.len() {
+        let end = (off + chunk).min(payload.len());
+        let mf = if end < payload.len() { 0x2000 } else { 0 };
+        let ip = packet(src, dst, proto, ttl, id, mf | (off / 8) as u16, &payload[off..end]);
+        if !super::send_frame(dst_mac, 0x0800, &ip) { return Err(IpError::TxFail); }
+        off = end;
+    }
+    Ok(())
+}
+
+/// Header plus payload; `frag` is the flags/offset word.
+fn packet(src: [u8; 4], dst: [u8; 4], proto: u8, ttl: u8, id: u16, frag: u16, payload: &[u8]) -> Vec<u8> {
+    let ip_len = 20 + payload.len();
+    let mut ip = Vec::with_capacity(ip_len);
+    ip.resize(20, 0);
+    ip[0] = 0x45;
+    ip[2..4].copy_from_slice(&(ip_len as u16).to_be_bytes());
+    ip[4..6].copy_from_slice(&id.to_be_bytes());
+    ip[6..8].copy_from_slice(&frag.to_be_bytes());
+    ip[8] = ttl;
+    ip[9] = proto;
+    ip[12..16].copy_from_slice(&src);
+    ip[16..20].copy_from_slice(&dst);
+    let csum = super::checksum16(&ip[..20]);
+    ip[10..12].copy_from_slice(&csum.to_be_bytes());
+    ip.extend_from_slice(payload);
+    ip
+}
+
+fn forget(r: &mut Reasm, i: usize) {
+    let p = r.list.remove(i);
+    r.bytes -= p.data.len();
+}
+
+/// Add one fragment. Returns the whole payload once every piece is in,
+/// `Ok(None)` while still waiting, or why the datagram was abandoned.
+pub fn reassemble(src: [u8; 4], dst: [u8; 4], proto: u8, id: u16, offset: usize, more: bool, data: &[u8]) -> Result<Option<Vec<u8>>, DropReason> {
+    let mut r = REASM.lock();
+    let now = time::uptime_ms();
+
+    // expire stale datagrams first so their memory counts for this one
+    let mut i = 0;
+    while i < r.list.len() {
+        if now.saturating_sub(r.list[i].started) > REASM_TIMEOUT_MS {
+            forget(&mut r, i);
+            r.stats.timed_out = r.stats.timed_out.wrapping_add(1);
+        } else {
+            i += 1;
+        }
+    }
+
+    let idx = match r.list.iter().position(|p| p.src == src && p.dst == dst && p.proto == proto && p.id == id) {
+        Some(i) => i,
+        None => {
+            if r.list.len() >= REASM_MAX_DATAGRAMS {
+                // oldest first out
+                forget(&mut r, 0);
+                r.stats.dropped = r.stats.dropped.wrapping_add(1);
+            }
+            r.list.push(Partial { src, dst, proto, id, started: now, data: Vec::new(), have: Vec::new(), total: None });
+            r.list.len() - 1
+        }
+    };
+
+    let end = offset + data.len();
+    let bad = end > MAX_PAYLOAD
+        || (more && !data.len().is_multiple_of(8))
+        || (!more && r.list[idx].total.is_some_and(|t| t != end))
+        || r.list[idx].total.is_some_and(|t| end > t);
+    if bad {
+        forget(&mut r, idx);
+        r.stats.dropped = r.stats.dropped.wrapping_add(1);
+        return Err(DropReason::Malformed);
+    }
+
+    let grow = end.saturating_sub(r.list[idx].data.len());
+    if r.bytes + grow > REASM_MAX_BYTES {
+        forget(&mut r, idx);
+        r.stats.dropped = r.stats.dropped.wrapping_add(1);
+        return Err(DropReason::QueueFull);
+    }
+    r.bytes += grow;
+
+    let p = &mut r.list[idx];
+    if p.data.len() < end { p.data.resize(end, 0); }
+    p.data[offset..end].copy_from_slice(data);
+    if !more { p.total = Some(end); }
+
+    p.have.push((offset, end));
+    p.have.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(p.have.len());
+    for &(s, e) in p.have.iter() {
+        match merged.last_mut() {
+            Some(last) if s <= last.1 => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    p.have = merged;
+
+    let done = matches!(p.total, Some(t) if p.have.len() == 1 && p.have[0] == (0, t));
+    if !done { return Ok(None); }
+
+    let p = r.list.remove(idx);
+    r.bytes -= p.data.len();
+    r.stats.reassembled = r.stats.reassembled.wrapping_add(1);
+    Ok(Some(p.data))
+}
+
+pub fn frag_stats() -> FragStats {
+    REASM.lock().stats
+}
+
+/// Internet checksum over the pseudo-header plus `seg` (a UDP or TCP header
+/// and payload). Verifying a received segment yields 0.
+pub fn pseudo_checksum(src: [u8; 4], dst: [u8; 4], proto: u8, seg: &[u8]) -> u16 {
+    let len = seg.len() as u16;
+    let mut tmp = Vec::with_capacity(12 + seg.len());
+    tmp.extend_from_slice(&src);
+    tmp.extend_from_slice(&dst);
+    tmp.push(0);
+    tmp.push(proto);
+    tmp.extend_from_slice(&len.to_be_bytes());
+    tmp.extend_from_slice(seg);
+    super::checksum16(&tmp)
+}
@@ -0,0 +1,441 @@
+#![allow(dead_code)]
+// src/net/ipv6.rs
+//
+// IPv6 addressing and output. When the interface comes up it takes a
+// link-local address built from its MAC (modified EUI-64) and solicits a
+// router; a Router Advertisement carrying an autonomous /64 prefix then
+// gives it a global address and default router (SLAAC, RFC 4862). The
+// advertisement itself is parsed in ndp.rs, which also keeps the neighbor
+// cache used here for link-layer addresses.
+//
+// Like ipv4.rs, `send` never polls and `resolve` does, and packets for ::1
+// or our own addresses go to `lo`.
+//
+// Limitations: no duplicate address detection, no extension headers on
+// output, and fragments are neither sent nor reassembled.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::ipv4::IpError;
+use super::ndp;
+use crate::fs::SpinLock;
+use crate::time;
+
+pub const PROTO_ICMPV6: u8 = 58;
+pub const DEFAULT_HOP_LIMIT: u8 = 64;
+
+pub const UNSPECIFIED: [u8; 16] = [0; 16];
+pub const LOOPBACK: [u8; 16] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+pub const ALL_NODES: [u8; 16] = [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+pub const ALL_ROUTERS: [u8; 16] = [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+/// Router Solicitations sent before giving up (RFC 4861 MAX_RTR_SOLICITATIONS).
+const RS_MAX: u8 = 3;
+const RS_INTERVAL_MS: u64 = 4_000;
+
+#[derive(Clone, Copy)]
+pub struct V6Config {
+    pub enabled: bool,
+    pub link_local: [u8; 16],
+    /// SLAAC address; all zero until a router advertises a prefix.
+    pub global: [u8; 16],
+    pub prefix_len: u8,
+    /// Uptime ms when the global address expires; `u64::MAX` = never.
+    pub global_until: u64,
+    /// Default router (link-local); all zero when none.
+    pub router: [u8; 16],
+    pub router_until: u64,
+    /// Recursive DNS server from the RA (RFC 8106); all zero when none.
+    pub dns: [u8; 16],
+    /// Link MTU from the RA; 0 = the interface MTU.
+    pub mtu: usize,
+    pub hop_limit: u8,
+}
+
+struct State {
+    cfg: V6Config,
+    rs_sent: u8,
+    rs_next: u64,
+}
+
+static STATE: SpinLock<State> = SpinLock::new(State {
+    cfg: V6Config {
+        enabled: false,
+        link_local: UNSPECIFIED,
+        global: UNSPECIFIED,
+        prefix_len: 0,
+        global_until: 0,
+        router: UNSPECIFIED,
+        router_until: 0,
+        dns: UNSPECIFIED,
+        mtu: 0,
+        hop_limit: DEFAULT_HOP_LIMIT,
+    },
+    rs_sent: 0,
+    rs_next: 0,
+});
+
+fn expire(c: &mut V6Config, now: u64) {
+    if c.global != UNSPECIFIED && now >= c.global_until {
+        c.global = UNSPECIFIED;
+        c.prefix_len = 0;
+    }
+    if c.router != UNSPECIFIED && now >= c.router_until {
+        c.router = UNSPECIFIED;
+    }
+}
+
+pub fn config() -> V6Config {
+    let mut s = STATE.lock();
+    expire(&mut s.cfg, time::uptime_ms());
+    s.cfg
+}
+
+/// Modified EUI-64 interface identifier: MAC split by ff:fe, U/L bit flipped.
+fn interface_id(mac: [u8; 6]) -> [u8; 8] {
+    [mac[0] ^ 0x02, mac[1], mac[2], 0xff, 0xfe, mac[3], mac[4], mac[5]]
+}
+
+/// fe80::/64 plus the interface identifier.
+pub fn link_local_for(mac: [u8; 6]) -> [u8; 16] {
+    let mut a = UNSPECIFIED;
+    a[0] = 0xfe;
+    a[1] = 0x80;
+    a[8..].copy_from_slice(&interface_id(mac));
+    a
+}
+
+/// ff02::1:ffXX:XXXX, the group NS for `addr` is sent to.
+pub fn solicited_node(addr: [u8; 16]) -> [u8; 16] {
+    let mut a = [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xff, 0, 0, 0];
+    a[13..].copy_from_slice(&addr[13..]);
+    a
+}
+
+/// 33:33 plus the low 32 bits of the group.
+pub fn multicast_mac(group: [u8; 16]) -> [u8; 6] {
+    [0x33, 0x33, group[12], group[13], group[14], group[15]]
+}
+
+pub fn is_multicast(a: [u8; 16]) -> bool { a[0] == 0xff }
+
+pub fn is_link_local(a: [u8; 16]) -> bool { a[0] == 0xfe && (a[1] & 0xc0) == 0x80 }
+
+fn prefix_match(a: [u8; 16], b: [u8; 16], len: u8) -> bool {
+    let len = len.min(128) as usize;
+    let (bytes, bits) = (len / 8, len % 8);
+    if a[..bytes] != b[..bytes] { return false; }
+    if bits == 0 { return true; }
+    let m = 0xffu8 << (8 - bits);
+    (a[bytes] & m) == (b[bytes] & m)
+}
+
+/// One of our unicast addresses.
+pub fn is_ours(a: [u8; 16]) -> bool {
+    let c = config();
+    c.enabled && a != UNSPECIFIED && (a == c.link_local || a == c.global)
+}
+
+/// Delivered through `lo` instead of a NIC: ::1 or one of our addresses.
+pub fn is_local(dst: [u8; 16]) -> bool {
+    dst == LOOPBACK || is_ours(dst)
+}
+
+/// Whether a packet to `dst` should be taken off the wire.
+pub fn accepts(dst: [u8; 16]) -> bool {
+    let c = config();
+    if !c.enabled { return false; }
+    dst == c.link_local
+        || (c.global != UNSPECIFIED && dst == c.global)
+        || dst == ALL_NODES
+        || dst == solicited_node(c.link_local)
+        || (c.global != UNSPECIFIED && dst == solicited_node(c.global))
+}
+
+/// Source for `dst`: link-local for link-scope destinations or when there is
+/// no global address yet, otherwise the SLAAC address.
+pub fn source_for(dst: [u8; 16]) -> [u8; 16] {
+    if dst == LOOPBACK { return LOOPBACK; }
+    let c = config();
+    let link_scope = is_link_local(dst) || (is_multicast(dst) && (dst[1] & 0x0f) <= 2);
+    if link_scope || c.global == UNSPECIFIED { c.link_local } else { c.global }
+}
+
+/// Where a packet for `dst` goes on the wire: on-link for link-local and
+/// our prefix, the default router otherwise.
+pub fn next_hop(dst: [u8; 16]) -> Option<[u8; 16]> {
+    let c = config();
+    if is_link_local(dst) || is_multicast(dst) {
+        return Some(dst);
+    }
+    if c.global != UNSPECIFIED && prefix_match(dst, c.global, c.prefix_len) {
+        return Some(dst);
+    }
+    if c.router != UNSPECIFIED { Some(c.router) } else { None }
+}
+
+/// Bring IPv6 up on the primary interface: link-local address, then a
+/// Router Solicitation (repeated from `tick` until a router answers).
+pub fn start(mac: [u8; 6]) {
+    {
+        let mut s = STATE.lock();
+        s.cfg.enabled = true;
+        s.cfg.link_local = link_local_for(mac);
+        s.rs_sent = 0;
+        s.rs_next = 0;
+    }
+    tick();
+}
+
+/// Router solicitation retries; run from `net::poll`.
+pub fn tick() {
+    let now = time::uptime_ms();
+    {
+        let mut s = STATE.lock();
+        if !s.cfg.enabled || s.cfg.router != UNSPECIFIED || s.rs_sent >= RS_MAX || now < s.rs_next {
+            return;
+        }
+        s.rs_sent += 1;
+        s.rs_next = now + RS_INTERVAL_MS;
+    }
+    ndp::send_rs();
+}
+
+/// Ask for a router again (e.g. after the link comes back).
+pub fn solicit() {
+    let mut s = STATE.lock();
+    s.rs_sent = 0;
+    s.rs_next = 0;
+}
+
+/// Default router from an RA; lifetime 0 withdraws it.
+pub fn set_router(router: [u8; 16], lifetime_s: u16) {
+    let mut s = STATE.lock();
+    if lifetime_s == 0 {
+        if s.cfg.router == router { s.cfg.router = UNSPECIFIED; }
+        return;
+    }
+    s.cfg.router = router;
+    s.cfg.router_until = time::uptime_ms() + lifetime_s as u64 * 1000;
+}
+
+/// SLAAC from a prefix information option: the prefix plus our interface
+/// identifier. Only /64 prefixes can be combined with an EUI-64 identifier.
+pub fn add_prefix(prefix: [u8; 16], len: u8, valid_s: u32) {
+    if len != 64 || is_link_local(prefix) || valid_s == 0 { return; }
+    let mut s = STATE.lock();
+    if !s.cfg.enabled { return; }
+    let mut a = prefix;
+    a[8..].copy_from_slice(&s.cfg.link_local[8..]);
+    s.cfg.global = a;
+    s.cfg.prefix_len = len;
+    s.cfg.global_until = if valid_s == u32::MAX {
+        u64::MAX
+    } else {
+        time::uptime_ms() + valid_s as u64 * 1000
+    };
+}
+
+pub fn set_dns(server: [u8; 16]) {
+    STATE.lock().cfg.dns = server;
+}
+
+pub fn set_link_params(mtu: usize, hop_limit: u8) {
+    let mut s = STATE.lock();
+    // IPv6 links carry at least 1280 bytes
+    if mtu >= 1280 { s.cfg.mtu = mtu; }
+    if hop_limit != 0 { s.cfg.hop_limit = hop_limit; }
+}
+
+fn check_source() -> Result<(), IpError> {
+    if !super::have_nic() { return Err(IpError::NoNic); }
+    if !config().enabled { return Err(IpError::NotConfigured); }
+    Ok(())
+}
+
+/// Link-layer address for `dst`, soliciting the next hop and polling until
+/// it answers. Never call it with a protocol lock held.
+pub fn resolve(dst: [u8; 16]) -> Result<[u8; 6], IpError> {
+    if is_local(dst) { return Ok([0; 6]); }
+    check_source()?;
+    if is_multicast(dst) { return Ok(multicast_mac(dst)); }
+    let hop = next_hop(dst).ok_or(IpError::NotConfigured)?;
+    if let Some(mac) = ndp::lookup(hop) {
+        return Ok(mac);
+    }
+
+    for _ in 0..3 {
+        if !ndp::send_ns(hop) { return Err(IpError::TxFail); }
+
+        let mut spins: u32 = 0;
+        while spins < 2_000_000 {
+            if super::poll() > 0 {
+                if let Some(mac) = ndp::lookup(hop) {
+                    return Ok(mac);
+                }
+            }
+            spins = spins.wrapping_add(1);
+            if (spins & 0x3FF) == 0 { time::cpu_pause(); }
+        }
+    }
+
+    Err(IpError::ArpTimeout)
+}
+
+/// Send one IPv6 packet. If the next hop isn't in the neighbor cache, a
+/// Neighbor Solicitation goes out instead and the caller gets `ArpTimeout`.
+pub fn send(src: [u8; 16], dst: [u8; 16], next_header: u8, payload: &[u8]) -> Result<(), IpError> {
+    let hop_limit = config().hop_limit;
+    send_with_hop_limit(src, dst, next_header, hop_limit, payload)
+}
+
+pub fn send_with_hop_limit(src: [u8; 16], dst: [u8; 16], next_header: u8, hop_limit: u8, payload: &[u8]) -> Result<(), IpError> {
+    let local = is_local(dst);
+    if !local { check_source()?; }
+    let mtu = if local {
+        super::loopback::MTU
+    } else {
+        let link_mtu = super::with_primary(|i| i.mtu()).unwrap_or(super::iface::DEFAULT_MTU);
+        match config().mtu {
+            0 => link_mtu,
+            m => m.min(link_mtu),
+        }
+    };
+    if 40 + payload.len() > mtu { return Err(IpError::TooLarge); }
+
+    let dst_mac = if local {
+        [0; 6]
+    } else if is_multicast(dst) {
+        multicast_mac(dst)
+    } else {
+        let hop = next_hop(dst).ok_or(IpError::NotConfigured)?;
+        match ndp::lookup(hop) {
+            Some(mac) => mac,
+            None => {
+                ndp::send_ns(hop);
+                return Err(IpError::ArpTimeout);
+            }
+        }
+    };
+
+    let mut ip = Vec::with_capacity(40 + payload.len());
+    ip.resize(40, 0);
+    ip[0] = 0x60;
+    ip[4..6].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+    ip[6] = next_header;
+    ip[7] = hop_limit;
+    ip[8..24].copy_from_slice(&src);
+    ip[24..40].copy_from_slice(&dst);
+    ip.extend_from_slice(payload);
+
+    let sent = if local { super::loopback_send(0x86DD, &ip) } else { super::send_frame(dst_mac, 0x86DD, &ip) };
+    if sent { Ok(()) } else { Err(IpError::TxFail) }
+}
+
+/// Internet checksum over the IPv6 pseudo-header plus `seg` (RFC 8200 8.1).
+pub fn pseudo_checksum(src: [u8; 16], dst: [u8; 16], next_header: u8, seg: &[u8]) -> u16 {
+    let mut tmp = Vec::with_capacity(40 + seg.len());
+    tmp.extend_from_slice(&src);
+    tmp.extend_from_slice(&dst);
+    tmp.extend_from_slice(&(seg.len() as u32).to_be_bytes());
+    tmp.extend_from_slice(&[0, 0, 0, next_header]);
+    tmp.extend_from_slice(seg);
+    super::checksum16(&tmp)
+}
+
+/// Echo reply to an echo request (`req` is the whole ICMPv6 message).
+pub fn send_echo_reply(src: [u8; 16], dst: [u8; 16], req: &[u8]) {
+    let mut msg = req.to_vec();
+    msg[0] = 129;
+    msg[2..4].copy_from_slice(&[0, 0]);
+    let c = pseudo_checksum(src, dst, PROTO_ICMPV6, &msg);
+    msg[2..4].copy_from_slice(&c.to_be_bytes());
+    let _ = send(src, dst, PROTO_ICMPV6, &msg);
+}
+
+// -----------------------------------------------------------------------------
+// Text form (RFC 5952)
+// -----------------------------------------------------------------------------
+
+/// Lowercase, leading zeros dropped, the longest run of two or more zero
+/// groups as "::". Returns the length written (at most 39).
+pub fn write_addr(out: &mut [u8], a: [u8; 16]) -> usize {
+    let g: [u16; 8] = core::array::from_fn(|i| u16::from_be_bytes([a[2 * i], a[2 * i + 1]]));
+
+    let (mut best, mut best_len) = (8usize, 0usize);
+    let mut i = 0;
+    while i < 8 {
+        if g[i] != 0 { i += 1; continue; }
+        let start = i;
+        while i < 8 && g[i] == 0 { i += 1; }
+        if i - start > best_len && i - start >= 2 {
+            best = start;
+            best_len = i - start;
+        }
+    }
+
+    let mut n = 0usize;
+    let mut put = |b: u8, n: &mut usize| {
+        if *n < out.len() { out[*n] = b; *n += 1; }
+    };
+    let mut i = 0;
+    while i < 8 {
+        if i == best {
+            put(b':', &mut n);
+            if i == 0 { put(b':', &mut n); }
+            i += best_len;
+            continue;
+        }
+        let v = g[i];
+        let mut started = false;
+        for shift in [12u32, 8, 4, 0] {
+            let d = ((v >> shift) & 0xF) as u8;
+            if d != 0 || started || shift == 0 {
+                started = true;
+                put(if d < 10 { b'0' + d } else { b'a' + d - 10 }, &mut n);
+            }
+        }
+        if i < 7 { put(b':', &mut n); }
+        i += 1;
+    }
+    n
+}
+
+fn parse_groups(s: &str, out: &mut Vec<u16>) -> Option<()> {
+    if s.is_empty() { return Some(()); }
+    for part in s.split(':') {
+        if part.is_empty() || part.len() > 4 { return None; }
+        out.push(u16::from_str_radix(part, 16).ok()?);
+    }
+    Some(())
+}
+
+/// Parse IPv6 text with at most one "::". Zone suffixes ("%eth0") are ignored.
+pub fn parse_addr(s: &str) -> Option<[u8; 16]> {
+    let s = s.split('%').next()?;
+    let mut head = Vec::new();
+    let mut tail = Vec::new();
+    match s.find("::") {
+        Some(pos) => {
+            parse_groups(&s[..pos], &mut head)?;
+            parse_groups(&s[pos + 2..], &mut tail)?;
+            if head.len() + tail.len() > 7 { return None; }
+        }
+        None => {
+            parse_groups(s, &mut head)?;
+            if head.len() != 8 { return None; }
+        }
+    }
+
+    let mut a = UNSPECIFIED;
+    for (i, v) in head.iter().enumerate() {
+        a[2 * i..2 * i + 2].copy_from_slice(&v.to_be_bytes());
+    }
+    let off = 8 - tail.len();
+    for (i, v) in tail.iter().enumerate() {
+        a[2 * (off + i)..2 * (off + i) + 2].copy_from_slice(&v.to_be_bytes());
+    }
+    Some(a)
+}
@@ -0,0 +1,62 @@
+#![allow(dead_code)]
+// src/net/loopback.rs
+//
+// The `lo` device: every frame sent on it is queued and comes back out of
+// `poll_recv` on the next `net::poll`, so it goes through the same demux,
+// sockets and capture hooks as traffic from a NIC. ipv4.rs and ipv6.rs send
+// 127.0.0.0/8, ::1 and our own addresses here instead of to the wire, which
+// lets on-guest clients reach on-guest servers, with or without a NIC.
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// Largest L3 packet; IPv4 can't go past 64 KiB anyway, so nothing fragments.
+pub const MTU: usize = 65535;
+/// Frames waiting for the next poll before sends start failing.
+const QUEUE_MAX: usize = 256;
+
+pub struct Loopback {
+    queue: VecDeque<Vec<u8>>,
+    /// Frame last returned by `poll_recv`; kept alive until the next call.
+    current: Vec<u8>,
+    dropped: u32,
+}
+
+impl Loopback {
+    pub fn new() -> Self {
+        Self { queue: VecDeque::new(), current: Vec::new(), dropped: 0 }
+    }
+}
+
+impl super::iface::NetDevice for Loopback {
+    fn driver(&self) -> &'static str { "loopback" }
+    fn mac(&self) -> [u8; 6] { [0; 6] }
+    fn link_up(&self) -> bool { true }
+    fn mtu(&self) -> usize { MTU }
+    fn is_loopback(&self) -> bool { true }
+
+    fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool {
+        if self.queue.len() >= QUEUE_MAX {
+            self.dropped = self.dropped.wrapping_add(1);
+            return false;
+        }
+        let mut f = Vec::with_capacity(14 + payload.len());
+        f.extend_from_slice(&dst);
+        f.extend_from_slice(&[0; 6]);
+        f.extend_from_slice(&ethertype.to_be_bytes());
+        f.extend_from_slice(payload);
+        self.queue.push_back(f);
+        true
+    }
+
+    fn poll_recv(&mut self) -> Option<&'static [u8]> {
+        self.current = self.queue.pop_front()?;
+        // the heap buffer stays put until the next call replaces `current`,
+        // which is all the trait promises
+        Some(unsafe { core::slice::from_raw_parts(self.current.as_ptr(), self.current.len()) })
+    }
+
+    fn rx_dropped(&self) -> u32 { self.dropped }
+}
@@ -0,0 +1,232 @@
+#![allow(dead_code)]
+// src/net/ndp.rs
+//
+// Neighbor Discovery (RFC 4861), the IPv6 counterpart of arp.rs:
+// - neighbor cache (IPv6 -> MAC) with aging,
+// - Neighbor Solicitation / Advertisement for address resolution, and
+//   answers to solicitations for our addresses,
+// - Router Solicitation, and Router Advertisements handed to ipv6.rs
+//   (default router, SLAAC prefix, MTU, RDNSS).
+//
+// The demultiplexer verifies the ICMPv6 checksum before calling `input`.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::demux::DropReason;
+use super::ipv6::{self, PROTO_ICMPV6, UNSPECIFIED};
+use crate::fs::SpinLock;
+use crate::time;
+
+/// Entries not refreshed for this long are dropped on lookup.
+pub const NDP_TIMEOUT_SECS: u64 = 120;
+const MAX_ENTRIES: usize = 64;
+
+const ROUTER_SOLICIT: u8 = 133;
+const ROUTER_ADVERT: u8 = 134;
+const NEIGHBOR_SOLICIT: u8 = 135;
+const NEIGHBOR_ADVERT: u8 = 136;
+
+const OPT_SOURCE_LLADDR: u8 = 1;
+const OPT_TARGET_LLADDR: u8 = 2;
+const OPT_PREFIX_INFO: u8 = 3;
+const OPT_MTU: u8 = 5;
+const OPT_RDNSS: u8 = 25;
+
+const NA_ROUTER: u8 = 0x80;
+const NA_SOLICITED: u8 = 0x40;
+const NA_OVERRIDE: u8 = 0x20;
+
+#[derive(Copy, Clone)]
+pub struct NeighborEntry {
+    pub ip: [u8; 16],
+    pub mac: [u8; 6],
+    /// `time::uptime_secs()` when the entry was last confirmed.
+    pub updated: u64,
+    pub router: bool,
+}
+
+static TABLE: SpinLock<Vec<NeighborEntry>> = SpinLock::new(Vec::new());
+
+fn expired(e: &NeighborEntry, now: u64) -> bool {
+    now.saturating_sub(e.updated) >= NDP_TIMEOUT_SECS
+}
+
+/// MAC for `ip` if we have a fresh entry.
+pub fn lookup(ip: [u8; 16]) -> Option<[u8; 6]> {
+    let now = time::uptime_secs();
+    let mut t = TABLE.lock();
+    t.retain(|e| !expired(e, now));
+    t.iter().find(|e| e.ip == ip).map(|e| e.mac)
+}
+
+/// Add or refresh an entry. When the table is full the stalest entry goes.
+pub fn insert(ip: [u8; 16], mac: [u8; 6], router: bool) {
+    let now = time::uptime_secs();
+    let mut t = TABLE.lock();
+    if let Some(e) = t.iter_mut().find(|e| e.ip == ip) {
+        e.mac = mac;
+        e.updated = now;
+        e.router |= router;
+        return;
+    }
+    if t.len() >= MAX_ENTRIES {
+        if let Some(oldest) = t.iter().enumerate().min_by_key(|(_, e)| e.updated).map(|(i, _)| i) {
+            t.swap_remove(oldest);
+        }
+    }
+    t.push(NeighborEntry { ip, mac, updated: now, router });
+}
+
+/// Unexpired entries.
+pub fn entries() -> Vec<NeighborEntry> {
+    let now = time::uptime_secs();
+    let mut t = TABLE.lock();
+    t.retain(|e| !expired(e, now));
+    t.clone()
+}
+
+pub fn flush() {
+    TABLE.lock().clear();
+}
+
+/// Link-layer address option (source or target).
+fn lladdr_opt(kind: u8, mac: [u8; 6]) -> [u8; 8] {
+    [kind, 1, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]]
+}
+
+fn send_icmp(src: [u8; 16], dst: [u8; 16], mut msg: Vec<u8>) -> bool {
+    let c = ipv6::pseudo_checksum(src, dst, PROTO_ICMPV6, &msg);
+    msg[2..4].copy_from_slice(&c.to_be_bytes());
+    // NDP messages must arrive with hop limit 255 (proof they stayed on-link)
+    ipv6::send_with_hop_limit(src, dst, PROTO_ICMPV6, 255, &msg).is_ok()
+}
+
+/// Multicast a Neighbor Solicitation for `target` to its solicited-node group.
+pub fn send_ns(target: [u8; 16]) -> bool {
+    let src = ipv6::source_for(target);
+    let mut msg = alloc::vec![0u8; 24];
+    msg[0] = NEIGHBOR_SOLICIT;
+    msg[8..24].copy_from_slice(&target);
+    msg.extend_from_slice(&lladdr_opt(OPT_SOURCE_LLADDR, super::config().mac));
+    send_icmp(src, ipv6::solicited_node(target), msg)
+}
+
+/// Ask routers on the link to advertise now.
+pub fn send_rs() -> bool {
+    let src = ipv6::config().link_local;
+    let mut msg = alloc::vec![0u8; 8];
+    msg[0] = ROUTER_SOLICIT;
+    msg.extend_from_slice(&lladdr_opt(OPT_SOURCE_LLADDR, super::config().mac));
+    send_icmp(src, ipv6::ALL_ROUTERS, msg)
+}
+
+fn send_na(target: [u8; 16], dst: [u8; 16], solicited: bool) -> bool {
+    let mut msg = alloc::vec![0u8; 24];
+    msg[0] = NEIGHBOR_ADVERT;
+    msg[4] = NA_OVERRIDE | if solicited { NA_SOLICITED } else { 0 };
+    msg[8..24].copy_from_slice(&target);
+    msg.extend_from_slice(&lladdr_opt(OPT_TARGET_LLADDR, super::config().mac));
+    send_icmp(target, dst, msg)
+}
+
+/// Options after the fixed part of an NDP message: (type, body incl. header).
+fn options(mut opts: &[u8]) -> Result<Vec<(u8, &[u8])>, DropReason> {
+    let mut out = Vec::new();
+    while !opts.is_empty() {
+        if opts.len() < 2 { return Err(DropReason::Malformed); }
+        let len = opts[1] as usize * 8;
+        if len == 0 || len > opts.len() { return Err(DropReason::Malformed); }
+        out.push((opts[0], &opts[..len]));
+        opts = &opts[len..];
+    }
+    Ok(out)
+}
+
+fn lladdr(body: &[u8]) -> Option<[u8; 6]> {
+    if body.len() < 8 { return None; }
+    Some([body[2], body[3], body[4], body[5], body[6], body[7]])
+}
+
+fn be32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+/// NDP message (types 133-137) from the demultiplexer.
+pub fn input(src: [u8; 16], dst: [u8; 16], hop_limit: u8, msg: &[u8]) -> Result<(), DropReason> {
+    if hop_limit != 255 || msg[1] != 0 { return Err(DropReason::Malformed); }
+
+    match msg[0] {
+        ROUTER_ADVERT => {
+            if msg.len() < 16 || !ipv6::is_link_local(src) { return Err(DropReason::Malformed); }
+            let cur_hop_limit = msg[4];
+            let lifetime = u16::from_be_bytes([msg[6], msg[7]]);
+            let mut mtu = 0usize;
+            for (kind, body) in options(&msg[16..])? {
+                match kind {
+                    OPT_SOURCE_LLADDR => {
+                        if let Some(mac) = lladdr(body) { insert(src, mac, true); }
+                    }
+                    OPT_PREFIX_INFO if body.len() >= 32 => {
+                        let len = body[2];
+                        let autonomous = (body[3] & 0x40) != 0;
+                        let valid = be32(&body[4..8]);
+                        let preferred = be32(&body[8..12]);
+                        let mut prefix = [0u8; 16];
+                        prefix.copy_from_slice(&body[16..32]);
+                        if autonomous && preferred <= valid {
+                            ipv6::add_prefix(prefix, len, valid);
+                        }
+                    }
+                    OPT_MTU if body.len() >= 8 => mtu = be32(&body[4..8]) as usize,
+                    OPT_RDNSS if body.len() >= 24 => {
+                        let mut server = [0u8; 16];
+                        server.copy_from_slice(&body[8..24]);
+                        if be32(&body[4..8]) != 0 { ipv6::set_dns(server); }
+                    }
+                    _ => {}
+                }
+            }
+            ipv6::set_link_params(mtu, cur_hop_limit);
+            ipv6::set_router(src, lifetime);
+            Ok(())
+        }
+        NEIGHBOR_SOLICIT => {
+            if msg.len() < 24 { return Err(DropReason::Malformed); }
+            let mut target = [0u8; 16];
+            target.copy_from_slice(&msg[8..24]);
+            if ipv6::is_multicast(target) { return Err(DropReason::Malformed); }
+            if !ipv6::is_ours(target) { return Err(DropReason::NotForUs); }
+
+            let opts = options(&msg[24..])?;
+            // from :: it's a duplicate address probe: answer to all-nodes
+            if src == UNSPECIFIED {
+                send_na(target, ipv6::ALL_NODES, false);
+                return Ok(());
+            }
+            if let Some(mac) = opts.iter().find(|(k, _)| *k == OPT_SOURCE_LLADDR).and_then(|(_, b)| lladdr(b)) {
+                insert(src, mac, false);
+            }
+            send_na(target, src, true);
+            Ok(())
+        }
+        NEIGHBOR_ADVERT => {
+            if msg.len() < 24 { return Err(DropReason::Malformed); }
+            let mut target = [0u8; 16];
+            target.copy_from_slice(&msg[8..24]);
+            let solicited = (msg[4] & NA_SOLICITED) != 0;
+            if solicited && ipv6::is_multicast(dst) { return Err(DropReason::Malformed); }
+            let router = (msg[4] & NA_ROUTER) != 0;
+            match options(&msg[24..])?.iter().find(|(k, _)| *k == OPT_TARGET_LLADDR).and_then(|(_, b)| lladdr(b)) {
+                Some(mac) => {
+                    insert(target, mac, router);
+                    Ok(())
+                }
+                None => Err(DropReason::Unsupported),
+            }
+        }
+        // routers only (133), redirects (137)
+        _ => Err(DropReason::Unsupported),
+    }
+}
@@ -0,0 +1,305 @@
+#![allow(dead_code)]
+// src/net/netconf.rs
+//
+// /etc/network.conf: how each interface comes up, plus the hostname, DNS
+// servers and the HTTPS proxy switch. Applied once per boot, right after the
+// first login has replayed the persistent store (before that /etc only holds
+// the defaults), and per interface by `ifup`/`ifdown`.
+//
+//   hostname othello            # sent to the DHCP server
+//   dns 10.0.2.3 2606:4700::1111  # asked before DHCP/RA-learned servers
+//   proxy off                   # https:// via the host-side proxy (on|off)
+//
+//   iface eth0 dhcp             # dhcp | static | off
+//   iface eth0 static
+//       address 10.0.2.15/24    # or `address` + `netmask 255.255.255.0`
+//       gateway 10.0.2.2
+//       dns 10.0.2.3
+//
+// `dns` lines before the first `iface` are global, after it they belong to
+// that stanza. Only the primary NIC carries IPv4 settings, so a dhcp/static
+// stanza for any other interface just brings its link up.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::ip::{self, IpAddr};
+use super::{dhcp, dns, http, DhcpError};
+
+pub const PATH: &str = "/etc/network.conf";
+
+/// Seeded into a fresh /etc, and used as-is when the file is missing.
+pub const DEFAULT: &str = "\
+# Network configuration, applied at boot and by ifup/ifdown.
+#
+# hostname othello
+# dns 10.0.2.3 1.1.1.1
+# proxy off
+#
+# iface eth0 static
+#     address 10.0.2.15/24
+#     gateway 10.0.2.2
+#     dns 10.0.2.3
+
+iface eth0 dhcp
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Dhcp,
+    Static,
+    Off,
+}
+
+/// One `iface` stanza.
+#[derive(Clone)]
+pub struct IfaceConf {
+    pub name: String,
+    pub method: Method,
+    pub address: [u8; 4],
+    pub netmask: [u8; 4],
+    pub gateway: [u8; 4],
+    pub dns: [u8; 4],
+    /// Line of the `iface` keyword, for error messages.
+    line: u32,
+}
+
+#[derive(Clone, Default)]
+pub struct NetConf {
+    pub hostname: Option<String>,
+    pub dns: Vec<IpAddr>,
+    pub proxy: Option<bool>,
+    pub ifaces: Vec<IfaceConf>,
+}
+
+impl NetConf {
+    /// The stanza for `name`; if it appears twice the last one wins.
+    pub fn iface(&self, name: &str) -> Option<&IfaceConf> {
+        self.ifaces.iter().rev().find(|s| s.name == name)
+    }
+
+    /// Interface names in file order, each once.
+    fn names(&self) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        for s in self.ifaces.iter() {
+            if !out.contains(&s.name) { out.push(s.name.clone()); }
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ConfError {
+    /// Line N isn't understood.
+    Syntax(u32),
+    /// The static stanza on line N has no address or netmask.
+    Incomplete(u32),
+    /// The file isn't UTF-8.
+    NotText,
+    NoSuchIface,
+    Dhcp(DhcpError),
+}
+
+/// Where `ifup`/`ifdown` left an interface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IfState {
+    /// Bound by DHCP to this address.
+    Dhcp([u8; 4]),
+    Static([u8; 4]),
+    /// Link up without IPv4 settings: `lo`, a secondary NIC, no stanza, or `off`.
+    Link,
+    Down,
+    /// No such interface on this machine (e.g. `-nic none`); only the boot
+    /// pass reports this, and skips the stanza.
+    Absent,
+}
+
+/// Outcome per interface of `up_all`/`down_all`.
+pub type Report = Vec<(String, Result<IfState, ConfError>)>;
+
+/// Set once the boot-time pass has run.
+static APPLIED: AtomicBool = AtomicBool::new(false);
+
+pub fn parse(text: &str) -> Result<NetConf, ConfError> {
+    let mut conf = NetConf::default();
+    for (i, raw) in text.lines().enumerate() {
+        let n = i as u32 + 1;
+        let words: Vec<&str> = raw.split('#').next().unwrap_or("").split_whitespace().collect();
+        let Some((&key, args)) = words.split_first() else { continue };
+
+        let ok = match (key, args) {
+            ("iface", [name, method]) => {
+                let method = match *method {
+                    "dhcp" => Method::Dhcp,
+                    "static" => Method::Static,
+                    "off" => Method::Off,
+                    _ => return Err(ConfError::Syntax(n)),
+                };
+                conf.ifaces.push(IfaceConf {
+                    name: String::from(*name),
+                    method,
+                    address: [0; 4],
+                    netmask: [0; 4],
+                    gateway: [0; 4],
+                    dns: [0; 4],
+                    line: n,
+                });
+                true
+            }
+            ("hostname", [h]) if valid_hostname(h) => {
+                conf.hostname = Some(String::from(*h));
+                true
+            }
+            ("proxy", ["on"]) => {
+                conf.proxy = Some(true);
+                true
+            }
+            ("proxy", ["off"]) => {
+                conf.proxy = Some(false);
+                true
+            }
+            _ => match conf.ifaces.last_mut() {
+                Some(s) => stanza_key(s, key, args).is_some(),
+                None if key == "dns" && !args.is_empty() => {
+                    let before = conf.dns.len();
+                    conf.dns.extend(args.iter().filter_map(|a| ip::parse(a)));
+                    conf.dns.len() - before == args.len()
+                }
+                None => false,
+            },
+        };
+        if !ok { return Err(ConfError::Syntax(n)); }
+    }
+
+    for s in conf.ifaces.iter() {
+        if s.method == Method::Static && (s.address == [0; 4] || s.netmask == [0; 4]) {
+            return Err(ConfError::Incomplete(s.line));
+        }
+    }
+    Ok(conf)
+}
+
+/// One `address`/`netmask`/`gateway`/`dns` line inside a stanza.
+fn stanza_key(s: &mut IfaceConf, key: &str, args: &[&str]) -> Option<()> {
+    let [v] = args else { return None };
+    match key {
+        "address" => {
+            let (a, prefix) = match v.split_once('/') {
+                Some((a, p)) => (a, Some(p)),
+                None => (*v, None),
+            };
+            s.address = dns::parse_ipv4_literal(a)?;
+            if let Some(p) = prefix {
+                let p = p.parse::<u8>().ok().filter(|&p| p <= 32)?;
+                s.netmask = prefix_mask(p);
+            }
+        }
+        "netmask" => s.netmask = dns::parse_ipv4_literal(v)?,
+        "gateway" => s.gateway = dns::parse_ipv4_literal(v)?,
+        "dns" => s.dns = dns::parse_ipv4_literal(v)?,
+        _ => return None,
+    }
+    Some(())
+}
+
+fn prefix_mask(p: u8) -> [u8; 4] {
+    let m = if p == 0 { 0 } else { u32::MAX << (32 - p as u32) };
+    m.to_be_bytes()
+}
+
+fn valid_hostname(h: &str) -> bool {
+    !h.is_empty() && h.len() <= 63 && h.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')
+}
+
+/// The config file, or `DEFAULT` if there is none.
+pub fn load() -> Result<NetConf, ConfError> {
+    let data = crate::fs::FS.lock().read_all(PATH);
+    match data {
+        Ok(d) => parse(core::str::from_utf8(&d).map_err(|_| ConfError::NotText)?),
+        Err(_) => parse(DEFAULT),
+    }
+}
+
+/// Hostname, preferred DNS servers and the proxy switch.
+fn apply_globals(conf: &NetConf) {
+    super::set_hostname(conf.hostname.as_deref().unwrap_or(super::DEFAULT_HOSTNAME));
+    dns::set_servers(conf.dns.clone());
+    http::set_https_proxy(conf.proxy.unwrap_or(false));
+}
+
+/// Bring `name` up and configure it from its stanza in the file.
+pub fn ifup(name: &str) -> Result<IfState, ConfError> {
+    up_with(&load()?, name)
+}
+
+fn up_with(conf: &NetConf, name: &str) -> Result<IfState, ConfError> {
+    let primary = super::set_iface_up(name, true).ok_or(ConfError::NoSuchIface)?;
+    let Some(s) = conf.iface(name).filter(|_| primary) else { return Ok(IfState::Link) };
+    match s.method {
+        Method::Dhcp => {
+            dhcp::acquire().map_err(ConfError::Dhcp)?;
+            Ok(IfState::Dhcp(super::config().ip))
+        }
+        Method::Static => {
+            super::set_static_config(s.address, s.netmask, s.gateway, s.dns);
+            Ok(IfState::Static(s.address))
+        }
+        Method::Off => Ok(IfState::Link),
+    }
+}
+
+/// Drop the interface's IPv4 settings (handing a DHCP lease back) and take
+/// its link down.
+pub fn ifdown(name: &str) -> Result<IfState, ConfError> {
+    let primary = super::iface_is_primary(name).ok_or(ConfError::NoSuchIface)?;
+    if primary {
+        // the RELEASE has to go out before the link does
+        if !dhcp::release() {
+            dhcp::forget();
+            super::clear_ipv4_config();
+        }
+        super::arp::flush();
+    }
+    super::set_iface_up(name, false);
+    Ok(IfState::Down)
+}
+
+/// `ifup -a` and boot: globals, then every interface in the file in order;
+/// `off` stanzas are taken down. Errs only if the file can't be parsed.
+pub fn up_all() -> Result<Report, ConfError> {
+    let conf = load()?;
+    apply_globals(&conf);
+    let mut out = Report::new();
+    for name in conf.names() {
+        let r = match conf.iface(&name).map(|s| s.method) {
+            Some(Method::Off) => ifdown(&name),
+            _ => up_with(&conf, &name),
+        };
+        out.push((name, r));
+    }
+    Ok(out)
+}
+
+/// `ifdown -a`: every interface the file names.
+pub fn down_all() -> Result<Report, ConfError> {
+    let conf = load()?;
+    Ok(conf.names().into_iter().map(|n| {
+        let r = ifdown(&n);
+        (n, r)
+    }).collect())
+}
+
+/// The once-per-boot `up_all`; None if it already ran. Stanzas for
+/// interfaces this machine doesn't have come back as `Absent`.
+pub fn boot() -> Option<Result<Report, ConfError>> {
+    if APPLIED.swap(true, Ordering::SeqCst) { return None; }
+    Some(up_all().map(|report| {
+        report.into_iter().map(|(n, r)| match r {
+            Err(ConfError::NoSuchIface) => (n, Ok(IfState::Absent)),
+            r => (n, r),
+        }).collect()
+    }))
+}
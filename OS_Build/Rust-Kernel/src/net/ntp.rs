@@ -0,0 +1,374 @@
+#![allow(dead_code)]
+// src/net/ntp.rs
+//
+// SNTP client (RFC 4330) that sets the wall clock in time.rs.
+//
+// Servers, in order: the NTP servers from the DHCP lease, then the one set
+// with `set_server` (name or address), then pool.ntp.org. `sync` is the
+// blocking query behind the `ntp` command: a few exchanges per server, the
+// one with the smallest round trip wins, and its offset is applied. After
+// the first success (or as soon as DHCP hands out an NTP server) `tick`,
+// called from `net::poll`, resyncs every `RESYNC_MS` without blocking: it
+// only uses `try_send_to` and `recv_queued`.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::ip::{self, IpAddr};
+use super::udp::{UdpError, UdpSocket};
+use super::{dhcp, dns};
+use crate::fs::SpinLock;
+use crate::time;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NtpError {
+    NoNic,
+    NotConfigured,
+    /// No server name resolved.
+    Dns,
+    Timeout,
+    Malformed,
+    /// Kiss-o'-Death (stratum 0) with its code, e.g. "RATE" or "DENY".
+    KissOfDeath([u8; 4]),
+    /// Server says its own clock isn't synchronized.
+    Unsynchronized,
+    TxFail,
+}
+
+const PORT: u16 = 123;
+const FALLBACK_SERVER: &str = "pool.ntp.org";
+/// Seconds from 1900-01-01 (NTP era 0) to 1970-01-01.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Exchanges per server in `sync`.
+const SAMPLES: usize = 4;
+const REPLY_TIMEOUT_MS: u64 = 1500;
+/// Background resync interval, and the retry interval after a failure.
+const RESYNC_MS: u64 = 3_600_000;
+const RETRY_MS: u64 = 60_000;
+
+/// One server's answer, as used to correct the clock.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub server: IpAddr,
+    /// Server time minus ours when the sample was taken.
+    pub offset_ms: i64,
+    /// Round trip, minus the server's processing time.
+    pub delay_ms: i64,
+    pub stratum: u8,
+    pub leap: u8,
+    pub precision: i8,
+    /// Reference ID: upstream IPv4 address, or an ASCII code at stratum 1.
+    pub ref_id: [u8; 4],
+    pub root_delay_ms: u32,
+    pub root_dispersion_ms: u32,
+}
+
+/// Last successful sync.
+#[derive(Clone, Copy, Debug)]
+pub struct SyncInfo {
+    pub sample: Sample,
+    /// `time::uptime_ms()` when it was applied.
+    pub at_ms: u64,
+    /// How many corrections have been applied since boot.
+    pub count: u32,
+}
+
+/// A background request in flight.
+struct Pending {
+    sock: UdpSocket,
+    server: IpAddr,
+    /// Our transmit timestamp, echoed back as the origin timestamp.
+    xmit: u64,
+    t1_ms: u64,
+    sent_at: u64,
+}
+
+struct Client {
+    configured: Option<String>,
+    /// Addresses of the configured/fallback server from the last lookup,
+    /// so `tick` can use them without DNS.
+    resolved: Vec<IpAddr>,
+    last: Option<SyncInfo>,
+    next_at: u64,
+    next_server: usize,
+    pending: Option<Pending>,
+}
+
+static CLIENT: SpinLock<Client> = SpinLock::new(Client {
+    configured: None,
+    resolved: Vec::new(),
+    last: None,
+    next_at: 0,
+    next_server: 0,
+    pending: None,
+});
+
+fn udp_err(e: UdpError) -> NtpError {
+    match e {
+        UdpError::NoNic => NtpError::NoNic,
+        UdpError::NotConfigured | UdpError::ArpTimeout => NtpError::NotConfigured,
+        UdpError::Timeout => NtpError::Timeout,
+        _ => NtpError::TxFail,
+    }
+}
+
+pub fn last_sync() -> Option<SyncInfo> {
+    CLIENT.lock().last
+}
+
+/// Configured server (name or address); `None` = DHCP / pool.ntp.org only.
+pub fn server() -> Option<String> {
+    CLIENT.lock().configured.clone()
+}
+
+pub fn set_server(name: Option<&str>) {
+    let mut c = CLIENT.lock();
+    c.configured = name.map(String::from);
+    c.resolved.clear();
+    c.next_server = 0;
+}
+
+/// Servers from the DHCP lease, then the configured one (resolved now, so
+/// this may poll), then the pool. Deduplicated.
+pub fn servers() -> Vec<IpAddr> {
+    let mut out: Vec<IpAddr> = Vec::new();
+    let mut add = |a: IpAddr| {
+        if !out.contains(&a) { out.push(a); }
+    };
+    if let Some(l) = dhcp::lease() {
+        for s in l.ntp.iter() { add(IpAddr::V4(*s)); }
+    }
+    let name = server().unwrap_or_else(|| String::from(FALLBACK_SERVER));
+    let resolved = match ip::parse(&name) {
+        Some(a) => alloc::vec![a],
+        None => dns::resolve_a(&name).map(|a| alloc::vec![IpAddr::V4(a)]).unwrap_or_default(),
+    };
+    for a in resolved.iter() { add(*a); }
+    CLIENT.lock().resolved = resolved;
+    out
+}
+
+/// Servers `tick` may use: the lease's plus what `servers` last resolved.
+fn cached_servers(c: &Client) -> Vec<IpAddr> {
+    let mut out: Vec<IpAddr> = Vec::new();
+    if let Some(l) = dhcp::lease() {
+        for s in l.ntp.iter() { out.push(IpAddr::V4(*s)); }
+    }
+    for a in c.resolved.iter() {
+        if !out.contains(a) { out.push(*a); }
+    }
+    out
+}
+
+// -----------------------------------------------------------------------------
+// Packets
+// -----------------------------------------------------------------------------
+
+fn unix_ms_to_ntp(ms: u64) -> u64 {
+    let secs = ms / 1000 + NTP_UNIX_OFFSET;
+    let frac = ((ms % 1000) << 32) / 1000;
+    (secs << 32) | frac
+}
+
+fn ntp_to_unix_ms(ts: u64) -> i64 {
+    let secs = (ts >> 32) as i64 - NTP_UNIX_OFFSET as i64;
+    let frac_ms = (((ts & 0xFFFF_FFFF) * 1000) >> 32) as i64;
+    secs * 1000 + frac_ms
+}
+
+/// NTP short format (16.16 seconds) in ms.
+fn short_to_ms(v: u32) -> u32 {
+    ((v as u64 * 1000) >> 16) as u32
+}
+
+fn be64(b: &[u8]) -> u64 {
+    let mut x = [0u8; 8];
+    x.copy_from_slice(&b[..8]);
+    u64::from_be_bytes(x)
+}
+
+fn be32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+/// Client request; returns it with the transmit timestamp it carries. The
+/// low bits of the fraction are random so replies can't be guessed.
+fn request(t1_ms: u64) -> ([u8; 48], u64) {
+    let mut p = [0u8; 48];
+    p[0] = (4 << 3) | 3; // LI 0, version 4, mode 3 (client)
+    let xmit = unix_ms_to_ntp(t1_ms) ^ (time::rdtsc() & 0x3FFFFF);
+    p[40..48].copy_from_slice(&xmit.to_be_bytes());
+    (p, xmit)
+}
+
+/// Check a server reply and work out offset and delay (RFC 4330 section 5).
+/// `t1_ms`/`t4_ms` are our wall-clock send and receive times; they come from
+/// `time::now_unix_ms`, which is TSC-based (ms resolution) even before the
+/// first sync.
+fn parse_reply(p: &[u8], server: IpAddr, xmit: u64, t1_ms: u64, t4_ms: u64) -> Result<Sample, NtpError> {
+    if p.len() < 48 { return Err(NtpError::Malformed); }
+    let leap = p[0] >> 6;
+    let mode = p[0] & 7;
+    let stratum = p[1];
+    if mode != 4 && mode != 5 { return Err(NtpError::Malformed); }
+    if be64(&p[24..32]) != xmit { return Err(NtpError::Malformed); }
+    let ref_id = [p[12], p[13], p[14], p[15]];
+    if stratum == 0 { return Err(NtpError::KissOfDeath(ref_id)); }
+    if leap == 3 || stratum > 15 { return Err(NtpError::Unsynchronized); }
+    let (t2, t3) = (be64(&p[32..40]), be64(&p[40..48]));
+    if t3 == 0 { return Err(NtpError::Malformed); }
+
+    let (t1, t4) = (t1_ms as i64, t4_ms as i64);
+    let (t2, t3) = (ntp_to_unix_ms(t2), ntp_to_unix_ms(t3));
+    Ok(Sample {
+        server,
+        offset_ms: ((t2 - t1) + (t3 - t4)) / 2,
+        delay_ms: ((t4 - t1) - (t3 - t2)).max(0),
+        stratum,
+        leap,
+        precision: p[3] as i8,
+        ref_id,
+        root_delay_ms: short_to_ms(be32(&p[4..8])),
+        root_dispersion_ms: short_to_ms(be32(&p[8..12])),
+    })
+}
+
+// -----------------------------------------------------------------------------
+// Blocking query
+// -----------------------------------------------------------------------------
+
+/// One exchange with `server`.
+pub fn query(server: IpAddr) -> Result<Sample, NtpError> {
+    let mut sock = UdpSocket::bind(0).map_err(udp_err)?;
+    let t1 = time::now_unix_ms();
+    let (req, xmit) = request(t1);
+    sock.send_to(&req, server, PORT).map_err(udp_err)?;
+
+    // one deadline for the whole exchange: stray datagrams must not restart it
+    let deadline = time::uptime_ms() + REPLY_TIMEOUT_MS;
+    let mut buf = [0u8; 128];
+    loop {
+        let now = time::uptime_ms();
+        if now >= deadline { return Err(NtpError::Timeout); }
+        sock.set_read_timeout(Some(deadline - now));
+        let (n, src, port) = sock.recv_from(&mut buf).map_err(udp_err)?;
+        let t4 = time::now_unix_ms();
+        if src != server || port != PORT { continue; }
+        match parse_reply(&buf[..n], server, xmit, t1, t4) {
+            // a stray or forged reply: keep waiting for ours
+            Err(NtpError::Malformed) => continue,
+            r => return r,
+        }
+    }
+}
+
+/// Query the servers in order, keep the best sample of the first that
+/// answers, and correct the wall clock by its offset.
+pub fn sync() -> Result<Sample, NtpError> {
+    let list = servers();
+    if list.is_empty() { return Err(NtpError::Dns); }
+    let mut err = NtpError::Timeout;
+    for &server in list.iter() {
+        let mut best: Option<Sample> = None;
+        for _ in 0..SAMPLES {
+            match query(server) {
+                Ok(s) => {
+                    if best.is_none_or(|b| s.delay_ms < b.delay_ms) { best = Some(s); }
+                }
+                Err(e @ NtpError::KissOfDeath(_)) => {
+                    err = e;
+                    break;
+                }
+                Err(e) => err = e,
+            }
+        }
+        if let Some(s) = best {
+            apply(s);
+            return Ok(s);
+        }
+    }
+    CLIENT.lock().next_at = time::uptime_ms() + RETRY_MS;
+    Err(err)
+}
+
+fn apply(s: Sample) {
+    let now = time::now_unix_ms() as i64;
+    time::set_wall_clock((now + s.offset_ms).max(0) as u64);
+    let mut c = CLIENT.lock();
+    let count = c.last.map_or(0, |l| l.count).wrapping_add(1);
+    let at_ms = time::uptime_ms();
+    c.last = Some(SyncInfo { sample: s, at_ms, count });
+    c.next_at = at_ms + RESYNC_MS;
+}
+
+// -----------------------------------------------------------------------------
+// Background resync
+// -----------------------------------------------------------------------------
+
+pub fn tick() {
+    let now = time::uptime_ms();
+    let mut c = CLIENT.lock();
+
+    if let Some(p) = c.pending.as_ref() {
+        let mut buf = [0u8; 128];
+        let mut got = None;
+        while let Some((n, src, port)) = p.sock.recv_queued(&mut buf) {
+            if src != p.server || port != PORT { continue; }
+            match parse_reply(&buf[..n], p.server, p.xmit, p.t1_ms, time::now_unix_ms()) {
+                Err(NtpError::Malformed) => continue,
+                r => {
+                    got = Some(r);
+                    break;
+                }
+            }
+        }
+        match got {
+            Some(Ok(s)) => {
+                c.pending = None;
+                drop(c);
+                apply(s);
+            }
+            Some(Err(_)) => {
+                // try the next server on the next round
+                c.pending = None;
+                c.next_server = c.next_server.wrapping_add(1);
+                c.next_at = now + RETRY_MS;
+            }
+            None if now >= p.sent_at + REPLY_TIMEOUT_MS => {
+                c.pending = None;
+                c.next_server = c.next_server.wrapping_add(1);
+                c.next_at = now + RETRY_MS;
+            }
+            None => {}
+        }
+        return;
+    }
+
+    if now < c.next_at || super::config().ip == [0, 0, 0, 0] { return; }
+    let list = cached_servers(&c);
+    if list.is_empty() {
+        // nothing we can reach without DNS; `ntp sync` fills the cache
+        c.next_at = now + RETRY_MS;
+        return;
+    }
+    let server = list[c.next_server % list.len()];
+    let Ok(sock) = UdpSocket::bind(0) else { return };
+    let t1 = time::now_unix_ms();
+    let (req, xmit) = request(t1);
+    match sock.try_send_to(&req, server, PORT) {
+        Ok(_) => c.pending = Some(Pending { sock, server, xmit, t1_ms: t1, sent_at: now }),
+        // next hop not resolved yet (the request for it is out): soon again
+        Err(UdpError::ArpTimeout) => c.next_at = now + 1000,
+        Err(_) => c.next_at = now + RETRY_MS,
+    }
+}
+
+/// Write the corrected time back to the CMOS RTC (as UTC).
+pub fn write_rtc() -> bool {
+    if !time::wall_clock_set() { return false; }
+    time::rtc_write(time::now());
+    true
+}
@@ -0,0 +1,545 @@
+#![allow(dead_code)]
+// src/net/pcap.rs
+//
+// Packet capture at the NIC boundary. `Interface::send_frame` and
+// `Interface::poll_recv` hand every frame to `capture_tx`/`capture_rx`; while
+// a capture is running, frames that pass its filter are timestamped and
+//   - appended to an in-memory libpcap file (written out by the shell on stop),
+//   - or streamed as libpcap records to COM1,
+//   - and/or queued for the `tcpdump` live view (`next_live`).
+// The hooks run with the interface table locked, so they never print, touch
+// the file system or send anything; they only copy into `CAPTURE`.
+//
+// `summary` decodes Ethernet/ARP/IPv4/IPv6/ICMP/UDP/TCP into one tcpdump-style
+// line, and `Filter` is the small "proto / host / port" language both
+// commands accept.
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::str;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::dns;
+use super::ip::{self, IpAddr};
+use crate::fs::SpinLock;
+use crate::time;
+
+/// Default capture file.
+pub const DEFAULT_PATH: &str = "/var/capture.pcap";
+/// Bytes kept of each frame (a whole frame always fits).
+const SNAPLEN: u32 = 65535;
+/// LINKTYPE_ETHERNET.
+const LINKTYPE_ETHERNET: u32 = 1;
+/// In-memory capture file limit; later frames are counted as dropped. `stop`
+/// copies the buffer into the file, so this stays well under the 4 MiB heap.
+const MAX_FILE_BYTES: usize = 512 * 1024;
+/// Frames waiting for the live view before the oldest are dropped.
+const LIVE_QUEUE: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dir {
+    Rx,
+    Tx,
+}
+
+/// Where captured frames go besides the live view.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Sink {
+    None,
+    /// Buffered in memory, saved to this path by `stop`'s caller.
+    File(String),
+    /// libpcap records written straight to COM1.
+    Serial,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcapError {
+    /// A capture is already running.
+    Busy,
+    /// No capture is running.
+    NotRunning,
+    /// Unknown filter word, or a term missing its argument.
+    BadFilter,
+    /// `host <name>` didn't resolve.
+    Dns,
+}
+
+/// One captured frame, as handed to the live view.
+pub struct Frame {
+    /// Microseconds since the Unix epoch.
+    pub ts_us: u64,
+    pub dir: Dir,
+    pub data: Vec<u8>,
+}
+
+/// Counters of a capture, while running or as returned by `stop`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CaptureStats {
+    /// Frames that passed the filter.
+    pub packets: u32,
+    /// Frames lost to the file size limit or a full live queue.
+    pub dropped: u32,
+    /// Size of the capture file so far (0 unless the sink is a file).
+    pub file_bytes: usize,
+}
+
+/// Result of `stop`: what to save, and the final counters.
+pub struct Finished {
+    pub path: Option<String>,
+    pub data: Vec<u8>,
+    pub stats: CaptureStats,
+}
+
+// -----------------------------------------------------------------------------
+// Filters
+// -----------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Term {
+    Arp,
+    Ip,
+    Ip6,
+    Icmp,
+    Icmp6,
+    Tcp,
+    Udp,
+    Host(IpAddr),
+    Port(u16),
+}
+
+/// Terms ANDed together, each possibly negated: `tcp port 80`,
+/// `host 10.0.2.2 and not arp`, `udp and not port 53`. Empty matches all.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    terms: Vec<(bool, Term)>,
+}
+
+impl Filter {
+    /// Parse filter words; `host` accepts an address or a name (resolved now).
+    pub fn parse(words: &[&str]) -> Result<Filter, PcapError> {
+        let mut terms = Vec::new();
+        let mut negate = false;
+        let mut it = words.iter();
+        while let Some(&w) = it.next() {
+            let t = match w {
+                "and" | "&&" => continue,
+                "not" | "!" => { negate = !negate; continue; }
+                "arp" => Term::Arp,
+                "ip" => Term::Ip,
+                "ip6" => Term::Ip6,
+                "icmp" => Term::Icmp,
+                "icmp6" => Term::Icmp6,
+                "tcp" => Term::Tcp,
+                "udp" => Term::Udp,
+                "host" => {
+                    let h = it.next().ok_or(PcapError::BadFilter)?;
+                    match ip::parse(h) {
+                        Some(a) => Term::Host(a),
+                        None => Term::Host(IpAddr::V4(dns::resolve_a(h).map_err(|_| PcapError::Dns)?)),
+                    }
+                }
+                "port" => {
+                    let p = it.next().and_then(|p| p.parse().ok()).ok_or(PcapError::BadFilter)?;
+                    Term::Port(p)
+                }
+                _ => return Err(PcapError::BadFilter),
+            };
+            terms.push((negate, t));
+            negate = false;
+        }
+        if negate { return Err(PcapError::BadFilter); }
+        Ok(Filter { terms })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn matches(&self, frame: &[u8]) -> bool {
+        if self.terms.is_empty() { return true; }
+        let p = parse(frame);
+        self.terms.iter().all(|&(neg, t)| term_matches(&p, t) != neg)
+    }
+
+    /// The filter written back out, for status lines.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        for &(neg, t) in &self.terms {
+            if !out.is_empty() { out.push_str(" and "); }
+            if neg { out.push_str("not "); }
+            match t {
+                Term::Arp => out.push_str("arp"),
+                Term::Ip => out.push_str("ip"),
+                Term::Ip6 => out.push_str("ip6"),
+                Term::Icmp => out.push_str("icmp"),
+                Term::Icmp6 => out.push_str("icmp6"),
+                Term::Tcp => out.push_str("tcp"),
+                Term::Udp => out.push_str("udp"),
+                Term::Host(a) => out.push_str(&format!("host {}", addr_text(a))),
+                Term::Port(n) => out.push_str(&format!("port {}", n)),
+            }
+        }
+        out
+    }
+}
+
+fn term_matches(p: &Parsed<'_>, t: Term) -> bool {
+    match t {
+        Term::Arp => p.ethertype == ETH_ARP,
+        Term::Ip => p.ethertype == ETH_IPV4,
+        Term::Ip6 => p.ethertype == ETH_IPV6,
+        Term::Icmp => p.ethertype == ETH_IPV4 && p.proto == PROTO_ICMP,
+        Term::Icmp6 => p.ethertype == ETH_IPV6 && p.proto == PROTO_ICMPV6,
+        Term::Tcp => p.proto == PROTO_TCP,
+        Term::Udp => p.proto == PROTO_UDP,
+        Term::Host(a) => p.src == Some(a) || p.dst == Some(a),
+        Term::Port(n) => p.ports().is_some_and(|(s, d)| s == n || d == n),
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Capture state
+// -----------------------------------------------------------------------------
+
+struct Capture {
+    sink: Sink,
+    live: bool,
+    filter: Filter,
+    file: Vec<u8>,
+    queue: VecDeque<Frame>,
+    start_tsc: u64,
+    start_unix_us: u64,
+    stats: CaptureStats,
+}
+
+/// Checked by the hooks before taking the lock, so idle capture costs nothing.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static CAPTURE: SpinLock<Option<Capture>> = SpinLock::new(None);
+
+/// Start capturing into `sink`; `live` also queues frames for `next_live`.
+pub fn start(sink: Sink, filter: Filter, live: bool) -> Result<(), PcapError> {
+    let mut c = CAPTURE.lock();
+    if c.is_some() { return Err(PcapError::Busy); }
+
+    let header = global_header();
+    let file = match sink {
+        Sink::File(_) => header.to_vec(),
+        Sink::Serial => {
+            serial_write(&header);
+            Vec::new()
+        }
+        Sink::None => Vec::new(),
+    };
+    let stats = CaptureStats { file_bytes: file.len(), ..CaptureStats::default() };
+    *c = Some(Capture {
+        sink,
+        live,
+        filter,
+        file,
+        queue: VecDeque::new(),
+        start_tsc: time::rdtsc(),
+        start_unix_us: time::now_unix_ms().saturating_mul(1000),
+        stats,
+    });
+    ACTIVE.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Stop the running capture and hand back the file contents (if any).
+pub fn stop() -> Result<Finished, PcapError> {
+    ACTIVE.store(false, Ordering::Release);
+    let c = CAPTURE.lock().take().ok_or(PcapError::NotRunning)?;
+    let path = match c.sink {
+        Sink::File(p) => Some(p),
+        _ => None,
+    };
+    Ok(Finished { path, data: c.file, stats: c.stats })
+}
+
+pub fn running() -> bool {
+    ACTIVE.load(Ordering::Acquire)
+}
+
+/// Sink, filter text and counters of the running capture.
+pub fn status() -> Option<(Sink, String, CaptureStats)> {
+    CAPTURE.lock().as_ref().map(|c| (c.sink.clone(), c.filter.text(), c.stats))
+}
+
+/// Oldest frame waiting for the live view.
+pub fn next_live() -> Option<Frame> {
+    CAPTURE.lock().as_mut().and_then(|c| c.queue.pop_front())
+}
+
+/// Hook for received frames (whole Ethernet frame, pad/FCS trimmed).
+pub fn capture_rx(frame: &[u8]) {
+    if !ACTIVE.load(Ordering::Acquire) { return; }
+    record(Dir::Rx, frame);
+}
+
+/// Hook for sent frames; the driver builds the header, so rebuild it here.
+pub fn capture_tx(dst: [u8; 6], src: [u8; 6], ethertype: u16, payload: &[u8]) {
+    if !ACTIVE.load(Ordering::Acquire) { return; }
+    let mut frame = Vec::with_capacity(14 + payload.len());
+    frame.extend_from_slice(&dst);
+    frame.extend_from_slice(&src);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    record(Dir::Tx, &frame);
+}
+
+fn record(dir: Dir, frame: &[u8]) {
+    let mut g = CAPTURE.lock();
+    let Some(c) = g.as_mut() else { return };
+    if !c.filter.matches(frame) { return; }
+    c.stats.packets = c.stats.packets.wrapping_add(1);
+
+    let ts_us = c.start_unix_us + time::tsc_to_us(time::rdtsc().wrapping_sub(c.start_tsc));
+    let keep = frame.len().min(SNAPLEN as usize);
+    let mut rec = [0u8; 16];
+    rec[0..4].copy_from_slice(&((ts_us / 1_000_000) as u32).to_le_bytes());
+    rec[4..8].copy_from_slice(&((ts_us % 1_000_000) as u32).to_le_bytes());
+    rec[8..12].copy_from_slice(&(keep as u32).to_le_bytes());
+    rec[12..16].copy_from_slice(&(frame.len() as u32).to_le_bytes());
+
+    match c.sink {
+        Sink::File(_) => {
+            if c.file.len() + rec.len() + keep > MAX_FILE_BYTES || c.file.try_reserve(rec.len() + keep).is_err() {
+                c.stats.dropped = c.stats.dropped.wrapping_add(1);
+            } else {
+                c.file.extend_from_slice(&rec);
+                c.file.extend_from_slice(&frame[..keep]);
+                c.stats.file_bytes = c.file.len();
+            }
+        }
+        Sink::Serial => {
+            serial_write(&rec);
+            serial_write(&frame[..keep]);
+        }
+        Sink::None => {}
+    }
+
+    if c.live {
+        if c.queue.len() >= LIVE_QUEUE {
+            c.queue.pop_front();
+            c.stats.dropped = c.stats.dropped.wrapping_add(1);
+        }
+        c.queue.push_back(Frame { ts_us, dir, data: frame.to_vec() });
+    }
+}
+
+/// libpcap file header: v2.4, microsecond timestamps, Ethernet.
+fn global_header() -> [u8; 24] {
+    let mut h = [0u8; 24];
+    h[0..4].copy_from_slice(&0xA1B2_C3D4u32.to_le_bytes());
+    h[4..6].copy_from_slice(&2u16.to_le_bytes());
+    h[6..8].copy_from_slice(&4u16.to_le_bytes());
+    // thiszone and sigfigs stay 0
+    h[16..20].copy_from_slice(&SNAPLEN.to_le_bytes());
+    h[20..24].copy_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+    h
+}
+
+fn serial_write(bytes: &[u8]) {
+    for &b in bytes {
+        crate::serial::serial_write_byte(b);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Decoding
+// -----------------------------------------------------------------------------
+
+const ETH_IPV4: u16 = 0x0800;
+const ETH_ARP: u16 = 0x0806;
+const ETH_IPV6: u16 = 0x86DD;
+const PROTO_ICMP: u8 = 1;
+const PROTO_TCP: u8 = 6;
+const PROTO_UDP: u8 = 17;
+const PROTO_ICMPV6: u8 = 58;
+const PROTO_FRAGMENT: u8 = 44;
+
+/// The parts of a frame filters and summaries look at.
+struct Parsed<'a> {
+    ethertype: u16,
+    src: Option<IpAddr>,
+    dst: Option<IpAddr>,
+    /// L4 protocol / next header; 0 when there is none.
+    proto: u8,
+    /// L4 header and payload; empty for non-first fragments.
+    l4: &'a [u8],
+    /// Fragment offset in bytes and the more-fragments flag, if fragmented.
+    frag: Option<(usize, bool)>,
+}
+
+impl Parsed<'_> {
+    fn ports(&self) -> Option<(u16, u16)> {
+        if (self.proto == PROTO_TCP || self.proto == PROTO_UDP) && self.l4.len() >= 4 {
+            Some((u16::from_be_bytes([self.l4[0], self.l4[1]]), u16::from_be_bytes([self.l4[2], self.l4[3]])))
+        } else {
+            None
+        }
+    }
+}
+
+fn parse(frame: &[u8]) -> Parsed<'_> {
+    let mut p = Parsed { ethertype: 0, src: None, dst: None, proto: 0, l4: &[], frag: None };
+    if frame.len() < 14 { return p; }
+    p.ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+    let body = &frame[14..];
+    match p.ethertype {
+        ETH_ARP if body.len() >= 28 => {
+            p.src = Some(IpAddr::V4([body[14], body[15], body[16], body[17]]));
+            p.dst = Some(IpAddr::V4([body[24], body[25], body[26], body[27]]));
+        }
+        ETH_IPV4 if body.len() >= 20 => {
+            let ihl = ((body[0] & 0x0F) as usize) * 4;
+            let total = (u16::from_be_bytes([body[2], body[3]]) as usize).min(body.len());
+            p.src = Some(IpAddr::V4([body[12], body[13], body[14], body[15]]));
+            p.dst = Some(IpAddr::V4([body[16], body[17], body[18], body[19]]));
+            p.proto = body[9];
+            let ff = u16::from_be_bytes([body[6], body[7]]);
+            let off = ((ff & 0x1FFF) as usize) * 8;
+            let mf = ff & 0x2000 != 0;
+            if off != 0 || mf { p.frag = Some((off, mf)); }
+            if off == 0 && ihl >= 20 && ihl <= total { p.l4 = &body[ihl..total]; }
+        }
+        ETH_IPV6 if body.len() >= 40 => {
+            let len = (u16::from_be_bytes([body[4], body[5]]) as usize).min(body.len() - 40);
+            let mut src = [0u8; 16];
+            let mut dst = [0u8; 16];
+            src.copy_from_slice(&body[8..24]);
+            dst.copy_from_slice(&body[24..40]);
+            p.src = Some(IpAddr::V6(src));
+            p.dst = Some(IpAddr::V6(dst));
+            p.proto = body[6];
+            p.l4 = &body[40..40 + len];
+            if p.proto == PROTO_FRAGMENT && p.l4.len() >= 8 {
+                let fo = u16::from_be_bytes([p.l4[2], p.l4[3]]);
+                let off = (fo & 0xFFF8) as usize;
+                p.frag = Some((off, fo & 1 != 0));
+                p.proto = p.l4[0];
+                p.l4 = if off == 0 { &p.l4[8..] } else { &[] };
+            }
+        }
+        _ => {}
+    }
+    p
+}
+
+fn addr_text(a: IpAddr) -> String {
+    let mut b = [0u8; 40];
+    let n = ip::write(&mut b, a);
+    String::from(str::from_utf8(&b[..n]).unwrap_or("?"))
+}
+
+fn mac_text(m: &[u8]) -> String {
+    format!("{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", m[0], m[1], m[2], m[3], m[4], m[5])
+}
+
+/// Well-known UDP services, for the summary line.
+fn udp_service(sport: u16, dport: u16) -> Option<&'static str> {
+    [sport, dport].iter().find_map(|&p| match p {
+        53 => Some("DNS"),
+        67 | 68 => Some("DHCP"),
+        123 => Some("NTP"),
+        546 | 547 => Some("DHCPv6"),
+        5353 => Some("mDNS"),
+        _ => None,
+    })
+}
+
+fn icmp_text(v6: bool, m: &[u8]) -> String {
+    if m.len() < 4 { return String::from("truncated"); }
+    let echo = |what: &str| {
+        if m.len() >= 8 {
+            format!("{}, id {}, seq {}", what, u16::from_be_bytes([m[4], m[5]]), u16::from_be_bytes([m[6], m[7]]))
+        } else {
+            String::from(what)
+        }
+    };
+    match (v6, m[0]) {
+        (false, 0) | (true, 129) => echo("echo reply"),
+        (false, 8) | (true, 128) => echo("echo request"),
+        (false, 3) | (true, 1) => format!("{} (code {})", super::unreachable_text(v6, m[1]), m[1]),
+        (false, 11) | (true, 3) => String::from("time exceeded in-transit"),
+        (true, 2) => String::from("packet too big"),
+        (true, 133) => String::from("router solicitation"),
+        (true, 134) => String::from("router advertisement"),
+        (true, 135) if m.len() >= 24 => format!("neighbor solicitation, who has {}", addr_text(IpAddr::V6(m[8..24].try_into().unwrap_or([0; 16])))),
+        (true, 136) if m.len() >= 24 => format!("neighbor advertisement, tgt is {}", addr_text(IpAddr::V6(m[8..24].try_into().unwrap_or([0; 16])))),
+        (_, t) => format!("type {}, code {}", t, m[1]),
+    }
+}
+
+fn tcp_flags(f: u8) -> String {
+    let mut s = String::new();
+    for (bit, c) in [(0x02, 'S'), (0x01, 'F'), (0x04, 'R'), (0x08, 'P'), (0x20, 'U')] {
+        if f & bit != 0 { s.push(c); }
+    }
+    if f & 0x10 != 0 { s.push('.'); }
+    if s.is_empty() { s.push_str("none"); }
+    s
+}
+
+/// One-line tcpdump-style description of an Ethernet frame.
+pub fn summary(frame: &[u8]) -> String {
+    if frame.len() < 14 { return format!("runt frame, length {}", frame.len()); }
+    let p = parse(frame);
+    let body = &frame[14..];
+    match p.ethertype {
+        ETH_ARP => {
+            if body.len() < 28 { return format!("ARP, truncated, length {}", body.len()); }
+            let (Some(sender), Some(target)) = (p.src, p.dst) else { return String::from("ARP") };
+            match u16::from_be_bytes([body[6], body[7]]) {
+                1 => format!("ARP, Request who-has {} tell {}, length {}", addr_text(target), addr_text(sender), body.len()),
+                2 => format!("ARP, Reply {} is-at {}, length {}", addr_text(sender), mac_text(&body[8..14]), body.len()),
+                op => format!("ARP, op {}, length {}", op, body.len()),
+            }
+        }
+        ETH_IPV4 | ETH_IPV6 => {
+            let (Some(src), Some(dst)) = (p.src, p.dst) else {
+                return format!("{}, truncated, length {}", if p.ethertype == ETH_IPV4 { "IP" } else { "IP6" }, body.len());
+            };
+            let v6 = p.ethertype == ETH_IPV6;
+            let fam = if v6 { "IP6" } else { "IP" };
+            let (s, d) = (addr_text(src), addr_text(dst));
+            if let Some((off, mf)) = p.frag {
+                if off != 0 {
+                    return format!("{} {} > {}: frag offset {}{}, proto {}", fam, s, d, off, if mf { "+" } else { "" }, p.proto);
+                }
+            }
+            let l4 = p.l4;
+            match p.proto {
+                PROTO_TCP if l4.len() >= 20 => {
+                    let (sp, dp) = p.ports().unwrap_or((0, 0));
+                    let seq = u32::from_be_bytes([l4[4], l4[5], l4[6], l4[7]]);
+                    let ack = u32::from_be_bytes([l4[8], l4[9], l4[10], l4[11]]);
+                    let doff = ((l4[12] >> 4) as usize) * 4;
+                    let flags = l4[13];
+                    let win = u16::from_be_bytes([l4[14], l4[15]]);
+                    let len = l4.len().saturating_sub(doff);
+                    let mut out = format!("{} {}.{} > {}.{}: Flags [{}], seq {}", fam, s, sp, d, dp, tcp_flags(flags), seq);
+                    if flags & 0x10 != 0 { out.push_str(&format!(", ack {}", ack)); }
+                    out.push_str(&format!(", win {}, length {}", win, len));
+                    out
+                }
+                PROTO_UDP if l4.len() >= 8 => {
+                    let (sp, dp) = p.ports().unwrap_or((0, 0));
+                    let len = l4.len() - 8;
+                    match udp_service(sp, dp) {
+                        Some(svc) => format!("{} {}.{} > {}.{}: UDP ({}), length {}", fam, s, sp, d, dp, svc, len),
+                        None => format!("{} {}.{} > {}.{}: UDP, length {}", fam, s, sp, d, dp, len),
+                    }
+                }
+                PROTO_ICMP if !v6 => format!("{} {} > {}: ICMP {}, length {}", fam, s, d, icmp_text(false, l4), l4.len()),
+                PROTO_ICMPV6 if v6 => format!("{} {} > {}: ICMP6, {}, length {}", fam, s, d, icmp_text(true, l4), l4.len()),
+                proto => format!("{} {} > {}: proto {}, length {}", fam, s, d, proto, l4.len()),
+            }
+        }
+        t => format!("{} > {}, ethertype 0x{:04x}, length {}", mac_text(&frame[6..12]), mac_text(&frame[0..6]), t, frame.len()),
+    }
+}
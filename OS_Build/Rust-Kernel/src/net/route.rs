@@ -0,0 +1,124 @@
+#![allow(dead_code)]
+// src/net/route.rs
+//
+// IPv4 routing table. 127.0.0.0/8 always points at `lo`, and two routes
+// follow the interface config: the connected subnet (on-link) and the
+// default route via the configured gateway. Static routes added with `route add` sit alongside them. Lookup
+// picks the longest matching prefix; a static route wins a tie, so
+// `route add default via ...` overrides the DHCP gateway.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use crate::fs::SpinLock;
+
+const MAX_ROUTES: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteKind {
+    /// 127.0.0.0/8 on `lo`.
+    Loopback,
+    /// Our own subnet, from the address and mask.
+    Connected,
+    /// Via the configured gateway.
+    Default,
+    /// Added by hand.
+    Static,
+}
+
+#[derive(Clone, Copy)]
+pub struct Route {
+    pub dest: [u8; 4],
+    pub mask: [u8; 4],
+    /// 0.0.0.0 = on-link (deliver straight to the destination).
+    pub gateway: [u8; 4],
+    pub kind: RouteKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteError {
+    /// Mask bits aren't contiguous.
+    BadMask,
+    Exists,
+    NotFound,
+    Full,
+    /// Loopback/connected/default routes follow the interface config and can't be deleted.
+    Fixed,
+}
+
+static STATIC: SpinLock<Vec<Route>> = SpinLock::new(Vec::new());
+
+fn apply(ip: [u8; 4], mask: [u8; 4]) -> [u8; 4] {
+    [ip[0] & mask[0], ip[1] & mask[1], ip[2] & mask[2], ip[3] & mask[3]]
+}
+
+/// Prefix length of a contiguous mask.
+pub fn prefix_len(mask: [u8; 4]) -> Option<u8> {
+    let m = u32::from_be_bytes(mask);
+    let len = m.leading_ones();
+    if m.checked_shl(len).unwrap_or(0) != 0 { return None; }
+    Some(len as u8)
+}
+
+pub fn mask_from_len(len: u8) -> [u8; 4] {
+    let len = len.min(32) as u32;
+    let m = if len == 0 { 0 } else { u32::MAX << (32 - len) };
+    m.to_be_bytes()
+}
+
+/// Routes derived from the interface config.
+fn derived() -> Vec<Route> {
+    let cfg = super::config();
+    let mut out = Vec::new();
+    out.push(Route { dest: [127, 0, 0, 0], mask: [255, 0, 0, 0], gateway: [0, 0, 0, 0], kind: RouteKind::Loopback });
+    if cfg.ip != [0, 0, 0, 0] && cfg.mask != [0, 0, 0, 0] {
+        out.push(Route { dest: apply(cfg.ip, cfg.mask), mask: cfg.mask, gateway: [0, 0, 0, 0], kind: RouteKind::Connected });
+    }
+    if cfg.gateway != [0, 0, 0, 0] {
+        out.push(Route { dest: [0, 0, 0, 0], mask: [0, 0, 0, 0], gateway: cfg.gateway, kind: RouteKind::Default });
+    }
+    out
+}
+
+pub fn add(dest: [u8; 4], mask: [u8; 4], gateway: [u8; 4]) -> Result<(), RouteError> {
+    prefix_len(mask).ok_or(RouteError::BadMask)?;
+    let dest = apply(dest, mask);
+    let mut t = STATIC.lock();
+    if t.iter().any(|r| r.dest == dest && r.mask == mask) { return Err(RouteError::Exists); }
+    if t.len() >= MAX_ROUTES { return Err(RouteError::Full); }
+    t.push(Route { dest, mask, gateway, kind: RouteKind::Static });
+    Ok(())
+}
+
+pub fn del(dest: [u8; 4], mask: [u8; 4]) -> Result<(), RouteError> {
+    let dest = apply(dest, mask);
+    let mut t = STATIC.lock();
+    if let Some(i) = t.iter().position(|r| r.dest == dest && r.mask == mask) {
+        t.remove(i);
+        return Ok(());
+    }
+    drop(t);
+    if derived().iter().any(|r| r.dest == dest && r.mask == mask) {
+        return Err(RouteError::Fixed);
+    }
+    Err(RouteError::NotFound)
+}
+
+pub fn flush() {
+    STATIC.lock().clear();
+}
+
+/// Every route, most specific first.
+pub fn routes() -> Vec<Route> {
+    let mut all = STATIC.lock().clone();
+    all.extend(derived());
+    all.sort_by_key(|r| core::cmp::Reverse(prefix_len(r.mask).unwrap_or(0)));
+    all
+}
+
+/// Best route for `dst`: longest prefix, static before derived.
+pub fn lookup(dst: [u8; 4]) -> Option<Route> {
+    // `routes` is stable-sorted with static routes first within a length
+    routes().into_iter().find(|r| apply(dst, r.mask) == r.dest)
+}
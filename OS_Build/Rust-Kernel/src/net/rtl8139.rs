@@ -0,0 +1,283 @@
+#![allow(dead_code)]
+// src/net/rtl8139.rs
+//
+// Realtek RTL8139 driver (port I/O, polled).
+//
+// IMPORTANT (DMA):
+// - RBSTART / TSAD* must be PHYSICAL addresses.
+// - This code assumes your kernel & these statics are identity-mapped below 4GiB.
+//   If your kernel is higher-half, you MUST translate virt->phys for these buffers.
+//
+// IMPORTANT (RX ring):
+// - The RTL8139 receive ring "wraps" at 8 KiB, but hardware can write into an
+//   "overflow" area appended after the ring. We allocate RX_RING + 16 + 2048,
+//   and read packets linearly using that overflow area (no modulo indexing).
+
+use core::ptr;
+
+use crate::portio::{inb, inl, inw, outb, outl, outw};
+use crate::time;
+
+const RTL_VENDOR_ID: u16 = 0x10ec;
+const RTL_DEVICE_ID: u16 = 0x8139;
+
+// RTL8139 registers (I/O base + offset)
+const IDR0: u16 = 0x00;
+const TSD0: u16 = 0x10;
+const TSAD0: u16 = 0x20;
+const RBSTART: u16 = 0x30;
+const CAPR: u16 = 0x38;
+const CBR: u16 = 0x3A;
+const IMR: u16 = 0x3C;
+const ISR: u16 = 0x3E;
+const TCR: u16 = 0x40;
+const RCR: u16 = 0x44;
+const CR: u16 = 0x37;
+const MSR: u16 = 0x58;
+
+const CR_RESET: u8 = 0x10;
+const CR_RX_ENABLE: u8 = 0x08;
+const CR_TX_ENABLE: u8 = 0x04;
+
+const MSR_LINKB: u8 = 0x04;
+
+// RX ring (wraps at 8 KiB)
+const RX_RING_LEN: usize = 8192;
+const RX_OVERFLOW: usize = 16 + 2048;
+const RX_BUFFER_SIZE: usize = RX_RING_LEN + RX_OVERFLOW;
+
+const TX_BUF_SIZE: usize = 1600;
+const NUM_TX: usize = 4;
+
+#[repr(align(16))]
+struct AlignedRx([u8; RX_BUFFER_SIZE]);
+
+#[link_section = ".nic_rx_buffer"]
+static mut RX_BUFFER: AlignedRx = AlignedRx([0u8; RX_BUFFER_SIZE]);
+
+#[link_section = ".nic_tx_buffers"]
+static mut TX_BUFFERS: [[u8; TX_BUF_SIZE]; NUM_TX] = [[0u8; TX_BUF_SIZE]; NUM_TX];
+
+static mut RX_SCRATCH: [u8; 2048] = [0u8; 2048];
+const RX_SCRATCH_LEN: usize = 2048;
+
+pub struct Rtl8139 {
+    io: u16,
+    pub mac: [u8; 6],
+    rx_off: usize, // 0..RX_RING_LEN
+    tx_cur: usize,
+    rx_dropped: u32,
+}
+
+impl Rtl8139 {
+    /// Find the card on PCI and bring it up.
+    pub fn probe() -> Option<Self> {
+        pci_find_rtl8139_io().map(Self::init)
+    }
+
+    fn init(io: u16) -> Self {
+        let mut mac = [0u8; 6];
+
+        unsafe {
+            // reset
+            outb(io + CR, CR_RESET);
+            while (inb(io + CR) & CR_RESET) != 0 {
+                time::cpu_pause();
+            }
+
+            // read MAC
+            for i in 0..6 {
+                mac[i] = inb(io + IDR0 + i as u16);
+            }
+
+            // program RX buffer physical address (DMA needs physical addresses)
+            // Use a raw const pointer to the static to avoid creating a shared
+            // reference to a `static mut` (Rust 2024 compatibility).
+            let rx_virt = &raw const RX_BUFFER as *const _ as u64;
+            let rx_phys = crate::bootinfo::virt_to_phys_u32(rx_virt).unwrap_or(0);
+            outl(io + RBSTART, rx_phys);
+
+            // RX config:
+            // - accept physical match (bit1) + broadcast (bit3)
+            // - wrap (bit7)
+            // - MXDMA = unlimited (bits 10:8 = 111)
+            let rcr = (1u32 << 0) | (1u32 << 1) | (1u32 << 3) | (1u32 << 7) | (0x7u32 << 8);
+            outl(io + RCR, rcr);
+
+            // TX config: MXDMA unlimited
+            outl(io + TCR, 0x7u32 << 8);
+
+            // Enable RX OK in IMR (we poll, but some QEMU models behave better with it enabled)
+            outw(io + IMR, 0x0001);
+            outw(io + ISR, 0xFFFF);
+
+            // enable RX/TX
+            outb(io + CR, CR_RX_ENABLE | CR_TX_ENABLE);
+
+            // CAPR is "read pointer - 16"
+            outw(io + CAPR, 0xFFF0);
+        }
+
+        Self { io, mac, rx_off: 0, tx_cur: 0, rx_dropped: 0 }
+    }
+
+    pub fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool {
+        // Ethernet minimum frame size is 60 bytes (excluding 4-byte CRC).
+        // If we transmit runt frames, some backends/emulations will drop them,
+        // which breaks ARP and small TCP packets (e.g. SYN is 58 bytes).
+        let total = 14 + payload.len();
+        let tx_total = core::cmp::max(total, 60);
+        if tx_total > TX_BUF_SIZE {
+            return false;
+        }
+
+        // Pick a TX descriptor that looks idle. Some emulations will drop frames if we
+// stomp an in-flight descriptor.
+let mut idx = self.tx_cur % NUM_TX;
+for _ in 0..NUM_TX {
+    let tsd = unsafe { inl(self.io + TSD0 + (idx as u16) * 4) };
+    // OWN (bit13) is typically set while the NIC owns the descriptor.
+    // If an emulation uses different semantics, this just becomes a best-effort heuristic.
+    let own = (tsd & (1u32 << 13)) != 0;
+    if !own { break; }
+    idx = (idx + 1) % NUM_TX;
+}
+self.tx_cur = (idx + 1) % NUM_TX;
+
+        unsafe {
+            let buf = &mut TX_BUFFERS[idx];
+            buf[0..6].copy_from_slice(&dst);
+            buf[6..12].copy_from_slice(&self.mac);
+            buf[12..14].copy_from_slice(&ethertype.to_be_bytes());
+            buf[14..14 + payload.len()].copy_from_slice(payload);
+            if tx_total > total {
+                // Zero padding to satisfy minimum frame size.
+                for b in buf[14 + payload.len()..tx_total].iter_mut() { *b = 0; }
+            }
+
+            let tx_virt = &TX_BUFFERS[idx] as *const _ as u64;
+            let phys = crate::bootinfo::virt_to_phys_u32(tx_virt).unwrap_or(0);
+            outl(self.io + TSAD0 + (idx as u16) * 4, phys);
+            outl(self.io + TSD0 + (idx as u16) * 4, tx_total as u32);
+        }
+
+        true
+    }
+
+    pub fn poll_recv(&mut self) -> Option<&'static [u8]> {
+        // Poll ring write pointer (CBR) instead of gating on ISR bits.
+        let cbr = unsafe { inw(self.io + CBR) as usize } & (RX_RING_LEN - 1);
+        if cbr == self.rx_off {
+            return None;
+        }
+
+        unsafe {
+            // Obtain a raw pointer to the RX buffer contents without creating a
+            // shared reference to the `static mut`.
+            let ring_ptr = &raw const RX_BUFFER.0 as *const u8;
+
+            // Header is 4 bytes at rx_off (status + length), potentially in the overflow region.
+            let off = self.rx_off;
+            let status = u16::from_le_bytes([
+                ptr::read_volatile(ring_ptr.add(off)),
+                ptr::read_volatile(ring_ptr.add(off + 1)),
+            ]);
+            let len_raw = u16::from_le_bytes([
+                ptr::read_volatile(ring_ptr.add(off + 2)),
+                ptr::read_volatile(ring_ptr.add(off + 3)),
+            ]) as usize;
+
+            // Length is the whole frame bytes (usually includes CRC). Keep it conservative.
+            if len_raw < 14 || len_raw > 2048 {
+                self.rx_dropped = self.rx_dropped.wrapping_add(1);
+                self.advance_rx(align4(4 + len_raw));
+                outw(self.io + ISR, 0xFFFF);
+                return None;
+            }
+
+            // Some emulators/NICs report the length including the 4-byte Ethernet FCS (CRC),
+            // others do not. If we blindly subtract 4 we can truncate ARP/IP packets and then
+            // everything (ARP, DHCP, TCP) mysteriously times out.
+            //
+            // Strategy:
+            // 1) Copy exactly `len_raw` bytes into RX_SCRATCH (bounded).
+            // 2) Trim the returned slice using L2 ethertype + IPv4 total length when possible.
+            let start = off + 4;
+
+            let copy_len = len_raw.min(RX_SCRATCH_LEN);
+            if start + copy_len <= RX_BUFFER_SIZE {
+                // Fast path: contiguous region available in the backing buffer.
+                // Copy using volatile reads to avoid creating shared refs.
+                let mut j = 0usize;
+                while j < copy_len {
+                    RX_SCRATCH[j] = ptr::read_volatile(ring_ptr.add(start + j));
+                    j += 1;
+                }
+            } else {
+                // Extremely rare fallback (shouldn't happen with our overflow size)
+                let mut j = 0usize;
+                while j < copy_len {
+                    let src = (start + j) & (RX_RING_LEN - 1);
+                    RX_SCRATCH[j] = ptr::read_volatile(ring_ptr.add(src));
+                    j += 1;
+                }
+            }
+
+            // Advance rx ptr: header(4) + len_raw, aligned to dword, wrap at 8KiB.
+            self.advance_rx(align4(4 + len_raw));
+
+            // ack everything (we poll)
+            outw(self.io + ISR, 0xFFFF);
+
+            if (status & 0x0001) == 0 {
+                self.rx_dropped = self.rx_dropped.wrapping_add(1);
+                return None;
+            }
+
+            let frame = &RX_SCRATCH[..copy_len];
+            Some(&frame[..super::trimmed_len(frame)])
+        }
+    }
+
+    pub fn link_up(&self) -> bool {
+        // MSR.LINKB is active low
+        unsafe { (inb(self.io + MSR) & MSR_LINKB) == 0 }
+    }
+
+    fn advance_rx(&mut self, bytes: usize) {
+        self.rx_off = (self.rx_off + bytes) & (RX_RING_LEN - 1);
+
+        // CAPR wants (rx_off - 16) modulo ring length.
+        let capr = (self.rx_off.wrapping_sub(16) & (RX_RING_LEN - 1)) as u16;
+        unsafe { outw(self.io + CAPR, capr); }
+    }
+}
+
+impl super::iface::NetDevice for Rtl8139 {
+    fn driver(&self) -> &'static str { "rtl8139" }
+    fn mac(&self) -> [u8; 6] { self.mac }
+    fn link_up(&self) -> bool { Rtl8139::link_up(self) }
+    fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool {
+        Rtl8139::send_frame(self, dst, ethertype, payload)
+    }
+    fn poll_recv(&mut self) -> Option<&'static [u8]> { Rtl8139::poll_recv(self) }
+    fn rx_dropped(&self) -> u32 { self.rx_dropped }
+}
+
+#[inline(always)]
+fn align4(x: usize) -> usize { (x + 3) & !3 }
+
+// -----------------------------------------------------------------------------
+// PCI helper
+// -----------------------------------------------------------------------------
+
+fn pci_find_rtl8139_io() -> Option<u16> {
+    let dev = crate::pci::find(RTL_VENDOR_ID, RTL_DEVICE_ID)?;
+    // BAR0
+    let io = dev.io_bar(0)?;
+
+    // enable I/O + bus master
+    dev.enable(crate::pci::CMD_IO | crate::pci::CMD_BUS_MASTER);
+
+    Some(io)
+}
@@ -1,22 +1,40 @@
 #![allow(dead_code)]
 
-//! Minimal TCP client (polling, single-thread friendly).
+//! TCP (polling, single-thread friendly).
+//!
+//! Every connection and listener is a `Tcb` in one table. The demux hands
+//! each segment to `input`, which runs the RFC 793 state machine and buffers
+//! received data per connection; `TcpStream` / `TcpListener` are handles
+//! into the table, so any number of connections progress while one of them
+//! is being read. `tick` (run from `net::poll`) drives the timers:
+//! retransmission, TIME_WAIT expiry and reaping of abandoned sockets.
 //!
 //! Supports:
-//! - Active open (SYN -> SYN/ACK -> ACK)
-//! - In-order receive buffering
-//! - Basic transmit (chunked to ~MSS)
+//! - Active open (SYN -> SYN/ACK -> ACK) and passive open via `TcpListener`
+//! - All RFC 793 states, including simultaneous open/close and TIME_WAIT
+//! - Retransmission on an RTO derived from measured RTT (RFC 6298, Karn's
+//!   rule), zero-window probing
+//! - NewReno congestion control: slow start, congestion avoidance, fast
+//!   retransmit and fast recovery with partial ACKs (RFC 5681, RFC 6582)
+//! - Out-of-order segments are held and merged once the gap is filled
+//! - Window scaling (RFC 7323); the advertised window is the free space in
+//!   the receive buffer
+//! - IPv4 and IPv6; listeners accept both
 //!
 //! Limitations:
-//! - No out-of-order reassembly
-//! - No full retransmission strategy
-//! - Small fixed window
+//! - No SACK, timestamps or delayed ACKs
 
 extern crate alloc;
 
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+use crate::fs::SpinLock;
 use crate::time;
 
+use super::demux::DropReason;
+use super::ip::{self, IpAddr};
+use super::ipv4::{self, IpError};
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TcpError {
     NoNic,
@@ -26,169 +44,1290 @@ pub enum TcpError {
     TxFail,
     Reset,
     Proto,
+    /// Port already bound by a listener.
+    AddrInUse,
+    /// The connection is closing or closed for sending.
+    Closed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+impl TcpState {
+    pub fn name(self) -> &'static str {
+        match self {
+            TcpState::Closed => "CLOSED",
+            TcpState::Listen => "LISTEN",
+            TcpState::SynSent => "SYN_SENT",
+            TcpState::SynReceived => "SYN_RCVD",
+            TcpState::Established => "ESTABLISHED",
+            TcpState::FinWait1 => "FIN_WAIT1",
+            TcpState::FinWait2 => "FIN_WAIT2",
+            TcpState::CloseWait => "CLOSE_WAIT",
+            TcpState::Closing => "CLOSING",
+            TcpState::LastAck => "LAST_ACK",
+            TcpState::TimeWait => "TIME_WAIT",
+        }
+    }
+}
+
+// flags
+const FIN: u16 = 0x01;
+const SYN: u16 = 0x02;
+const RST: u16 = 0x04;
+const PSH: u16 = 0x08;
+const ACK: u16 = 0x10;
+
+/// Receive buffer per connection; the advertised window is what's left of it.
+/// Kept small: every connection (and every unaccepted listener child) can
+/// fill it, and they all share the 4 MiB kernel heap.
+const RX_CAP: usize = 64 * 1024;
+/// Our window scale shift: 65535 << 1 covers RX_CAP.
+const RCV_SHIFT: u8 = 1;
+/// Bytes queued for sending (unacknowledged plus not yet sent).
+const TX_CAP: usize = 64 * 1024;
+/// Out-of-order segments held per connection (a window's worth at 1460 bytes).
+const OOO_MAX: usize = 45;
+/// Over IPv4; the IPv6 header is 20 bytes longer.
+const OUR_MSS: u16 = 1460;
+const DEFAULT_MSS: u16 = 536;
+
+/// Retransmission timeout bounds (RFC 6298), in ms.
+const RTO_INITIAL_MS: u64 = 1_000;
+const RTO_MIN_MS: u64 = 200;
+const RTO_MAX_MS: u64 = 60_000;
+/// Consecutive timeouts before the connection is given up.
+const MAX_RETRIES: u8 = 8;
+const DUPACK_THRESHOLD: u8 = 3;
+
+/// 2*MSL, with a short MSL as most small stacks use.
+const TIME_WAIT_MS: u64 = 30_000;
+/// Abandoned connections stuck in FIN_WAIT2 are dropped after this long.
+const FIN_WAIT2_MS: u64 = 60_000;
+const DEFAULT_BACKLOG: usize = 8;
+
+const EPHEMERAL_BASE: u16 = 49152;
+
+type Id = u32;
+
+struct Tcb {
+    state: TcpState,
+    local_ip: IpAddr,
+    local_port: u16,
+    remote_ip: IpAddr,
+    remote_port: u16,
+
+    // send sequence space; `tx[0]` is the byte at `snd_una`
+    iss: u32,
+    snd_una: u32,
+    snd_nxt: u32,
+    /// Highest sequence number sent (`snd_nxt` is rewound on a timeout).
+    snd_max: u32,
+    snd_wnd: u32,
+    /// SEG.SEQ / SEG.ACK of the segment that last set `snd_wnd`
+    /// (SND.WL1 / SND.WL2), so a stale segment can't shrink it.
+    snd_wl1: u32,
+    snd_wl2: u32,
+    snd_mss: u16,
+    snd_shift: u8,
+    tx: Vec<u8>,
+    /// close() was called: the FIN follows the last byte of `tx`.
+    fin_queued: bool,
+    fin_sent: bool,
+    fin_acked: bool,
+
+    // receive sequence space
+    irs: u32,
+    rcv_nxt: u32,
+    rcv_shift: u8,
+    /// Window in the last segment we sent, in bytes.
+    adv_wnd: u32,
+    rx: Vec<u8>,
+    /// Segments beyond `rcv_nxt`, waiting for the gap to fill.
+    ooo: Vec<(u32, Vec<u8>)>,
+    /// Sequence number of a FIN that arrived ahead of `rcv_nxt`.
+    ooo_fin: Option<u32>,
+    fin_rcvd: bool,
+    reset: bool,
+    /// Retransmissions ran out without an answer from the peer.
+    timed_out: bool,
+    /// Window scale shift from the peer's SYN, if it offered one.
+    peer_ws: Option<u8>,
+
+    // congestion control, in bytes
+    cwnd: u32,
+    ssthresh: u32,
+    dupacks: u8,
+    in_recovery: bool,
+    recover: u32,
+
+    // RTT estimation: one segment is timed at a time
+    srtt: u64,
+    rttvar: u64,
+    rto: u64,
+    rtt_seq: Option<u32>,
+    rtt_start: u64,
+    /// Retransmission deadline (uptime ms), 0 = not armed.
+    rto_at: u64,
+    retries: u8,
+    retransmits: u32,
+
+    // passive open: listener this connection came from, until accepted
+    parent: Option<Id>,
+    backlog: usize,
+
+    /// No handle refers to this TCB; reap once CLOSED.
+    orphan: bool,
+    /// TIME_WAIT / FIN_WAIT2 deadline (uptime ms), 0 = none.
+    timer: u64,
+}
+
+impl Tcb {
+    fn new(state: TcpState, local_port: u16, remote_ip: IpAddr, remote_port: u16) -> Self {
+        let iss = (time::rdtsc() as u32) ^ 0xA5A5_5A5A;
+        Self {
+            state,
+            local_ip: ip::source_for(remote_ip),
+            local_port,
+            remote_ip,
+            remote_port,
+            iss,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_max: iss,
+            snd_wnd: 0,
+            snd_wl1: 0,
+            snd_wl2: iss,
+            snd_mss: DEFAULT_MSS,
+            snd_shift: 0,
+            tx: Vec::new(),
+            fin_queued: false,
+            fin_sent: false,
+            fin_acked: false,
+            irs: 0,
+            rcv_nxt: 0,
+            rcv_shift: 0,
+            adv_wnd: 0,
+            rx: Vec::new(),
+            ooo: Vec::new(),
+            ooo_fin: None,
+            fin_rcvd: false,
+            reset: false,
+            timed_out: false,
+            peer_ws: None,
+            cwnd: 0,
+            ssthresh: u32::MAX,
+            dupacks: 0,
+            in_recovery: false,
+            recover: iss,
+            srtt: 0,
+            rttvar: 0,
+            rto: RTO_INITIAL_MS,
+            rtt_seq: None,
+            rtt_start: 0,
+            rto_at: 0,
+            retries: 0,
+            retransmits: 0,
+            parent: None,
+            backlog: 0,
+            orphan: false,
+            timer: 0,
+        }
+    }
+
+    /// Free receive buffer space, in bytes.
+    fn rcv_wnd(&self) -> u32 {
+        RX_CAP.saturating_sub(self.rx.len()) as u32
+    }
+
+    fn arm(&mut self, ms: u64) {
+        self.timer = time::uptime_ms() + ms;
+    }
+
+    fn arm_rto(&mut self) {
+        self.rto_at = time::uptime_ms() + self.rto;
+    }
+
+    /// 1 while our SYN is still unacknowledged (it sits at `snd_una`).
+    fn syn_pending(&self) -> u32 {
+        (self.snd_una == self.iss) as u32
+    }
+
+    fn in_flight(&self) -> u32 {
+        self.snd_nxt.wrapping_sub(self.snd_una)
+    }
+
+    /// Bytes of `tx` already sent (SYN and FIN take sequence space, not data).
+    fn sent_data(&self) -> usize {
+        let n = self.in_flight().saturating_sub(self.syn_pending() + self.fin_sent as u32);
+        (n as usize).min(self.tx.len())
+    }
+
+    /// The handshake fixed MSS and window scaling: start congestion control.
+    fn synchronize(&mut self, mss: Option<u16>) {
+        self.snd_mss = mss.unwrap_or(DEFAULT_MSS).min(our_mss(self.remote_ip));
+        if let Some(shift) = self.peer_ws {
+            self.snd_shift = shift.min(14);
+            self.rcv_shift = RCV_SHIFT;
+        }
+        // RFC 3390 initial window
+        let mss = self.snd_mss as u32;
+        self.cwnd = (4 * mss).min((2 * mss).max(4380));
+    }
+
+    /// Fold one RTT measurement into SRTT/RTTVAR (RFC 6298 section 2).
+    fn rtt_sample(&mut self, r: u64) {
+        if self.srtt == 0 {
+            self.srtt = r.max(1);
+            self.rttvar = r / 2;
+        } else {
+            self.rttvar = (3 * self.rttvar + self.srtt.abs_diff(r)) / 4;
+            self.srtt = (7 * self.srtt + r) / 8;
+        }
+        self.rto = (self.srtt + (4 * self.rttvar).max(1)).clamp(RTO_MIN_MS, RTO_MAX_MS);
+    }
+
+    /// Why the connection died, if it died abnormally.
+    fn error(&self) -> Option<TcpError> {
+        if self.timed_out {
+            Some(TcpError::Timeout)
+        } else if self.reset {
+            Some(TcpError::Reset)
+        } else {
+            None
+        }
+    }
 }
 
+struct Table {
+    next_id: Id,
+    tcbs: BTreeMap<Id, Tcb>,
+}
+
+static TCP: SpinLock<Table> = SpinLock::new(Table { next_id: 1, tcbs: BTreeMap::new() });
+
+impl Table {
+    fn add(&mut self, tcb: Tcb) -> Id {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        self.tcbs.insert(id, tcb);
+        id
+    }
+
+    fn port_in_use(&self, port: u16) -> bool {
+        self.tcbs.values().any(|t| t.local_port == port)
+    }
+
+    /// Connection for a segment: exact 4-tuple, then a listener on the port.
+    fn find(&self, local_port: u16, remote_ip: IpAddr, remote_port: u16) -> Option<Id> {
+        let mut listener = None;
+        let mut loose = None;
+        for (id, t) in self.tcbs.iter() {
+            if t.local_port != local_port { continue; }
+            if t.state == TcpState::Listen {
+                listener = Some(*id);
+            } else if t.remote_port == remote_port {
+                if t.remote_ip == remote_ip {
+                    return Some(*id);
+                }
+                // Some user-mode NATs present replies with unexpected L3
+                // sources; accept a port match for our own active opens.
+                if t.parent.is_none() && t.state != TcpState::Closed && t.remote_ip.is_v6() == remote_ip.is_v6() {
+                    loose = Some(*id);
+                }
+            }
+        }
+        loose.or(listener)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Sequence arithmetic
+// -----------------------------------------------------------------------------
+
+fn seq_lt(a: u32, b: u32) -> bool { (a.wrapping_sub(b) as i32) < 0 }
+fn seq_le(a: u32, b: u32) -> bool { (a.wrapping_sub(b) as i32) <= 0 }
+
+// -----------------------------------------------------------------------------
+// Output
+// -----------------------------------------------------------------------------
+
 fn write_u16_be(buf: &mut [u8], off: usize, v: u16) { buf[off..off+2].copy_from_slice(&v.to_be_bytes()); }
 fn write_u32_be(buf: &mut [u8], off: usize, v: u32) { buf[off..off+4].copy_from_slice(&v.to_be_bytes()); }
 
-fn tcp_checksum(src_ip: [u8;4], dst_ip: [u8;4], seg: &[u8]) -> u16 {
-    let len = seg.len() as u16;
-    let mut tmp = Vec::with_capacity(12 + seg.len());
-    tmp.extend_from_slice(&src_ip);
-    tmp.extend_from_slice(&dst_ip);
-    tmp.push(0);
-    tmp.push(6);
-    tmp.extend_from_slice(&len.to_be_bytes());
-    tmp.extend_from_slice(seg);
-    super::checksum16(&tmp)
-}
-
-fn route_next_hop(src_ip: [u8;4], dst_ip: [u8;4], mask: [u8;4], gw: [u8;4]) -> [u8;4] {
-    // If mask is 0.0.0.0 (some DHCP failures), treat everything as off-subnet and use the gateway.
-    let mask_is_zero = mask == [0,0,0,0];
-    if !mask_is_zero && (super::same_subnet(src_ip, dst_ip, mask) || gw == [0,0,0,0]) {
-        dst_ip
-    } else if gw != [0,0,0,0] {
-        gw
-    } else {
-        dst_ip
+fn tcp_checksum(src_ip: IpAddr, dst_ip: IpAddr, seg: &[u8]) -> u16 {
+    ip::pseudo_checksum(src_ip, dst_ip, ipv4::PROTO_TCP, seg)
+}
+
+fn our_mss(remote: IpAddr) -> u16 {
+    OUR_MSS - (ip::header_len(remote) - 20) as u16
+}
+
+fn ip_err(e: IpError) -> TcpError {
+    match e {
+        IpError::NoNic => TcpError::NoNic,
+        IpError::NotConfigured => TcpError::NotConfigured,
+        IpError::ArpTimeout => TcpError::ArpTimeout,
+        IpError::TooLarge | IpError::TxFail => TcpError::TxFail,
     }
 }
 
-pub struct TcpStream {
-    remote_ip: [u8;4],
-    remote_port: u16,
-    local_port: u16,
+/// Make sure the next hop for `dst_ip` is in the ARP table (or neighbor
+/// cache). Polls, so never call it with the TCP table locked.
+fn ensure_route(dst_ip: IpAddr) -> Result<(), TcpError> {
+    ip::resolve(dst_ip).map(|_| ()).map_err(ip_err)
+}
+
+fn send_segment_raw(
+    src_ip: IpAddr,
+    dst_ip: IpAddr,
+    src_port: u16,
+    dst_port: u16,
     seq: u32,
     ack: u32,
-    rx: Vec<u8>,
-    fin_seen: bool,
-    rst_seen: bool,
+    flags: u16,
+    window: u16,
+    options: &[u8],
+    payload: &[u8],
+) -> Result<(), TcpError> {
+    let opt_len = options.len();
+    let hdr_len = 20 + opt_len;
+    let tcp_len = hdr_len + payload.len();
+
+    let mut seg = Vec::with_capacity(tcp_len);
+    seg.resize(hdr_len, 0);
+    write_u16_be(&mut seg, 0, src_port);
+    write_u16_be(&mut seg, 2, dst_port);
+    write_u32_be(&mut seg, 4, seq);
+    write_u32_be(&mut seg, 8, ack);
+
+    let data_off_words = (hdr_len / 4) as u8;
+    seg[12] = data_off_words << 4;
+    seg[13] = (flags & 0xFF) as u8;
+    write_u16_be(&mut seg, 14, window);
+    write_u16_be(&mut seg, 16, 0);
+    write_u16_be(&mut seg, 18, 0);
+
+    if opt_len > 0 {
+        seg[20..20+opt_len].copy_from_slice(options);
+    }
+    if !payload.is_empty() {
+        seg.extend_from_slice(payload);
+    }
+
+    let csum = tcp_checksum(src_ip, dst_ip, &seg);
+    write_u16_be(&mut seg, 16, csum);
+
+    // Never polls: if the next hop isn't known yet, the IP layer asks for it
+    // and the retransmission timer (or the peer's) carries the segment.
+    ip::send(src_ip, dst_ip, ipv4::PROTO_TCP, &seg).map_err(ip_err)
 }
 
-impl TcpStream {
-    pub fn connect(remote_ip: [u8;4], remote_port: u16, timeout_spins: u32) -> Result<Self, TcpError> {
-        super::init();
+/// MSS, then NOP + window scale.
+fn syn_opts(mss: u16) -> [u8; 8] {
+    [2, 4, (mss >> 8) as u8, (mss & 0xFF) as u8, 1, 3, 3, RCV_SHIFT]
+}
 
-        let (src_ip, mask, gw, our_mac) = unsafe {
-            if super::NET.rtl.is_none() { return Err(TcpError::NoNic); }
-            if super::NET.cfg.ip == [0,0,0,0] { return Err(TcpError::NotConfigured); }
-            (super::NET.cfg.ip, super::NET.cfg.mask, super::NET.cfg.gateway, super::NET.cfg.mac)
-        };
+fn send(t: &mut Tcb, seq: u32, flags: u16, payload: &[u8]) -> Result<(), TcpError> {
+    let syn = (flags & SYN) != 0;
+    // window scale goes in every active SYN, and in a SYN/ACK only if the
+    // peer offered it
+    let all = syn_opts(our_mss(t.remote_ip));
+    let opts: &[u8] = if !syn {
+        &[]
+    } else if t.state == TcpState::SynSent || t.peer_ws.is_some() {
+        &all
+    } else {
+        &all[..4]
+    };
 
-        let next_hop = route_next_hop(src_ip, remote_ip, mask, gw);
-        let dst_mac = match super::arp_resolve(next_hop, our_mac, src_ip) {
-            Ok(m) => m,
-            Err(super::PingError::ArpTimeout) => return Err(TcpError::ArpTimeout),
-            Err(super::PingError::NoNic) => return Err(TcpError::NoNic),
-            Err(super::PingError::NotConfigured) => return Err(TcpError::NotConfigured),
-            _ => return Err(TcpError::Timeout),
-        };
+    // the window in a SYN is never scaled
+    let shift = if syn { 0 } else { t.rcv_shift };
+    let window = (t.rcv_wnd() >> shift).min(0xFFFF) as u16;
+    t.adv_wnd = (window as u32) << shift;
 
-        let local_port: u16 = 49152u16.wrapping_add((time::rdtsc() as u16) & 0x3FFF);
-        let iss: u32 = (time::rdtsc() as u32) ^ 0xA5A5_5A5A;
+    let ack = if (flags & ACK) != 0 { t.rcv_nxt } else { 0 };
+    send_segment_raw(t.local_ip, t.remote_ip, t.local_port, t.remote_port,
+                     seq, ack, flags, window, opts, payload)
+}
 
-        // SYN options: MSS 1460 (kind=2,len=4,val=0x05B4) + pad to 4 bytes
-        let opts = [2u8, 4u8, 0x05u8, 0xB4u8];
+fn send_ack(t: &mut Tcb) {
+    let _ = send(t, t.snd_nxt, ACK, &[]);
+}
 
-        // SYN retry loop
-        for _ in 0..3 {
-            Self::send_segment_raw(src_ip, remote_ip, dst_mac, local_port, remote_port, iss, 0, 0x02, 4096, &opts, &[])?;
+fn send_syn(t: &mut Tcb) {
+    let flags = if t.state == TcpState::SynSent { SYN } else { SYN | ACK };
+    let _ = send(t, t.iss, flags, &[]);
+}
 
-            let mut spins: u32 = 0;
-            while spins < timeout_spins {
-                spins = spins.wrapping_add(1);
-                time::cpu_pause();
+/// Send queued data, then the FIN, as far as the peer's window and the
+/// congestion window allow.
+fn output(t: &mut Tcb) {
+    if !matches!(t.state,
+        TcpState::Established | TcpState::CloseWait | TcpState::FinWait1 | TcpState::Closing | TcpState::LastAck) {
+        return;
+    }
+
+    while !t.fin_sent {
+        let sent = t.sent_data();
+        let unsent = t.tx.len() - sent;
+
+        if unsent == 0 {
+            if t.fin_queued {
+                let seq = t.snd_nxt;
+                let _ = send(t, seq, FIN | ACK, &[]);
+                t.snd_nxt = t.snd_nxt.wrapping_add(1);
+                t.fin_sent = true;
+                if seq_lt(t.snd_max, t.snd_nxt) { t.snd_max = t.snd_nxt; }
+                if t.rto_at == 0 { t.arm_rto(); }
+            }
+            break;
+        }
+
+        let room = t.snd_wnd.min(t.cwnd).saturating_sub(t.in_flight()) as usize;
+        let take = unsent.min(t.snd_mss as usize).min(room);
+        if take == 0 {
+            // window closed: the RTO doubles as the persist timer
+            if t.rto_at == 0 { t.arm_rto(); }
+            break;
+        }
+
+        let seq = t.snd_nxt;
+        let chunk = t.tx[sent..sent + take].to_vec();
+        let flags = if take == unsent { ACK | PSH } else { ACK };
+        if send(t, seq, flags, &chunk).is_err() {
+            // next hop not resolved yet; the RTO tries again
+            if t.rto_at == 0 { t.arm_rto(); }
+            break;
+        }
+        // Karn: only time segments carrying new data
+        if t.rtt_seq.is_none() && seq == t.snd_max {
+            t.rtt_seq = Some(seq.wrapping_add(take as u32));
+            t.rtt_start = time::uptime_ms();
+        }
+        t.snd_nxt = t.snd_nxt.wrapping_add(take as u32);
+        if seq_lt(t.snd_max, t.snd_nxt) { t.snd_max = t.snd_nxt; }
+        if t.rto_at == 0 { t.arm_rto(); }
+    }
+}
+
+/// Resend the segment at `snd_una` (data, or the FIN once all data is
+/// acked). A zero-window probe sends a single byte.
+fn retransmit_first(t: &mut Tcb, probe: bool) {
+    t.retransmits = t.retransmits.wrapping_add(1);
+    t.rtt_seq = None;
+
+    let seq = t.snd_una;
+    if !t.tx.is_empty() {
+        let len = if probe { 1 } else { t.tx.len().min(t.snd_mss as usize) };
+        let chunk = t.tx[..len].to_vec();
+        let _ = send(t, seq, ACK | PSH, &chunk);
+        let end = seq.wrapping_add(len as u32);
+        if seq_lt(t.snd_nxt, end) { t.snd_nxt = end; }
+        if seq_lt(t.snd_max, end) { t.snd_max = end; }
+    } else if t.fin_sent {
+        let _ = send(t, seq, FIN | ACK, &[]);
+    }
+}
+
+/// Move `n` bytes out of the receive buffer, telling the peer once the
+/// window has opened by a useful amount (RFC 1122 receiver-side SWS).
+fn consume_rx(t: &mut Tcb, n: usize) {
+    if n == 0 { return; }
+    t.rx.drain(..n);
+    let grown = t.rcv_wnd().saturating_sub(t.adv_wnd);
+    if grown >= 2 * t.snd_mss as u32
+        && matches!(t.state, TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2)
+    {
+        send_ack(t);
+    }
+}
+
+/// RST in response to `seg` (from `src` to `dst`) when it matches no
+/// connection or is unacceptable in its state (RFC 793 p.36).
+fn send_reset_for(src: IpAddr, dst: IpAddr, seg: &[u8]) {
+    let sport = u16::from_be_bytes([seg[0], seg[1]]);
+    let dport = u16::from_be_bytes([seg[2], seg[3]]);
+    let seq = u32::from_be_bytes([seg[4], seg[5], seg[6], seg[7]]);
+    let ack = u32::from_be_bytes([seg[8], seg[9], seg[10], seg[11]]);
+    let flags = seg[13] as u16;
+    let len = (seg.len() - ((seg[12] >> 4) as usize) * 4) as u32;
+    if (flags & RST) != 0 { return; }
+    if (flags & ACK) != 0 {
+        let _ = send_segment_raw(dst, src, dport, sport, ack, 0, RST, 0, &[], &[]);
+    } else {
+        let seg_len = len + (flags & SYN != 0) as u32 + (flags & FIN != 0) as u32;
+        let _ = send_segment_raw(dst, src, dport, sport, 0, seq.wrapping_add(seg_len), RST | ACK, 0, &[], &[]);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Input: the state machine
+// -----------------------------------------------------------------------------
+
+/// MSS and window scale shift from SYN options.
+fn parse_syn_options(opts: &[u8]) -> (Option<u16>, Option<u8>) {
+    let mut mss = None;
+    let mut ws = None;
+    let mut i = 0usize;
+    while i < opts.len() {
+        match opts[i] {
+            0 => break,
+            1 => { i += 1; continue; }
+            kind => {
+                if i + 1 >= opts.len() { break; }
+                let len = opts[i + 1] as usize;
+                if len < 2 || i + len > opts.len() { break; }
+                if kind == 2 && len == 4 {
+                    mss = Some(u16::from_be_bytes([opts[i + 2], opts[i + 3]]));
+                } else if kind == 3 && len == 3 {
+                    ws = Some(opts[i + 2]);
+                }
+                i += len;
+            }
+        }
+    }
+    (mss, ws)
+}
+
+/// One TCP segment (header + payload) from the demux.
+pub fn input(src: IpAddr, dst: IpAddr, seg: &[u8]) -> Result<(), DropReason> {
+    if tcp_checksum(src, dst, seg) != 0 {
+        return Err(DropReason::Malformed);
+    }
+
+    let sport = u16::from_be_bytes([seg[0], seg[1]]);
+    let dport = u16::from_be_bytes([seg[2], seg[3]]);
+    let seq = u32::from_be_bytes([seg[4], seg[5], seg[6], seg[7]]);
+    let ack = u32::from_be_bytes([seg[8], seg[9], seg[10], seg[11]]);
+    let data_off = ((seg[12] >> 4) as usize) * 4;
+    let flags = seg[13] as u16;
+    let wnd = u16::from_be_bytes([seg[14], seg[15]]);
+    let opts = &seg[20..data_off];
+    let payload = &seg[data_off..];
+
+    let mut table = TCP.lock();
+    let Some(id) = table.find(dport, src, sport) else {
+        drop(table);
+        send_reset_for(src, dst, seg);
+        return Err(DropReason::NoSocket);
+    };
+
+    let state = table.tcbs[&id].state;
+    match state {
+        TcpState::Closed => {
+            drop(table);
+            send_reset_for(src, dst, seg);
+            Err(DropReason::NoSocket)
+        }
+        TcpState::Listen => {
+            if (flags & RST) != 0 { return Err(DropReason::Unsupported); }
+            if (flags & ACK) != 0 || (flags & SYN) == 0 {
+                drop(table);
+                send_reset_for(src, dst, seg);
+                return Err(DropReason::Unsupported);
+            }
+
+            let backlog = table.tcbs[&id].backlog;
+            let pending = table.tcbs.values().filter(|t| t.parent == Some(id)).count();
+            if pending >= backlog {
+                return Err(DropReason::QueueFull);
+            }
+
+            let (mss, ws) = parse_syn_options(opts);
+            let mut child = Tcb::new(TcpState::SynReceived, dport, src, sport);
+            child.local_ip = dst;
+            child.parent = Some(id);
+            child.irs = seq;
+            child.rcv_nxt = seq.wrapping_add(1);
+            child.snd_nxt = child.iss.wrapping_add(1);
+            child.snd_max = child.snd_nxt;
+            child.snd_wnd = wnd as u32;
+            child.snd_wl1 = seq;
+            child.peer_ws = ws;
+            child.synchronize(mss);
+            child.arm_rto();
+            send_syn(&mut child);
+            table.add(child);
+            Ok(())
+        }
+        TcpState::SynSent => {
+            let t = table.tcbs.get_mut(&id).unwrap();
+            let ack_ok = (flags & ACK) != 0
+                && seq_lt(t.iss, ack) && seq_le(ack, t.snd_nxt);
+            if (flags & ACK) != 0 && !ack_ok {
+                drop(table);
+                send_reset_for(src, dst, seg);
+                return Err(DropReason::Unsupported);
+            }
+            if (flags & RST) != 0 {
+                if ack_ok {
+                    t.reset = true;
+                    t.state = TcpState::Closed;
+                    t.rto_at = 0;
+                    return Ok(());
+                }
+                return Err(DropReason::Unsupported);
+            }
+            if (flags & SYN) == 0 {
+                return Err(DropReason::Unsupported);
+            }
+
+            let (mss, ws) = parse_syn_options(opts);
+            t.irs = seq;
+            t.rcv_nxt = seq.wrapping_add(1);
+            t.snd_wnd = wnd as u32;
+            t.snd_wl1 = seq;
+            t.peer_ws = ws;
+            t.synchronize(mss);
+            if ack_ok {
+                t.snd_wl2 = ack;
+                t.snd_una = ack;
+                t.state = TcpState::Established;
+                t.rto_at = 0;
+                t.retries = 0;
+                if t.rtt_seq.take().is_some() {
+                    t.rtt_sample(time::uptime_ms() - t.rtt_start);
+                }
+                // data riding on the SYN/ACK
+                if !payload.is_empty() {
+                    deliver(t, payload);
+                }
+                send_ack(t);
+            } else {
+                // simultaneous open
+                t.state = TcpState::SynReceived;
+                t.arm_rto();
+                send_syn(t);
+            }
+            Ok(())
+        }
+        _ => {
+            let t = table.tcbs.get_mut(&id).unwrap();
+            let r = synchronized(t, seq, ack, flags, wnd, payload);
+            if t.state == TcpState::Closed && t.parent.is_some() {
+                // half-open child reset before accept: forget it
+                table.tcbs.remove(&id);
+            }
+            r
+        }
+    }
+}
+
+/// ACK processing for a synchronized connection: slide the send window,
+/// take an RTT sample, and run NewReno (RFC 5681 / RFC 6582).
+fn process_ack(t: &mut Tcb, seq: u32, ack: u32, wnd: u32, has_data: bool) {
+    let mss = t.snd_mss as u32;
+
+    if seq_lt(t.snd_una, ack) {
+        let acked = ack.wrapping_sub(t.snd_una) - t.syn_pending();
+        if t.fin_queued && acked as usize > t.tx.len() {
+            t.fin_acked = true;
+            t.fin_sent = true;
+        }
+        let n = (acked as usize).min(t.tx.len());
+        t.tx.drain(..n);
+        t.snd_una = ack;
+        // an ACK for data sent before a timeout rewound snd_nxt
+        if seq_lt(t.snd_nxt, ack) { t.snd_nxt = ack; }
+        update_window(t, seq, ack, wnd);
+        t.retries = 0;
+        t.dupacks = 0;
+
+        if let Some(s) = t.rtt_seq {
+            if seq_le(s, ack) {
+                t.rtt_seq = None;
+                t.rtt_sample(time::uptime_ms() - t.rtt_start);
+            }
+        }
+
+        if t.in_recovery {
+            if seq_le(t.recover, ack) {
+                // full ACK: leave fast recovery
+                t.in_recovery = false;
+                t.cwnd = t.ssthresh.min(t.in_flight().max(mss) + mss);
+            } else {
+                // partial ACK: the next hole is lost too
+                retransmit_first(t, false);
+                t.cwnd = t.cwnd.saturating_sub(acked).saturating_add(mss);
+            }
+        } else if t.cwnd < t.ssthresh {
+            t.cwnd = t.cwnd.saturating_add(acked.min(mss));
+        } else {
+            t.cwnd = t.cwnd.saturating_add((mss * mss / t.cwnd.max(1)).max(1));
+        }
+
+        t.rto_at = 0;
+        if t.snd_una != t.snd_max { t.arm_rto(); }
+    } else if ack == t.snd_una {
+        if !has_data && wnd == t.snd_wnd && t.snd_una != t.snd_max {
+            t.dupacks = t.dupacks.saturating_add(1);
+            if t.dupacks == DUPACK_THRESHOLD && !t.in_recovery && seq_lt(t.recover, ack) {
+                // fast retransmit, then fast recovery
+                t.ssthresh = (t.in_flight() / 2).max(2 * mss);
+                t.recover = t.snd_max;
+                t.in_recovery = true;
+                retransmit_first(t, false);
+                t.cwnd = t.ssthresh + 3 * mss;
+            } else if t.in_recovery {
+                t.cwnd = t.cwnd.saturating_add(mss);
+            }
+        }
+        update_window(t, seq, ack, wnd);
+    }
+}
+
+/// Take the peer's window from a segment no older than the one it was
+/// last taken from (RFC 793 p.72).
+fn update_window(t: &mut Tcb, seq: u32, ack: u32, wnd: u32) {
+    if seq_lt(t.snd_wl1, seq) || (t.snd_wl1 == seq && seq_le(t.snd_wl2, ack)) {
+        t.snd_wnd = wnd;
+        t.snd_wl1 = seq;
+        t.snd_wl2 = ack;
+    }
+}
 
-                if let Some((flags, seq_r, ack_r, payload)) = Self::poll_for_segment(src_ip, remote_ip, local_port, remote_port) {
-                    if (flags & 0x04) != 0 { return Err(TcpError::Reset); } // RST
-                    if (flags & 0x12) == 0x12 { // SYN|ACK
-                        if ack_r != iss.wrapping_add(1) { continue; }
-                        let ack = seq_r.wrapping_add(1);
-
-                        // ACK to finish handshake
-                        Self::send_segment_raw(src_ip, remote_ip, dst_mac, local_port, remote_port, iss.wrapping_add(1), ack, 0x10, 4096, &[], &[])?;
-
-                        let mut s = TcpStream {
-                            remote_ip,
-                            remote_port,
-                            local_port,
-                            seq: iss.wrapping_add(1),
-                            ack,
-                            rx: Vec::new(),
-                            fin_seen: false,
-                            rst_seen: false,
-                        };
-
-                        if !payload.is_empty() {
-                            s.rx.extend_from_slice(payload);
-                            s.ack = s.ack.wrapping_add(payload.len() as u32);
-                            let _ = s.send_ack();
-                        }
-
-                        return Ok(s);
+/// Append in-order data, then whatever of the out-of-order queue it makes
+/// contiguous. When the heap can't hold it the data is left unacknowledged
+/// for the peer to retransmit.
+fn deliver(t: &mut Tcb, data: &[u8]) {
+    let take = data.len().min(t.rcv_wnd() as usize);
+    if t.rx.try_reserve(take).is_err() { return; }
+    t.rx.extend_from_slice(&data[..take]);
+    t.rcv_nxt = t.rcv_nxt.wrapping_add(take as u32);
+
+    let mut i = 0usize;
+    while i < t.ooo.len() {
+        let start = t.ooo[i].0;
+        let end = start.wrapping_add(t.ooo[i].1.len() as u32);
+        if seq_le(end, t.rcv_nxt) {
+            t.ooo.swap_remove(i);
+        } else if seq_le(start, t.rcv_nxt) {
+            let skip = t.rcv_nxt.wrapping_sub(start) as usize;
+            let n = (t.ooo[i].1.len() - skip).min(t.rcv_wnd() as usize);
+            if t.rx.try_reserve(n).is_err() { return; }
+            let (_, seg) = t.ooo.swap_remove(i);
+            t.rx.extend_from_slice(&seg[skip..skip + n]);
+            t.rcv_nxt = t.rcv_nxt.wrapping_add(n as u32);
+            // rcv_nxt moved: earlier entries may now fit
+            i = 0;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Segment arrival in SYN_RCVD and every later state (RFC 793 p.69 onwards).
+fn synchronized(t: &mut Tcb, seq: u32, ack: u32, flags: u16, wnd: u16, payload: &[u8]) -> Result<(), DropReason> {
+    let mut data = payload;
+    let seg_seq = seq;
+    let mut seq = seq;
+
+    // 1. acceptability against the receive window, trimming any overlap
+    //    with bytes already received
+    let seg_len = data.len() as u32;
+    let rcv_wnd = t.rcv_wnd();
+    let win_end = t.rcv_nxt.wrapping_add(rcv_wnd);
+    let acceptable = if seg_len == 0 {
+        seq == t.rcv_nxt || (seq_le(t.rcv_nxt, seq) && seq_lt(seq, win_end))
+    } else if rcv_wnd == 0 {
+        false
+    } else {
+        let last = seq.wrapping_add(seg_len - 1);
+        (seq_le(t.rcv_nxt, seq) && seq_lt(seq, win_end))
+            || (seq_le(t.rcv_nxt, last) && seq_lt(last, win_end))
+    };
+    if !acceptable {
+        if (flags & RST) == 0 { send_ack(t); }
+        return Err(DropReason::Unsupported);
+    }
+    if seq_lt(seq, t.rcv_nxt) {
+        let skip = t.rcv_nxt.wrapping_sub(seq) as usize;
+        data = &data[skip.min(data.len())..];
+        seq = t.rcv_nxt;
+    }
+
+    // 2. RST
+    if (flags & RST) != 0 {
+        t.reset = !matches!(t.state, TcpState::TimeWait | TcpState::Closing | TcpState::LastAck);
+        t.state = TcpState::Closed;
+        t.timer = 0;
+        t.rto_at = 0;
+        return Ok(());
+    }
+
+    // 4. SYN in the window is an error
+    if (flags & SYN) != 0 {
+        let _ = send(t, t.snd_nxt, RST, &[]);
+        t.reset = true;
+        t.state = TcpState::Closed;
+        t.rto_at = 0;
+        return Ok(());
+    }
+
+    // 5. ACK
+    if (flags & ACK) == 0 {
+        return Err(DropReason::Unsupported);
+    }
+    let wnd = (wnd as u32) << t.snd_shift;
+    if t.state == TcpState::SynReceived {
+        if seq_lt(t.snd_una, ack) && seq_le(ack, t.snd_nxt) {
+            t.state = TcpState::Established;
+            t.snd_una = ack;
+            t.snd_wnd = wnd;
+            t.snd_wl1 = seg_seq;
+            t.snd_wl2 = ack;
+            t.rto_at = 0;
+            t.retries = 0;
+        } else {
+            let _ = send(t, ack, RST, &[]);
+            return Err(DropReason::Unsupported);
+        }
+    } else if seq_lt(t.snd_max, ack) {
+        // acks something we never sent
+        send_ack(t);
+        return Err(DropReason::Unsupported);
+    } else {
+        process_ack(t, seg_seq, ack, wnd, !data.is_empty());
+    }
+
+    let fin_acked = t.fin_acked;
+    match t.state {
+        TcpState::FinWait1 if fin_acked => {
+            t.state = TcpState::FinWait2;
+            if t.orphan { t.arm(FIN_WAIT2_MS); }
+        }
+        TcpState::Closing if fin_acked => {
+            t.state = TcpState::TimeWait;
+            t.arm(TIME_WAIT_MS);
+        }
+        TcpState::LastAck if fin_acked => {
+            t.state = TcpState::Closed;
+            t.rto_at = 0;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // 7. segment text
+    let mut need_ack = false;
+    if !data.is_empty() {
+        if matches!(t.state, TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2) {
+            if seq != t.rcv_nxt {
+                // hold it, and ACK at once: the duplicate ACK lets the peer
+                // fast-retransmit the hole (a FIN on it is taken later)
+                let keep = data.len().min(win_end.wrapping_sub(seq) as usize);
+                let mut seg = Vec::new();
+                if t.ooo.len() < OOO_MAX
+                    && !t.ooo.iter().any(|(s, _)| *s == seq)
+                    && seg.try_reserve_exact(keep).is_ok()
+                    && t.ooo.try_reserve(1).is_ok()
+                {
+                    seg.extend_from_slice(&data[..keep]);
+                    t.ooo.push((seq, seg));
+                    if (flags & FIN) != 0 && keep == data.len() {
+                        t.ooo_fin = Some(seq.wrapping_add(keep as u32));
                     }
                 }
+                send_ack(t);
+                output(t);
+                return Ok(());
+            }
+            let before = t.rcv_nxt;
+            deliver(t, data);
+            // only the part of the segment itself counts toward a FIN on it
+            if t.rcv_nxt.wrapping_sub(before) < data.len() as u32 {
+                send_ack(t);
+                return Ok(());
+            }
+        }
+        need_ack = true;
+    }
+
+    // 8. FIN (only once everything before it has been taken; one that came
+    //    early is remembered until the gap in front of it fills)
+    let fin_end = seq.wrapping_add(data.len() as u32);
+    let mut fin = (flags & FIN) != 0 && seq_le(fin_end, t.rcv_nxt);
+    if (flags & FIN) != 0 && !fin && data.is_empty() {
+        t.ooo_fin = Some(fin_end);
+    }
+    if !t.fin_rcvd && t.ooo_fin == Some(t.rcv_nxt) {
+        fin = true;
+    }
+    if fin {
+        t.ooo_fin = None;
+        if !t.fin_rcvd {
+            t.rcv_nxt = t.rcv_nxt.wrapping_add(1);
+            t.fin_rcvd = true;
+        }
+        need_ack = true;
+        match t.state {
+            TcpState::SynReceived | TcpState::Established => t.state = TcpState::CloseWait,
+            TcpState::FinWait1 => {
+                if fin_acked {
+                    t.state = TcpState::TimeWait;
+                    t.arm(TIME_WAIT_MS);
+                } else {
+                    t.state = TcpState::Closing;
+                }
+            }
+            TcpState::FinWait2 | TcpState::TimeWait => {
+                t.state = TcpState::TimeWait;
+                t.arm(TIME_WAIT_MS);
+            }
+            _ => {}
+        }
+    }
+
+    if need_ack { send_ack(t); }
+    output(t);
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+// Timers
+// -----------------------------------------------------------------------------
+
+/// Retransmission timeout: back off the RTO and go back to `snd_una` with a
+/// one-segment congestion window (RFC 6298 section 5, RFC 5681 section 3.1).
+fn on_rto(t: &mut Tcb) {
+    let probe = t.snd_wnd == 0 && t.snd_una == t.snd_max && !t.tx.is_empty();
+    if !probe {
+        if t.retries >= MAX_RETRIES {
+            t.state = TcpState::Closed;
+            t.timed_out = true;
+            return;
+        }
+        t.retries += 1;
+    }
+    t.rto = (t.rto * 2).min(RTO_MAX_MS);
+
+    if t.snd_una == t.iss {
+        // the SYN itself is still unacknowledged
+        t.retransmits = t.retransmits.wrapping_add(1);
+        t.rtt_seq = None;
+        send_syn(t);
+    } else if probe {
+        retransmit_first(t, true);
+    } else {
+        let mss = t.snd_mss as u32;
+        t.ssthresh = (t.snd_max.wrapping_sub(t.snd_una) / 2).max(2 * mss);
+        t.cwnd = mss;
+        t.in_recovery = false;
+        t.dupacks = 0;
+        t.recover = t.snd_max;
+        t.snd_nxt = t.snd_una;
+        t.fin_sent = t.fin_acked;
+        t.retransmits = t.retransmits.wrapping_add(1);
+        t.rtt_seq = None;
+        output(t);
+    }
+    t.arm_rto();
+}
+
+/// Expire timers and reap dead TCBs. Called from `net::poll`.
+pub fn tick() {
+    let now = time::uptime_ms();
+    let mut table = TCP.lock();
+    let mut dead: Vec<Id> = Vec::new();
+
+    for (id, t) in table.tcbs.iter_mut() {
+        if t.rto_at != 0 && now >= t.rto_at {
+            t.rto_at = 0;
+            let outstanding = matches!(t.state, TcpState::SynSent | TcpState::SynReceived)
+                || t.snd_una != t.snd_max
+                || (t.snd_wnd == 0 && !t.tx.is_empty());
+            if outstanding && t.state != TcpState::Closed { on_rto(t); }
+        }
+        if t.timer != 0 && now >= t.timer {
+            t.timer = 0;
+            if matches!(t.state, TcpState::TimeWait | TcpState::FinWait2) {
+                t.state = TcpState::Closed;
             }
         }
+        // a child still tied to its listener never got a handle
+        if t.state == TcpState::Closed && (t.orphan || t.parent.is_some()) {
+            dead.push(*id);
+        }
+    }
+    for id in dead {
+        table.tcbs.remove(&id);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Handles
+// -----------------------------------------------------------------------------
+
+fn pick_ephemeral(table: &Table) -> Option<u16> {
+    let off = time::rdtsc() as u16;
+    (0..0x4000u16)
+        .map(|k| EPHEMERAL_BASE + (off.wrapping_add(k) & 0x3FFF))
+        .find(|p| !table.port_in_use(*p))
+}
+
+/// Snapshot of one TCB for `netstat`-style listings.
+#[derive(Clone, Copy)]
+pub struct TcpInfo {
+    pub local_ip: IpAddr,
+    pub local_port: u16,
+    pub remote_ip: IpAddr,
+    pub remote_port: u16,
+    pub state: TcpState,
+    pub rx_queued: usize,
+    pub tx_queued: usize,
+    pub cwnd: u32,
+    pub rto_ms: u64,
+    pub retransmits: u32,
+}
+
+pub fn connections() -> Vec<TcpInfo> {
+    TCP.lock().tcbs.values().map(|t| TcpInfo {
+        local_ip: t.local_ip,
+        local_port: t.local_port,
+        remote_ip: t.remote_ip,
+        remote_port: t.remote_port,
+        state: t.state,
+        rx_queued: t.rx.len(),
+        tx_queued: t.tx.len(),
+        cwnd: t.cwnd,
+        rto_ms: t.rto,
+        retransmits: t.retransmits,
+    }).collect()
+}
+
+pub struct TcpListener {
+    id: Id,
+    port: u16,
+}
+
+impl TcpListener {
+    pub fn bind(port: u16) -> Result<Self, TcpError> {
+        Self::bind_with_backlog(port, DEFAULT_BACKLOG)
+    }
+
+    pub fn bind_with_backlog(port: u16, backlog: usize) -> Result<Self, TcpError> {
+        super::init();
+        if !super::have_iface() { return Err(TcpError::NoNic); }
+
+        let mut table = TCP.lock();
+        if table.tcbs.values().any(|t| t.local_port == port && t.state == TcpState::Listen) {
+            return Err(TcpError::AddrInUse);
+        }
+        let mut t = Tcb::new(TcpState::Listen, port, IpAddr::V4([0, 0, 0, 0]), 0);
+        t.backlog = backlog.max(1);
+        let id = table.add(t);
+        Ok(Self { id, port })
+    }
 
+    pub fn local_port(&self) -> u16 { self.port }
+
+    /// Hand out one fully established connection, if any is waiting.
+    pub fn try_accept(&self) -> Option<TcpStream> {
+        super::poll();
+        let mut table = TCP.lock();
+        let (id, t) = table.tcbs.iter_mut().find(|(_, t)| {
+            t.parent == Some(self.id) && !matches!(t.state, TcpState::SynReceived | TcpState::Closed)
+        })?;
+        t.parent = None;
+        Some(TcpStream { id: *id })
+    }
+
+    /// Wait for a connection (handshake completed by the state machine).
+    pub fn accept(&self, timeout_spins: u32) -> Result<TcpStream, TcpError> {
+        let mut spins: u32 = 0;
+        while spins < timeout_spins {
+            if let Some(s) = self.try_accept() {
+                return Ok(s);
+            }
+            spins = spins.wrapping_add(1);
+            time::cpu_pause();
+        }
         Err(TcpError::Timeout)
     }
+}
 
-    pub fn write_all(&mut self, data: &[u8]) -> Result<(), TcpError> {
-        let (src_ip, mask, gw, our_mac) = unsafe {
-            if super::NET.rtl.is_none() { return Err(TcpError::NoNic); }
-            if super::NET.cfg.ip == [0,0,0,0] { return Err(TcpError::NotConfigured); }
-            (super::NET.cfg.ip, super::NET.cfg.mask, super::NET.cfg.gateway, super::NET.cfg.mac)
-        };
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        let mut table = TCP.lock();
+        table.tcbs.remove(&self.id);
+        // connections nobody accepted: reset them
+        let pending: Vec<Id> = table.tcbs.iter()
+            .filter(|(_, t)| t.parent == Some(self.id))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in pending {
+            if let Some(mut t) = table.tcbs.remove(&id) {
+                let seq = t.snd_nxt;
+                let _ = send(&mut t, seq, RST, &[]);
+            }
+        }
+    }
+}
+
+pub struct TcpStream {
+    id: Id,
+}
+
+impl TcpStream {
+    /// Active open. The SYN is retransmitted on the RTO; gives up after
+    /// three times `timeout_spins` without an answer.
+    pub fn connect(remote_ip: IpAddr, remote_port: u16, timeout_spins: u32) -> Result<Self, TcpError> {
+        super::init();
+        ensure_route(remote_ip)?;
 
-        let next_hop = route_next_hop(src_ip, self.remote_ip, mask, gw);
-        let dst_mac = match super::arp_resolve(next_hop, our_mac, src_ip) {
-            Ok(m) => m,
-            Err(super::PingError::ArpTimeout) => return Err(TcpError::ArpTimeout),
-            Err(super::PingError::NoNic) => return Err(TcpError::NoNic),
-            Err(super::PingError::NotConfigured) => return Err(TcpError::NotConfigured),
-            _ => return Err(TcpError::Timeout),
+        let id = {
+            let mut table = TCP.lock();
+            let port = pick_ephemeral(&table).ok_or(TcpError::AddrInUse)?;
+            let mut t = Tcb::new(TcpState::SynSent, port, remote_ip, remote_port);
+            t.snd_nxt = t.iss.wrapping_add(1);
+            t.snd_max = t.snd_nxt;
+            t.rtt_seq = Some(t.snd_nxt);
+            t.rtt_start = time::uptime_ms();
+            send_syn(&mut t);
+            t.arm_rto();
+            table.add(t)
         };
+        let s = TcpStream { id };
+
+        let mut spins: u32 = 0;
+        while spins < timeout_spins.saturating_mul(3) {
+            spins = spins.wrapping_add(1);
+            time::cpu_pause();
+
+            super::poll();
+            match s.state() {
+                TcpState::SynSent | TcpState::SynReceived => {}
+                TcpState::Closed => {
+                    return Err(s.with(|t| t.error()).unwrap_or(TcpError::Proto));
+                }
+                _ => return Ok(s),
+            }
+        }
+
+        Err(TcpError::Timeout)
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut Tcb) -> R) -> R {
+        let mut table = TCP.lock();
+        let t = table.tcbs.get_mut(&self.id).expect("tcp: handle without TCB");
+        f(t)
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.with(|t| t.state)
+    }
+
+    pub fn peer(&self) -> (IpAddr, u16) {
+        self.with(|t| (t.remote_ip, t.remote_port))
+    }
+
+    pub fn local_port(&self) -> u16 {
+        self.with(|t| t.local_port)
+    }
+
+    /// Queue `data` on the connection. Returns once all of it is queued
+    /// (not necessarily acknowledged), waiting while the send buffer is full.
+    pub fn write_all(&mut self, data: &[u8]) -> Result<(), TcpError> {
+        let remote = self.with(|t| t.remote_ip);
+        ensure_route(remote)?;
 
         let mut off = 0usize;
+        let mut stalled: u32 = 0;
         while off < data.len() {
-            let take = (data.len() - off).min(1460);
-            let chunk = &data[off..off+take];
-
-            Self::send_segment_raw(src_ip, self.remote_ip, dst_mac, self.local_port, self.remote_port,
-                                   self.seq, self.ack, 0x18, 4096, &[], chunk)?;
-            self.seq = self.seq.wrapping_add(take as u32);
-            off += take;
+            let queued = self.with(|t| {
+                if let Some(e) = t.error() { return Err(e); }
+                if t.fin_queued || !matches!(t.state, TcpState::Established | TcpState::CloseWait) {
+                    return Err(TcpError::Closed);
+                }
+                let take = (data.len() - off).min(TX_CAP.saturating_sub(t.tx.len()));
+                t.tx.extend_from_slice(&data[off..off + take]);
+                output(t);
+                Ok(take)
+            })?;
 
-            self.poll_once();
+            if queued == 0 {
+                stalled = stalled.wrapping_add(1);
+                if stalled > 20_000_000 { return Err(TcpError::Timeout); }
+                time::cpu_pause();
+            } else {
+                stalled = 0;
+                off += queued;
+            }
+            super::poll();
         }
 
         Ok(())
     }
 
+    /// Read whatever is buffered (waiting up to `timeout_spins` for
+    /// something to arrive). `Ok(0)` means the peer closed its side.
+    pub fn read(&mut self, buf: &mut [u8], timeout_spins: u32) -> Result<usize, TcpError> {
+        let mut spins: u32 = 0;
+        loop {
+            let r = self.with(|t| {
+                if !t.rx.is_empty() {
+                    let n = buf.len().min(t.rx.len());
+                    buf[..n].copy_from_slice(&t.rx[..n]);
+                    consume_rx(t, n);
+                    return Some(Ok(n));
+                }
+                if let Some(e) = t.error() { return Some(Err(e)); }
+                if t.fin_rcvd || t.state == TcpState::Closed { return Some(Ok(0)); }
+                None
+            });
+            if let Some(r) = r { return r; }
+            if spins >= timeout_spins { return Err(TcpError::Timeout); }
+            spins = spins.wrapping_add(1);
+            time::cpu_pause();
+            super::poll();
+        }
+    }
+
     pub fn read_to_end(&mut self, max_bytes: usize, timeout_spins: u32) -> Result<Vec<u8>, TcpError> {
         // NOTE: the old implementation returned Ok(empty) on a read timeout,
         // which then caused the HTTP layer to report a confusing Parse error.
         // We now surface real TCP timeouts/resets.
 
         let mut idle: u32 = 0;
-        let mut got_any = !self.rx.is_empty();
+        let mut out: Vec<u8> = Vec::new();
+
+        loop {
+            let (eof, err) = self.with(|t| {
+                let take = t.rx.len().min(max_bytes - out.len());
+                out.extend_from_slice(&t.rx[..take]);
+                consume_rx(t, take);
+                (t.fin_rcvd || t.state == TcpState::Closed, t.error())
+            });
+            if let Some(e) = err { return Err(e); }
+            if eof || out.len() >= max_bytes { break; }
 
-        while !self.fin_seen && self.rx.len() < max_bytes {
+            let before = out.len();
             let mut spins: u32 = 0;
             let mut progressed = false;
             while spins < timeout_spins {
                 spins = spins.wrapping_add(1);
                 time::cpu_pause();
-                if self.poll_once() {
+                super::poll();
+                if self.with(|t| !t.rx.is_empty() || t.fin_rcvd || t.state == TcpState::Closed) {
                     progressed = true;
-                    got_any = true;
                     break;
                 }
             }
 
-            if progressed {
+            if progressed || out.len() > before {
                 idle = 0;
             } else {
                 idle = idle.wrapping_add(1);
@@ -196,194 +1335,44 @@ impl TcpStream {
             }
         }
 
-        if self.rst_seen { return Err(TcpError::Reset); }
-        if !got_any { return Err(TcpError::Timeout); }
-        Ok(core::mem::take(&mut self.rx))
+        if out.is_empty() && !self.with(|t| t.fin_rcvd) { return Err(TcpError::Timeout); }
+        Ok(out)
     }
 
+    /// Queue our FIN behind any unsent data. The TCB finishes the close
+    /// (and TIME_WAIT) in the background once the handle is dropped.
     pub fn close(&mut self) -> Result<(), TcpError> {
-        let (src_ip, mask, gw, our_mac) = unsafe {
-            if super::NET.rtl.is_none() { return Err(TcpError::NoNic); }
-            if super::NET.cfg.ip == [0,0,0,0] { return Err(TcpError::NotConfigured); }
-            (super::NET.cfg.ip, super::NET.cfg.mask, super::NET.cfg.gateway, super::NET.cfg.mac)
-        };
-
-        let next_hop = route_next_hop(src_ip, self.remote_ip, mask, gw);
-        let dst_mac = match super::arp_resolve(next_hop, our_mac, src_ip) {
-            Ok(m) => m,
-            Err(_) => return Err(TcpError::ArpTimeout),
-        };
-
-        Self::send_segment_raw(src_ip, self.remote_ip, dst_mac, self.local_port, self.remote_port,
-                               self.seq, self.ack, 0x11, 4096, &[], &[])?;
-        self.seq = self.seq.wrapping_add(1);
-        Ok(())
-    }
-
-    fn send_ack(&mut self) -> Result<(), TcpError> {
-        let (src_ip, mask, gw, our_mac) = unsafe {
-            if super::NET.rtl.is_none() { return Err(TcpError::NoNic); }
-            if super::NET.cfg.ip == [0,0,0,0] { return Err(TcpError::NotConfigured); }
-            (super::NET.cfg.ip, super::NET.cfg.mask, super::NET.cfg.gateway, super::NET.cfg.mac)
-        };
-
-        let next_hop = route_next_hop(src_ip, self.remote_ip, mask, gw);
-        let dst_mac = match super::arp_resolve(next_hop, our_mac, src_ip) {
-            Ok(m) => m,
-            Err(_) => return Err(TcpError::ArpTimeout),
-        };
-
-        Self::send_segment_raw(src_ip, self.remote_ip, dst_mac, self.local_port, self.remote_port,
-                               self.seq, self.ack, 0x10, 4096, &[], &[])?;
-        Ok(())
-    }
-
-    fn poll_once(&mut self) -> bool {
-        let our_ip = unsafe { super::NET.cfg.ip };
-        let Some((flags, seq_r, _ack_r, payload)) = Self::poll_for_segment(our_ip, self.remote_ip, self.local_port, self.remote_port) else {
-            return false;
-        };
-
-        if (flags & 0x04) != 0 {
-            // RST: surface as an error instead of silently turning into an HTTP Parse.
-            self.rst_seen = true;
-            self.fin_seen = true;
-            return true;
-        }
-
-        if seq_r == self.ack {
-    let mut did_any = false;
-
-    if !payload.is_empty() {
-        self.rx.extend_from_slice(payload);
-        self.ack = self.ack.wrapping_add(payload.len() as u32);
-        did_any = true;
-    }
-
-    // FIN can be piggy-backed on the last data segment. If we don't account for it,
-    // higher layers can hang forever waiting for EOF.
-    if (flags & 0x01) != 0 {
-        self.ack = self.ack.wrapping_add(1);
-        self.fin_seen = true;
-        did_any = true;
-    }
-
-    if did_any {
-        let _ = self.send_ack();
-        return true;
+        self.with(|t| {
+            match t.state {
+                TcpState::Established | TcpState::SynReceived => t.state = TcpState::FinWait1,
+                TcpState::CloseWait => t.state = TcpState::LastAck,
+                TcpState::SynSent => {
+                    t.state = TcpState::Closed;
+                    t.rto_at = 0;
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            }
+            t.fin_queued = true;
+            output(t);
+            Ok(())
+        })
     }
 }
 
-
-        false
-    }
-
-    fn poll_for_segment(our_ip: [u8;4], _remote_ip: [u8;4], local_port: u16, remote_port: u16)
-        -> Option<(u16, u32, u32, &'static [u8])>
-    {
-        let frame_opt = unsafe { super::NET.rtl.as_mut().unwrap().poll_recv() };
-        let Some(frame) = frame_opt else { return None; };
-
-        if frame.len() < 14 + 20 { return None; }
-        let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
-        if ethertype != 0x0800 { return None; }
-
-        let ip_off = 14;
-        let ver_ihl = frame[ip_off];
-        if (ver_ihl >> 4) != 4 { return None; }
-        let ihl = ((ver_ihl & 0x0F) as usize) * 4;
-        if frame.len() < ip_off + ihl + 20 { return None; }
-        if frame[ip_off + 9] != 6 { return None; }
-
-        let _src = [frame[ip_off + 12], frame[ip_off + 13], frame[ip_off + 14], frame[ip_off + 15]];
-        let dst = [frame[ip_off + 16], frame[ip_off + 17], frame[ip_off + 18], frame[ip_off + 19]];
-        if dst != our_ip { return None; }
-        // Some user-mode NATs / emulations can present replies with unexpected L3 sources.
-        // As long as it is destined to us and the TCP ports match, accept it.
-
-        let tcp_off = ip_off + ihl;
-        let sp = u16::from_be_bytes([frame[tcp_off], frame[tcp_off + 1]]);
-        let dp = u16::from_be_bytes([frame[tcp_off + 2], frame[tcp_off + 3]]);
-        if sp != remote_port || dp != local_port { return None; }
-
-        let seq = u32::from_be_bytes([frame[tcp_off + 4], frame[tcp_off + 5], frame[tcp_off + 6], frame[tcp_off + 7]]);
-        let ack = u32::from_be_bytes([frame[tcp_off + 8], frame[tcp_off + 9], frame[tcp_off + 10], frame[tcp_off + 11]]);
-        let data_off = ((frame[tcp_off + 12] >> 4) as usize) * 4;
-        let flags = frame[tcp_off + 13] as u16;
-
-        if data_off < 20 { return None; }
-        let payload_off = tcp_off + data_off;
-        if payload_off > frame.len() { return None; }
-
-        let payload = &frame[payload_off..];
-
-        Some((flags, seq, ack, payload))
-    }
-
-    fn send_segment_raw(
-        src_ip: [u8;4],
-        dst_ip: [u8;4],
-        dst_mac: [u8;6],
-        src_port: u16,
-        dst_port: u16,
-        seq: u32,
-        ack: u32,
-        flags: u16,
-        window: u16,
-        options: &[u8],
-        payload: &[u8],
-    ) -> Result<(), TcpError> {
-        let opt_len = options.len();
-        let hdr_len = 20 + opt_len;
-        let tcp_len = hdr_len + payload.len();
-        let ip_len = 20 + tcp_len;
-
-        let mut seg = Vec::with_capacity(tcp_len);
-        seg.resize(hdr_len, 0);
-        write_u16_be(&mut seg, 0, src_port);
-        write_u16_be(&mut seg, 2, dst_port);
-        write_u32_be(&mut seg, 4, seq);
-        write_u32_be(&mut seg, 8, ack);
-
-        let data_off_words = (hdr_len / 4) as u8;
-        seg[12] = data_off_words << 4;
-        seg[13] = (flags & 0xFF) as u8;
-        write_u16_be(&mut seg, 14, window);
-        write_u16_be(&mut seg, 16, 0);
-        write_u16_be(&mut seg, 18, 0);
-
-        if opt_len > 0 {
-            seg[20..20+opt_len].copy_from_slice(options);
-        }
-        if !payload.is_empty() {
-            seg.extend_from_slice(payload);
-        }
-
-        let csum = tcp_checksum(src_ip, dst_ip, &seg);
-        write_u16_be(&mut seg, 16, csum);
-
-        let mut ip = Vec::with_capacity(ip_len);
-        ip.resize(20, 0);
-        ip[0] = 0x45;
-        ip[1] = 0;
-        write_u16_be(&mut ip, 2, ip_len as u16);
-        write_u16_be(&mut ip, 4, (time::rdtsc() as u16) ^ 0x1234);
-        write_u16_be(&mut ip, 6, 0);
-        ip[8] = 64;
-        ip[9] = 6;
-        write_u16_be(&mut ip, 10, 0);
-        ip[12..16].copy_from_slice(&src_ip);
-        ip[16..20].copy_from_slice(&dst_ip);
-        let ip_csum = super::checksum16(&ip[..20]);
-        write_u16_be(&mut ip, 10, ip_csum);
-
-        ip.extend_from_slice(&seg);
-
-        let ok = unsafe {
-            let rtl = super::NET.rtl.as_mut().unwrap();
-            rtl.send_frame(dst_mac, 0x0800, &ip)
-        };
-        if !ok { return Err(TcpError::TxFail); }
-        Ok(())
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        let _ = self.close();
+        let mut table = TCP.lock();
+        if let Some(t) = table.tcbs.get_mut(&self.id) {
+            t.orphan = true;
+            t.rx = Vec::new();
+            t.ooo = Vec::new();
+            match t.state {
+                TcpState::Closed => { table.tcbs.remove(&self.id); }
+                TcpState::FinWait2 => t.arm(FIN_WAIT2_MS),
+                _ => {}
+            }
+        }
     }
 }
@@ -0,0 +1,138 @@
+#![allow(dead_code)]
+// src/net/udp.rs
+//
+// UDP sockets for applications (DHCP, DNS, or anything else datagram based).
+// A bound `UdpSocket` owns the demultiplexer's receive queue for its port;
+// sending goes through ip.rs to the IPv4 or IPv6 output, so one socket talks
+// to both families. Receiving is
+// polled: `recv_from` drives `net::poll` until a datagram arrives or the
+// read timeout passes.
+
+extern crate alloc;
+
+use super::demux::{self, UdpQueue};
+use super::ip::{self, IpAddr};
+use super::ipv4::{self, IpError};
+use crate::time;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UdpError {
+    NoNic,
+    NotConfigured,
+    /// Port already bound by another socket.
+    AddrInUse,
+    ArpTimeout,
+    Timeout,
+    /// Over the 64 KiB IPv4 datagram limit, or the link MTU for IPv6.
+    TooLarge,
+    /// Broadcast destination without `set_broadcast(true)`.
+    Broadcast,
+    TxFail,
+}
+
+const EPHEMERAL_BASE: u16 = 49152;
+const MAX_PAYLOAD: usize = ipv4::MAX_PAYLOAD - 8;
+
+fn ip_err(e: IpError) -> UdpError {
+    match e {
+        IpError::NoNic => UdpError::NoNic,
+        IpError::NotConfigured => UdpError::NotConfigured,
+        IpError::ArpTimeout => UdpError::ArpTimeout,
+        IpError::TooLarge => UdpError::TooLarge,
+        IpError::TxFail => UdpError::TxFail,
+    }
+}
+
+pub struct UdpSocket {
+    q: UdpQueue,
+    broadcast: bool,
+    /// ms; `None` waits forever.
+    read_timeout: Option<u64>,
+}
+
+impl UdpSocket {
+    /// Bind `port`, or a free ephemeral port when `port` is 0.
+    pub fn bind(port: u16) -> Result<Self, UdpError> {
+        super::init();
+        if !super::have_iface() { return Err(UdpError::NoNic); }
+
+        let q = if port != 0 {
+            demux::udp_open(port).ok_or(UdpError::AddrInUse)?
+        } else {
+            let off = time::rdtsc() as u16;
+            (0..0x4000u16)
+                .find_map(|k| demux::udp_open(EPHEMERAL_BASE + (off.wrapping_add(k) & 0x3FFF)))
+                .ok_or(UdpError::AddrInUse)?
+        };
+        Ok(Self { q, broadcast: false, read_timeout: None })
+    }
+
+    pub fn local_port(&self) -> u16 { self.q.port() }
+
+    /// Allow sending to 255.255.255.255 and the subnet broadcast address.
+    pub fn set_broadcast(&mut self, on: bool) { self.broadcast = on; }
+
+    pub fn set_read_timeout(&mut self, ms: Option<u64>) { self.read_timeout = ms; }
+
+    /// Send one datagram; resolves the next hop first, so this may poll.
+    pub fn send_to(&self, data: &[u8], dst_ip: IpAddr, dst_port: u16) -> Result<usize, UdpError> {
+        if data.len() > MAX_PAYLOAD { return Err(UdpError::TooLarge); }
+        if ip::is_broadcast(dst_ip) && !self.broadcast { return Err(UdpError::Broadcast); }
+        ip::resolve(dst_ip).map_err(ip_err)?;
+        self.try_send_to(data, dst_ip, dst_port)
+    }
+
+    /// `send_to` without polling, for code that runs inside `net::poll`. If
+    /// the next hop isn't resolved yet, it is asked for and `ArpTimeout`
+    /// comes back; retry later.
+    pub fn try_send_to(&self, data: &[u8], dst_ip: IpAddr, dst_port: u16) -> Result<usize, UdpError> {
+        if data.len() > MAX_PAYLOAD { return Err(UdpError::TooLarge); }
+        if ip::is_broadcast(dst_ip) && !self.broadcast { return Err(UdpError::Broadcast); }
+
+        let src_ip = ip::source_for(dst_ip);
+        let len = 8 + data.len();
+        let mut dgram = alloc::vec![0u8; len];
+        dgram[0..2].copy_from_slice(&self.local_port().to_be_bytes());
+        dgram[2..4].copy_from_slice(&dst_port.to_be_bytes());
+        dgram[4..6].copy_from_slice(&(len as u16).to_be_bytes());
+        dgram[8..].copy_from_slice(data);
+        // 0 means "no checksum" on the wire, so a computed 0 is sent as 0xFFFF
+        let csum = match ip::pseudo_checksum(src_ip, dst_ip, ipv4::PROTO_UDP, &dgram) {
+            0 => 0xFFFF,
+            c => c,
+        };
+        dgram[6..8].copy_from_slice(&csum.to_be_bytes());
+
+        ip::send(src_ip, dst_ip, ipv4::PROTO_UDP, &dgram).map_err(ip_err)?;
+        Ok(data.len())
+    }
+
+    /// Next queued datagram without waiting: (length, source ip, source port).
+    /// A datagram longer than `buf` is truncated.
+    pub fn try_recv_from(&self, buf: &mut [u8]) -> Option<(usize, IpAddr, u16)> {
+        super::poll();
+        self.recv_queued(buf)
+    }
+
+    /// Next datagram already queued, without polling the interfaces.
+    pub fn recv_queued(&self, buf: &mut [u8]) -> Option<(usize, IpAddr, u16)> {
+        let d = self.q.recv()?;
+        let n = d.data.len().min(buf.len());
+        buf[..n].copy_from_slice(&d.data[..n]);
+        Some((n, d.src_ip, d.src_port))
+    }
+
+    /// Wait for a datagram, up to the read timeout.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, IpAddr, u16), UdpError> {
+        let deadline = self.read_timeout.map(|ms| time::uptime_ms() + ms);
+        loop {
+            if let Some(r) = self.try_recv_from(buf) {
+                return Ok(r);
+            }
+            if let Some(t) = deadline {
+                if time::uptime_ms() >= t { return Err(UdpError::Timeout); }
+            }
+            time::cpu_pause();
+        }
+    }
+}
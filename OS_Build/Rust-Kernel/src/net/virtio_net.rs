@@ -0,0 +1,261 @@
+#![allow(dead_code)]
+// src/net/virtio_net.rs
+//
+// virtio-net over the legacy PCI transport (see virtio.rs), polled.
+//
+// - Queue 0 receives, queue 1 transmits. Every packet is preceded by the
+//   10-byte legacy `virtio_net_hdr` (no MRG_RXBUF), kept in its own descriptor
+//   as legacy devices without ANY_LAYOUT expect.
+// - RX buffers are posted once and recycled; TX buffers come from a small pool
+//   and are reclaimed from the used ring before each send.
+// - Checksum offload: when the device offers GUEST_CSUM we accept frames with
+//   partial checksums and complete them here. Outgoing frames already carry
+//   full checksums from the stack, so the device's CSUM feature is not used.
+
+extern crate alloc;
+
+use alloc::alloc::{alloc_zeroed, Layout};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ptr;
+
+use crate::time;
+use crate::virtio::{self, LegacyDevice, VirtQueue, VirtioError};
+
+// feature bits
+const F_CSUM: u32 = 1 << 0;
+const F_GUEST_CSUM: u32 = 1 << 1;
+const F_MAC: u32 = 1 << 5;
+const F_STATUS: u32 = 1 << 16;
+
+// config space
+const CFG_MAC: u16 = 0;
+const CFG_STATUS: u16 = 6;
+const S_LINK_UP: u16 = 1;
+
+// virtio_net_hdr.flags
+const HDR_F_NEEDS_CSUM: u8 = 1;
+const HDR_F_DATA_VALID: u8 = 2;
+
+const HDR_LEN: usize = 10;
+const BUF_SIZE: usize = 2048;
+const MAX_RX: usize = 64;
+const MAX_TX: usize = 16;
+
+const QUEUE_RX: u16 = 0;
+const QUEUE_TX: u16 = 1;
+
+const NO_SLOT: u16 = u16::MAX;
+
+static mut RX_SCRATCH: [u8; BUF_SIZE] = [0u8; BUF_SIZE];
+
+pub struct VirtioNet {
+    dev: LegacyDevice,
+    pub mac: [u8; 6],
+    features: u32,
+
+    rx: VirtQueue,
+    rx_bufs: *mut u8,
+    rx_slot: Vec<u16>, // descriptor head -> buffer slot
+
+    tx: VirtQueue,
+    tx_bufs: *mut u8,
+    tx_slot: Vec<u16>,
+    tx_free: Vec<u16>,
+
+    rx_dropped: u32,
+}
+
+// Owned by the single NET state; no interrupts.
+unsafe impl Send for VirtioNet {}
+
+fn alloc_bufs(n: usize) -> Result<*mut u8, VirtioError> {
+    let layout = Layout::from_size_align(n * BUF_SIZE, 16).map_err(|_| VirtioError::NoQueue)?;
+    let p = unsafe { alloc_zeroed(layout) };
+    if p.is_null() { Err(VirtioError::NoQueue) } else { Ok(p) }
+}
+
+impl VirtioNet {
+    pub fn probe() -> Result<Self, VirtioError> {
+        let dev = LegacyDevice::probe(virtio::DEVICE_NET).ok_or(VirtioError::NoDevice)?;
+        Self::init(dev)
+    }
+
+    fn init(dev: LegacyDevice) -> Result<Self, VirtioError> {
+        let offered = dev.device_features();
+        let features = offered & (F_MAC | F_STATUS | F_GUEST_CSUM);
+        dev.set_guest_features(features);
+
+        let setup = |i: u16| dev.setup_queue(i).inspect_err(|_| dev.fail());
+        let rx = setup(QUEUE_RX)?;
+        let tx = setup(QUEUE_TX)?;
+
+        let mut mac = [0u8; 6];
+        if (features & F_MAC) != 0 {
+            for (i, b) in mac.iter_mut().enumerate() {
+                *b = dev.config_u8(CFG_MAC + i as u16);
+            }
+        } else {
+            // no MAC from the device: locally administered, derived from the TSC
+            let t = time::rdtsc().to_le_bytes();
+            mac = [0x02, 0x00, t[0], t[1], t[2], t[3]];
+        }
+
+        let n_rx = ((rx.size / 2) as usize).clamp(1, MAX_RX);
+        let n_tx = ((tx.size / 2) as usize).clamp(1, MAX_TX);
+        let rx_bufs = alloc_bufs(n_rx)?;
+        let tx_bufs = alloc_bufs(n_tx)?;
+
+        let mut nic = Self {
+            mac,
+            features,
+            rx_slot: vec![NO_SLOT; rx.size as usize],
+            tx_slot: vec![NO_SLOT; tx.size as usize],
+            tx_free: (0..n_tx as u16).rev().collect(),
+            rx,
+            rx_bufs,
+            tx,
+            tx_bufs,
+            dev,
+            rx_dropped: 0,
+        };
+
+        for slot in 0..n_rx as u16 {
+            nic.post_rx(slot)?;
+        }
+        nic.dev.driver_ok();
+        nic.dev.notify(QUEUE_RX);
+        Ok(nic)
+    }
+
+    fn buf(base: *mut u8, slot: u16) -> *mut u8 {
+        unsafe { base.add(slot as usize * BUF_SIZE) }
+    }
+
+    fn post_rx(&mut self, slot: u16) -> Result<(), VirtioError> {
+        let p = Self::buf(self.rx_bufs, slot);
+        let head = self.rx.push(&[
+            (p as *const u8, HDR_LEN, true),
+            (unsafe { p.add(HDR_LEN) } as *const u8, BUF_SIZE - HDR_LEN, true),
+        ])?;
+        self.rx_slot[head as usize] = slot;
+        Ok(())
+    }
+
+    pub fn link_up(&self) -> bool {
+        if (self.features & F_STATUS) == 0 { return true; }
+        (self.dev.config_u16(CFG_STATUS) & S_LINK_UP) != 0
+    }
+
+    pub fn csum_offload(&self) -> bool {
+        (self.features & F_GUEST_CSUM) != 0
+    }
+
+    fn reclaim_tx(&mut self) {
+        while let Some((head, _)) = self.tx.pop_used() {
+            let slot = self.tx_slot[head as usize];
+            if slot != NO_SLOT {
+                self.tx_slot[head as usize] = NO_SLOT;
+                self.tx_free.push(slot);
+            }
+        }
+    }
+
+    pub fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool {
+        let total = 14 + payload.len();
+        if HDR_LEN + total > BUF_SIZE { return false; }
+
+        self.reclaim_tx();
+        let mut spins = 0u32;
+        while self.tx_free.is_empty() {
+            if spins > 2_000_000 { return false; }
+            time::cpu_pause();
+            spins += 1;
+            self.reclaim_tx();
+        }
+        let Some(slot) = self.tx_free.pop() else { return false; };
+
+        let p = Self::buf(self.tx_bufs, slot);
+        unsafe {
+            // all-zero header: no offload, no GSO
+            let buf = core::slice::from_raw_parts_mut(p, HDR_LEN + total);
+            for b in buf[..HDR_LEN].iter_mut() { *b = 0; }
+            let f = &mut buf[HDR_LEN..];
+            f[0..6].copy_from_slice(&dst);
+            f[6..12].copy_from_slice(&self.mac);
+            f[12..14].copy_from_slice(&ethertype.to_be_bytes());
+            f[14..].copy_from_slice(payload);
+        }
+
+        let head = match self.tx.push(&[
+            (p as *const u8, HDR_LEN, false),
+            (unsafe { p.add(HDR_LEN) } as *const u8, total, false),
+        ]) {
+            Ok(h) => h,
+            Err(_) => {
+                self.tx_free.push(slot);
+                return false;
+            }
+        };
+        self.tx_slot[head as usize] = slot;
+        self.dev.notify(QUEUE_TX);
+
+        true
+    }
+
+    pub fn poll_recv(&mut self) -> Option<&'static [u8]> {
+        let (head, len) = self.rx.pop_used()?;
+        let slot = self.rx_slot[head as usize];
+        if slot == NO_SLOT { return None; }
+        self.rx_slot[head as usize] = NO_SLOT;
+
+        let p = Self::buf(self.rx_bufs, slot);
+        let len = (len as usize).min(BUF_SIZE);
+        let frame_len = len.saturating_sub(HDR_LEN);
+
+        let scratch = &raw mut RX_SCRATCH as *mut u8;
+        let (flags, csum_start, csum_offset) = unsafe {
+            let h = core::slice::from_raw_parts(p as *const u8, HDR_LEN);
+            ptr::copy_nonoverlapping(p.add(HDR_LEN), scratch, frame_len);
+            (h[0], u16::from_le_bytes([h[6], h[7]]) as usize, u16::from_le_bytes([h[8], h[9]]) as usize)
+        };
+
+        // give the buffer straight back to the device
+        let _ = self.post_rx(slot);
+        self.dev.notify(QUEUE_RX);
+
+        unsafe {
+            if frame_len < 14 {
+                self.rx_dropped = self.rx_dropped.wrapping_add(1);
+                return None;
+            }
+
+            let frame = core::slice::from_raw_parts_mut(scratch, frame_len);
+
+            // partial checksum: the field holds the pseudo-header sum, finish it
+            if (flags & HDR_F_NEEDS_CSUM) != 0 {
+                let at = csum_start + csum_offset;
+                if at + 2 > frame_len || csum_start >= frame_len {
+                    self.rx_dropped = self.rx_dropped.wrapping_add(1);
+                    return None;
+                }
+                let c = super::checksum16(&frame[csum_start..]);
+                frame[at..at + 2].copy_from_slice(&c.to_be_bytes());
+            }
+
+            let n = super::trimmed_len(frame);
+            Some(&frame[..n])
+        }
+    }
+}
+
+impl super::iface::NetDevice for VirtioNet {
+    fn driver(&self) -> &'static str { "virtio-net" }
+    fn mac(&self) -> [u8; 6] { self.mac }
+    fn link_up(&self) -> bool { VirtioNet::link_up(self) }
+    fn send_frame(&mut self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> bool {
+        VirtioNet::send_frame(self, dst, ethertype, payload)
+    }
+    fn poll_recv(&mut self) -> Option<&'static [u8]> { VirtioNet::poll_recv(self) }
+    fn rx_dropped(&self) -> u32 { self.rx_dropped }
+}
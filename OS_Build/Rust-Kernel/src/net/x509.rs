@@ -0,0 +1,679 @@
+#![allow(dead_code)]
+// src/net/x509.rs
+//
+// X.509 certificates (RFC 5280) for TLS server authentication:
+// - a DER reader and a certificate parser (names, validity, public key,
+//   basic constraints, key usage, extended key usage, subject alt names),
+// - signature checks through pubkey.rs (RSA PKCS#1 v1.5 / PSS, ECDSA
+//   P-256/P-384 with SHA-256/384/512),
+// - chain building from the server's certificates up to a trusted root,
+//   with host name matching (one leading wildcard label, IP SANs).
+//
+// Trusted roots are the DER files in net/certs/, built in, plus any added
+// at runtime with `add_root` (`tls trust <file>`).
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::fs::SpinLock;
+use crate::pubkey::{self, Curve, HashAlg};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CertError {
+    Malformed,
+    /// Signature or key algorithm we don't implement.
+    Unsupported,
+    BadSignature,
+    Expired,
+    NotYetValid,
+    /// No path to a trusted root.
+    UnknownIssuer,
+    /// Certificate isn't for the host we asked for.
+    NameMismatch,
+    /// An issuer isn't allowed to sign certificates.
+    NotCa,
+    /// Leaf not allowed for TLS server authentication.
+    BadUsage,
+    /// A critical extension we don't understand.
+    UnknownCritical,
+    ChainTooLong,
+}
+
+/// Deepest chain we follow (leaf + intermediates).
+const MAX_DEPTH: usize = 8;
+
+// -----------------------------------------------------------------------------
+// DER
+// -----------------------------------------------------------------------------
+
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTF8: u8 = 0x0C;
+const TAG_PRINTABLE: u8 = 0x13;
+const TAG_IA5: u8 = 0x16;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GEN_TIME: u8 = 0x18;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+
+/// Sequential reader over DER-encoded TLVs.
+#[derive(Clone, Copy)]
+pub struct Der<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Der { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Next element: (tag, contents, whole encoding).
+    pub fn read(&mut self) -> Option<(u8, &'a [u8], &'a [u8])> {
+        let d = self.data;
+        if d.len() < 2 { return None; }
+        let tag = d[0];
+        let (len, hdr) = match d[1] {
+            l if l < 0x80 => (l as usize, 2usize),
+            0x81 => (*d.get(2)? as usize, 3),
+            0x82 => (u16::from_be_bytes([*d.get(2)?, *d.get(3)?]) as usize, 4),
+            0x83 => (((*d.get(2)? as usize) << 16) | ((*d.get(3)? as usize) << 8) | *d.get(4)? as usize, 5),
+            _ => return None,
+        };
+        let end = hdr.checked_add(len)?;
+        if end > d.len() { return None; }
+        self.data = &d[end..];
+        Some((tag, &d[hdr..end], &d[..end]))
+    }
+
+    /// Next element, which must have tag `tag`; its contents.
+    pub fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.read()? {
+            (t, v, _) if t == tag => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Contents of an optional element tagged `tag` (e.g. `[0] EXPLICIT`).
+    pub fn optional(&mut self, tag: u8) -> Option<&'a [u8]> {
+        if self.peek_tag() != Some(tag) { return None; }
+        self.expect(tag)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// OIDs (contents octets)
+// -----------------------------------------------------------------------------
+
+const OID_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_RSA_PSS: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0a];
+const OID_SHA256_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const OID_SHA384_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const OID_SHA512_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const OID_EC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_P384: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_ECDSA_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const OID_ECDSA_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04];
+const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+const OID_SHA384: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02];
+const OID_SHA512: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03];
+
+const OID_CN: &[u8] = &[0x55, 0x04, 0x03];
+const OID_ORG: &[u8] = &[0x55, 0x04, 0x0a];
+
+const OID_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x0f];
+const OID_SAN: &[u8] = &[0x55, 0x1d, 0x11];
+const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1d, 0x13];
+const OID_POLICIES: &[u8] = &[0x55, 0x1d, 0x20];
+const OID_EXT_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25];
+const OID_ANY_EKU: &[u8] = &[0x55, 0x1d, 0x25, 0x00];
+const OID_SERVER_AUTH: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01];
+
+/// keyUsage bit 5 (keyCertSign), as the first content byte of the BIT STRING.
+const KU_CERT_SIGN: u8 = 0x04;
+
+// -----------------------------------------------------------------------------
+// Certificates
+// -----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PublicKey {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    Ec { curve: Curve, point: Vec<u8> },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SigAlg {
+    RsaPkcs1(HashAlg),
+    RsaPss(HashAlg),
+    Ecdsa(HashAlg),
+}
+
+#[derive(Clone, Debug)]
+pub struct Certificate {
+    pub der: Vec<u8>,
+    /// The signed part (TBSCertificate, whole encoding).
+    tbs: Vec<u8>,
+    sig_alg: Option<SigAlg>,
+    signature: Vec<u8>,
+    /// Raw DER names, compared byte for byte when chaining.
+    pub issuer: Vec<u8>,
+    pub subject: Vec<u8>,
+    /// YYYYMMDDHHMMSS as a number, comparable with `now_key`.
+    pub not_before: u64,
+    pub not_after: u64,
+    pub key: Option<PublicKey>,
+    pub is_ca: bool,
+    pub path_len: Option<u32>,
+    /// keyUsage first byte, when present.
+    key_usage: Option<u8>,
+    /// extKeyUsage allows serverAuth (true when the extension is absent).
+    server_auth: bool,
+    pub dns_names: Vec<String>,
+    pub ip_addrs: Vec<Vec<u8>>,
+    has_san: bool,
+    unknown_critical: bool,
+}
+
+fn hash_oid(oid: &[u8]) -> Option<HashAlg> {
+    match oid {
+        OID_SHA256 => Some(HashAlg::Sha256),
+        OID_SHA384 => Some(HashAlg::Sha384),
+        OID_SHA512 => Some(HashAlg::Sha512),
+        _ => None,
+    }
+}
+
+/// AlgorithmIdentifier of a signature.
+fn sig_alg(alg: &[u8]) -> Option<SigAlg> {
+    let mut d = Der::new(alg);
+    let oid = d.expect(TAG_OID)?;
+    match oid {
+        OID_SHA256_RSA => Some(SigAlg::RsaPkcs1(HashAlg::Sha256)),
+        OID_SHA384_RSA => Some(SigAlg::RsaPkcs1(HashAlg::Sha384)),
+        OID_SHA512_RSA => Some(SigAlg::RsaPkcs1(HashAlg::Sha512)),
+        OID_ECDSA_SHA256 => Some(SigAlg::Ecdsa(HashAlg::Sha256)),
+        OID_ECDSA_SHA384 => Some(SigAlg::Ecdsa(HashAlg::Sha384)),
+        OID_ECDSA_SHA512 => Some(SigAlg::Ecdsa(HashAlg::Sha512)),
+        OID_RSA_PSS => {
+            // RSASSA-PSS-params: [0] hashAlgorithm (default SHA-1, which we refuse)
+            let mut p = Der::new(d.expect(TAG_SEQUENCE)?);
+            let mut h = Der::new(p.expect(0xA0)?);
+            let mut alg = Der::new(h.expect(TAG_SEQUENCE)?);
+            hash_oid(alg.expect(TAG_OID)?).map(SigAlg::RsaPss)
+        }
+        _ => None,
+    }
+}
+
+fn parse_key(spki: &[u8]) -> Option<PublicKey> {
+    let mut d = Der::new(spki);
+    let mut alg = Der::new(d.expect(TAG_SEQUENCE)?);
+    let bits = d.expect(TAG_BIT_STRING)?;
+    if bits.first() != Some(&0) { return None; }
+    let bits = &bits[1..];
+    match alg.expect(TAG_OID)? {
+        OID_RSA => {
+            let mut k = Der::new(bits);
+            let mut seq = Der::new(k.expect(TAG_SEQUENCE)?);
+            let n = seq.expect(TAG_INTEGER)?;
+            let e = seq.expect(TAG_INTEGER)?;
+            Some(PublicKey::Rsa { n: n.to_vec(), e: e.to_vec() })
+        }
+        OID_EC_KEY => {
+            let curve = match alg.expect(TAG_OID)? {
+                OID_P256 => Curve::P256,
+                OID_P384 => Curve::P384,
+                _ => return None,
+            };
+            Some(PublicKey::Ec { curve, point: bits.to_vec() })
+        }
+        _ => None,
+    }
+}
+
+fn digits(b: &[u8]) -> Option<u64> {
+    let mut v = 0u64;
+    for &c in b {
+        if !c.is_ascii_digit() { return None; }
+        v = v * 10 + (c - b'0') as u64;
+    }
+    Some(v)
+}
+
+/// UTCTime / GeneralizedTime (Zulu, with seconds) as YYYYMMDDHHMMSS.
+fn parse_time(tag: u8, v: &[u8]) -> Option<u64> {
+    if v.last() != Some(&b'Z') { return None; }
+    let v = &v[..v.len() - 1];
+    match (tag, v.len()) {
+        (TAG_UTC_TIME, 12) => {
+            let yy = digits(&v[..2])?;
+            let year = if yy < 50 { 2000 + yy } else { 1900 + yy };
+            Some(year * 10_000_000_000 + digits(&v[2..])?)
+        }
+        (TAG_GEN_TIME, 14) => digits(v),
+        _ => None,
+    }
+}
+
+/// The current time in the form `not_before`/`not_after` use.
+pub fn now_key() -> u64 {
+    let t = crate::time::now();
+    t.year as u64 * 10_000_000_000
+        + t.month as u64 * 100_000_000
+        + t.day as u64 * 1_000_000
+        + t.hour as u64 * 10_000
+        + t.minute as u64 * 100
+        + t.second as u64
+}
+
+impl Certificate {
+    pub fn parse(der: &[u8]) -> Result<Certificate, CertError> {
+        Self::parse_inner(der).ok_or(CertError::Malformed)
+    }
+
+    fn parse_inner(der: &[u8]) -> Option<Certificate> {
+        let mut outer = Der::new(der);
+        let mut cert = Der::new(outer.expect(TAG_SEQUENCE)?);
+        if !outer.is_empty() { return None; }
+
+        let (tag, tbs_body, tbs_whole) = cert.read()?;
+        if tag != TAG_SEQUENCE { return None; }
+        let outer_alg = cert.expect(TAG_SEQUENCE)?;
+        let sig_alg = sig_alg(outer_alg);
+        let sig_bits = cert.expect(TAG_BIT_STRING)?;
+        if sig_bits.first() != Some(&0) { return None; }
+
+        let mut tbs = Der::new(tbs_body);
+        tbs.optional(0xA0); // version
+        tbs.expect(TAG_INTEGER)?; // serial
+        // the signed copy of the algorithm must match the unsigned one
+        if tbs.expect(TAG_SEQUENCE)? != outer_alg { return None; }
+        let (_, _, issuer) = tbs.read()?;
+        let mut validity = Der::new(tbs.expect(TAG_SEQUENCE)?);
+        let (t1, v1, _) = validity.read()?;
+        let (t2, v2, _) = validity.read()?;
+        let (_, _, subject) = tbs.read()?;
+        let spki = tbs.expect(TAG_SEQUENCE)?;
+
+        let mut c = Certificate {
+            der: der.to_vec(),
+            tbs: tbs_whole.to_vec(),
+            sig_alg,
+            signature: sig_bits[1..].to_vec(),
+            issuer: issuer.to_vec(),
+            subject: subject.to_vec(),
+            not_before: parse_time(t1, v1)?,
+            not_after: parse_time(t2, v2)?,
+            key: parse_key(spki),
+            is_ca: false,
+            path_len: None,
+            key_usage: None,
+            server_auth: true,
+            dns_names: Vec::new(),
+            ip_addrs: Vec::new(),
+            has_san: false,
+            unknown_critical: false,
+        };
+
+        // skip issuer/subject unique IDs, then [3] extensions
+        while let Some(tag) = tbs.peek_tag() {
+            let (_, body, _) = tbs.read()?;
+            if tag == 0xA3 {
+                c.parse_extensions(Der::new(body).expect(TAG_SEQUENCE)?)?;
+            }
+        }
+        Some(c)
+    }
+
+    fn parse_extensions(&mut self, exts: &[u8]) -> Option<()> {
+        let mut exts = Der::new(exts);
+        while !exts.is_empty() {
+            let mut ext = Der::new(exts.expect(TAG_SEQUENCE)?);
+            let oid = ext.expect(TAG_OID)?;
+            let critical = match ext.optional(TAG_BOOLEAN) {
+                Some(b) => b.first().is_some_and(|&v| v != 0),
+                None => false,
+            };
+            let mut val = Der::new(ext.expect(TAG_OCTET_STRING)?);
+            match oid {
+                OID_BASIC_CONSTRAINTS => {
+                    let mut bc = Der::new(val.expect(TAG_SEQUENCE)?);
+                    if let Some(b) = bc.optional(TAG_BOOLEAN) {
+                        self.is_ca = b.first().is_some_and(|&v| v != 0);
+                    }
+                    if let Some(n) = bc.optional(TAG_INTEGER) {
+                        self.path_len = Some(n.iter().fold(0u32, |a, &b| a.saturating_mul(256).saturating_add(b as u32)));
+                    }
+                }
+                OID_KEY_USAGE => {
+                    let bits = val.expect(TAG_BIT_STRING)?;
+                    self.key_usage = Some(bits.get(1).copied().unwrap_or(0));
+                }
+                OID_EXT_KEY_USAGE => {
+                    let mut seq = Der::new(val.expect(TAG_SEQUENCE)?);
+                    self.server_auth = false;
+                    while !seq.is_empty() {
+                        let p = seq.expect(TAG_OID)?;
+                        if p == OID_SERVER_AUTH || p == OID_ANY_EKU { self.server_auth = true; }
+                    }
+                }
+                OID_SAN => {
+                    self.has_san = true;
+                    let mut names = Der::new(val.expect(TAG_SEQUENCE)?);
+                    while !names.is_empty() {
+                        let (tag, v, _) = names.read()?;
+                        match tag {
+                            // [2] dNSName
+                            0x82 => self.dns_names.push(String::from_utf8_lossy(v).to_ascii_lowercase()),
+                            // [7] iPAddress
+                            0x87 => self.ip_addrs.push(v.to_vec()),
+                            _ => {}
+                        }
+                    }
+                }
+                OID_POLICIES => {}
+                _ => {
+                    if critical { self.unknown_critical = true; }
+                }
+            }
+        }
+        Some(())
+    }
+
+    /// Does `key` (the issuer's) verify this certificate's signature?
+    pub fn signed_by(&self, key: &PublicKey) -> Result<(), CertError> {
+        let alg = self.sig_alg.ok_or(CertError::Unsupported)?;
+        if verify(key, alg, &self.tbs, &self.signature)? {
+            Ok(())
+        } else {
+            Err(CertError::BadSignature)
+        }
+    }
+
+    pub fn check_time(&self, now: u64) -> Result<(), CertError> {
+        if now < self.not_before { return Err(CertError::NotYetValid); }
+        if now > self.not_after { return Err(CertError::Expired); }
+        Ok(())
+    }
+
+    /// SAN match for a host name or IP literal; the subject CN is only used
+    /// when there is no SAN extension at all.
+    pub fn matches_host(&self, host: &str) -> bool {
+        if let Some(ip) = super::ip::parse(host) {
+            let bytes: Vec<u8> = match ip {
+                super::ip::IpAddr::V4(a) => a.to_vec(),
+                super::ip::IpAddr::V6(a) => a.to_vec(),
+            };
+            return self.ip_addrs.contains(&bytes);
+        }
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if self.has_san {
+            return self.dns_names.iter().any(|p| name_matches(p, &host));
+        }
+        name_attr(&self.subject, OID_CN).is_some_and(|cn| name_matches(&cn, &host))
+    }
+
+    /// Common name (or organisation) of the subject, for display.
+    pub fn subject_name(&self) -> String {
+        display_name(&self.subject)
+    }
+
+    pub fn issuer_name(&self) -> String {
+        display_name(&self.issuer)
+    }
+}
+
+/// `*.example.com` matches exactly one label; anything else must be equal.
+/// `host` is already lowercase; DNS names compare without case.
+fn name_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.').to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(rest) => match host.split_once('.') {
+            Some((first, tail)) => !first.is_empty() && tail == rest && rest.contains('.'),
+            None => false,
+        },
+        None => pattern == host,
+    }
+}
+
+/// First attribute `oid` in a DER Name.
+fn name_attr(name: &[u8], oid: &[u8]) -> Option<String> {
+    let mut n = Der::new(name);
+    let mut rdns = Der::new(n.expect(TAG_SEQUENCE)?);
+    while !rdns.is_empty() {
+        let mut set = Der::new(rdns.expect(TAG_SET)?);
+        while !set.is_empty() {
+            let mut atv = Der::new(set.expect(TAG_SEQUENCE)?);
+            if atv.expect(TAG_OID)? == oid {
+                let (tag, v, _) = atv.read()?;
+                if matches!(tag, TAG_UTF8 | TAG_PRINTABLE | TAG_IA5) || tag == 0x14 {
+                    return Some(String::from_utf8_lossy(v).into_owned());
+                }
+            }
+        }
+    }
+    None
+}
+
+fn display_name(name: &[u8]) -> String {
+    name_attr(name, OID_CN)
+        .or_else(|| name_attr(name, OID_ORG))
+        .unwrap_or_else(|| String::from("?"))
+}
+
+/// Check `sig` over `msg` with `key`. `Err(Unsupported)` when the key and
+/// algorithm don't go together.
+pub fn verify(key: &PublicKey, alg: SigAlg, msg: &[u8], sig: &[u8]) -> Result<bool, CertError> {
+    match (key, alg) {
+        (PublicKey::Rsa { n, e }, SigAlg::RsaPkcs1(h)) => Ok(pubkey::rsa_pkcs1_verify(n, e, h, msg, sig)),
+        (PublicKey::Rsa { n, e }, SigAlg::RsaPss(h)) => Ok(pubkey::rsa_pss_verify(n, e, h, msg, sig)),
+        (PublicKey::Ec { curve, point }, SigAlg::Ecdsa(h)) => Ok(pubkey::ecdsa_verify(*curve, point, h, msg, sig)),
+        _ => Err(CertError::Unsupported),
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Trust store
+// -----------------------------------------------------------------------------
+
+static BUILTIN_ROOTS: &[&[u8]] = &[
+    include_bytes!("certs/isrg_root_x1.der"),
+    include_bytes!("certs/isrg_root_x2.der"),
+    include_bytes!("certs/digicert_global_root_ca.der"),
+    include_bytes!("certs/digicert_global_root_g2.der"),
+    include_bytes!("certs/digicert_global_root_g3.der"),
+    include_bytes!("certs/gts_root_r1.der"),
+    include_bytes!("certs/gts_root_r2.der"),
+    include_bytes!("certs/gts_root_r3.der"),
+    include_bytes!("certs/gts_root_r4.der"),
+    include_bytes!("certs/globalsign_root_ca.der"),
+    include_bytes!("certs/globalsign_root_ca_r3.der"),
+    include_bytes!("certs/globalsign_root_r46.der"),
+    include_bytes!("certs/globalsign_root_e46.der"),
+    include_bytes!("certs/amazon_root_ca_1.der"),
+    include_bytes!("certs/amazon_root_ca_2.der"),
+    include_bytes!("certs/amazon_root_ca_3.der"),
+    include_bytes!("certs/amazon_root_ca_4.der"),
+    include_bytes!("certs/usertrust_rsa.der"),
+    include_bytes!("certs/usertrust_ecc.der"),
+    include_bytes!("certs/comodo_aaa_services_root.der"),
+    include_bytes!("certs/sectigo_server_root_r46.der"),
+    include_bytes!("certs/sectigo_server_root_e46.der"),
+    include_bytes!("certs/go_daddy_root_g2.der"),
+    include_bytes!("certs/starfield_root_g2.der"),
+    include_bytes!("certs/microsoft_rsa_root_2017.der"),
+    include_bytes!("certs/microsoft_ecc_root_2017.der"),
+];
+
+/// Roots added at runtime.
+static EXTRA_ROOTS: SpinLock<Vec<Vec<u8>>> = SpinLock::new(Vec::new());
+
+/// Trust another root (DER). Returns its subject name.
+pub fn add_root(der: &[u8]) -> Result<String, CertError> {
+    let c = Certificate::parse(der)?;
+    if c.key.is_none() { return Err(CertError::Unsupported); }
+    if !c.is_ca { return Err(CertError::NotCa); }
+    let mut roots = EXTRA_ROOTS.lock();
+    if !roots.iter().any(|r| r[..] == *der) {
+        roots.push(der.to_vec());
+    }
+    Ok(c.subject_name())
+}
+
+/// Every trusted root, parsed.
+pub fn roots() -> Vec<Certificate> {
+    let mut out: Vec<Certificate> = BUILTIN_ROOTS.iter().filter_map(|d| Certificate::parse(d).ok()).collect();
+    out.extend(EXTRA_ROOTS.lock().iter().filter_map(|d| Certificate::parse(d).ok()));
+    out
+}
+
+pub fn builtin_root_count() -> usize {
+    BUILTIN_ROOTS.len()
+}
+
+pub fn extra_root_count() -> usize {
+    EXTRA_ROOTS.lock().len()
+}
+
+/// PEM `CERTIFICATE` blocks (or one raw DER certificate) into DER.
+pub fn pem_to_der(data: &[u8]) -> Vec<Vec<u8>> {
+    if data.first() == Some(&TAG_SEQUENCE) {
+        return alloc::vec![data.to_vec()];
+    }
+    let text = String::from_utf8_lossy(data);
+    let mut out = Vec::new();
+    let mut rest: &str = &text;
+    while let Some(start) = rest.find("-----BEGIN CERTIFICATE-----") {
+        let body = &rest[start + 27..];
+        let Some(end) = body.find("-----END CERTIFICATE-----") else { break };
+        if let Some(der) = base64_decode(&body[..end]) {
+            out.push(der);
+        }
+        rest = &body[end + 25..];
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    for c in s.bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' | b'\r' | b'\n' | b' ' | b'\t' => continue,
+            _ => return None,
+        } as u32;
+        acc = (acc << 6) | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+// -----------------------------------------------------------------------------
+// Chain validation
+// -----------------------------------------------------------------------------
+
+/// Validate a server's certificate list (leaf first) for `host`: every
+/// link signed by the next, ending at a trusted root, all within their
+/// validity period, issuers (the root too) marked as CAs within their
+/// path length. Returns the parsed path (leaf .. last certificate below the
+/// root) and the root.
+pub fn verify_chain(chain: &[Vec<u8>], host: &str) -> Result<(Vec<Certificate>, Certificate), CertError> {
+    if chain.is_empty() { return Err(CertError::Malformed); }
+    let certs: Vec<Certificate> = chain.iter().map(|d| Certificate::parse(d)).collect::<Result<_, _>>()?;
+    let roots = roots();
+    let now = now_key();
+
+    let leaf = &certs[0];
+    if leaf.unknown_critical { return Err(CertError::UnknownCritical); }
+    leaf.check_time(now)?;
+    if !leaf.server_auth { return Err(CertError::BadUsage); }
+    if !leaf.matches_host(host) { return Err(CertError::NameMismatch); }
+
+    let mut path: Vec<Certificate> = alloc::vec![leaf.clone()];
+    let mut used = alloc::vec![false; certs.len()];
+    used[0] = true;
+    let mut last_err = CertError::UnknownIssuer;
+
+    loop {
+        let cur = path.last().ok_or(CertError::Malformed)?;
+
+        // the server sent a trusted root itself
+        if let Some(root) = roots.iter().find(|r| r.der == cur.der) {
+            path.pop();
+            if path.is_empty() { return Err(CertError::UnknownIssuer); }
+            check_issuer(root, path.len())?;
+            return Ok((path, root.clone()));
+        }
+
+        // signed by a trusted root?
+        for root in roots.iter().filter(|r| r.subject == cur.issuer) {
+            let Some(key) = root.key.as_ref() else { continue };
+            match cur.signed_by(key) {
+                Ok(()) => {
+                    check_issuer(root, path.len())?;
+                    root.check_time(now)?;
+                    return Ok((path, root.clone()));
+                }
+                Err(e) => last_err = e,
+            }
+        }
+
+        if path.len() >= MAX_DEPTH { return Err(CertError::ChainTooLong); }
+
+        // otherwise by one of the intermediates the server sent
+        let mut next = None;
+        for (i, c) in certs.iter().enumerate() {
+            if used[i] || c.subject != cur.issuer { continue; }
+            let Some(key) = c.key.as_ref() else { continue };
+            match cur.signed_by(key) {
+                Ok(()) => {
+                    next = Some(i);
+                    break;
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        let Some(i) = next else { return Err(last_err) };
+        let ca = &certs[i];
+        check_issuer(ca, path.len())?;
+        ca.check_time(now)?;
+        used[i] = true;
+        path.push(ca.clone());
+    }
+}
+
+/// May `ca` sign the last of `path_len` certificates (leaf first) below it?
+/// Applies to trusted roots as much as to intermediates.
+fn check_issuer(ca: &Certificate, path_len: usize) -> Result<(), CertError> {
+    if !ca.is_ca { return Err(CertError::NotCa); }
+    if ca.key_usage.is_some_and(|ku| ku & KU_CERT_SIGN == 0) { return Err(CertError::NotCa); }
+    if ca.unknown_critical { return Err(CertError::UnknownCritical); }
+    // pathLenConstraint counts the intermediates below this one
+    if ca.path_len.is_some_and(|n| (path_len - 1) as u32 > n) { return Err(CertError::ChainTooLong); }
+    Ok(())
+}
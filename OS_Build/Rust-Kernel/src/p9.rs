@@ -0,0 +1,559 @@
+#![allow(dead_code)]
+// src/p9.rs
+// 9P2000.L client over virtio-9p (QEMU `-virtfs`), polling, one request in flight.
+//
+// QEMU side, e.g.:
+//   -virtfs local,path=/home/me/share,mount_tag=host0,security_model=none,id=fs0
+//
+// The share is grafted into the shell namespace at a mount point (default
+// `/host`). The RamFs only holds an empty directory there; every path below it
+// is forwarded to the host and never touches the persist log.
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::fs::SpinLock;
+use crate::virtio::{self, LegacyDevice, VirtQueue};
+
+const MSIZE: u32 = 16 * 1024;
+const IOHDRSZ: u32 = 24; // size[4] type[1] tag[2] fid[4] offset[8] count[4] + slack
+const VERSION: &str = "9P2000.L";
+
+const NOTAG: u16 = 0xFFFF;
+const NOFID: u32 = 0xFFFF_FFFF;
+const MAXWELEM: usize = 16;
+
+// message types (9P2000.L subset)
+const RLERROR: u8 = 7;
+const TLOPEN: u8 = 12;
+const TLCREATE: u8 = 14;
+const TGETATTR: u8 = 24;
+const TREADDIR: u8 = 40;
+const TMKDIR: u8 = 72;
+const TUNLINKAT: u8 = 76;
+const TVERSION: u8 = 100;
+const TATTACH: u8 = 104;
+const TWALK: u8 = 110;
+const TREAD: u8 = 116;
+const TWRITE: u8 = 118;
+const TCLUNK: u8 = 120;
+
+// Linux open(2) flags as used by 9P2000.L
+const O_RDONLY: u32 = 0;
+const O_WRONLY: u32 = 1;
+const O_TRUNC: u32 = 0o1000;
+
+const AT_REMOVEDIR: u32 = 0x200;
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const GETATTR_BASIC: u64 = 0x0000_07FF;
+
+const ENOENT: u32 = 2;
+const RPC_TIMEOUT_SPINS: u32 = 50_000_000;
+/// Largest host file read into memory (the whole kernel heap is 4 MiB).
+const MAX_READ: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum P9Error {
+    NoDevice,
+    NotMounted,
+    /// A share is already mounted at another path.
+    AlreadyMounted,
+    Virtio(virtio::VirtioError),
+    Proto,
+    NotFound,
+    NotDir,
+    InvalidPath,
+    /// The host file is larger than MAX_READ.
+    TooLarge,
+    /// errno reported by the server (Rlerror)
+    Remote(u32),
+}
+
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Attr {
+    pub mode: u32,
+    pub size: u64,
+}
+
+impl Attr {
+    pub fn is_dir(&self) -> bool { (self.mode & S_IFMT) == S_IFDIR }
+}
+
+// -----------------------------------------------------------------------------
+// Wire helpers
+// -----------------------------------------------------------------------------
+
+struct Msg(Vec<u8>);
+
+impl Msg {
+    fn new(typ: u8, tag: u16) -> Self {
+        let mut v = Vec::with_capacity(64);
+        v.extend_from_slice(&[0, 0, 0, 0, typ]);
+        v.extend_from_slice(&tag.to_le_bytes());
+        Msg(v)
+    }
+    fn u8(mut self, v: u8) -> Self { self.0.push(v); self }
+    fn u16(mut self, v: u16) -> Self { self.0.extend_from_slice(&v.to_le_bytes()); self }
+    fn u32(mut self, v: u32) -> Self { self.0.extend_from_slice(&v.to_le_bytes()); self }
+    fn u64(mut self, v: u64) -> Self { self.0.extend_from_slice(&v.to_le_bytes()); self }
+    fn str(mut self, s: &str) -> Self {
+        self.0.extend_from_slice(&(s.len() as u16).to_le_bytes());
+        self.0.extend_from_slice(s.as_bytes());
+        self
+    }
+    fn bytes(mut self, b: &[u8]) -> Self { self.0.extend_from_slice(b); self }
+    fn finish(mut self) -> Vec<u8> {
+        let n = self.0.len() as u32;
+        self.0[0..4].copy_from_slice(&n.to_le_bytes());
+        self.0
+    }
+}
+
+struct Cursor<'a> {
+    b: &'a [u8],
+    off: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], P9Error> {
+        if self.off + n > self.b.len() { return Err(P9Error::Proto); }
+        let s = &self.b[self.off..self.off + n];
+        self.off += n;
+        Ok(s)
+    }
+    fn u8(&mut self) -> Result<u8, P9Error> { Ok(self.take(1)?[0]) }
+    fn u16(&mut self) -> Result<u16, P9Error> { let s = self.take(2)?; Ok(u16::from_le_bytes([s[0], s[1]])) }
+    fn u32(&mut self) -> Result<u32, P9Error> { let s = self.take(4)?; Ok(u32::from_le_bytes([s[0], s[1], s[2], s[3]])) }
+    fn u64(&mut self) -> Result<u64, P9Error> {
+        let s = self.take(8)?;
+        let mut a = [0u8; 8];
+        a.copy_from_slice(s);
+        Ok(u64::from_le_bytes(a))
+    }
+    fn str(&mut self) -> Result<String, P9Error> {
+        let n = self.u16()? as usize;
+        let s = self.take(n)?;
+        Ok(String::from_utf8_lossy(s).into_owned())
+    }
+    fn qid(&mut self) -> Result<(), P9Error> { self.take(13).map(|_| ()) }
+}
+
+// -----------------------------------------------------------------------------
+// Client
+// -----------------------------------------------------------------------------
+
+struct Client {
+    dev: LegacyDevice,
+    vq: VirtQueue,
+    msize: u32,
+    root: u32,
+    next_fid: u32,
+    tag: u16,
+    mount_tag: String,
+    mount_point: String,
+    resp: Vec<u8>,
+}
+
+/// Runs before the fields are dropped: the device is reset while the ring is
+/// still allocated (also when `connect` gives up half way).
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.dev.set_status(0);
+    }
+}
+
+static CLIENT: SpinLock<Option<Client>> = SpinLock::new(None);
+
+impl Client {
+    fn connect(mount_point: &str) -> Result<Self, P9Error> {
+        let dev = LegacyDevice::probe(virtio::DEVICE_9P).ok_or(P9Error::NoDevice)?;
+
+        // feature bit 0: MOUNT_TAG in config space
+        let features = dev.device_features();
+        dev.set_guest_features(features & 0x1);
+
+        let vq = match dev.setup_queue(0) {
+            Ok(q) => q,
+            Err(e) => { dev.fail(); return Err(P9Error::Virtio(e)); }
+        };
+        dev.driver_ok();
+
+        let mut mount_tag = String::new();
+        if (features & 0x1) != 0 {
+            let n = dev.config_u16(0) as usize;
+            for i in 0..n.min(64) {
+                mount_tag.push(dev.config_u8(2 + i as u16) as char);
+            }
+        }
+
+        let mut c = Client {
+            dev,
+            vq,
+            msize: MSIZE,
+            root: 0,
+            next_fid: 1,
+            tag: 1,
+            mount_tag,
+            mount_point: mount_point.to_string(),
+            resp: alloc::vec![0u8; MSIZE as usize],
+        };
+
+        // Tversion: size[4] Tversion tag[2] msize[4] version[s]
+        let r = c.rpc(Msg::new(TVERSION, NOTAG).u32(MSIZE).str(VERSION).finish())?;
+        let mut cur = Cursor { b: &r, off: 0 };
+        let msize = cur.u32()?;
+        let ver = cur.str()?;
+        if ver != VERSION { return Err(P9Error::Proto); }
+        c.msize = msize.min(MSIZE);
+
+        // Tattach: fid[4] afid[4] uname[s] aname[s] n_uname[4]
+        let root = c.alloc_fid();
+        c.rpc(Msg::new(TATTACH, c.tag).u32(root).u32(NOFID).str("othello").str("").u32(0).finish())?;
+        c.root = root;
+        Ok(c)
+    }
+
+    fn alloc_fid(&mut self) -> u32 {
+        let f = self.next_fid;
+        self.next_fid = self.next_fid.wrapping_add(1).max(1);
+        f
+    }
+
+    /// Send one T-message, return the R-message body (after size/type/tag).
+    fn rpc(&mut self, req: Vec<u8>) -> Result<Vec<u8>, P9Error> {
+        let want = req[4] + 1;
+        let head = self.vq
+            .push(&[(req.as_ptr(), req.len(), false), (self.resp.as_ptr(), self.resp.len(), true)])
+            .map_err(P9Error::Virtio)?;
+        self.dev.notify(self.vq.index);
+        let len = self.vq.wait_for(head, RPC_TIMEOUT_SPINS).map_err(P9Error::Virtio)? as usize;
+        self.dev.ack_isr();
+        self.tag = self.tag.wrapping_add(1);
+        if self.tag == NOTAG { self.tag = 1; }
+
+        if len < 7 { return Err(P9Error::Proto); }
+        let size = u32::from_le_bytes([self.resp[0], self.resp[1], self.resp[2], self.resp[3]]) as usize;
+        if size < 7 || size > len.min(self.resp.len()) { return Err(P9Error::Proto); }
+        let typ = self.resp[4];
+        let body = self.resp[7..size].to_vec();
+
+        if typ == RLERROR {
+            let mut cur = Cursor { b: &body, off: 0 };
+            let ecode = cur.u32()?;
+            return Err(if ecode == ENOENT { P9Error::NotFound } else { P9Error::Remote(ecode) });
+        }
+        if typ != want { return Err(P9Error::Proto); }
+        Ok(body)
+    }
+
+    /// Walk from the root to `comps`, returning a fresh fid.
+    fn walk(&mut self, comps: &[&str]) -> Result<u32, P9Error> {
+        let fid = self.alloc_fid();
+        let mut from = self.root;
+        let mut done = 0usize;
+        loop {
+            let chunk = &comps[done..comps.len().min(done + MAXWELEM)];
+            let mut m = Msg::new(TWALK, self.tag).u32(from).u32(fid).u16(chunk.len() as u16);
+            for c in chunk { m = m.str(c); }
+            let r = match self.rpc(m.finish()) {
+                Ok(r) => r,
+                Err(e) => {
+                    if from == fid { let _ = self.clunk(fid); }
+                    return Err(e);
+                }
+            };
+            let nwqid = Cursor { b: &r, off: 0 }.u16()? as usize;
+            if nwqid < chunk.len() {
+                // partial walk: newfid is not created by the server
+                if from == fid { let _ = self.clunk(fid); }
+                return Err(P9Error::NotFound);
+            }
+            done += chunk.len();
+            from = fid;
+            if done >= comps.len() { return Ok(fid); }
+        }
+    }
+
+    fn clunk(&mut self, fid: u32) -> Result<(), P9Error> {
+        self.rpc(Msg::new(TCLUNK, self.tag).u32(fid).finish()).map(|_| ())
+    }
+
+    fn lopen(&mut self, fid: u32, flags: u32) -> Result<u32, P9Error> {
+        let r = self.rpc(Msg::new(TLOPEN, self.tag).u32(fid).u32(flags).finish())?;
+        let mut cur = Cursor { b: &r, off: 0 };
+        cur.qid()?;
+        cur.u32()
+    }
+
+    fn getattr(&mut self, fid: u32) -> Result<Attr, P9Error> {
+        let r = self.rpc(Msg::new(TGETATTR, self.tag).u32(fid).u64(GETATTR_BASIC).finish())?;
+        let mut cur = Cursor { b: &r, off: 0 };
+        let _valid = cur.u64()?;
+        cur.qid()?;
+        let mode = cur.u32()?;
+        let _uid = cur.u32()?;
+        let _gid = cur.u32()?;
+        let _nlink = cur.u64()?;
+        let _rdev = cur.u64()?;
+        let size = cur.u64()?;
+        Ok(Attr { mode, size })
+    }
+
+    fn io_chunk(&self, iounit: u32) -> u32 {
+        let max = self.msize - IOHDRSZ;
+        if iounit == 0 { max } else { iounit.min(max) }
+    }
+
+    fn read_fid(&mut self, fid: u32, iounit: u32) -> Result<Vec<u8>, P9Error> {
+        let chunk = self.io_chunk(iounit);
+        let mut out = Vec::new();
+        loop {
+            let r = self.rpc(Msg::new(TREAD, self.tag).u32(fid).u64(out.len() as u64).u32(chunk).finish())?;
+            let mut cur = Cursor { b: &r, off: 0 };
+            let n = cur.u32()? as usize;
+            if n == 0 { break; }
+            // the file may have grown since getattr
+            if out.len() + n > MAX_READ { return Err(P9Error::TooLarge); }
+            out.extend_from_slice(cur.take(n)?);
+        }
+        Ok(out)
+    }
+
+    fn write_fid(&mut self, fid: u32, iounit: u32, mut offset: u64, data: &[u8]) -> Result<(), P9Error> {
+        let chunk = self.io_chunk(iounit) as usize;
+        let mut off = 0usize;
+        while off < data.len() {
+            let take = (data.len() - off).min(chunk);
+            let r = self.rpc(
+                Msg::new(TWRITE, self.tag).u32(fid).u64(offset).u32(take as u32).bytes(&data[off..off + take]).finish(),
+            )?;
+            let n = Cursor { b: &r, off: 0 }.u32()? as usize;
+            if n == 0 { return Err(P9Error::Proto); }
+            off += n;
+            offset += n as u64;
+        }
+        Ok(())
+    }
+
+    fn readdir_fid(&mut self, fid: u32) -> Result<Vec<DirEntry>, P9Error> {
+        let mut out = Vec::new();
+        let mut offset = 0u64;
+        loop {
+            let r = self.rpc(Msg::new(TREADDIR, self.tag).u32(fid).u64(offset).u32(self.msize - IOHDRSZ).finish())?;
+            let mut cur = Cursor { b: &r, off: 0 };
+            let n = cur.u32()? as usize;
+            if n == 0 { break; }
+            let data = cur.take(n)?;
+            let mut ent = Cursor { b: data, off: 0 };
+            while ent.off < data.len() {
+                ent.qid()?;
+                offset = ent.u64()?;
+                let typ = ent.u8()?;
+                let name = ent.str()?;
+                if name == "." || name == ".." { continue; }
+                out.push(DirEntry { name, is_dir: typ == 4 }); // DT_DIR
+            }
+        }
+        Ok(out)
+    }
+
+    // ---- path-level operations (comps relative to the share root) ----
+
+    fn stat(&mut self, comps: &[&str]) -> Result<Attr, P9Error> {
+        let fid = self.walk(comps)?;
+        let r = self.getattr(fid);
+        let _ = self.clunk(fid);
+        r
+    }
+
+    fn list(&mut self, comps: &[&str]) -> Result<Vec<DirEntry>, P9Error> {
+        let fid = self.walk(comps)?;
+        let r = (|| {
+            if !self.getattr(fid)?.is_dir() { return Err(P9Error::NotDir); }
+            self.lopen(fid, O_RDONLY)?;
+            self.readdir_fid(fid)
+        })();
+        let _ = self.clunk(fid);
+        r
+    }
+
+    fn read_all(&mut self, comps: &[&str]) -> Result<Vec<u8>, P9Error> {
+        let fid = self.walk(comps)?;
+        let r = (|| {
+            if self.getattr(fid)?.size > MAX_READ as u64 { return Err(P9Error::TooLarge); }
+            let iounit = self.lopen(fid, O_RDONLY)?;
+            self.read_fid(fid, iounit)
+        })();
+        let _ = self.clunk(fid);
+        r
+    }
+
+    fn write_all(&mut self, comps: &[&str], data: &[u8], append: bool) -> Result<(), P9Error> {
+        let (leaf, parent) = comps.split_last().ok_or(P9Error::InvalidPath)?;
+
+        let fid = match self.walk(comps) {
+            Ok(fid) => fid,
+            Err(P9Error::NotFound) => {
+                // Tlcreate turns the parent fid into an open fid for the new file.
+                let fid = self.walk(parent)?;
+                let r = (|| {
+                    let rr = self.rpc(
+                        Msg::new(TLCREATE, self.tag).u32(fid).str(leaf).u32(O_WRONLY).u32(0o644).u32(0).finish(),
+                    )?;
+                    let mut cur = Cursor { b: &rr, off: 0 };
+                    cur.qid()?;
+                    let iounit = cur.u32()?;
+                    self.write_fid(fid, iounit, 0, data)
+                })();
+                let _ = self.clunk(fid);
+                return r;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let r = (|| {
+            let attr = self.getattr(fid)?;
+            if attr.is_dir() { return Err(P9Error::InvalidPath); }
+            let (flags, offset) = if append { (O_WRONLY, attr.size) } else { (O_WRONLY | O_TRUNC, 0) };
+            let iounit = self.lopen(fid, flags)?;
+            self.write_fid(fid, iounit, offset, data)
+        })();
+        let _ = self.clunk(fid);
+        r
+    }
+
+    fn mkdir(&mut self, comps: &[&str]) -> Result<(), P9Error> {
+        let (leaf, parent) = comps.split_last().ok_or(P9Error::InvalidPath)?;
+        let dfid = self.walk(parent)?;
+        let r = self.rpc(Msg::new(TMKDIR, self.tag).u32(dfid).str(leaf).u32(0o755).u32(0).finish()).map(|_| ());
+        let _ = self.clunk(dfid);
+        r
+    }
+
+    fn remove(&mut self, comps: &[&str]) -> Result<(), P9Error> {
+        let (leaf, parent) = comps.split_last().ok_or(P9Error::InvalidPath)?;
+        let flags = if self.stat(comps)?.is_dir() { AT_REMOVEDIR } else { 0 };
+        let dfid = self.walk(parent)?;
+        let r = self.rpc(Msg::new(TUNLINKAT, self.tag).u32(dfid).str(leaf).u32(flags).finish()).map(|_| ());
+        let _ = self.clunk(dfid);
+        r
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Public API (absolute shell paths)
+// -----------------------------------------------------------------------------
+
+/// Probe virtio-9p, negotiate 9P2000.L and graft the share at `mount_point`.
+/// Mounting again at the same path is a no-op.
+pub fn mount(mount_point: &str) -> Result<(), P9Error> {
+    if mount_point == "/" || !mount_point.starts_with('/') { return Err(P9Error::InvalidPath); }
+    let mut g = CLIENT.lock();
+    if let Some(c) = g.as_ref() {
+        return if c.mount_point == mount_point { Ok(()) } else { Err(P9Error::AlreadyMounted) };
+    }
+    let c = Client::connect(mount_point)?;
+    *g = Some(c);
+    drop(g);
+
+    let mut fs = crate::fs::FS.lock();
+    let _ = fs.mkdir_p_nodirty(mount_point);
+    Ok(())
+}
+
+pub fn unmount() -> Result<(), P9Error> {
+    let Some(c) = CLIENT.lock().take() else { return Err(P9Error::NotMounted); };
+    drop(c); // resets the device, then frees the ring
+    Ok(())
+}
+
+/// (mount point, mount tag) when a share is attached.
+pub fn mount_info() -> Option<(String, String)> {
+    CLIENT.lock().as_ref().map(|c| (c.mount_point.clone(), c.mount_tag.clone()))
+}
+
+/// If `abs_path` lives under the mount point, return its components relative
+/// to the share root (empty for the mount point itself).
+fn host_components(abs_path: &str) -> Option<Vec<String>> {
+    let (mp, _) = mount_info()?;
+    let rest = abs_path.strip_prefix(mp.as_str())?;
+    if !rest.is_empty() && !rest.starts_with('/') { return None; }
+    Some(rest.split('/').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect())
+}
+
+/// True if this absolute path is served by the 9P share.
+pub fn owns(abs_path: &str) -> bool {
+    host_components(abs_path).is_some()
+}
+
+fn with_client<R>(abs_path: &str, f: impl FnOnce(&mut Client, &[&str]) -> Result<R, P9Error>) -> Result<R, P9Error> {
+    let comps = host_components(abs_path).ok_or(P9Error::NotMounted)?;
+    let refs: Vec<&str> = comps.iter().map(|s| s.as_str()).collect();
+    let mut g = CLIENT.lock();
+    let c = g.as_mut().ok_or(P9Error::NotMounted)?;
+    f(c, &refs)
+}
+
+pub fn stat(abs_path: &str) -> Result<Attr, P9Error> {
+    with_client(abs_path, |c, p| c.stat(p))
+}
+
+pub fn is_dir(abs_path: &str) -> bool {
+    stat(abs_path).map(|a| a.is_dir()).unwrap_or(false)
+}
+
+pub fn list(abs_path: &str) -> Result<Vec<DirEntry>, P9Error> {
+    with_client(abs_path, |c, p| c.list(p))
+}
+
+pub fn read_all(abs_path: &str) -> Result<Vec<u8>, P9Error> {
+    with_client(abs_path, |c, p| c.read_all(p))
+}
+
+pub fn write_all(abs_path: &str, data: &[u8]) -> Result<(), P9Error> {
+    with_client(abs_path, |c, p| c.write_all(p, data, false))
+}
+
+pub fn append_all(abs_path: &str, data: &[u8]) -> Result<(), P9Error> {
+    with_client(abs_path, |c, p| c.write_all(p, data, true))
+}
+
+/// Create an empty file if it does not exist yet.
+pub fn touch(abs_path: &str) -> Result<(), P9Error> {
+    with_client(abs_path, |c, p| match c.stat(p) {
+        Ok(_) => Ok(()),
+        Err(P9Error::NotFound) => c.write_all(p, &[], false),
+        Err(e) => Err(e),
+    })
+}
+
+/// mkdir -p semantics below the mount point.
+pub fn mkdir_p(abs_path: &str) -> Result<(), P9Error> {
+    with_client(abs_path, |c, p| {
+        for i in 1..=p.len() {
+            match c.stat(&p[..i]) {
+                Ok(a) if a.is_dir() => continue,
+                Ok(_) => return Err(P9Error::NotDir),
+                Err(P9Error::NotFound) => c.mkdir(&p[..i])?,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn remove(abs_path: &str) -> Result<(), P9Error> {
+    with_client(abs_path, |c, p| {
+        if p.is_empty() { return Err(P9Error::InvalidPath); }
+        c.remove(p)
+    })
+}
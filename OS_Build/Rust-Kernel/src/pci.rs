@@ -0,0 +1,111 @@
+#![allow(dead_code)]
+// src/pci.rs
+// Legacy PCI configuration space access (mechanism #1, ports 0xCF8/0xCFC).
+//
+// Shared by the NIC drivers and the virtio devices. Everything here is a
+// brute-force bus scan; there is no hotplug and no PCIe ECAM support.
+
+use crate::portio::{inl, outl};
+
+const PCI_CONFIG_ADDR: u16 = 0xCF8;
+const PCI_CONFIG_DATA: u16 = 0xCFC;
+
+// command register bits
+pub const CMD_IO: u16 = 0x0001;
+pub const CMD_MEM: u16 = 0x0002;
+pub const CMD_BUS_MASTER: u16 = 0x0004;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub dev: u8,
+    pub func: u8,
+    pub vendor: u16,
+    pub device: u16,
+}
+
+fn cfg_addr(bus: u8, dev: u8, func: u8, off: u8) -> u32 {
+    0x8000_0000u32
+        | ((bus as u32) << 16)
+        | ((dev as u32) << 11)
+        | ((func as u32) << 8)
+        | ((off as u32) & 0xFC)
+}
+
+pub fn read_u32(bus: u8, dev: u8, func: u8, off: u8) -> u32 {
+    unsafe {
+        outl(PCI_CONFIG_ADDR, cfg_addr(bus, dev, func, off));
+        inl(PCI_CONFIG_DATA)
+    }
+}
+
+pub fn write_u32(bus: u8, dev: u8, func: u8, off: u8, val: u32) {
+    unsafe {
+        outl(PCI_CONFIG_ADDR, cfg_addr(bus, dev, func, off));
+        outl(PCI_CONFIG_DATA, val);
+    }
+}
+
+/// Find the first function matching any of the (vendor, device) pairs.
+pub fn find_any(ids: &[(u16, u16)]) -> Option<PciDevice> {
+    for bus in 0u8..=255 {
+        for dev in 0u8..32 {
+            for func in 0u8..8 {
+                let vd = read_u32(bus, dev, func, 0x00);
+                let vendor = (vd & 0xFFFF) as u16;
+                if vendor == 0xFFFF { continue; }
+                let device = (vd >> 16) as u16;
+                if ids.iter().any(|&(v, d)| v == vendor && d == device) {
+                    return Some(PciDevice { bus, dev, func, vendor, device });
+                }
+            }
+        }
+    }
+    None
+}
+
+pub fn find(vendor: u16, device: u16) -> Option<PciDevice> {
+    find_any(&[(vendor, device)])
+}
+
+impl PciDevice {
+    pub fn read_u32(&self, off: u8) -> u32 {
+        read_u32(self.bus, self.dev, self.func, off)
+    }
+
+    pub fn write_u32(&self, off: u8, val: u32) {
+        write_u32(self.bus, self.dev, self.func, off, val)
+    }
+
+    /// Raw BAR register (0..=5).
+    pub fn bar(&self, n: u8) -> u32 {
+        self.read_u32(0x10 + n * 4)
+    }
+
+    /// I/O port base of BAR `n`, if it is an I/O BAR.
+    pub fn io_bar(&self, n: u8) -> Option<u16> {
+        let bar = self.bar(n);
+        if (bar & 0x1) == 0 { return None; }
+        Some((bar & 0xFFFC) as u16)
+    }
+
+    /// Physical base of memory BAR `n` (handles 64-bit BARs).
+    pub fn mem_bar(&self, n: u8) -> Option<u64> {
+        let bar = self.bar(n);
+        if (bar & 0x1) != 0 { return None; }
+        let mut base = (bar & 0xFFFF_FFF0) as u64;
+        if ((bar >> 1) & 0x3) == 0x2 && n < 5 {
+            base |= (self.bar(n + 1) as u64) << 32;
+        }
+        if base == 0 { None } else { Some(base) }
+    }
+
+    /// Set bits in the command register (e.g. CMD_IO | CMD_BUS_MASTER).
+    pub fn enable(&self, bits: u16) {
+        let cmdsts = self.read_u32(0x04);
+        let cmd = (cmdsts & 0xFFFF) as u16;
+        let sts = (cmdsts >> 16) as u16;
+        let new_cmd = cmd | bits;
+        self.write_u32(0x04, ((sts as u32) << 16) | (new_cmd as u32));
+    }
+}
@@ -4,22 +4,40 @@
 // Replays into RamFs at boot and supports `sync` to flush dirty changes.
 //
 // Layout:
-//   [base+0] superblock (magic + head pointer)
+//   [base+0] superblock (magic + head pointer + crypto parameters)
 //   [base+1 .. base+head-1] log records
 //
+// Encryption: every record is sealed with ChaCha20-Poly1305 under one store
+// key. The superblock keeps that key wrapped once per account (a key slot:
+// PBKDF2-HMAC-SHA256 of the user's password with the slot's own salt), so
+// any enrolled user can unlock it. The first login creates the key and its
+// slot; later accounts are enrolled when they log in while the store is
+// already unlocked. Nothing is replayed into RamFs until `unlock()` has been
+// given an enrolled user's password.
+//
 // Record types:
 //   PUT: path -> bytes
 //   DEL: path deleted
 //
+// Record header (16 bytes):
+//   [0..4] magic  [4] kind  [5] flags  [6..8] path_len  [8..12] data_len  [12..16] crc32
+// followed by path (`owner\0path` with REC_F_OWNER), data_len bytes of (possibly compressed) data and, when
+// REC_F_SHA256 is set, a SHA-256 of the uncompressed data. With REC_F_AEAD
+// that body is encrypted and framed as nonce(12) | body | tag(16), using
+// header bytes [0..12] as associated data. The CRC covers everything from [4]
+// to the end of the record (it catches torn writes; the tag catches tampering).
+//
 // This is intentionally simple + robust; we can add compaction later.
 
 extern crate alloc;
 
+use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 
-use crate::{ata, crc32};
-use crate::fs::{FS, FsError};
+use crate::{ata, crc32, kdf, lz4, rng, sha256, time};
+use crate::chacha20poly1305 as aead;
+use crate::fs::{self, FS, FsError, SpinLock};
 
 const SUPER_MAGIC: u32 = 0x4F46_5342; // 'OFSB'
 const REC_MAGIC:   u32 = 0x4F46_5331; // 'OFS1'
@@ -30,6 +48,46 @@ const RESERVED_SECTORS: u32 = 65536; // 65536 * 512 = 32 MiB
 
 const KIND_PUT: u8 = 1;
 const KIND_DEL: u8 = 2;
+const KIND_SNAP: u8 = 3; // path = snapshot name, data = creation time
+
+const SNAP_NAME_MAX: usize = 32;
+
+// record flags (header byte 5)
+const REC_F_LZ4: u8 = 0x01;    // data = raw_len u32 + LZ4 block
+const REC_F_SHA256: u8 = 0x02; // 32-byte digest of the raw data follows
+const REC_F_AEAD: u8 = 0x04;   // body sealed with ChaCha20-Poly1305
+const REC_F_OWNER: u8 = 0x08;  // path field carries `owner\0path`
+const REC_F_KNOWN: u8 = REC_F_LZ4 | REC_F_SHA256 | REC_F_AEAD | REC_F_OWNER;
+
+// superblock option bits (byte 6); zero = defaults, so older images keep working
+const OPT_NO_COMPRESS: u8 = 0x01;
+const OPT_SHA256: u8 = 0x02;
+
+// superblock crypto area: [16] mode, [20..24] iterations, [24..28] first
+// log sector, [40..72] key verifier, [72..80] nonce counter, [80..84] crc32
+// of [16..80]
+const SB_CRYPTO: usize = 16;
+const SB_CRYPTO_END: usize = 80;
+const CRYPTO_NONE: u8 = 0;
+/// Random store key, wrapped per user in the key slots.
+const CRYPTO_CHACHA20: u8 = 1;
+const KDF_ITERATIONS: u32 = 20_000;
+const VERIFY_LABEL: &[u8] = b"othello persist key check";
+const WRAP_LABEL: &[u8] = b"othello persist key wrap";
+
+// key slots: [96..488] seven slots of id(8) | salt(16) | wrapped key(32),
+// [488..492] crc32 of [96..488]. id = first 8 bytes of SHA-256(user name);
+// all zero = free. wrapped = store key ^ HMAC(PBKDF2(password, salt), WRAP_LABEL).
+const SB_SLOTS: usize = 96;
+const SLOT_LEN: usize = 56;
+const KEY_SLOTS: usize = 7;
+const SB_SLOTS_END: usize = SB_SLOTS + SLOT_LEN * KEY_SLOTS;
+
+// don't bother compressing tiny files
+const COMPRESS_MIN: usize = 64;
+
+// ATA PIO transfers at most 255 sectors per command
+const IO_CHUNK_SECTORS: u32 = 128;
 
 #[derive(Debug, Clone, Copy)]
 pub enum PersistError {
@@ -37,14 +95,149 @@ pub enum PersistError {
     Corrupt,
     NoSpace,
     Disabled,
+    /// encrypted store not unlocked yet
+    Locked,
+    BadPassword,
+    /// encrypted store has no key slot for this user
+    NoKeySlot,
+    /// every key slot is taken
+    KeySlotsFull,
+    SnapshotExists,
+    NoSuchSnapshot,
+    InvalidName,
+}
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub name: String,
+    pub created: String,
+    /// log size (sectors) right after the marker
+    pub sectors: u32,
 }
 
 static mut ENABLED: bool = false;
 static mut BASE_LBA: u32 = 0;
 static mut HEAD_REL: u32 = 0; // next free sector offset from base
+// first record; past 1 only after a plaintext log was encrypted in place
+static mut START_REL: u32 = 1;
+static mut REGION_SECTORS: u32 = RESERVED_SECTORS;
+static mut OPTIONS: u8 = 0;
+
+#[derive(Clone, Copy)]
+struct CryptoParams {
+    mode: u8,
+    iterations: u32,
+    verifier: [u8; 32],
+    nonce_ctr: u64,
+}
+
+static mut CRYPTO: CryptoParams = CryptoParams {
+    mode: CRYPTO_NONE,
+    iterations: 0,
+    verifier: [0; 32],
+    nonce_ctr: 0,
+};
+#[derive(Clone, Copy)]
+struct KeySlot {
+    id: [u8; 8],
+    salt: [u8; 16],
+    wrapped: [u8; 32],
+}
+
+const FREE_SLOT: KeySlot = KeySlot { id: [0; 8], salt: [0; 16], wrapped: [0; 32] };
+
+static mut SLOTS: [KeySlot; KEY_SLOTS] = [FREE_SLOT; KEY_SLOTS];
+static mut KEY: Option<[u8; 32]> = None;
+static mut UNLOCKED: bool = false;
+
+/// Logical vs on-disk bytes of PUT records written since boot.
+#[derive(Clone, Copy, Default)]
+pub struct PersistStats {
+    pub raw_bytes: u64,
+    pub stored_bytes: u64,
+}
+
+static mut STATS: PersistStats = PersistStats { raw_bytes: 0, stored_bytes: 0 };
+
+// Log bytes written for each account's files since the log was last cleared.
+// The log never gives space back, so quotas count this as well as live bytes.
+static LOG_USAGE: SpinLock<BTreeMap<String, u64>> = SpinLock::new(BTreeMap::new());
 
 pub fn enabled() -> bool { unsafe { ENABLED } }
 
+pub fn encrypted() -> bool { unsafe { CRYPTO.mode != CRYPTO_NONE } }
+/// Accounts that can unlock the store (0 for a plaintext store).
+pub fn key_slots_used() -> usize {
+    let slots = unsafe { SLOTS };
+    slots.iter().filter(|s| s.id != [0; 8]).count()
+}
+pub fn unlocked() -> bool { unsafe { UNLOCKED } }
+
+/// Does `user` hold the first key slot, i.e. did their login create the store?
+pub fn is_store_owner(user: &str) -> bool {
+    encrypted() && unsafe { SLOTS[0].id } == user_id(user)
+}
+
+pub fn log_usage_of(owner: &str) -> u64 {
+    LOG_USAGE.lock().get(owner).copied().unwrap_or(0)
+}
+
+fn charge_log(owner: &str, sectors: u32) {
+    *LOG_USAGE.lock().entry(owner.to_string()).or_insert(0) += sectors as u64 * 512;
+}
+
+/// (bytes used by the log incl. superblock, bytes in the reserved region)
+pub fn disk_usage() -> Option<(u64, u64)> {
+    if !enabled() { return None; }
+    unsafe { Some((HEAD_REL as u64 * 512, REGION_SECTORS as u64 * 512)) }
+}
+
+pub fn compression_enabled() -> bool { unsafe { (OPTIONS & OPT_NO_COMPRESS) == 0 } }
+pub fn strong_hash_enabled() -> bool { unsafe { (OPTIONS & OPT_SHA256) != 0 } }
+pub fn stats() -> PersistStats { unsafe { STATS } }
+
+/// Toggle LZ4 for new records (existing records are read either way).
+pub fn set_compression(on: bool) -> Result<(), PersistError> {
+    set_option(OPT_NO_COMPRESS, !on)
+}
+
+/// Toggle the SHA-256 digest on new records.
+pub fn set_strong_hash(on: bool) -> Result<(), PersistError> {
+    set_option(OPT_SHA256, on)
+}
+
+fn set_option(bit: u8, on: bool) -> Result<(), PersistError> {
+    if !enabled() { return Err(PersistError::Disabled); }
+    unsafe {
+        if on { OPTIONS |= bit; } else { OPTIONS &= !bit; }
+    }
+    write_superblock()
+}
+
+fn read_span(lba: u32, count: u32, out: &mut [u8]) -> Result<(), PersistError> {
+    let mut done = 0u32;
+    while done < count {
+        let n = (count - done).min(IO_CHUNK_SECTORS);
+        let off = (done * 512) as usize;
+        ata::read_sectors_lba28(lba + done, n as u8, &mut out[off..off + (n * 512) as usize])
+            .map_err(PersistError::Ata)?;
+        done += n;
+    }
+    Ok(())
+}
+
+fn write_span(lba: u32, count: u32, data: &[u8]) -> Result<(), PersistError> {
+    let mut done = 0u32;
+    while done < count {
+        let n = (count - done).min(IO_CHUNK_SECTORS);
+        let off = (done * 512) as usize;
+        ata::write_sectors_lba28(lba + done, n as u8, &data[off..off + (n * 512) as usize])
+            .map_err(PersistError::Ata)?;
+        done += n;
+    }
+    Ok(())
+}
+
 pub fn init() -> Result<(), PersistError> {
     let drive = ata::identify().map_err(PersistError::Ata)?;
     let total = drive.total_sectors;
@@ -60,6 +253,7 @@ pub fn init() -> Result<(), PersistError> {
 
     unsafe {
         BASE_LBA = base;
+        REGION_SECTORS = reserve;
         ENABLED = true;
     }
 
@@ -70,7 +264,7 @@ pub fn init() -> Result<(), PersistError> {
     let magic = u32::from_le_bytes([sec[0], sec[1], sec[2], sec[3]]);
     if magic != SUPER_MAGIC {
         // format new superblock
-        unsafe { HEAD_REL = 1; }
+        unsafe { HEAD_REL = 1; START_REL = 1; OPTIONS = 0; CRYPTO.mode = CRYPTO_NONE; }
         write_superblock().map_err(|_| PersistError::Ata(ata::AtaError::Timeout))?;
         return Ok(());
     }
@@ -89,18 +283,250 @@ pub fn init() -> Result<(), PersistError> {
         return Err(PersistError::Corrupt);
     }
 
-    unsafe { HEAD_REL = head.max(1); }
+    unsafe {
+        HEAD_REL = head.max(1);
+        OPTIONS = sec[6];
+    }
+
+    if sec[SB_CRYPTO] != CRYPTO_NONE {
+        let c = &sec[SB_CRYPTO_END..SB_CRYPTO_END + 4];
+        if u32::from_le_bytes([c[0], c[1], c[2], c[3]]) != crc32::crc32(&sec[SB_CRYPTO..SB_CRYPTO_END]) {
+            return Err(PersistError::Corrupt);
+        }
+        if sec[SB_CRYPTO] != CRYPTO_CHACHA20 {
+            unsafe { ENABLED = false; }
+            return Err(PersistError::Disabled);
+        }
+        let c = &sec[SB_SLOTS_END..SB_SLOTS_END + 4];
+        if u32::from_le_bytes([c[0], c[1], c[2], c[3]]) != crc32::crc32(&sec[SB_SLOTS..SB_SLOTS_END]) {
+            return Err(PersistError::Corrupt);
+        }
+        for (i, raw) in sec[SB_SLOTS..SB_SLOTS_END].chunks_exact(SLOT_LEN).enumerate() {
+            let mut k = FREE_SLOT;
+            k.id.copy_from_slice(&raw[0..8]);
+            k.salt.copy_from_slice(&raw[8..24]);
+            k.wrapped.copy_from_slice(&raw[24..56]);
+            unsafe { SLOTS[i] = k; }
+        }
+        let start = u32::from_le_bytes([sec[24], sec[25], sec[26], sec[27]]);
+        unsafe { START_REL = start.clamp(1, HEAD_REL); }
+        let mut p = CryptoParams {
+            mode: sec[SB_CRYPTO],
+            iterations: u32::from_le_bytes([sec[20], sec[21], sec[22], sec[23]]),
+            verifier: [0; 32],
+            nonce_ctr: 0,
+        };
+        p.verifier.copy_from_slice(&sec[40..72]);
+        let mut ctr = [0u8; 8];
+        ctr.copy_from_slice(&sec[72..80]);
+        p.nonce_ctr = u64::from_le_bytes(ctr);
+        unsafe { CRYPTO = p; }
+    }
+    Ok(())
+}
+
+/// Called by the login screen with the user's name and password. Unwraps
+/// the store key from the user's slot, replays the log into RamFs and seeds
+/// the default layout if needed. A plaintext store (fresh or from an older
+/// build) is encrypted on first unlock with a slot for this user; once the
+/// store is unlocked, a user without a slot is enrolled. `BadPassword` and
+/// `NoKeySlot` should block a login, nothing else.
+pub fn unlock(user: &str, password: &str) -> Result<(), PersistError> {
+    if unlocked() { return enroll(user, password); }
+    if !enabled() {
+        after_replay();
+        return Ok(());
+    }
+
+    let res = if encrypted() {
+        let p = unsafe { CRYPTO };
+        let slot = find_slot(user).ok_or(PersistError::NoKeySlot)?;
+        let key = unwrap_key(&unsafe { SLOTS[slot] }, password, p.iterations);
+        if !aead::ct_eq(&kdf::hmac_sha256(&key, VERIFY_LABEL), &p.verifier) {
+            return Err(PersistError::BadPassword);
+        }
+        unsafe { KEY = Some(key); }
+        replay(false)
+    } else {
+        // migrate: read the plaintext log, then encrypt it. If either step
+        // fails the store stays plaintext (and locked) as it was.
+        replay(true).and_then(|()| encrypt_store(user, password))
+    };
+
+    if res.is_ok() {
+        unsafe { UNLOCKED = true; }
+    }
+    after_replay();
+    res
+}
+
+fn derive_key(password: &str, salt: &[u8; 16], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    kdf::pbkdf2_sha256(password.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+fn user_id(user: &str) -> [u8; 8] {
+    let d = sha256::sha256(user.as_bytes());
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&d[..8]);
+    id
+}
+
+fn find_slot(user: &str) -> Option<usize> {
+    let id = user_id(user);
+    let slots = unsafe { SLOTS };
+    slots.iter().position(|s| s.id == id)
+}
+
+/// Wrapping and unwrapping are the same XOR with this.
+fn wrap_pad(password: &str, salt: &[u8; 16], iterations: u32) -> [u8; 32] {
+    kdf::hmac_sha256(&derive_key(password, salt, iterations), WRAP_LABEL)
+}
+
+fn xor32(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let mut out = *a;
+    for (o, x) in out.iter_mut().zip(b.iter()) { *o ^= x; }
+    out
+}
+
+fn unwrap_key(slot: &KeySlot, password: &str, iterations: u32) -> [u8; 32] {
+    xor32(&slot.wrapped, &wrap_pad(password, &slot.salt, iterations))
+}
+
+fn new_slot(user: &str, password: &str, key: &[u8; 32]) -> KeySlot {
+    let mut salt = [0u8; 16];
+    salt.copy_from_slice(&entropy()[..16]);
+    let wrapped = xor32(key, &wrap_pad(password, &salt, unsafe { CRYPTO.iterations }));
+    KeySlot { id: user_id(user), salt, wrapped }
+}
+
+/// Wrap `key` for `user` in a free slot and write the superblock.
+fn add_slot(user: &str, password: &str, key: &[u8; 32]) -> Result<(), PersistError> {
+    let slots = unsafe { SLOTS };
+    let i = slots.iter().position(|s| s.id == [0; 8]).ok_or(PersistError::KeySlotsFull)?;
+    unsafe { SLOTS[i] = new_slot(user, password, key); }
+    write_superblock()
+}
+
+/// Store already unlocked: give `user` a slot if they have none, or check
+/// their password against the one they have.
+fn enroll(user: &str, password: &str) -> Result<(), PersistError> {
+    let Some(key) = (unsafe { KEY }) else { return Ok(()) };
+    match find_slot(user) {
+        Some(i) => {
+            let k = unwrap_key(&unsafe { SLOTS[i] }, password, unsafe { CRYPTO.iterations });
+            if aead::ct_eq(&k, &key) { Ok(()) } else { Err(PersistError::BadPassword) }
+        }
+        None => add_slot(user, password, &key),
+    }
+}
+
+/// Salts and the store key come from crate::rng. On a CPU with RDRAND that
+/// is real entropy; without it the pool only has the RTC (guessable to the
+/// minute) and TSC jitter of a fixed spin loop, which keeps values distinct
+/// per machine and format but is weak against a determined guesser.
+fn entropy() -> [u8; 32] {
+    let mut out = [0u8; 32];
+    rng::fill(&mut out);
+    out
+}
+
+/// Encrypt a replayed plaintext store with a new key and a slot for `user`.
+/// Sealed copies of every file go after the plaintext log first; one
+/// superblock write then switches the mode, key slot and log start together,
+/// and only after that is the plaintext scrubbed. A crash before the switch
+/// leaves the plaintext store as it was, one after it the encrypted one.
+fn encrypt_store(user: &str, password: &str) -> Result<(), PersistError> {
+    let old_head = unsafe { HEAD_REL };
+    let key = entropy();
+    unsafe {
+        CRYPTO = CryptoParams {
+            mode: CRYPTO_CHACHA20,
+            iterations: KDF_ITERATIONS,
+            verifier: kdf::hmac_sha256(&key, VERIFY_LABEL),
+            nonce_ctr: 0,
+        };
+        SLOTS = [FREE_SLOT; KEY_SLOTS];
+        SLOTS[0] = new_slot(user, password, &key);
+        KEY = Some(key);
+    }
+
+    // the plaintext copy is scrubbed below, so only the sealed one counts
+    let plain_usage = core::mem::take(&mut *LOG_USAGE.lock());
+    FS.lock().mark_all_dirty();
+    let sealed = write_dirty(write_record).and_then(|_| {
+        unsafe { START_REL = old_head; }
+        write_superblock()
+    });
+    if let Err(e) = sealed {
+        // the superblock still describes the plaintext log: keep using it
+        unsafe {
+            CRYPTO.mode = CRYPTO_NONE;
+            KEY = None;
+            HEAD_REL = old_head;
+            START_REL = 1;
+        }
+        *LOG_USAGE.lock() = plain_usage;
+        return Err(e);
+    }
+    scrub(old_head)
+}
+
+/// Zero log sectors [1, end).
+fn scrub(end: u32) -> Result<(), PersistError> {
+    scrub_range(1, end)
+}
+
+/// Zero log sectors [start, end).
+fn scrub_range(start: u32, end: u32) -> Result<(), PersistError> {
+    let base = unsafe { BASE_LBA };
+    let zero = alloc::vec![0u8; (IO_CHUNK_SECTORS * 512) as usize];
+    let mut rel = start.max(1);
+    while rel < end {
+        let n = (end - rel).min(IO_CHUNK_SECTORS);
+        write_span(base + rel, n, &zero)?;
+        rel += n;
+    }
     Ok(())
 }
 
+/// Seed the default layout if the log didn't provide one, and pick up the
+/// quota table.
+fn after_replay() {
+    let has_etc = FS.lock().exists("/etc");
+    if !has_etc {
+        fs::init_default_layout();
+    }
+    FS.lock().reload_quotas();
+}
+
 pub fn mount_into_ramfs() -> Result<(), PersistError> {
+    replay(!encrypted())
+}
+
+fn replay(allow_plain: bool) -> Result<(), PersistError> {
+    LOG_USAGE.lock().clear();
+    walk_log(allow_plain, |first, next, kind, owner, path, data| {
+        charge_log(owner, next - first);
+        apply_record(kind, owner, path, data);
+        true
+    })
+}
+
+/// Decode records in log order and hand each one to `f` as
+/// (first sector, next sector, kind, owner, path, data). `f` returns false to stop.
+fn walk_log(
+    allow_plain: bool,
+    mut f: impl FnMut(u32, u32, u8, &str, &str, &[u8]) -> bool,
+) -> Result<(), PersistError> {
     if !enabled() { return Err(PersistError::Disabled); }
 
-    let (base, head) = unsafe { (BASE_LBA, HEAD_REL) };
-    if head <= 1 { return Ok(()); }
+    let (base, head, start) = unsafe { (BASE_LBA, HEAD_REL, START_REL) };
+    if head <= start { return Ok(()); }
 
     // Iterate records
-    let mut rel = 1u32;
+    let mut rel = start;
     let mut sector = [0u8; 512];
 
     while rel < head {
@@ -115,49 +541,126 @@ pub fn mount_into_ramfs() -> Result<(), PersistError> {
         }
 
         let kind = sector[4];
+        let flags = sector[5];
         let path_len = u16::from_le_bytes([sector[6], sector[7]]) as usize;
         let data_len = u32::from_le_bytes([sector[8], sector[9], sector[10], sector[11]]) as usize;
         let crc = u32::from_le_bytes([sector[12], sector[13], sector[14], sector[15]]);
+        if (flags & !REC_F_KNOWN) != 0 {
+            return Err(PersistError::Corrupt);
+        }
 
-        let total_len = 16 + path_len + data_len;
-        let sectors_needed = ((total_len + 511) / 512).max(1);
+        let sealed = (flags & REC_F_AEAD) != 0;
+        if !sealed && !allow_plain {
+            return Err(PersistError::Corrupt);
+        }
+        let hash_len = if (flags & REC_F_SHA256) != 0 { sha256::DIGEST_LEN } else { 0 };
+        let frame_len = if sealed { aead::NONCE_LEN + aead::TAG_LEN } else { 0 };
+        let total_len = 16 + frame_len + path_len + data_len + hash_len;
+        let sectors_needed = total_len.div_ceil(512).max(1);
+        if rel + sectors_needed as u32 > head {
+            return Err(PersistError::Corrupt);
+        }
         let mut buf = Vec::with_capacity(sectors_needed * 512);
         buf.extend_from_slice(&sector);
 
         if sectors_needed > 1 {
             let mut tmp = alloc::vec![0u8; (sectors_needed - 1) * 512];
-            ata::read_sectors_lba28(base + rel + 1, (sectors_needed - 1) as u8, &mut tmp).map_err(PersistError::Ata)?;
+            read_span(base + rel + 1, (sectors_needed - 1) as u32, &mut tmp)?;
             buf.extend_from_slice(&tmp);
         }
 
-        let payload = &buf[4..total_len]; // kind..data
+        let payload = &buf[4..total_len]; // kind..data(+digest)
         let calc = crc32::crc32(payload);
         if calc != crc {
             return Err(PersistError::Corrupt);
         }
 
-        let path_bytes = &buf[16..16 + path_len];
-        let path = core::str::from_utf8(path_bytes).map_err(|_| PersistError::Corrupt)?.to_string();
-        let data = &buf[16 + path_len .. 16 + path_len + data_len];
+        let body = if sealed { 16 + aead::NONCE_LEN } else { 16 };
+        let body_end = body + path_len + data_len + hash_len;
+        if sealed {
+            let key = unsafe { KEY }.ok_or(PersistError::Locked)?;
+            let mut aad = [0u8; 12];
+            aad.copy_from_slice(&buf[0..12]);
+            let mut nonce = [0u8; aead::NONCE_LEN];
+            nonce.copy_from_slice(&buf[16..16 + aead::NONCE_LEN]);
+            let mut tag = [0u8; aead::TAG_LEN];
+            tag.copy_from_slice(&buf[body_end..body_end + aead::TAG_LEN]);
+            if !aead::open(&key, &nonce, &aad, &mut buf[body..body_end], &tag) {
+                return Err(PersistError::Corrupt);
+            }
+        }
 
-        apply_record(kind, &path, data);
+        let path_bytes = &buf[body..body + path_len];
+        let field = core::str::from_utf8(path_bytes).map_err(|_| PersistError::Corrupt)?;
+        let (owner, path) = if (flags & REC_F_OWNER) != 0 {
+            field.split_once('\0').ok_or(PersistError::Corrupt)?
+        } else {
+            (fs::ROOT_OWNER, field)
+        };
+        let (owner, path) = (owner.to_string(), path.to_string());
+        let stored = &buf[body + path_len .. body + path_len + data_len];
+        let data = decode_data(flags, stored, &buf[body + path_len + data_len .. body_end])?;
 
-        rel += sectors_needed as u32;
+        let next = rel + sectors_needed as u32;
+        if !f(rel, next, kind, &owner, &path, &data) {
+            break;
+        }
+        rel = next;
     }
 
     Ok(())
 }
 
-fn apply_record(kind: u8, path: &str, data: &[u8]) {
+/// Undo compression and check the optional digest.
+fn decode_data(flags: u8, stored: &[u8], digest: &[u8]) -> Result<Vec<u8>, PersistError> {
+    let data = if (flags & REC_F_LZ4) != 0 {
+        if stored.len() < 4 { return Err(PersistError::Corrupt); }
+        let raw_len = u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]) as usize;
+        lz4::decompress(&stored[4..], raw_len).ok_or(PersistError::Corrupt)?
+    } else {
+        stored.to_vec()
+    };
+
+    if (flags & REC_F_SHA256) != 0 && sha256::sha256(&data)[..] != *digest {
+        return Err(PersistError::Corrupt);
+    }
+    Ok(data)
+}
+
+/// Pick the on-disk form of `data`: (flags, stored bytes, digest).
+fn encode_data(data: &[u8]) -> (u8, Vec<u8>, Option<[u8; 32]>) {
+    let mut flags = 0u8;
+    let digest = if strong_hash_enabled() {
+        flags |= REC_F_SHA256;
+        Some(sha256::sha256(data))
+    } else {
+        None
+    };
+
+    if compression_enabled() && data.len() >= COMPRESS_MIN {
+        let block = lz4::compress(data);
+        if block.len() + 4 < data.len() {
+            let mut stored = Vec::with_capacity(block.len() + 4);
+            stored.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            stored.extend_from_slice(&block);
+            return (flags | REC_F_LZ4, stored, digest);
+        }
+    }
+    (flags, data.to_vec(), digest)
+}
+
+fn apply_record(kind: u8, owner: &str, path: &str, data: &[u8]) {
     // Apply into RAM fs without marking dirty
     let mut fs = FS.lock();
     match kind {
         KIND_PUT => {
+            let prev = fs.set_owner(owner);
             // ensure parent dirs
             if let Ok((parent, _leaf)) = split_parent(path) {
                 let _ = fs.mkdir_p(&parent);
             }
             let _ = fs.write_all_nodirty(path, data);
+            fs.set_owner(&prev);
         }
         KIND_DEL => {
             let _ = fs.rm_nodirty(path);
@@ -182,53 +685,120 @@ fn write_superblock() -> Result<(), PersistError> {
     let mut sec = [0u8; 512];
     sec[0..4].copy_from_slice(&SUPER_MAGIC.to_le_bytes());
     sec[4..6].copy_from_slice(&VERSION.to_le_bytes());
-    // [6..8] reserved
+    sec[6] = unsafe { OPTIONS };
+    // [7] reserved
     sec[8..12].copy_from_slice(&head.to_le_bytes());
     let crc = crc32::crc32(&sec[0..12]);
     sec[12..16].copy_from_slice(&crc.to_le_bytes());
 
+    let p = unsafe { CRYPTO };
+    if p.mode != CRYPTO_NONE {
+        sec[SB_CRYPTO] = p.mode;
+        sec[20..24].copy_from_slice(&p.iterations.to_le_bytes());
+        sec[24..28].copy_from_slice(&unsafe { START_REL }.to_le_bytes());
+        sec[40..72].copy_from_slice(&p.verifier);
+        sec[72..80].copy_from_slice(&p.nonce_ctr.to_le_bytes());
+        let c = crc32::crc32(&sec[SB_CRYPTO..SB_CRYPTO_END]);
+        sec[SB_CRYPTO_END..SB_CRYPTO_END + 4].copy_from_slice(&c.to_le_bytes());
+
+        let slots = unsafe { SLOTS };
+        for (raw, k) in sec[SB_SLOTS..SB_SLOTS_END].chunks_exact_mut(SLOT_LEN).zip(slots.iter()) {
+            raw[0..8].copy_from_slice(&k.id);
+            raw[8..24].copy_from_slice(&k.salt);
+            raw[24..56].copy_from_slice(&k.wrapped);
+        }
+        let c = crc32::crc32(&sec[SB_SLOTS..SB_SLOTS_END]);
+        sec[SB_SLOTS_END..SB_SLOTS_END + 4].copy_from_slice(&c.to_le_bytes());
+    }
+
     ata::write_sectors_lba28(base, 1, &sec).map_err(PersistError::Ata)?;
     Ok(())
 }
 
-fn append_record(kind: u8, path: &str, data: &[u8]) -> Result<(), PersistError> {
+fn append_record(kind: u8, owner: Option<&str>, path: &str, data: &[u8]) -> Result<(), PersistError> {
+    write_record(kind, owner, path, data)?;
+    write_superblock()
+}
+
+/// Write one record at the head without committing it: it only becomes part
+/// of the log once the superblock is written with the new head.
+fn write_record(kind: u8, owner: Option<&str>, path: &str, data: &[u8]) -> Result<(), PersistError> {
     if !enabled() { return Err(PersistError::Disabled); }
+    if !unlocked() { return Err(PersistError::Locked); }
+    let key = if encrypted() { Some(unsafe { KEY }.ok_or(PersistError::Locked)?) } else { None };
 
     let base = unsafe { BASE_LBA };
     let head = unsafe { HEAD_REL };
 
-    let path_b = path.as_bytes();
+    let field = match owner {
+        Some(o) => alloc::format!("{o}\0{path}"),
+        None => path.to_string(),
+    };
+    let path_b = field.as_bytes();
     let path_len = path_b.len();
-    let data_len = data.len();
+    let (mut flags, stored, digest) = encode_data(data);
+    if key.is_some() { flags |= REC_F_AEAD; }
+    if owner.is_some() { flags |= REC_F_OWNER; }
+    let data_len = stored.len();
+    let hash_len = if digest.is_some() { sha256::DIGEST_LEN } else { 0 };
+    let frame_len = if key.is_some() { aead::NONCE_LEN + aead::TAG_LEN } else { 0 };
 
-    // header(16) + payload
-    let total_len = 16 + path_len + data_len;
-    let sectors_needed = ((total_len + 511) / 512).max(1);
+    // header(16) + [nonce] + payload + [tag]
+    let total_len = 16 + frame_len + path_len + data_len + hash_len;
+    let sectors_needed = total_len.div_ceil(512).max(1);
 
     // Build contiguous buffer
     let mut buf = alloc::vec![0u8; sectors_needed * 512];
     buf[0..4].copy_from_slice(&REC_MAGIC.to_le_bytes());
     buf[4] = kind;
-    buf[5] = 0;
+    buf[5] = flags;
     buf[6..8].copy_from_slice(&(path_len as u16).to_le_bytes());
     buf[8..12].copy_from_slice(&(data_len as u32).to_le_bytes());
 
-    // payload for CRC: kind..data_len + path + data
-    buf[16..16+path_len].copy_from_slice(path_b);
-    buf[16+path_len .. 16+path_len+data_len].copy_from_slice(data);
-    let crc = crc32::crc32(&buf[4..(16+path_len+data_len)]);
+    // payload for CRC: kind..data_len + path + data (+ digest)
+    let body = if key.is_some() { 16 + aead::NONCE_LEN } else { 16 };
+    let body_end = body + path_len + data_len + hash_len;
+    buf[body..body+path_len].copy_from_slice(path_b);
+    buf[body+path_len .. body+path_len+data_len].copy_from_slice(&stored);
+    if let Some(d) = digest {
+        buf[body+path_len+data_len .. body_end].copy_from_slice(&d);
+    }
+
+    if let Some(key) = key {
+        // counter persists in the superblock; the TSC half covers a crash
+        // between writing a record and bumping the counter
+        let ctr = unsafe { CRYPTO.nonce_ctr };
+        unsafe { CRYPTO.nonce_ctr = ctr.wrapping_add(1); }
+        let mut nonce = [0u8; aead::NONCE_LEN];
+        nonce[..8].copy_from_slice(&ctr.to_le_bytes());
+        nonce[8..].copy_from_slice(&(time::rdtsc() as u32).to_le_bytes());
+        buf[16..body].copy_from_slice(&nonce);
+
+        let mut aad = [0u8; 12];
+        aad.copy_from_slice(&buf[0..12]);
+        let tag = aead::seal(&key, &nonce, &aad, &mut buf[body..body_end]);
+        buf[body_end..body_end + aead::TAG_LEN].copy_from_slice(&tag);
+    }
+
+    let crc = crc32::crc32(&buf[4..total_len]);
     buf[12..16].copy_from_slice(&crc.to_le_bytes());
 
     // capacity check: keep one sector for superblock
     // (reserve size isn't explicitly tracked; we rely on tail partition big enough)
-    if head + (sectors_needed as u32) >= RESERVED_SECTORS {
+    if head + (sectors_needed as u32) >= unsafe { REGION_SECTORS } {
         return Err(PersistError::NoSpace);
     }
 
-    ata::write_sectors_lba28(base + head, sectors_needed as u8, &buf).map_err(PersistError::Ata)?;
+    write_span(base + head, sectors_needed as u32, &buf)?;
+
+    if kind == KIND_PUT {
+        unsafe {
+            STATS.raw_bytes += data.len() as u64;
+            STATS.stored_bytes += (sectors_needed * 512) as u64;
+        }
+    }
 
     unsafe { HEAD_REL = head + sectors_needed as u32; }
-    write_superblock()?;
     Ok(())
 }
 
@@ -237,7 +807,15 @@ fn append_record(kind: u8, path: &str, data: &[u8]) -> Result<(), PersistError>
 /// - DEL: for deleted paths (tracked by RamFs)
 pub fn sync_dirty() -> Result<usize, PersistError> {
     if !enabled() { return Err(PersistError::Disabled); }
+    if !unlocked() { return Err(PersistError::Locked); }
+    write_dirty(append_record)
+}
+
+/// `append_record` or `write_record`: (kind, owner, path, data).
+type RecordWriter = fn(u8, Option<&str>, &str, &[u8]) -> Result<(), PersistError>;
 
+/// Hand every pending delete and dirty file to `write`.
+fn write_dirty(write: RecordWriter) -> Result<usize, PersistError> {
     // Collect dirty files + deletes
     let (puts, dels) = {
         let mut fs = FS.lock();
@@ -247,37 +825,107 @@ pub fn sync_dirty() -> Result<usize, PersistError> {
     let mut wrote = 0usize;
 
     for p in dels {
-        append_record(KIND_DEL, &p, &[])?;
+        write(KIND_DEL, None, &p, &[])?;
         wrote += 1;
     }
 
     for p in puts {
-        let bytes = {
+        let (bytes, owner, payer) = {
             let fs = FS.lock();
-            match fs.read_all(&p) {
-                Ok(v) => v,
-                Err(_) => continue,
+            match (fs.read_all(&p), fs.owner_of(&p), fs.payer_of(&p)) {
+                (Ok(v), Ok(o), Ok(w)) => (v, o, w),
+                _ => continue,
             }
         };
-        append_record(KIND_PUT, &p, &bytes)?;
+        let head = unsafe { HEAD_REL };
+        write(KIND_PUT, Some(&owner), &p, &bytes)?;
+        charge_log(&payer, unsafe { HEAD_REL } - head);
         wrote += 1;
     }
 
     Ok(wrote)
 }
 
-/// (Optional) wipe persistent region (dangerous; mainly for dev)
+/// Flush pending changes and append a named snapshot marker.
+pub fn snapshot(name: &str) -> Result<(), PersistError> {
+    if name.is_empty() || name.len() > SNAP_NAME_MAX || name.bytes().any(|b| b <= b' ' || b == b'/') {
+        return Err(PersistError::InvalidName);
+    }
+    if list_snapshots()?.iter().any(|s| s.name == name) {
+        return Err(PersistError::SnapshotExists);
+    }
+    sync_dirty()?;
+
+    let mut tbuf = [0u8; 32];
+    let n = time::format_datetime(&mut tbuf, time::now());
+    append_record(KIND_SNAP, None, name, &tbuf[..n])
+}
+
+/// Snapshot markers in log order.
+pub fn list_snapshots() -> Result<Vec<Snapshot>, PersistError> {
+    if !unlocked() { return Err(PersistError::Locked); }
+    let mut out = Vec::new();
+    walk_log(!encrypted(), |_, next, kind, _, path, data| {
+        if kind == KIND_SNAP {
+            out.push(Snapshot {
+                name: path.to_string(),
+                created: String::from_utf8_lossy(data).into_owned(),
+                sectors: next,
+            });
+        }
+        true
+    })?;
+    Ok(out)
+}
+
+/// Rebuild RamFs from the log up to snapshot `name`, then append that state
+/// (rewriting every file and deleting what the snapshot didn't have) so the
+/// later records and snapshots stay in the log and can be rolled back to in
+/// turn. Unsynced changes are discarded.
+pub fn rollback(name: &str) -> Result<(), PersistError> {
+    let snap = list_snapshots()?
+        .into_iter()
+        .find(|s| s.name == name)
+        .ok_or(PersistError::NoSuchSnapshot)?;
+
+    let before = FS.lock().file_paths();
+    FS.lock().reset();
+
+    // replay only up to the marker; the superblock keeps the real head, and
+    // the log space used after it stays used
+    let head = unsafe { HEAD_REL };
+    let logged = LOG_USAGE.lock().clone();
+    unsafe { HEAD_REL = snap.sectors; }
+    let res = replay(!encrypted());
+    unsafe { HEAD_REL = head; }
+    *LOG_USAGE.lock() = logged;
+    after_replay();
+    res?;
+
+    {
+        let mut fs = FS.lock();
+        for p in before.iter() {
+            fs.mark_gone(p);
+        }
+        fs.mark_all_dirty();
+    }
+    sync_dirty().map(|_| ())
+}
+
+/// (Optional) wipe persistent region (dangerous; mainly for dev). An
+/// encrypted store keeps its key and slots; anything else stays locked until
+/// the next login encrypts it with a password.
 pub fn format() -> Result<(), PersistError> {
     if !enabled() { return Err(PersistError::Disabled); }
-    let base = unsafe { BASE_LBA };
+    // an encrypted store keeps its key; without it there is nothing to format with
+    if encrypted() && unsafe { KEY }.is_none() { return Err(PersistError::Locked); }
 
-    // zero first 128 sectors of region (super + some log) for quick reset
-    let zero = [0u8; 512];
-    for i in 0..128u32 {
-        ata::write_sectors_lba28(base + i, 1, &zero).map_err(PersistError::Ata)?;
-    }
+    // zero the used log (at least the first 128 sectors) so nothing stale survives
+    let head = unsafe { HEAD_REL };
+    scrub(head.max(128))?;
 
-    unsafe { HEAD_REL = 1; }
+    unsafe { HEAD_REL = 1; START_REL = 1; }
+    LOG_USAGE.lock().clear();
     write_superblock()?;
     Ok(())
 }
@@ -0,0 +1,392 @@
+#![allow(dead_code)]
+// src/pubkey.rs
+// Public-key signature verification for TLS and X.509:
+// - RSA PKCS#1 v1.5 and RSA-PSS (RFC 8017), any modulus size bignum.rs takes,
+// - ECDSA over NIST P-256 and P-384 (FIPS 186-4), Jacobian coordinates.
+// Verification only: there are no private keys in the kernel.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bignum::{self, Limbs, Modulus};
+use crate::chacha20poly1305::ct_eq;
+use crate::sha256;
+use crate::sha512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashAlg {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlg {
+    pub fn len(self) -> usize {
+        match self {
+            HashAlg::Sha256 => 32,
+            HashAlg::Sha384 => 48,
+            HashAlg::Sha512 => 64,
+        }
+    }
+
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlg::Sha256 => sha256::sha256(data).to_vec(),
+            HashAlg::Sha384 => sha512::sha384(data).to_vec(),
+            HashAlg::Sha512 => sha512::sha512(data).to_vec(),
+        }
+    }
+
+    /// DER DigestInfo header in front of the hash (PKCS#1 v1.5).
+    fn digest_info(self) -> &'static [u8] {
+        match self {
+            HashAlg::Sha256 => &[0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20],
+            HashAlg::Sha384 => &[0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0x04, 0x30],
+            HashAlg::Sha512 => &[0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03, 0x05, 0x00, 0x04, 0x40],
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// RSA
+// -----------------------------------------------------------------------------
+
+/// Smallest modulus we accept.
+const RSA_MIN_BITS: usize = 2048;
+const RSA_MAX_BITS: usize = 8192;
+
+/// sig^e mod n as a big-endian string the length of the modulus.
+fn rsa_public(n: &[u8], e: &[u8], sig: &[u8]) -> Option<Vec<u8>> {
+    let m = Modulus::new(n)?;
+    let bits = bignum::bit_len(&m.m);
+    if !(RSA_MIN_BITS..=RSA_MAX_BITS).contains(&bits) || sig.len() != m.bytes() { return None; }
+    let s = bignum::from_be(sig, m.limbs())?;
+    if bignum::cmp(&s, &m.m) != core::cmp::Ordering::Less { return None; }
+    let e = bignum::from_be(e, e.len().div_ceil(4).max(1))?;
+    let r = m.leave_mont(&m.pow(&m.enter_mont(&s), &e));
+    Some(bignum::to_be(&r, m.bytes()))
+}
+
+/// RSASSA-PKCS1-v1_5 with modulus `n` and exponent `e` (big-endian).
+pub fn rsa_pkcs1_verify(n: &[u8], e: &[u8], hash: HashAlg, msg: &[u8], sig: &[u8]) -> bool {
+    let Some(em) = rsa_public(n, e, sig) else { return false };
+    let info = hash.digest_info();
+    let t_len = info.len() + hash.len();
+    if em.len() < t_len + 11 { return false; }
+
+    // 00 01 FF..FF 00 DigestInfo hash
+    let mut expect = vec![0xFFu8; em.len()];
+    expect[0] = 0;
+    expect[1] = 1;
+    let split = em.len() - t_len;
+    expect[split - 1] = 0;
+    expect[split..split + info.len()].copy_from_slice(info);
+    expect[split + info.len()..].copy_from_slice(&hash.digest(msg));
+    ct_eq(&em, &expect)
+}
+
+fn mgf1(hash: HashAlg, seed: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len + hash.len());
+    let mut counter = 0u32;
+    while out.len() < len {
+        let mut input = seed.to_vec();
+        input.extend_from_slice(&counter.to_be_bytes());
+        out.extend_from_slice(&hash.digest(&input));
+        counter += 1;
+    }
+    out.truncate(len);
+    out
+}
+
+/// RSASSA-PSS with MGF1 over the same hash and a salt as long as the hash
+/// (what TLS 1.3 requires).
+pub fn rsa_pss_verify(n: &[u8], e: &[u8], hash: HashAlg, msg: &[u8], sig: &[u8]) -> bool {
+    let Some(m) = Modulus::new(n) else { return false };
+    let em_bits = bignum::bit_len(&m.m) - 1;
+    let Some(full) = rsa_public(n, e, sig) else { return false };
+    let em_len = em_bits.div_ceil(8);
+    // a whole leading byte is zero when the modulus is 8k+1 bits long
+    if full.len() > em_len && full[0] != 0 { return false; }
+    let em = &full[full.len() - em_len..];
+
+    let h_len = hash.len();
+    let s_len = h_len;
+    if em_len < h_len + s_len + 2 || em[em_len - 1] != 0xBC { return false; }
+
+    let db_len = em_len - h_len - 1;
+    let (masked_db, h) = (&em[..db_len], &em[db_len..em_len - 1]);
+    let top_bits = 8 * em_len - em_bits;
+    if top_bits > 0 && (masked_db[0] >> (8 - top_bits)) != 0 { return false; }
+
+    let mut db = mgf1(hash, h, db_len);
+    for (d, x) in db.iter_mut().zip(masked_db.iter()) {
+        *d ^= *x;
+    }
+    if top_bits > 0 { db[0] &= 0xFF >> top_bits; }
+
+    let ps_len = db_len - s_len - 1;
+    if db[..ps_len].iter().any(|&b| b != 0) || db[ps_len] != 1 { return false; }
+
+    let mut m2 = vec![0u8; 8];
+    m2.extend_from_slice(&hash.digest(msg));
+    m2.extend_from_slice(&db[db_len - s_len..]);
+    ct_eq(&hash.digest(&m2), h)
+}
+
+// -----------------------------------------------------------------------------
+// ECDSA (P-256, P-384)
+// -----------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Curve {
+    P256,
+    P384,
+}
+
+impl Curve {
+    pub fn coord_len(self) -> usize {
+        match self {
+            Curve::P256 => 32,
+            Curve::P384 => 48,
+        }
+    }
+
+    /// (p, n, b, Gx, Gy), big-endian.
+    fn params(self) -> [&'static [u8]; 5] {
+        match self {
+            Curve::P256 => [&P256_P, &P256_N, &P256_B, &P256_GX, &P256_GY],
+            Curve::P384 => [&P384_P, &P384_N, &P384_B, &P384_GX, &P384_GY],
+        }
+    }
+}
+
+const P256_P: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+];
+
+const P256_N: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xbc, 0xe6, 0xfa, 0xad, 0xa7, 0x17, 0x9e, 0x84, 0xf3, 0xb9, 0xca, 0xc2, 0xfc, 0x63, 0x25, 0x51,
+];
+
+const P256_B: [u8; 32] = [
+    0x5a, 0xc6, 0x35, 0xd8, 0xaa, 0x3a, 0x93, 0xe7, 0xb3, 0xeb, 0xbd, 0x55, 0x76, 0x98, 0x86, 0xbc,
+    0x65, 0x1d, 0x06, 0xb0, 0xcc, 0x53, 0xb0, 0xf6, 0x3b, 0xce, 0x3c, 0x3e, 0x27, 0xd2, 0x60, 0x4b,
+];
+
+const P256_GX: [u8; 32] = [
+    0x6b, 0x17, 0xd1, 0xf2, 0xe1, 0x2c, 0x42, 0x47, 0xf8, 0xbc, 0xe6, 0xe5, 0x63, 0xa4, 0x40, 0xf2,
+    0x77, 0x03, 0x7d, 0x81, 0x2d, 0xeb, 0x33, 0xa0, 0xf4, 0xa1, 0x39, 0x45, 0xd8, 0x98, 0xc2, 0x96,
+];
+
+const P256_GY: [u8; 32] = [
+    0x4f, 0xe3, 0x42, 0xe2, 0xfe, 0x1a, 0x7f, 0x9b, 0x8e, 0xe7, 0xeb, 0x4a, 0x7c, 0x0f, 0x9e, 0x16,
+    0x2b, 0xce, 0x33, 0x57, 0x6b, 0x31, 0x5e, 0xce, 0xcb, 0xb6, 0x40, 0x68, 0x37, 0xbf, 0x51, 0xf5,
+];
+
+const P384_P: [u8; 48] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+];
+
+const P384_N: [u8; 48] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xc7, 0x63, 0x4d, 0x81, 0xf4, 0x37, 0x2d, 0xdf,
+    0x58, 0x1a, 0x0d, 0xb2, 0x48, 0xb0, 0xa7, 0x7a, 0xec, 0xec, 0x19, 0x6a, 0xcc, 0xc5, 0x29, 0x73,
+];
+
+const P384_B: [u8; 48] = [
+    0xb3, 0x31, 0x2f, 0xa7, 0xe2, 0x3e, 0xe7, 0xe4, 0x98, 0x8e, 0x05, 0x6b, 0xe3, 0xf8, 0x2d, 0x19,
+    0x18, 0x1d, 0x9c, 0x6e, 0xfe, 0x81, 0x41, 0x12, 0x03, 0x14, 0x08, 0x8f, 0x50, 0x13, 0x87, 0x5a,
+    0xc6, 0x56, 0x39, 0x8d, 0x8a, 0x2e, 0xd1, 0x9d, 0x2a, 0x85, 0xc8, 0xed, 0xd3, 0xec, 0x2a, 0xef,
+];
+
+const P384_GX: [u8; 48] = [
+    0xaa, 0x87, 0xca, 0x22, 0xbe, 0x8b, 0x05, 0x37, 0x8e, 0xb1, 0xc7, 0x1e, 0xf3, 0x20, 0xad, 0x74,
+    0x6e, 0x1d, 0x3b, 0x62, 0x8b, 0xa7, 0x9b, 0x98, 0x59, 0xf7, 0x41, 0xe0, 0x82, 0x54, 0x2a, 0x38,
+    0x55, 0x02, 0xf2, 0x5d, 0xbf, 0x55, 0x29, 0x6c, 0x3a, 0x54, 0x5e, 0x38, 0x72, 0x76, 0x0a, 0xb7,
+];
+
+const P384_GY: [u8; 48] = [
+    0x36, 0x17, 0xde, 0x4a, 0x96, 0x26, 0x2c, 0x6f, 0x5d, 0x9e, 0x98, 0xbf, 0x92, 0x92, 0xdc, 0x29,
+    0xf8, 0xf4, 0x1d, 0xbd, 0x28, 0x9a, 0x14, 0x7c, 0xe9, 0xda, 0x31, 0x13, 0xb5, 0xf0, 0xb8, 0xc0,
+    0x0a, 0x60, 0xb1, 0xce, 0x1d, 0x7e, 0x81, 0x9d, 0x7a, 0x43, 0x1d, 0x7c, 0x90, 0xea, 0x0e, 0x5f,
+];
+
+/// Point in Jacobian coordinates, Montgomery-form field elements; Z = 0 is
+/// the point at infinity.
+#[derive(Clone)]
+struct Point {
+    x: Limbs,
+    y: Limbs,
+    z: Limbs,
+}
+
+struct Field<'a> {
+    p: &'a Modulus,
+}
+
+impl Field<'_> {
+    fn mul(&self, a: &[u32], b: &[u32]) -> Limbs { self.p.mont_mul(a, b) }
+    fn sqr(&self, a: &[u32]) -> Limbs { self.p.mont_mul(a, a) }
+    fn add(&self, a: &[u32], b: &[u32]) -> Limbs { self.p.add(a, b) }
+    fn sub(&self, a: &[u32], b: &[u32]) -> Limbs { self.p.sub(a, b) }
+
+    /// 2P (a = -3 formulas).
+    fn double(&self, p: &Point) -> Point {
+        if bignum::is_zero(&p.z) { return p.clone(); }
+        let delta = self.sqr(&p.z);
+        let gamma = self.sqr(&p.y);
+        let beta = self.mul(&p.x, &gamma);
+        let t = self.mul(&self.sub(&p.x, &delta), &self.add(&p.x, &delta));
+        let alpha = self.add(&self.add(&t, &t), &t);
+        let beta4 = self.add(&self.add(&beta, &beta), &self.add(&beta, &beta));
+        let x3 = self.sub(&self.sqr(&alpha), &self.add(&beta4, &beta4));
+        let yz = self.add(&p.y, &p.z);
+        let z3 = self.sub(&self.sub(&self.sqr(&yz), &gamma), &delta);
+        let g2 = self.sqr(&gamma);
+        let g4 = self.add(&g2, &g2);
+        let g8 = self.add(&g4, &g4);
+        let y3 = self.sub(&self.mul(&alpha, &self.sub(&beta4, &x3)), &self.add(&g8, &g8));
+        Point { x: x3, y: y3, z: z3 }
+    }
+
+    /// P + Q.
+    fn add_points(&self, p: &Point, q: &Point) -> Point {
+        if bignum::is_zero(&p.z) { return q.clone(); }
+        if bignum::is_zero(&q.z) { return p.clone(); }
+        let z1z1 = self.sqr(&p.z);
+        let z2z2 = self.sqr(&q.z);
+        let u1 = self.mul(&p.x, &z2z2);
+        let u2 = self.mul(&q.x, &z1z1);
+        let s1 = self.mul(&self.mul(&p.y, &q.z), &z2z2);
+        let s2 = self.mul(&self.mul(&q.y, &p.z), &z1z1);
+        let h = self.sub(&u2, &u1);
+        let r = self.sub(&s2, &s1);
+        if bignum::is_zero(&h) {
+            if bignum::is_zero(&r) { return self.double(p); }
+            return Point { x: p.x.clone(), y: p.y.clone(), z: vec![0; p.z.len()] };
+        }
+        let r = self.add(&r, &r);
+        let h2 = self.add(&h, &h);
+        let i = self.sqr(&h2);
+        let j = self.mul(&h, &i);
+        let v = self.mul(&u1, &i);
+        let x3 = self.sub(&self.sub(&self.sqr(&r), &j), &self.add(&v, &v));
+        let s1j = self.mul(&s1, &j);
+        let y3 = self.sub(&self.mul(&r, &self.sub(&v, &x3)), &self.add(&s1j, &s1j));
+        let zz = self.add(&p.z, &q.z);
+        let z3 = self.mul(&self.sub(&self.sub(&self.sqr(&zz), &z1z1), &z2z2), &h);
+        Point { x: x3, y: y3, z: z3 }
+    }
+}
+
+/// ECDSA verification. `point` is the uncompressed public key (04 || X || Y),
+/// `sig` the DER `SEQUENCE { r INTEGER, s INTEGER }`.
+pub fn ecdsa_verify(curve: Curve, point: &[u8], hash: HashAlg, msg: &[u8], sig: &[u8]) -> bool {
+    let Some((r, s)) = parse_ecdsa_sig(sig) else { return false };
+    ecdsa_verify_rs(curve, point, &hash.digest(msg), r, s)
+}
+
+/// DER ECDSA signature into its big-endian r and s.
+fn parse_ecdsa_sig(sig: &[u8]) -> Option<(&[u8], &[u8])> {
+    fn int(b: &[u8]) -> Option<(&[u8], &[u8])> {
+        if b.len() < 2 || b[0] != 0x02 || b[1] & 0x80 != 0 { return None; }
+        let len = b[1] as usize;
+        if b.len() < 2 + len || len == 0 { return None; }
+        Some((&b[2..2 + len], &b[2 + len..]))
+    }
+    if sig.len() < 2 || sig[0] != 0x30 { return None; }
+    let (body, rest) = match sig[1] {
+        l if l < 0x80 => (sig.get(2..2 + l as usize)?, sig.get(2 + l as usize..)?),
+        0x81 => {
+            let l = *sig.get(2)? as usize;
+            (sig.get(3..3 + l)?, sig.get(3 + l..)?)
+        }
+        _ => return None,
+    };
+    if !rest.is_empty() { return None; }
+    let (r, body) = int(body)?;
+    let (s, body) = int(body)?;
+    if !body.is_empty() { return None; }
+    Some((r, s))
+}
+
+fn ecdsa_verify_rs(curve: Curve, point: &[u8], digest: &[u8], r: &[u8], s: &[u8]) -> bool {
+    let clen = curve.coord_len();
+    if point.len() != 1 + 2 * clen || point[0] != 0x04 { return false; }
+    let [p_be, n_be, b_be, gx_be, gy_be] = curve.params();
+    let (Some(p), Some(n)) = (Modulus::new(p_be), Modulus::new(n_be)) else { return false };
+    let f = Field { p: &p };
+
+    // 1 <= r, s < n
+    let in_range = |v: &[u8]| -> Option<Limbs> {
+        let x = bignum::from_be(v, n.limbs())?;
+        if bignum::is_zero(&x) || bignum::cmp(&x, &n.m) != core::cmp::Ordering::Less { return None; }
+        Some(x)
+    };
+    let (Some(r), Some(s)) = (in_range(r), in_range(s)) else { return false };
+
+    // public key: coordinates below p and on y^2 = x^3 - 3x + b
+    let coord = |v: &[u8]| -> Option<Limbs> {
+        let x = bignum::from_be(v, p.limbs())?;
+        if bignum::cmp(&x, &p.m) != core::cmp::Ordering::Less { return None; }
+        Some(p.enter_mont(&x))
+    };
+    let (Some(qx), Some(qy)) = (coord(&point[1..1 + clen]), coord(&point[1 + clen..])) else { return false };
+    let Some(b) = coord(b_be) else { return false };
+    let x3 = f.mul(&f.sqr(&qx), &qx);
+    let three_x = f.add(&f.add(&qx, &qx), &qx);
+    if bignum::cmp(&f.sqr(&qy), &f.add(&f.sub(&x3, &three_x), &b)) != core::cmp::Ordering::Equal { return false; }
+
+    // e = leftmost bits of the digest, as many as n has
+    let n_bits = bignum::bit_len(&n.m);
+    let mut e_be = digest.to_vec();
+    if e_be.len() * 8 > n_bits {
+        e_be.truncate(n_bits.div_ceil(8));
+        let shift = e_be.len() * 8 - n_bits;
+        if shift > 0 {
+            for i in (0..e_be.len()).rev() {
+                let hi = if i > 0 { e_be[i - 1] << (8 - shift) } else { 0 };
+                e_be[i] = (e_be[i] >> shift) | hi;
+            }
+        }
+    }
+    let Some(e) = n.reduce_be(&e_be) else { return false };
+
+    // u1 = e / s, u2 = r / s (mod n)
+    let w = n.inv(&n.enter_mont(&s));
+    // (w is in Montgomery form, so one mont_mul gives the plain product)
+    let u1 = n.mont_mul(&e, &w);
+    let u2 = n.mont_mul(&r, &w);
+
+    let (Some(gx), Some(gy)) = (coord(gx_be), coord(gy_be)) else { return false };
+    let one = p.one();
+    let g = Point { x: gx, y: gy, z: one.clone() };
+    let q = Point { x: qx, y: qy, z: one };
+    let gq = f.add_points(&g, &q);
+
+    // u1*G + u2*Q, one pass over both scalars
+    let mut acc = Point { x: vec![0; p.limbs()], y: vec![0; p.limbs()], z: vec![0; p.limbs()] };
+    let bits = bignum::bit_len(&u1).max(bignum::bit_len(&u2));
+    for i in (0..bits).rev() {
+        acc = f.double(&acc);
+        match (bignum::bit(&u1, i), bignum::bit(&u2, i)) {
+            (true, true) => acc = f.add_points(&acc, &gq),
+            (true, false) => acc = f.add_points(&acc, &g),
+            (false, true) => acc = f.add_points(&acc, &q),
+            (false, false) => {}
+        }
+    }
+    if bignum::is_zero(&acc.z) { return false; }
+
+    // affine x = X / Z^2, then mod n
+    let zi = p.inv(&acc.z);
+    let x = p.leave_mont(&f.mul(&acc.x, &f.sqr(&zi)));
+    let Some(v) = n.reduce_be(&bignum::to_be(&x, clen)) else { return false };
+    bignum::cmp(&v, &r) == core::cmp::Ordering::Equal
+}
@@ -92,9 +92,18 @@ fn find_free_slot() -> Option<usize> {
     None
 }
 
+/// Names nobody may register: `root` owns the system files (and is exempt
+/// from quotas), and `admin` would pass for an administrator.
+pub const RESERVED_NAMES: [&str; 2] = ["root", "admin"];
+
+pub fn is_reserved(username: &str) -> bool {
+    RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(username))
+}
+
 pub fn validate_username(username: &str) -> bool {
     let b = username.as_bytes();
     if b.is_empty() || b.len() > MAX_USERNAME { return false; }
+    if is_reserved(username) { return false; }
     for &ch in b {
         // allow a-z A-Z 0-9 _ - .
         let ok = (ch >= b'a' && ch <= b'z') || (ch >= b'A' && ch <= b'Z') || (ch >= b'0' && ch <= b'9') || ch == b'_' || ch == b'-' || ch == b'.';
@@ -104,6 +113,7 @@ pub fn validate_username(username: &str) -> bool {
 }
 
 pub fn create_user(username: &str, password: &str) -> Result<(), &'static str> {
+    if is_reserved(username) { return Err("That username is reserved"); }
     if !validate_username(username) { return Err("Invalid username (use a-z A-Z 0-9 _ - .)"); }
     if password.is_empty() || password.len() > MAX_PASSWORD { return Err("Invalid password length"); }
 
@@ -0,0 +1,84 @@
+#![allow(dead_code)]
+// src/rng.rs
+// Random bytes for keys, salts and nonces. A SHA-256 pool seeded from
+// RDRAND (when the CPU has it), the RTC and TSC jitter, stirred again with
+// fresh TSC/RDRAND samples on every call. Output blocks are
+// SHA-256(pool || counter), so the pool never leaves this file.
+
+use core::arch::asm;
+
+use crate::fs::SpinLock;
+use crate::sha256::Sha256;
+use crate::time;
+
+struct Pool {
+    state: [u8; 32],
+    counter: u64,
+    seeded: bool,
+}
+
+static POOL: SpinLock<Pool> = SpinLock::new(Pool { state: [0; 32], counter: 0, seeded: false });
+
+fn has_rdrand() -> bool {
+    let r = core::arch::x86_64::__cpuid(1);
+    (r.ecx >> 30) & 1 == 1
+}
+
+/// One RDRAND value; `None` when the CPU lacks it or keeps failing.
+fn rdrand() -> Option<u64> {
+    if !has_rdrand() { return None; }
+    for _ in 0..10 {
+        let v: u64;
+        let ok: u8;
+        unsafe {
+            asm!("rdrand {v}", "setc {ok}", v = out(reg) v, ok = out(reg_byte) ok, options(nomem, nostack));
+        }
+        if ok != 0 { return Some(v); }
+    }
+    None
+}
+
+fn stir(h: &mut Sha256) {
+    h.update(&time::rdtsc().to_le_bytes());
+    if let Some(v) = rdrand() { h.update(&v.to_le_bytes()); }
+}
+
+fn seed(p: &mut Pool) {
+    let mut h = Sha256::new();
+    h.update(&p.state);
+    let dt = time::rtc_now();
+    h.update(&[dt.year as u8, dt.month, dt.day, dt.hour, dt.minute, dt.second]);
+    // timing jitter of short spins, plus RDRAND if present
+    for _ in 0..64 {
+        stir(&mut h);
+        time::spin(200);
+    }
+    p.state = h.finalize();
+    p.seeded = true;
+}
+
+/// Fill `out` with random bytes.
+pub fn fill(out: &mut [u8]) {
+    let mut p = POOL.lock();
+    if !p.seeded { seed(&mut p); }
+
+    let mut h = Sha256::new();
+    h.update(&p.state);
+    stir(&mut h);
+    p.state = h.finalize();
+
+    for chunk in out.chunks_mut(32) {
+        p.counter = p.counter.wrapping_add(1);
+        let mut h = Sha256::new();
+        h.update(&p.state);
+        h.update(&p.counter.to_le_bytes());
+        let block = h.finalize();
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+
+    // forward secrecy: later pool states don't reveal this output
+    let mut h = Sha256::new();
+    h.update(&p.state);
+    h.update(b"rekey");
+    p.state = h.finalize();
+}
@@ -14,6 +14,7 @@ mod mouse;
 mod idt;
 mod framebuffer_driver;
 mod bootinfo;
+mod acpi;
 mod font;
 mod gui;
 mod wallpaper;
@@ -29,10 +30,24 @@ mod time;
 mod heap;
 mod portio;
 mod crc32;
+mod lz4;
+mod inflate;
+mod sha256;
+mod kdf;
+mod chacha20poly1305;
+mod sha512;
+mod aes_gcm;
+mod x25519;
+mod bignum;
+mod pubkey;
+mod rng;
 mod ata;
 mod persist;
 mod fs;
 mod fs_cmds;
+mod pci;
+mod virtio;
+mod p9;
 
 pub use serial::serial_write_str;
 
@@ -102,21 +117,15 @@ pub extern "C" fn _start(boot_info: *const framebuffer_driver::BootVideoInfoRaw)
     // initialize registry state (in-memory for now)
     registry::init();
 
-    // Filesystem (RAM overlay) + persistent backing store (IDE tail log)
+    // Filesystem (RAM overlay) + persistent backing store (IDE tail log).
+    // The log is encrypted; login::try_submit() calls persist::unlock(), which
+    // replays it into RamFs and seeds the default layout.
     fs_cmds::init_cwd();
-    if persist::init().is_ok() {
-        let _ = persist::mount_into_ramfs();
-    }
+    let _ = persist::init();
 
-    // If persistence is empty, seed a default layout.
-    {
-        let fsg = fs::FS.lock();
-        let has_etc = fsg.exists("/etc");
-        drop(fsg);
-        if !has_etc {
-            fs::init_default_layout();
-            let _ = persist::sync_dirty(); // optional: write initial layout
-        }
+    // Host share (QEMU -virtfs ... mount_tag=host0). Absent device is fine.
+    if p9::mount("/host").is_ok() {
+        serial_write_str("KERNEL: 9p share mounted at /host.\n");
     }
 
 
@@ -389,6 +389,23 @@ fn print_line(bytes: &[u8], _fg: u32) {
     }
 }
 
+// Overwrite the newest line instead of adding one (progress readouts)
+fn replace_last_line(bytes: &[u8], fg: u32) {
+    if !gui::shell_is_visible() { return; }
+    let replaced = with_scrollback_if_some(|sb| match sb.last_mut() {
+        Some(l) => {
+            *l = bytes.to_vec();
+            true
+        }
+        None => false,
+    });
+    if replaced == Some(true) {
+        redraw_terminal_viewport();
+    } else {
+        print_line(bytes, fg);
+    }
+}
+
 // Force append and scroll viewport to bottom (used when a command is entered)
 fn print_line_force(bytes: &[u8], _fg: u32) {
     if !gui::shell_is_visible() { return; }
@@ -760,7 +777,7 @@ fn exec_command(line: &[u8]) -> Option<AppState> {
     while !arg.is_empty() && arg[0] == b' ' { arg = &arg[1..]; }
 
     // Try filesystem / persistence commands first:
-    // pwd, cd, ls, cat, mkdir, touch, rm, write, append, sync, persist
+    // pwd, cd, ls, cat, mkdir, touch, rm, write, append, sync, persist, du, df, quota, mount, umount
     if let (Ok(cmd_s), Ok(arg_s)) = (core::str::from_utf8(cmd), core::str::from_utf8(arg)) {
         let mut argv: [&str; 16] = [""; 16];
         let mut argc = 0usize;
@@ -794,7 +811,7 @@ fn exec_command(line: &[u8]) -> Option<AppState> {
 
     match cmd {
         b"help" => {
-            print_line(b"Commands: help, clear, net, ipconfig, dhcp, ipset, ping, about, login, reg, edit, tsc, echo <text>, pwd, cd, ls, cat, mkdir, touch, rm, write, append, sync, persist", DIM);
+            print_line(b"Commands: help, clear, net [stats], ipconfig, dhcp [renew|release], ipset, ifup, ifdown, ping, ping6, traceroute, tcpdump, pcap, arp, ndp, route, nslookup, dig, tls, ntp, wget, curl, about, shutdown, login, reg, edit, tsc, echo <text>, pwd, cd, ls, cat, mkdir, touch, rm, write, append, sync, persist, du, df, quota, mount, umount", DIM);
             print_line(b"Tips: click the dock 'T' to hide/show the shell.", DIM);
             print_line(b"      click traffic lights to close/min/max.", DIM);
             None
@@ -804,8 +821,16 @@ fn exec_command(line: &[u8]) -> Option<AppState> {
             None
         }
         b"net" => {
-            // Initialize NIC (RTL8139) if needed, then list detected adapters.
+            // Probe NICs if needed, then list the interface table.
             net::init();
+            if arg == b"stats" {
+                net::poll();
+                print_line(b"Receive path:", OK);
+                print_str_lines(&net::demux_report(), FG);
+                print_line(b"TCP sockets:", OK);
+                print_str_lines(&net::tcp_report(), FG);
+                return None;
+            }
             let r = net::net_scan();
             if r.devices.is_empty() {
                 print_line(b"No network adapters detected.", ERR);
@@ -823,15 +848,48 @@ fn exec_command(line: &[u8]) -> Option<AppState> {
         }
         b"dhcp" => {
             net::init();
-            match net::dhcp_acquire() {
+            let r = match arg {
+                b"" => net::dhcp_acquire(),
+                b"renew" => net::dhcp::renew(),
+                b"release" => {
+                    if net::dhcp::release() {
+                        print_line(b"DHCP: lease released", OK);
+                    } else {
+                        print_line(b"DHCP: no lease to release", ERR);
+                    }
+                    return None;
+                }
+                _ => {
+                    print_line(b"usage: dhcp [renew | release]", ERR);
+                    return None;
+                }
+            };
+            match r {
                 Ok(()) => print_line(b"DHCP: bound", OK),
-                Err(net::DhcpError::NoNic) => print_line(b"DHCP: no NIC detected", ERR),
-                Err(net::DhcpError::Timeout) => print_line(b"DHCP: timeout (no offer/ack)", ERR),
-                Err(net::DhcpError::Malformed) => print_line(b"DHCP: malformed reply", ERR),
-                Err(net::DhcpError::Nack) => print_line(b"DHCP: NACK (request denied)", ERR),
+                Err(e) => print_line(format!("DHCP: {}", net::dhcp_error_text(e)).as_bytes(), ERR),
             }
             None
         }
+        b"ifup" => {
+            cmd_ifupdown(arg, true);
+            None
+        }
+        b"ifdown" => {
+            cmd_ifupdown(arg, false);
+            None
+        }
+        b"wget" => {
+            cmd_wget(arg);
+            None
+        }
+        b"curl" => {
+            cmd_curl(arg);
+            None
+        }
+        b"shutdown" | b"poweroff" => {
+            cmd_shutdown();
+            None
+        }
         b"ipset" => {
             cmd_ipset(arg);
             None
@@ -840,6 +898,68 @@ fn exec_command(line: &[u8]) -> Option<AppState> {
             cmd_ping(arg);
             None
         }
+        b"arp" => {
+            match arg {
+                b"" | b"-a" => print_str_lines(&net::arp_report(), FG),
+                b"flush" | b"-d" => {
+                    net::arp::flush();
+                    print_line(b"arp: table flushed", OK);
+                }
+                _ => print_line(b"usage: arp [-a | flush]", ERR),
+            }
+            None
+        }
+        b"ping6" => {
+            cmd_ping6(arg);
+            None
+        }
+        b"traceroute" => {
+            cmd_traceroute(arg, false);
+            None
+        }
+        b"traceroute6" => {
+            cmd_traceroute(arg, true);
+            None
+        }
+        b"tcpdump" => {
+            cmd_tcpdump(arg);
+            None
+        }
+        b"pcap" => {
+            cmd_pcap(arg);
+            None
+        }
+        b"ndp" => {
+            match arg {
+                b"" | b"show" => print_str_lines(&net::ndp_report(), FG),
+                b"flush" => {
+                    net::ndp::flush();
+                    print_line(b"ndp: neighbor cache flushed", OK);
+                }
+                _ => print_line(b"usage: ndp [show | flush]", ERR),
+            }
+            None
+        }
+        b"route" => {
+            cmd_route(arg);
+            None
+        }
+        b"nslookup" => {
+            cmd_dns(arg, false);
+            None
+        }
+        b"dig" => {
+            cmd_dns(arg, true);
+            None
+        }
+        b"tls" => {
+            cmd_tls(arg);
+            None
+        }
+        b"ntp" => {
+            cmd_ntp(arg);
+            None
+        }
         b"about" => {
             print_line(b"", FG);
             print_line(b"Othello OS", OK);
@@ -989,6 +1109,12 @@ fn print_kv(prefix: &[u8], value: &[u8], fg: u32) {
     print_line(&buf[..n], fg);
 }
 
+fn print_kv_ipv6(prefix: &[u8], ip: [u8; 16], fg: u32) {
+    let mut v = [0u8; 40];
+    let n = net::ipv6::write_addr(&mut v, ip);
+    print_kv(prefix, &v[..n], fg);
+}
+
 fn print_kv_ipv4(prefix: &[u8], ip: [u8; 4], fg: u32) {
     let mut buf = [0u8; 192];
     let mut n = 0usize;
@@ -1013,13 +1139,30 @@ fn cmd_ipconfig() {
 
     let cfg = net::config();
     print_line(b"", FG);
+    print_kv(b"   Host Name . . . . . . . . . . . . : ", net::hostname().as_bytes(), FG);
     print_kv(b"   DHCP Enabled . . . . . . . . . . : ", if cfg.dhcp_bound { b"Yes" } else { b"No" }, FG);
     print_kv_ipv4(b"   IPv4 Address. . . . . . . . . . . : ", cfg.ip, FG);
     print_kv_ipv4(b"   Subnet Mask . . . . . . . . . . . : ", cfg.mask, FG);
     print_kv_ipv4(b"   Default Gateway . . . . . . . . . : ", cfg.gateway, FG);
-    print_kv_ipv4(b"   DNS Servers . . . . . . . . . . . : ", cfg.dns, FG);
-    if cfg.dhcp_bound {
-        print_kv_ipv4(b"   DHCP Server . . . . . . . . . . . : ", cfg.server_id, FG);
+    match net::dhcp::lease() {
+        Some(l) => print_lease(&l),
+        None => print_kv_ipv4(b"   DNS Servers . . . . . . . . . . . : ", cfg.dns, FG),
+    }
+
+    // let a pending Router Advertisement land before showing IPv6
+    net::poll();
+    let v6 = net::ipv6::config();
+    if v6.enabled {
+        print_kv_ipv6(b"   Link-local IPv6 Address . . . . . : ", v6.link_local, FG);
+        if v6.global != [0; 16] {
+            print_kv_ipv6(b"   IPv6 Address (SLAAC). . . . . . . : ", v6.global, FG);
+        }
+        if v6.router != [0; 16] {
+            print_kv_ipv6(b"   IPv6 Default Gateway. . . . . . . : ", v6.router, FG);
+        }
+        if v6.dns != [0; 16] {
+            print_kv_ipv6(b"   IPv6 DNS Server . . . . . . . . . : ", v6.dns, FG);
+        }
     }
 
     if let Some(mac) = net::mac() {
@@ -1033,6 +1176,122 @@ fn cmd_ipconfig() {
     }
 }
 
+/// The DHCP-only part of `ipconfig`.
+fn print_lease(l: &net::dhcp::Lease) {
+    let dns_key: &[u8] = b"   DNS Servers . . . . . . . . . . . : ";
+    let more_key: &[u8] = b"                                       ";
+    for (i, &d) in l.dns.iter().enumerate() {
+        print_kv_ipv4(if i == 0 { dns_key } else { more_key }, d, FG);
+    }
+    if l.dns.is_empty() {
+        print_kv_ipv4(dns_key, [0, 0, 0, 0], FG);
+    }
+    print_kv(b"   DHCP State. . . . . . . . . . . . : ", net::dhcp::state().name().as_bytes(), FG);
+    print_kv_ipv4(b"   DHCP Server . . . . . . . . . . . : ", l.server_id, FG);
+    if !l.hostname.is_empty() {
+        print_kv(b"   Host Name . . . . . . . . . . . . : ", l.hostname.as_bytes(), FG);
+    }
+    if !l.domain.is_empty() {
+        print_kv(b"   Connection-specific DNS Suffix. . : ", l.domain.as_bytes(), FG);
+    }
+    let search_key: &[u8] = b"   DNS Suffix Search List. . . . . . : ";
+    for (i, d) in l.search.iter().enumerate() {
+        print_kv(if i == 0 { search_key } else { more_key }, d.as_bytes(), FG);
+    }
+    let ntp_key: &[u8] = b"   NTP Servers . . . . . . . . . . . : ";
+    for (i, &n) in l.ntp.iter().enumerate() {
+        print_kv_ipv4(if i == 0 { ntp_key } else { more_key }, n, FG);
+    }
+    if l.mtu != 0 {
+        let mut v = [0u8; 20];
+        let n = write_u64_dec(&mut v, l.mtu as u64);
+        print_kv(b"   MTU . . . . . . . . . . . . . . . : ", &v[..n], FG);
+    }
+
+    let now = crate::time::uptime_ms();
+    let left = |secs: u32| -> ([u8; 24], usize) {
+        let mut v = [0u8; 24];
+        if secs == 0xFFFF_FFFF {
+            v[..8].copy_from_slice(b"infinite");
+            return (v, 8);
+        }
+        let end = l.obtained_ms + secs as u64 * 1000;
+        let mut n = write_u64_dec(&mut v, end.saturating_sub(now) / 1000);
+        v[n..n + 2].copy_from_slice(b" s");
+        n += 2;
+        (v, n)
+    };
+    let (v, n) = left(l.lease_secs);
+    print_kv(b"   Lease Expires In. . . . . . . . . : ", &v[..n], FG);
+    let (v, n) = left(l.t1_secs);
+    print_kv(b"   Renewal (T1) In . . . . . . . . . : ", &v[..n], FG);
+    let (v, n) = left(l.t2_secs);
+    print_kv(b"   Rebinding (T2) In . . . . . . . . : ", &v[..n], FG);
+}
+
+/// Flush the filesystem, hand back the DHCP lease, then power off (ACPI
+/// ports of QEMU, Bochs and VirtualBox). Halts if none of them works.
+fn cmd_shutdown() {
+    if let Some(out) = crate::fs_cmds::try_handle("sync", &[]) {
+        print_str_lines(&out, FG);
+    }
+    net::shutdown();
+    print_line(b"Powering off...", DIM);
+    unsafe {
+        crate::portio::outw(0x604, 0x2000);
+        crate::portio::outw(0xB004, 0x2000);
+        crate::portio::outw(0x4004, 0x3400);
+        loop {
+            core::arch::asm!("cli; hlt", options(nomem, nostack));
+        }
+    }
+}
+
+/// `ifup <iface>|-a` / `ifdown <iface>|-a`, driven by /etc/network.conf.
+fn cmd_ifupdown(arg: &[u8], up: bool) {
+    let name = if up { "ifup" } else { "ifdown" };
+    let target = core::str::from_utf8(arg).unwrap_or("").trim();
+    if target.is_empty() || target.contains(' ') {
+        print_line(format!("usage: {} <iface> | -a", name).as_bytes(), DIM);
+        return;
+    }
+    net::init();
+    let r = match (target, up) {
+        ("-a", true) => net::netconf::up_all(),
+        ("-a", false) => net::netconf::down_all(),
+        (t, true) => Ok(alloc::vec![(alloc::string::String::from(t), net::netconf::ifup(t))]),
+        (t, false) => Ok(alloc::vec![(alloc::string::String::from(t), net::netconf::ifdown(t))]),
+    };
+    match r {
+        Ok(report) => print_ifstates(name, report),
+        Err(e) => print_line(format!("{}: {}", name, net::netconf_error_text(e)).as_bytes(), ERR),
+    }
+}
+
+fn print_ifstates(name: &str, report: net::netconf::Report) {
+    for (ifname, r) in report {
+        match r {
+            Ok(st) => {
+                let fg = if st == net::netconf::IfState::Absent { DIM } else { OK };
+                print_line(net::ifstate_text(&ifname, st).as_bytes(), fg);
+            }
+            Err(e) => print_line(format!("{}: {}: {}", name, ifname, net::netconf_error_text(e)).as_bytes(), ERR),
+        }
+    }
+}
+
+/// First login of the boot: /etc now holds the replayed store, so bring the
+/// network up the way /etc/network.conf says.
+fn apply_network_conf() {
+    let Some(r) = net::netconf::boot() else { return };
+    print_line(b"network: applying /etc/network.conf", DIM);
+    match r {
+        Ok(report) => print_ifstates("network", report),
+        Err(e) => print_line(format!("network: {}", net::netconf_error_text(e)).as_bytes(), ERR),
+    }
+    print_prompt_and_input();
+}
+
 fn cmd_ipset(arg: &[u8]) {
     let arg_s = match core::str::from_utf8(arg) {
         Ok(s) => s,
@@ -1079,72 +1338,863 @@ fn cmd_ipset(arg: &[u8]) {
     print_line(b"ipset: ok", OK);
 }
 
-fn cmd_ping(arg: &[u8]) {
+/// `<net>/<prefix>` or `default`.
+fn parse_route_dest(s: &str) -> Option<([u8; 4], [u8; 4])> {
+    if s == "default" { return Some(([0, 0, 0, 0], [0, 0, 0, 0])); }
+    let (ip, len) = s.split_once('/')?;
+    let len: u8 = len.parse().ok()?;
+    if len > 32 { return None; }
+    Some((parse_ipv4_str(ip)?, net::route::mask_from_len(len)))
+}
+
+/// `nslookup [-type=T] <name|ip>` and `dig <name> [type]` / `dig -x <ip>`.
+/// An address on its own asks for its PTR record; a name with no type asks
+/// for A and AAAA (nslookup) or just A (dig).
+fn cmd_dns(arg: &[u8], dig: bool) {
+    let arg_s = match core::str::from_utf8(arg) {
+        Ok(s) => s,
+        Err(_) => { print_line(b"dns: invalid UTF-8 args", ERR); return; }
+    };
+
+    let mut name: Option<&str> = None;
+    let mut qtype: Option<u16> = None;
+    let mut reverse = false;
+    for tok in arg_s.split_whitespace() {
+        let t = tok.strip_prefix("-type=").or_else(|| tok.strip_prefix("-query="));
+        if let Some(t) = t {
+            match net::dns::type_from_name(t) {
+                Some(q) => qtype = Some(q),
+                None => { print_line(b"dns: unknown record type", ERR); return; }
+            }
+        } else if tok == "-x" {
+            reverse = true;
+        } else if let (true, Some(q)) = (name.is_some(), net::dns::type_from_name(tok)) {
+            qtype = Some(q);
+        } else if name.is_none() {
+            name = Some(tok);
+        }
+    }
+    let Some(target) = name else {
+        if dig {
+            print_line(b"Usage: dig <name> [A|AAAA|MX|TXT|PTR|CNAME|NS|SOA] | dig -x <ip>", DIM);
+        } else {
+            print_line(b"Usage: nslookup [-type=A|AAAA|MX|TXT|PTR|CNAME|NS|SOA] <name|ip>", DIM);
+        }
+        return;
+    };
+
+    net::init();
+    let addr = net::ip::parse(target);
+    if reverse && addr.is_none() {
+        print_line(b"dns: -x needs an IPv4 or IPv6 address", ERR);
+        return;
+    }
+    let report = match (addr, qtype) {
+        (Some(a), None) | (Some(a), Some(net::dns::TYPE_PTR)) => {
+            net::dns_report(&net::dns::reverse_name(a), &[net::dns::TYPE_PTR])
+        }
+        (_, Some(q)) => net::dns_report(target, &[q]),
+        (None, None) if dig => net::dns_report(target, &[net::dns::TYPE_A]),
+        (None, None) => net::dns_report(target, &[net::dns::TYPE_A, net::dns::TYPE_AAAA]),
+    };
+    print_str_lines(&report, FG);
+}
+
+/// `tls [status]`, `tls roots`, `tls trust <file>` (PEM or DER),
+/// `tls proxy on|off` and `tls connect <host> [port]`.
+fn cmd_tls(arg: &[u8]) {
     let arg_s = match core::str::from_utf8(arg) {
         Ok(s) => s,
-        Err(_) => { print_line(b"ping: invalid UTF-8 args", ERR); return; }
+        Err(_) => { print_line(b"tls: invalid UTF-8 args", ERR); return; }
     };
 
     let mut it = arg_s.split_whitespace();
-    let ip_s = match it.next() {
-        Some(s) => s,
-        None => { print_line(b"Usage: ping <ip> [count]", DIM); return; }
+    match it.next().unwrap_or("status") {
+        "status" => print_str_lines(&net::tls_report(), FG),
+        "roots" => print_str_lines(&net::tls_roots_report(), FG),
+        "trust" => {
+            let Some(path) = it.next() else {
+                print_line(b"Usage: tls trust <file.pem|file.der>", DIM);
+                return;
+            };
+            let data = match crate::fs_cmds::read_file("tls", path) {
+                Ok(d) => d,
+                Err(e) => { print_line(e.as_bytes(), ERR); return; }
+            };
+            let certs = net::x509::pem_to_der(&data);
+            if certs.is_empty() {
+                print_line(b"tls: no certificate found in file", ERR);
+                return;
+            }
+            for der in certs.iter() {
+                match net::x509::add_root(der) {
+                    Ok(name) => print_line(format!("tls: trusting {}", name).as_bytes(), OK),
+                    Err(_) => print_line(b"tls: unusable certificate skipped", ERR),
+                }
+            }
+        }
+        "proxy" => match it.next() {
+            Some("on") => {
+                net::http::set_https_proxy(true);
+                print_line(b"tls: https:// now goes through the host proxy (10.0.2.2:8000)", OK);
+            }
+            Some("off") => {
+                net::http::set_https_proxy(false);
+                print_line(b"tls: https:// now uses native TLS", OK);
+            }
+            _ => print_line(b"Usage: tls proxy on|off", DIM),
+        },
+        "connect" => {
+            let Some(host) = it.next() else {
+                print_line(b"Usage: tls connect <host> [port]", DIM);
+                return;
+            };
+            let port = match it.next().map(|p| p.parse::<u16>()) {
+                None => 443,
+                Some(Ok(p)) => p,
+                Some(Err(_)) => { print_line(b"tls: invalid port", ERR); return; }
+            };
+            net::init();
+            print_str_lines(&net::tls_connect_report(host, port), FG);
+        }
+        _ => print_line(b"Usage: tls [status | roots | trust <file> | proxy on|off | connect <host> [port]]", DIM),
+    }
+}
+
+/// `ntp [status]`, `ntp sync`, `ntp server <name|ip|auto>`, `ntp rtc`.
+fn cmd_ntp(arg: &[u8]) {
+    let arg_s = match core::str::from_utf8(arg) {
+        Ok(s) => s,
+        Err(_) => { print_line(b"ntp: invalid UTF-8 args", ERR); return; }
     };
 
-    let dst = match parse_ipv4_str(ip_s) {
-        Some(v) => v,
-        None => { print_line(b"ping: invalid IP", ERR); return; }
+    let mut it = arg_s.split_whitespace();
+    match it.next().unwrap_or("status") {
+        "status" => print_str_lines(&net::ntp_report(), FG),
+        "sync" => {
+            net::init();
+            print_str_lines(&net::ntp_sync_report(), FG);
+        }
+        "server" => match it.next() {
+            None => print_line(b"Usage: ntp server <name|ip|auto>", DIM),
+            Some("auto") => {
+                net::ntp::set_server(None);
+                print_line(b"ntp: using DHCP servers, then pool.ntp.org", OK);
+            }
+            Some(name) => {
+                net::ntp::set_server(Some(name));
+                print_line(format!("ntp: server set to {} (run ntp sync)", name).as_bytes(), OK);
+            }
+        },
+        "rtc" => {
+            if net::ntp::write_rtc() {
+                print_line(b"ntp: RTC set to the corrected time (UTC)", OK);
+            } else {
+                print_line(b"ntp: clock not synced yet (run ntp sync)", ERR);
+            }
+        }
+        _ => print_line(b"Usage: ntp [status | sync | server <name|ip|auto> | rtc]", DIM),
+    }
+}
+
+fn cmd_route(arg: &[u8]) {
+    let arg_s = match core::str::from_utf8(arg) {
+        Ok(s) => s,
+        Err(_) => { print_line(b"route: invalid UTF-8 args", ERR); return; }
     };
 
-    let count = it.next().and_then(|c| c.parse::<u32>().ok()).unwrap_or(4).min(20);
+    let mut it = arg_s.split_whitespace();
+    let sub = it.next().unwrap_or("show");
+    match sub {
+        "show" => print_str_lines(&net::route_report(), FG),
+        "flush" => {
+            net::route::flush();
+            print_line(b"route: static routes flushed", OK);
+        }
+        "add" | "del" => {
+            let (dest, mask) = match it.next().and_then(parse_route_dest) {
+                Some(v) => v,
+                None => { print_line(b"route: expected <net>/<prefix> or default", ERR); return; }
+            };
+            let r = if sub == "add" {
+                // no "via" means the network is on-link
+                let gw = match (it.next(), it.next()) {
+                    (None, _) => [0, 0, 0, 0],
+                    (Some("via"), Some(g)) => match parse_ipv4_str(g) {
+                        Some(v) => v,
+                        None => { print_line(b"route: invalid gateway", ERR); return; }
+                    },
+                    _ => { print_line(b"usage: route add <net>/<prefix>|default [via <gw>]", ERR); return; }
+                };
+                net::route::add(dest, mask, gw)
+            } else {
+                net::route::del(dest, mask)
+            };
+            match r {
+                Ok(()) => print_line(b"route: ok", OK),
+                Err(net::route::RouteError::BadMask) => print_line(b"route: invalid prefix", ERR),
+                Err(net::route::RouteError::Exists) => print_line(b"route: route already exists", ERR),
+                Err(net::route::RouteError::NotFound) => print_line(b"route: no such route", ERR),
+                Err(net::route::RouteError::Full) => print_line(b"route: table full", ERR),
+                Err(net::route::RouteError::Fixed) => print_line(b"route: route comes from the interface config (use ipset)", ERR),
+            }
+        }
+        _ => {
+            print_line(b"usage: route [show | flush]", DIM);
+            print_line(b"       route add <net>/<prefix>|default [via <gw>]", DIM);
+            print_line(b"       route del <net>/<prefix>|default", DIM);
+        }
+    }
+}
 
-    let mut banner = [0u8; 64];
-    let mut n = 0usize;
-    banner[n..n+5].copy_from_slice(b"PING ");
-    n += 5;
-    n += write_ipv4(&mut banner[n..], dst);
-    print_line(&banner[..n], OK);
-
-    let mut seq: u16 = 1;
-    for _ in 0..count {
-        match net::ping_once(dst, seq) {
-            Ok(r) => {
-                let mut line = [0u8; 96];
-                let mut p = 0usize;
-                line[p..p+11].copy_from_slice(b"Reply from ");
-                p += 11;
-                p += write_ipv4(&mut line[p..], dst);
+/// Options shared by `ping`/`ping6` and `traceroute`, parsed from argv.
+struct ProbeArgs<'a> {
+    host: &'a str,
+    count: u32,
+    interval_ms: u64,
+    /// traceroute: probes per hop / highest TTL / skip reverse lookups.
+    probes: u32,
+    max_hops: u8,
+    numeric: bool,
+    v6: bool,
+    opts: net::PingOptions,
+}
 
-                line[p..p+6].copy_from_slice(b": seq=");
-                p += 6;
-                p += write_u64_dec(&mut line[p..], r.seq as u64);
+/// Parse `[-c n] [-i secs] [-s size] [-t ttl] [-W secs] [-m hops] [-q n] [-n] [-4|-6] <host> [count]`.
+/// Every command accepts every flag; the ones it doesn't use are ignored.
+fn parse_probe_args(s: &str, v6: bool, opts: net::PingOptions) -> Result<ProbeArgs<'_>, &'static str> {
+    let mut a = ProbeArgs {
+        host: "",
+        count: 4,
+        interval_ms: 1000,
+        probes: 3,
+        max_hops: 30,
+        numeric: false,
+        v6,
+        opts,
+    };
+    let mut it = s.split_whitespace();
+    while let Some(w) = it.next() {
+        match w {
+            "-n" => a.numeric = true,
+            "-4" => a.v6 = false,
+            "-6" => a.v6 = true,
+            "-c" | "-i" | "-s" | "-t" | "-W" | "-w" | "-m" | "-q" => {
+                let v = it.next().ok_or("missing option value")?;
+                match w {
+                    "-c" => a.count = v.parse().ok().filter(|&c| (1..=1000).contains(&c)).ok_or("count must be 1-1000")?,
+                    "-i" => a.interval_ms = parse_secs_ms(v).filter(|&ms| ms >= 100).ok_or("interval must be at least 0.1 s")?,
+                    "-s" => a.opts.size = v.parse().ok().filter(|&n| n <= net::PING_MAX_SIZE).ok_or("invalid packet size")?,
+                    "-t" => a.opts.ttl = v.parse().ok().filter(|&t| t > 0).ok_or("ttl must be 1-255")?,
+                    "-m" => a.max_hops = v.parse().ok().filter(|&t| t > 0).ok_or("max hops must be 1-255")?,
+                    "-q" => a.probes = v.parse().ok().filter(|&q| (1..=10).contains(&q)).ok_or("probes must be 1-10")?,
+                    _ => a.opts.timeout_ms = parse_secs_ms(v).filter(|&ms| (100..=60_000).contains(&ms)).ok_or("timeout must be 0.1-60 s")?,
+                }
+            }
+            _ if w.starts_with('-') => return Err("unknown option"),
+            _ if a.host.is_empty() => a.host = w,
+            // old `ping <ip> [count]` form
+            _ => a.count = w.parse().ok().filter(|&c| (1..=1000).contains(&c)).ok_or("count must be 1-1000")?,
+        }
+    }
+    if a.host.is_empty() { return Err("no host given"); }
+    Ok(a)
+}
+
+/// "1.5" (seconds, up to millisecond precision) -> 1500.
+fn parse_secs_ms(s: &str) -> Option<u64> {
+    let (whole, frac) = s.split_once('.').unwrap_or((s, ""));
+    if frac.len() > 3 || (whole.is_empty() && frac.is_empty()) { return None; }
+    let w: u64 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+    let mut f: u64 = if frac.is_empty() { 0 } else { frac.parse().ok()? };
+    for _ in frac.len()..3 { f *= 10; }
+    w.checked_mul(1000)?.checked_add(f)
+}
+
+/// Literal address, or a name looked up over DNS: AAAA when `v6`, else A
+/// falling back to AAAA for v6-only hosts.
+fn resolve_target(host: &str, v6: bool) -> Result<net::ip::IpAddr, &'static str> {
+    if let Some(a) = net::ip::parse(host) {
+        return Ok(a);
+    }
+    let aaaa = || net::dns::resolve_aaaa(host).map(net::ip::IpAddr::V6).map_err(net::dns_error_text);
+    if v6 { return aaaa(); }
+    match net::dns::resolve_a(host) {
+        Ok(a) => Ok(net::ip::IpAddr::V4(a)),
+        Err(net::dns::DnsError::NoAnswer) => aaaa(),
+        Err(e) => Err(net::dns_error_text(e)),
+    }
+}
+
+fn addr_string(a: net::ip::IpAddr) -> alloc::string::String {
+    let mut b = [0u8; 40];
+    let n = net::ip::write(&mut b, a);
+    alloc::string::String::from(core::str::from_utf8(&b[..n]).unwrap_or("?"))
+}
+
+/// Microseconds as "12.345".
+fn fmt_ms(us: u64) -> alloc::string::String {
+    format!("{}.{:03}", us / 1000, us % 1000)
+}
+
+/// Keep the NIC serviced until `deadline` (uptime ms).
+fn wait_until(deadline: u64) {
+    while time::uptime_ms() < deadline {
+        net::poll();
+        time::cpu_pause();
+    }
+}
+
+/// Errors that end a ping/traceroute run, with the text to print.
+fn probe_fatal(name: &str, e: net::PingError, v6: bool) -> Option<alloc::string::String> {
+    let why = match e {
+        net::PingError::NoNic => "no NIC",
+        net::PingError::NotConfigured if v6 => "no route (no IPv6 router has advertised yet)",
+        net::PingError::NotConfigured => "no IPv4 config (run dhcp or ipset)",
+        net::PingError::TooLarge => "packet too large for the link",
+        _ => return None,
+    };
+    Some(format!("{}: {}", name, why))
+}
+
+fn cmd_ping(arg: &[u8]) {
+    run_ping("ping", arg, false);
+}
 
-                line[p..p+5].copy_from_slice(b" ttl=");
-                p += 5;
-                p += write_u64_dec(&mut line[p..], r.ttl as u64);
+fn cmd_ping6(arg: &[u8]) {
+    run_ping("ping6", arg, true);
+}
 
-                line[p..p+5].copy_from_slice(b" tsc=");
-                p += 5;
-                p += write_u64_dec(&mut line[p..], r.rtt_tsc);
+fn run_ping(name: &str, arg: &[u8], v6: bool) {
+    let arg_s = match core::str::from_utf8(arg) {
+        Ok(s) => s,
+        Err(_) => { print_line(format!("{}: invalid UTF-8 args", name).as_bytes(), ERR); return; }
+    };
+    let a = match parse_probe_args(arg_s, v6, net::PingOptions::default()) {
+        Ok(a) => a,
+        Err(e) => {
+            print_line(format!("{}: {}", name, e).as_bytes(), ERR);
+            print_line(format!("Usage: {} [-c count] [-i secs] [-s size] [-t ttl] [-W secs] [-4|-6] <host>", name).as_bytes(), DIM);
+            return;
+        }
+    };
 
-                print_line(&line[..p], FG);
+    net::init();
+    let dst = match resolve_target(a.host, a.v6) {
+        Ok(d) => d,
+        Err(e) => { print_line(format!("{}: {}: {}", name, a.host, e).as_bytes(), ERR); return; }
+    };
+    let addr = addr_string(dst);
+    let ip_hdr = if dst.is_v6() { 40 } else { 20 };
+    print_line(format!("PING {} ({}) {}({}) bytes of data.", a.host, addr, a.opts.size, a.opts.size + 8 + ip_hdr).as_bytes(), OK);
+
+    let (mut sent, mut received, mut errors) = (0u32, 0u32, 0u32);
+    let (mut min, mut max, mut sum, mut sum_sq) = (u64::MAX, 0u64, 0u64, 0u128);
+    let started = time::uptime_ms();
+    for i in 0..a.count {
+        let seq = (i + 1) as u16;
+        let t0 = time::uptime_ms();
+        sent += 1;
+        match net::ping(dst, seq, &a.opts) {
+            Ok(r) if r.kind == net::PingKind::Echo => {
+                received += 1;
+                min = min.min(r.rtt_us);
+                max = max.max(r.rtt_us);
+                sum += r.rtt_us;
+                sum_sq += r.rtt_us as u128 * r.rtt_us as u128;
+                print_line(format!("{} bytes from {}: icmp_seq={} {}={} time={} ms",
+                    r.bytes, addr, seq, if dst.is_v6() { "hlim" } else { "ttl" }, r.ttl, fmt_ms(r.rtt_us)).as_bytes(), FG);
             }
-            Err(net::PingError::Timeout) => {
-                print_line(b"Request timed out.", ERR);
+            Ok(r) => {
+                errors += 1;
+                let why = match r.kind {
+                    net::PingKind::Unreachable(code) => net::unreachable_text(dst.is_v6(), code),
+                    _ => "Time to live exceeded",
+                };
+                print_line(format!("From {} icmp_seq={} {}", addr_string(r.from), seq, why).as_bytes(), ERR);
             }
-            Err(net::PingError::NotConfigured) => {
-                print_line(b"ping: no IPv4 config (run dhcp or ipset)", ERR);
-                return;
+            Err(net::PingError::Timeout) => {
+                print_line(format!("Request timeout for icmp_seq {}", seq).as_bytes(), ERR);
             }
             Err(net::PingError::ArpTimeout) => {
-                print_line(b"ping: ARP timeout", ERR);
+                errors += 1;
+                let why = if dst.is_v6() { "neighbor discovery timeout" } else { "ARP timeout" };
+                print_line(format!("From local icmp_seq={} {}", seq, why).as_bytes(), ERR);
             }
-            Err(_) => {
-                print_line(b"ping: failed", ERR);
+            Err(e) => match probe_fatal(name, e, dst.is_v6()) {
+                Some(msg) => { print_line(msg.as_bytes(), ERR); return; }
+                None => { errors += 1; print_line(format!("{}: send failed", name).as_bytes(), ERR); }
+            },
+        }
+        if i + 1 < a.count { wait_until(t0 + a.interval_ms); }
+    }
+
+    print_line(format!("--- {} ping statistics ---", a.host).as_bytes(), OK);
+    let loss = (sent - received) * 100 / sent;
+    let errs = if errors > 0 { format!(", +{} errors", errors) } else { alloc::string::String::new() };
+    print_line(format!("{} packets transmitted, {} received{}, {}% packet loss, time {}ms",
+        sent, received, errs, loss, time::uptime_ms() - started).as_bytes(), FG);
+    if received > 0 {
+        let n = received as u64;
+        let avg = sum / n;
+        // mdev = sqrt(E[x^2] - E[x]^2), as ping(8) reports it
+        let var = (sum_sq / n as u128).saturating_sub(avg as u128 * avg as u128);
+        let mdev = var.isqrt() as u64;
+        print_line(format!("rtt min/avg/max/mdev = {}/{}/{}/{} ms", fmt_ms(min), fmt_ms(avg), fmt_ms(max), fmt_ms(mdev)).as_bytes(), FG);
+    }
+}
+
+/// traceroute's "!H"-style marker for an unreachable code.
+fn unreachable_flag(v6: bool, code: u8) -> alloc::string::String {
+    let f = match (v6, code) {
+        (false, 0) | (true, 0) => "N",
+        (false, 1) | (true, 3) => "H",
+        (false, 2) | (false, 3) | (true, 4) => "P",
+        (false, 9) | (false, 10) | (false, 13) | (true, 1) => "X",
+        _ => return format!("!<{}>", code),
+    };
+    format!("!{}", f)
+}
+
+fn cmd_traceroute(arg: &[u8], v6: bool) {
+    let name = if v6 { "traceroute6" } else { "traceroute" };
+    let arg_s = match core::str::from_utf8(arg) {
+        Ok(s) => s,
+        Err(_) => { print_line(format!("{}: invalid UTF-8 args", name).as_bytes(), ERR); return; }
+    };
+    // 60-byte IPv4 probes like traceroute(8)
+    let defaults = net::PingOptions { size: 32, ttl: 0, timeout_ms: 3000 };
+    let mut a = match parse_probe_args(arg_s, v6, defaults) {
+        Ok(a) => a,
+        Err(e) => {
+            print_line(format!("{}: {}", name, e).as_bytes(), ERR);
+            print_line(format!("Usage: {} [-n] [-m max_hops] [-q probes] [-w secs] [-s size] [-4|-6] <host>", name).as_bytes(), DIM);
+            return;
+        }
+    };
+
+    net::init();
+    let dst = match resolve_target(a.host, a.v6) {
+        Ok(d) => d,
+        Err(e) => { print_line(format!("{}: {}: {}", name, a.host, e).as_bytes(), ERR); return; }
+    };
+    let ip_hdr = if dst.is_v6() { 40 } else { 20 };
+    print_line(format!("traceroute to {} ({}), {} hops max, {} byte packets",
+        a.host, addr_string(dst), a.max_hops, a.opts.size + 8 + ip_hdr).as_bytes(), OK);
+
+    let mut seq: u16 = 0;
+    for ttl in 1..=a.max_hops {
+        a.opts.ttl = ttl;
+        let mut line = format!("{:>2} ", ttl);
+        let mut last: Option<net::ip::IpAddr> = None;
+        let mut done = false;
+        for _ in 0..a.probes {
+            seq = seq.wrapping_add(1);
+            match net::ping(dst, seq, &a.opts) {
+                Ok(r) => {
+                    if last != Some(r.from) {
+                        let addr = addr_string(r.from);
+                        let host = if a.numeric { None } else { net::dns::resolve_ptr(r.from).ok() };
+                        match host {
+                            Some(h) => line.push_str(&format!(" {} ({})", h.trim_end_matches('.'), addr)),
+                            None => line.push_str(&format!(" {}", addr)),
+                        }
+                        last = Some(r.from);
+                    }
+                    line.push_str(&format!("  {} ms", fmt_ms(r.rtt_us)));
+                    match r.kind {
+                        net::PingKind::Echo => done = true,
+                        net::PingKind::Unreachable(code) => {
+                            line.push(' ');
+                            line.push_str(&unreachable_flag(dst.is_v6(), code));
+                            done = true;
+                        }
+                        net::PingKind::TimeExceeded => {}
+                    }
+                }
+                Err(net::PingError::Timeout) | Err(net::PingError::ArpTimeout) => line.push_str(" *"),
+                Err(e) => {
+                    print_line(line.as_bytes(), FG);
+                    let msg = probe_fatal(name, e, dst.is_v6()).unwrap_or_else(|| format!("{}: send failed", name));
+                    print_line(msg.as_bytes(), ERR);
+                    return;
+                }
             }
         }
-        seq = seq.wrapping_add(1);
-        time::spin(2_000_000);
+        print_line(line.as_bytes(), FG);
+        if done { return; }
+    }
+}
+
+/// `-c count`, `-w file|serial` and the filter words of `tcpdump`/`pcap start`.
+fn parse_capture_args(name: &str, s: &str, default: net::pcap::Sink) -> Option<(u32, net::pcap::Sink, net::pcap::Filter)> {
+    let mut count = 0u32;
+    let mut sink = default;
+    let mut words: Vec<&str> = Vec::new();
+    let mut it = s.split_whitespace();
+    while let Some(w) = it.next() {
+        match w {
+            "-c" => match it.next().and_then(|v| v.parse().ok()) {
+                Some(c) => count = c,
+                None => { print_line(format!("{}: -c needs a count", name).as_bytes(), ERR); return None; }
+            },
+            "-w" => match it.next() {
+                Some("serial") => sink = net::pcap::Sink::Serial,
+                Some(p) => sink = net::pcap::Sink::File(alloc::string::String::from(p)),
+                None => { print_line(format!("{}: -w needs a file name or 'serial'", name).as_bytes(), ERR); return None; }
+            },
+            _ => words.push(w),
+        }
+    }
+    match net::pcap::Filter::parse(&words) {
+        Ok(f) => Some((count, sink, f)),
+        Err(e) => {
+            print_line(format!("{}: {}", name, net::pcap_error_text(e)).as_bytes(), ERR);
+            print_line(b"filter: [not] arp|ip|ip6|icmp|icmp6|tcp|udp|host <addr>|port <n> [and ...]", DIM);
+            None
+        }
+    }
+}
+
+/// Stop the running capture and save its file, if it has one.
+fn finish_capture(name: &str) {
+    let done = match net::pcap::stop() {
+        Ok(d) => d,
+        Err(e) => { print_line(format!("{}: {}", name, net::pcap_error_text(e)).as_bytes(), ERR); return; }
+    };
+    print_line(format!("{} packets captured, {} dropped", done.stats.packets, done.stats.dropped).as_bytes(), FG);
+    if let Some(path) = done.path {
+        match crate::fs_cmds::write_file(name, &path, &done.data) {
+            Ok(()) => print_line(format!("{}: wrote {} bytes to {}", name, done.data.len(), path).as_bytes(), OK),
+            Err(e) => print_line(e.as_bytes(), ERR),
+        }
+    }
+}
+
+fn cmd_tcpdump(arg: &[u8]) {
+    let arg_s = match core::str::from_utf8(arg) {
+        Ok(s) => s,
+        Err(_) => { print_line(b"tcpdump: invalid UTF-8 args", ERR); return; }
+    };
+    let Some((count, sink, filter)) = parse_capture_args("tcpdump", arg_s, net::pcap::Sink::None) else { return };
+
+    net::init();
+    if let Err(e) = net::pcap::start(sink, filter, true) {
+        print_line(format!("tcpdump: {}", net::pcap_error_text(e)).as_bytes(), ERR);
+        return;
+    }
+    print_line(b"tcpdump: listening, press any key to stop", DIM);
+
+    let mut seen = 0u32;
+    'outer: loop {
+        net::poll();
+        while let Some(f) = net::pcap::next_live() {
+            let secs = f.ts_us / 1_000_000;
+            let line = format!("{:02}:{:02}:{:02}.{:06} {} {}",
+                secs / 3600 % 24, secs / 60 % 60, secs % 60, f.ts_us % 1_000_000,
+                if f.dir == net::pcap::Dir::Rx { "In " } else { "Out" },
+                net::pcap::summary(&f.data));
+            print_line(line.as_bytes(), FG);
+            seen += 1;
+            if count != 0 && seen >= count { break 'outer; }
+        }
+        if keyboard::keyboard_poll_scancode().is_some_and(|sc| sc & 0x80 == 0) { break; }
+        time::cpu_pause();
+    }
+    finish_capture("tcpdump");
+}
+
+fn cmd_pcap(arg: &[u8]) {
+    let arg_s = match core::str::from_utf8(arg) {
+        Ok(s) => s,
+        Err(_) => { print_line(b"pcap: invalid UTF-8 args", ERR); return; }
+    };
+    let (sub, rest) = arg_s.trim().split_once(' ').unwrap_or((arg_s.trim(), ""));
+    match sub {
+        "" | "status" => print_str_lines(&net::pcap_report(), FG),
+        "start" => {
+            let default = net::pcap::Sink::File(alloc::string::String::from(net::pcap::DEFAULT_PATH));
+            let Some((_, sink, filter)) = parse_capture_args("pcap", rest, default) else { return };
+            net::init();
+            match net::pcap::start(sink, filter, false) {
+                Ok(()) => print_str_lines(&net::pcap_report(), OK),
+                Err(e) => print_line(format!("pcap: {}", net::pcap_error_text(e)).as_bytes(), ERR),
+            }
+        }
+        "stop" => finish_capture("pcap"),
+        _ => {
+            print_line(b"usage: pcap [status | stop]", DIM);
+            print_line(b"       pcap start [-w <file>|serial] [filter]", DIM);
+        }
+    }
+}
+
+/// Heap left to the rest of the kernel while a download grows.
+const DOWNLOAD_HEAP_RESERVE: usize = 512 * 1024;
+
+/// Can a download holding `got` bytes take `n` more? Growing it may move it
+/// into an allocation twice the size while the old one is still live, so
+/// that much has to be free on top of the reserve.
+fn download_fits(got: usize, n: usize) -> bool {
+    let (used, size) = crate::heap::usage();
+    size.saturating_sub(used) >= DOWNLOAD_HEAP_RESERVE + 2 * (got + n)
+}
+
+fn fmt_size(n: u64) -> alloc::string::String {
+    if n >= 1 << 20 {
+        format!("{}.{} MiB", n >> 20, ((n & 0xF_FFFF) * 10) >> 20)
+    } else if n >= 1024 {
+        format!("{} KiB", n >> 10)
+    } else {
+        format!("{} B", n)
+    }
+}
+
+fn progress_text(got: usize, total: Option<u64>, elapsed_ms: u64) -> alloc::string::String {
+    let rate = (got as u64 * 1000).checked_div(elapsed_ms).unwrap_or(0);
+    match total {
+        Some(t) if t > 0 => format!("  {} / {} bytes  {:>3}%  {}/s", got, t, got as u64 * 100 / t, fmt_size(rate)),
+        _ => format!("  {} bytes  {}/s", got, fmt_size(rate)),
+    }
+}
+
+/// File name a URL is saved under: its last path segment.
+fn url_file_name(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, r)| r);
+    let path = rest.split(['?', '#']).next().unwrap_or("");
+    let name = path.split_once('/').map_or("", |(_, p)| p.rsplit('/').next().unwrap_or(""));
+    if name.is_empty() { "index.html" } else { name }
+}
+
+/// Send the request and stop at the response head, reporting failures.
+fn http_open(name: &str, req: &net::http::Request) -> Option<net::http::BodyReader> {
+    net::init();
+    match net::http::open(req) {
+        Ok(r) => Some(r),
+        Err(e) => {
+            print_line(format!("{}: {}", name, net::http_error_text(e)).as_bytes(), ERR);
+            None
+        }
+    }
+}
+
+fn print_http_status(r: &net::http::BodyReader) {
+    let mut line = format!("HTTP {} {}", r.status, r.reason);
+    if let Some(t) = r.headers.get("content-type") {
+        line.push_str(&format!(", {}", t));
+    }
+    if let Some(n) = r.content_length() {
+        line.push_str(&format!(", {} bytes", n));
+    }
+    let fg = match r.status {
+        200..=299 => OK,
+        400.. => ERR,
+        _ => FG,
+    };
+    print_line(line.as_bytes(), fg);
+}
+
+/// Hand the rest of the body to `sink` chunk by chunk, with a progress line
+/// unless `quiet`, and return its length. Stops when the heap runs short
+/// (see `download_fits`); any key aborts.
+fn download_body(
+    name: &str,
+    r: &mut net::http::BodyReader,
+    quiet: bool,
+    mut sink: impl FnMut(&[u8]) -> Result<(), alloc::string::String>,
+) -> Result<usize, alloc::string::String> {
+    let too_big = || format!("{}: too large for the free kernel memory", name);
+    let total = r.content_length();
+    if total.is_some_and(|t| !download_fits(0, t as usize)) { return Err(too_big()); }
+
+    let start = time::uptime_ms();
+    let mut shown = start;
+    let mut got = 0usize;
+    let mut chunk = [0u8; 2048];
+    if !quiet { print_line(progress_text(0, total, 0).as_bytes(), DIM); }
+    loop {
+        let n = r.read(&mut chunk).map_err(|e| format!("{}: {}", name, net::http_error_text(e)))?;
+        if n == 0 { break; }
+        if !download_fits(got, n) { return Err(too_big()); }
+        sink(&chunk[..n])?;
+        got += n;
+
+        let now = time::uptime_ms();
+        if !quiet && now.saturating_sub(shown) >= 250 {
+            shown = now;
+            replace_last_line(progress_text(got, total, now - start).as_bytes(), DIM);
+        }
+        if keyboard::keyboard_poll_scancode().is_some_and(|sc| sc & 0x80 == 0) {
+            return Err(format!("{}: interrupted after {} bytes", name, got));
+        }
+    }
+    if !quiet {
+        replace_last_line(progress_text(got, total, time::uptime_ms() - start).as_bytes(), DIM);
+    }
+    Ok(got)
+}
+
+/// Stream the body into `path` (a directory gets `file` appended), then
+/// flush the persistent store if asked. A failed download leaves what
+/// arrived so far in the file.
+fn save_download(name: &str, r: &mut net::http::BodyReader, quiet: bool, path: &str, file: &str, sync: bool) {
+    let mut path = alloc::string::String::from(path);
+    let is_dir = fs::normalize_path(&crate::fs_cmds::cwd(), &path).is_ok_and(|abs| fs::FS.lock().is_dir(&abs));
+    if path.ends_with('/') || is_dir {
+        if !path.ends_with('/') { path.push('/'); }
+        path.push_str(file);
+    }
+    let saved = crate::fs_cmds::write_file(name, &path, &[])
+        .and_then(|()| download_body(name, r, quiet, |b| crate::fs_cmds::append_file(name, &path, b)));
+    match saved {
+        Ok(n) => print_line(format!("{}: saved {} ({} bytes)", name, path, n).as_bytes(), OK),
+        Err(e) => {
+            print_line(e.as_bytes(), ERR);
+            return;
+        }
+    }
+    if sync {
+        if let Some(out) = crate::fs_cmds::try_handle("sync", &[]) {
+            print_str_lines(&out, FG);
+        }
+    }
+}
+
+/// `wget [-q] [--sync] <url> [path]`: download into the filesystem.
+fn cmd_wget(arg: &[u8]) {
+    const USAGE: &[u8] = b"usage: wget [-q] [--sync] <url> [path]";
+    let Ok(s) = core::str::from_utf8(arg) else { print_line(USAGE, ERR); return };
+    let (mut quiet, mut sync) = (false, false);
+    let mut words: Vec<&str> = Vec::new();
+    for w in s.split_whitespace() {
+        match w {
+            "-q" => quiet = true,
+            "--sync" => sync = true,
+            _ if w.starts_with('-') => { print_line(USAGE, ERR); return; }
+            _ => words.push(w),
+        }
+    }
+    let (url, path) = match words.as_slice() {
+        [u] => (*u, None),
+        [u, p] => (*u, Some(*p)),
+        _ => { print_line(USAGE, DIM); return; }
+    };
+    let file = url_file_name(url);
+
+    if !quiet { print_line(format!("--> GET {}", url).as_bytes(), DIM); }
+    let Some(mut r) = http_open("wget", &net::http::Request::new(net::http::Method::Get, url)) else { return };
+    if !quiet || !(200..300).contains(&r.status) { print_http_status(&r); }
+    if !(200..300).contains(&r.status) {
+        print_line(b"wget: not saved", ERR);
+        return;
+    }
+    save_download("wget", &mut r, quiet, path.unwrap_or(file), file, sync);
+}
+
+/// Split on whitespace, keeping "double" or 'single' quoted runs together.
+fn split_quoted(s: &str) -> Option<Vec<alloc::string::String>> {
+    let mut out = Vec::new();
+    let mut cur = alloc::string::String::new();
+    let (mut quote, mut in_word) = (None, false);
+    for c in s.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => cur.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word { out.push(core::mem::take(&mut cur)); }
+                in_word = false;
+            }
+            (None, c) => {
+                cur.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() { return None; }
+    if in_word { out.push(cur); }
+    Some(out)
+}
+
+/// `curl [-I] [-L] [-s] [-o file | -O] [-X method] [-d data] [--sync] <url>`:
+/// print a response, or save it with -o/-O.
+fn cmd_curl(arg: &[u8]) {
+    const USAGE: &[u8] = b"usage: curl [-I] [-L] [-s] [-o file | -O] [-X method] [-d data] [--sync] <url>";
+    let Some(args) = core::str::from_utf8(arg).ok().and_then(split_quoted) else { print_line(USAGE, ERR); return };
+    let (mut head, mut follow, mut silent, mut remote_name, mut sync) = (false, false, false, false, false);
+    let mut out: Option<&str> = None;
+    let mut method: Option<net::http::Method> = None;
+    let mut data: Option<&str> = None;
+    let mut url: Option<&str> = None;
+    let mut it = args.iter().map(|a| a.as_str());
+    while let Some(w) = it.next() {
+        match w {
+            "-I" => head = true,
+            "-L" => follow = true,
+            "-s" => silent = true,
+            "-O" => remote_name = true,
+            "--sync" => sync = true,
+            "-o" => out = it.next(),
+            "-d" => data = it.next(),
+            "-X" => {
+                method = it.next().and_then(net::http::Method::parse);
+                if method.is_none() { print_line(b"curl: -X takes GET, HEAD, POST, PUT or DELETE", ERR); return; }
+            }
+            _ if w.starts_with('-') || url.is_some() => { print_line(USAGE, ERR); return; }
+            _ => url = Some(w),
+        }
+    }
+    let Some(url) = url else { print_line(USAGE, DIM); return };
+    let out = out.or(if remote_name { Some(url_file_name(url)) } else { None });
+
+    let method = method.unwrap_or(match (head, data) {
+        (true, _) => net::http::Method::Head,
+        (false, Some(_)) => net::http::Method::Post,
+        (false, None) => net::http::Method::Get,
+    });
+    let form: &[(&str, &str)] = &[("Content-Type", "application/x-www-form-urlencoded")];
+    let req = net::http::Request {
+        headers: if data.is_some() { form } else { &[] },
+        body: data.map_or(&[][..], str::as_bytes),
+        follow_redirects: follow,
+        ..net::http::Request::new(method, url)
+    };
+    let Some(mut r) = http_open("curl", &req) else { return };
+
+    if head {
+        print_line(format!("{} {} {}", r.version, r.status, r.reason).as_bytes(), if r.status >= 400 { ERR } else { OK });
+        for (k, v) in r.headers.iter() {
+            print_line(format!("{}: {}", k, v).as_bytes(), FG);
+        }
+        return;
+    }
+    if !silent { print_http_status(&r); }
+    if (300..400).contains(&r.status) && !follow {
+        if let Some(loc) = r.headers.get("location") {
+            print_line(format!("curl: redirected to {} (use -L to follow)", loc).as_bytes(), DIM);
+        }
+    }
+
+    if let Some(path) = out {
+        save_download("curl", &mut r, silent, path, url_file_name(url), sync);
+        return;
+    }
+    let mut body: Vec<u8> = Vec::new();
+    let collected = download_body("curl", &mut r, true, |b| {
+        body.try_reserve(b.len()).map_err(|_| alloc::string::String::from("curl: out of memory"))?;
+        body.extend_from_slice(b);
+        Ok(())
+    });
+    if let Err(e) = collected {
+        print_line(e.as_bytes(), ERR);
+        return;
+    }
+    match core::str::from_utf8(&body) {
+        Ok(text) => print_str_lines(text, FG),
+        Err(_) => print_line(format!("curl: {} bytes of binary output not shown (use -o <file>)", body.len()).as_bytes(), DIM),
     }
 }
 
@@ -1370,6 +2420,7 @@ pub fn run_shell() -> ! {
                             if let login::LoginOutcome::Success = outcome {
                                 // Successful auth -> desktop terminal
                                 set_app(AppState::Terminal);
+                                apply_network_conf();
                             }
                         }
                         AppState::Regedit => {
@@ -1480,11 +2531,11 @@ AppState::Terminal => match ch {
         }
 
 
-// Update on-screen clock once per RTC second.
+// Update on-screen clock once per wall-clock second.
 // - Login: full-screen re-render (safe)
 // - Desktop: redraw taskbar only (won't erase window contents)
 {
-    let dt = time::rtc_now();
+    let dt = time::now();
     if dt.second != last_clock_sec {
         last_clock_sec = dt.second;
         if gui::ui_mode() == gui::UiMode::Login {
@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
 use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 /// read Time-Stamp Counter, useful for crude profiling/pacing
 #[inline]
@@ -29,6 +30,79 @@ pub fn spin(iter: u64) {
     }
 }
 
+// =============================================================================
+// TSC calibration
+// - Measures TSC ticks over a 10 ms PIT channel 2 one-shot, once, on first use.
+// - Gives the network stack wall-clock-ish timeouts (ARP aging, retransmits)
+//   without needing timer interrupts.
+// =============================================================================
+
+static TSC_HZ: AtomicU64 = AtomicU64::new(0);
+
+/// Fallback when the PIT never fires (no legacy PIT): assume 2 GHz.
+const TSC_HZ_FALLBACK: u64 = 2_000_000_000;
+
+fn calibrate_tsc() -> u64 {
+    const PIT_HZ: u64 = 1_193_182;
+    const DIVISOR: u16 = (PIT_HZ / 100) as u16; // 10 ms
+
+    unsafe {
+        let saved = inb(0x61);
+        // gate channel 2 off, speaker off
+        outb(0x61, saved & !0x03);
+        // channel 2, lobyte/hibyte, mode 0 (interrupt on terminal count), binary
+        outb(0x43, 0xB0);
+        outb(0x42, DIVISOR as u8);
+        outb(0x42, (DIVISOR >> 8) as u8);
+
+        // gate on: counting starts, OUT2 (bit 5) goes high at zero
+        outb(0x61, (saved & !0x02) | 0x01);
+        let t0 = rdtsc();
+        let mut guard: u32 = 0;
+        while (inb(0x61) & 0x20) == 0 {
+            guard = guard.wrapping_add(1);
+            if guard > 50_000_000 {
+                outb(0x61, saved);
+                return TSC_HZ_FALLBACK;
+            }
+        }
+        let t1 = rdtsc();
+        outb(0x61, saved);
+
+        let hz = t1.wrapping_sub(t0) * 100;
+        if hz == 0 { TSC_HZ_FALLBACK } else { hz }
+    }
+}
+
+/// TSC ticks per second (calibrated on first call).
+pub fn tsc_hz() -> u64 {
+    let hz = TSC_HZ.load(Ordering::Relaxed);
+    if hz != 0 { return hz; }
+    let hz = calibrate_tsc();
+    TSC_HZ.store(hz, Ordering::Relaxed);
+    hz
+}
+
+/// Convert a TSC delta to milliseconds.
+pub fn tsc_to_ms(ticks: u64) -> u64 {
+    ticks / (tsc_hz() / 1000).max(1)
+}
+
+/// Convert a TSC delta to microseconds.
+pub fn tsc_to_us(ticks: u64) -> u64 {
+    ((ticks as u128 * 1_000_000) / tsc_hz().max(1) as u128) as u64
+}
+
+/// Milliseconds since the TSC was reset (roughly, since power-on).
+pub fn uptime_ms() -> u64 {
+    tsc_to_ms(rdtsc())
+}
+
+/// Whole seconds since the TSC was reset.
+pub fn uptime_secs() -> u64 {
+    uptime_ms() / 1000
+}
+
 // =============================================================================
 // CMOS RTC (real-time clock)
 // - Works on PC-compatible systems / QEMU.
@@ -72,12 +146,20 @@ fn cmos_read(reg: u8) -> u8 {
     }
 }
 
+#[inline]
+fn cmos_write(reg: u8, value: u8) {
+    unsafe {
+        outb(0x70, reg | 0x80);
+        outb(0x71, value);
+    }
+}
+
 #[inline]
 fn rtc_updating() -> bool {
     (cmos_read(0x0A) & 0x80) != 0
 }
 
-fn read_rtc_once() -> (u8, u8, u8, u8, u8, u8, u8) {
+fn read_rtc_once(century_reg: Option<u8>) -> (u8, u8, u8, u8, u8, u8, u8) {
     // (sec, min, hour, day, month, year2, century)
     while rtc_updating() {}
     let sec = cmos_read(0x00);
@@ -86,7 +168,8 @@ fn read_rtc_once() -> (u8, u8, u8, u8, u8, u8, u8) {
     let day = cmos_read(0x07);
     let mon = cmos_read(0x08);
     let yr  = cmos_read(0x09);
-    let cen = cmos_read(0x32); // may be 0 on some BIOSes
+    // only where the FADT says there is one (usually 0x32)
+    let cen = century_reg.map_or(0, cmos_read);
     (sec, min, hour, day, mon, yr, cen)
 }
 
@@ -98,11 +181,12 @@ fn read_rtc_once() -> (u8, u8, u8, u8, u8, u8, u8) {
 /// - We normalize 12h -> 24h if needed.
 pub fn rtc_now() -> DateTime {
     // Read until stable (two consecutive reads match).
-    let mut a = read_rtc_once();
-    let mut b = read_rtc_once();
+    let century_reg = crate::acpi::rtc_century_register();
+    let mut a = read_rtc_once(century_reg);
+    let mut b = read_rtc_once(century_reg);
     while a != b {
         a = b;
-        b = read_rtc_once();
+        b = read_rtc_once(century_reg);
     }
     let (mut sec, mut min, mut hour, mut day, mut mon, mut yr, mut cen) = b;
 
@@ -143,6 +227,130 @@ pub fn rtc_now() -> DateTime {
     DateTime { year, month: mon, day, hour, minute: min, second: sec }
 }
 
+/// write `dt` to the CMOS RTC, in whatever BCD/12h mode it is set to
+pub fn rtc_write(dt: DateTime) {
+    let status_b = cmos_read(0x0B);
+    let is_binary = (status_b & 0x04) != 0;
+    let is_24h    = (status_b & 0x02) != 0;
+
+    let enc = |v: u8| if is_binary { v } else { ((v / 10) << 4) | (v % 10) };
+    let hour = if is_24h {
+        enc(dt.hour)
+    } else {
+        let h12 = match dt.hour % 12 { 0 => 12, h => h };
+        enc(h12) | if dt.hour >= 12 { 0x80 } else { 0 }
+    };
+
+    let century_reg = crate::acpi::rtc_century_register();
+    while rtc_updating() {}
+    // SET bit: freeze updates while the registers are written
+    cmos_write(0x0B, status_b | 0x80);
+    cmos_write(0x00, enc(dt.second));
+    cmos_write(0x02, enc(dt.minute));
+    cmos_write(0x04, hour);
+    cmos_write(0x07, enc(dt.day));
+    cmos_write(0x08, enc(dt.month));
+    cmos_write(0x09, enc((dt.year % 100) as u8));
+    if let Some(r) = century_reg {
+        cmos_write(r, enc((dt.year / 100) as u8));
+    }
+    cmos_write(0x0B, status_b & !0x80);
+}
+
+// =============================================================================
+// Wall clock
+// - Before an NTP sync this is the RTC (which may be local time), read once
+//   and then carried forward by the TSC, so it has millisecond resolution
+//   and never steps; NTP measures offsets against it.
+// - `set_wall_clock` pins a Unix time to the current uptime; from then on
+//   wall time is that plus the TSC-based uptime, in UTC.
+// =============================================================================
+
+/// Unix milliseconds at `WALL_BASE_UPTIME_MS`; 0 = never set.
+static WALL_BASE_UNIX_MS: AtomicU64 = AtomicU64::new(0);
+static WALL_BASE_UPTIME_MS: AtomicU64 = AtomicU64::new(0);
+/// The RTC reading the clock runs from until it is set; 0 = not read yet.
+static RTC_BASE_UNIX_MS: AtomicU64 = AtomicU64::new(0);
+static RTC_BASE_UPTIME_MS: AtomicU64 = AtomicU64::new(0);
+
+/// days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// seconds since the Unix epoch (`dt` taken as UTC)
+pub fn to_unix(dt: DateTime) -> u64 {
+    let days = days_from_civil(dt.year as i64, dt.month as u32, dt.day as u32);
+    let secs = days * 86_400 + dt.hour as i64 * 3600 + dt.minute as i64 * 60 + dt.second as i64;
+    secs.max(0) as u64
+}
+
+pub fn from_unix(secs: u64) -> DateTime {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as u16;
+    DateTime {
+        year,
+        month,
+        day,
+        hour: (rem / 3600) as u8,
+        minute: (rem / 60 % 60) as u8,
+        second: (rem % 60) as u8,
+    }
+}
+
+/// Pin the wall clock to `unix_ms` (UTC) as of now.
+pub fn set_wall_clock(unix_ms: u64) {
+    WALL_BASE_UPTIME_MS.store(uptime_ms(), Ordering::Relaxed);
+    WALL_BASE_UNIX_MS.store(unix_ms.max(1), Ordering::Relaxed);
+}
+
+/// true once something (NTP) has set the wall clock
+pub fn wall_clock_set() -> bool {
+    WALL_BASE_UNIX_MS.load(Ordering::Relaxed) != 0
+}
+
+/// Current wall time in Unix milliseconds (RTC-based until it has been set).
+pub fn now_unix_ms() -> u64 {
+    let base = WALL_BASE_UNIX_MS.load(Ordering::Relaxed);
+    if base == 0 {
+        return rtc_unix_ms();
+    }
+    base + uptime_ms().saturating_sub(WALL_BASE_UPTIME_MS.load(Ordering::Relaxed))
+}
+
+/// The RTC as of the first call, plus uptime since: monotonic, unlike the
+/// RTC's whole seconds.
+fn rtc_unix_ms() -> u64 {
+    let mut base = RTC_BASE_UNIX_MS.load(Ordering::Relaxed);
+    if base == 0 {
+        RTC_BASE_UPTIME_MS.store(uptime_ms(), Ordering::Relaxed);
+        base = (to_unix(rtc_now()) * 1000).max(1);
+        RTC_BASE_UNIX_MS.store(base, Ordering::Relaxed);
+    }
+    base + uptime_ms().saturating_sub(RTC_BASE_UPTIME_MS.load(Ordering::Relaxed))
+}
+
+/// Current wall-clock date and time.
+pub fn now() -> DateTime {
+    if !wall_clock_set() { return rtc_now(); }
+    from_unix(now_unix_ms() / 1000)
+}
+
 /// format as ASCII: "MM/DD/YYYY HH:MM:SS" (19 chars)
 /// returns number of bytes written
 pub fn format_datetime(buf: &mut [u8; 32], dt: DateTime) -> usize {
@@ -0,0 +1,311 @@
+#![allow(dead_code)]
+// src/virtio.rs
+// Legacy (virtio 0.9.5) PCI transport + split virtqueues, polling only.
+//
+// QEMU exposes "transitional" virtio devices with an I/O BAR0 that speaks the
+// legacy register layout. On q35 this is the case for devices plugged straight
+// into the root complex; behind a PCIe root port add `disable-legacy=off`.
+//
+// IMPORTANT (DMA):
+// - Queue memory and buffers are handed to the device as PHYSICAL addresses.
+// - Everything we pass comes from the kernel image (statics / kernel heap), so
+//   `bootinfo::virt_to_phys` is enough to translate.
+
+extern crate alloc;
+
+use core::alloc::Layout;
+use core::ptr;
+use core::sync::atomic::{fence, Ordering};
+
+use crate::pci::{self, PciDevice};
+use crate::portio::{inb, inl, inw, outb, outl, outw};
+use crate::time;
+
+pub const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+
+// Transitional (legacy) PCI device ids
+pub const DEVICE_NET: u16 = 0x1000;
+pub const DEVICE_BLOCK: u16 = 0x1001;
+pub const DEVICE_9P: u16 = 0x1009;
+
+// Legacy register layout (I/O BAR0 + offset)
+const REG_DEVICE_FEATURES: u16 = 0x00;
+const REG_GUEST_FEATURES: u16 = 0x04;
+const REG_QUEUE_PFN: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0C;
+const REG_QUEUE_SELECT: u16 = 0x0E;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_STATUS: u16 = 0x12;
+const REG_ISR: u16 = 0x13;
+const REG_CONFIG: u16 = 0x14; // device-specific config (MSI-X disabled)
+
+pub const STATUS_ACKNOWLEDGE: u8 = 1;
+pub const STATUS_DRIVER: u8 = 2;
+pub const STATUS_DRIVER_OK: u8 = 4;
+pub const STATUS_FAILED: u8 = 0x80;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+const QUEUE_ALIGN: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VirtioError {
+    NoDevice,
+    NoQueue,
+    QueueFull,
+    Timeout,
+    BadAddress,
+}
+
+/// A legacy virtio device reached through its I/O BAR.
+pub struct LegacyDevice {
+    pub pci: PciDevice,
+    pub io: u16,
+}
+
+impl LegacyDevice {
+    /// Find a transitional device, reset it and acknowledge it.
+    pub fn probe(device_id: u16) -> Option<Self> {
+        let pci = pci::find(VIRTIO_VENDOR_ID, device_id)?;
+        let io = pci.io_bar(0)?;
+        pci.enable(pci::CMD_IO | pci::CMD_BUS_MASTER);
+
+        let dev = Self { pci, io };
+        dev.set_status(0); // reset
+        dev.set_status(STATUS_ACKNOWLEDGE);
+        dev.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        Some(dev)
+    }
+
+    pub fn device_features(&self) -> u32 {
+        unsafe { inl(self.io + REG_DEVICE_FEATURES) }
+    }
+
+    pub fn set_guest_features(&self, features: u32) {
+        unsafe { outl(self.io + REG_GUEST_FEATURES, features); }
+    }
+
+    pub fn status(&self) -> u8 {
+        unsafe { inb(self.io + REG_STATUS) }
+    }
+
+    pub fn set_status(&self, st: u8) {
+        unsafe { outb(self.io + REG_STATUS, st); }
+    }
+
+    pub fn driver_ok(&self) {
+        self.set_status(self.status() | STATUS_DRIVER_OK);
+    }
+
+    pub fn fail(&self) {
+        self.set_status(self.status() | STATUS_FAILED);
+    }
+
+    /// Reading ISR acknowledges the interrupt (we poll, but keep it clean).
+    pub fn ack_isr(&self) -> u8 {
+        unsafe { inb(self.io + REG_ISR) }
+    }
+
+    pub fn config_u8(&self, off: u16) -> u8 {
+        unsafe { inb(self.io + REG_CONFIG + off) }
+    }
+
+    pub fn config_u16(&self, off: u16) -> u16 {
+        unsafe { inw(self.io + REG_CONFIG + off) }
+    }
+
+    pub fn notify(&self, queue: u16) {
+        fence(Ordering::SeqCst);
+        unsafe { outw(self.io + REG_QUEUE_NOTIFY, queue); }
+    }
+
+    /// Allocate and register virtqueue `index` using the size the device reports
+    /// (legacy devices do not allow the driver to pick a size).
+    pub fn setup_queue(&self, index: u16) -> Result<VirtQueue, VirtioError> {
+        unsafe { outw(self.io + REG_QUEUE_SELECT, index); }
+        let size = unsafe { inw(self.io + REG_QUEUE_SIZE) };
+        if size == 0 { return Err(VirtioError::NoQueue); }
+
+        let q = VirtQueue::alloc(index, size)?;
+        let pfn = (q.phys >> 12) as u32;
+        unsafe { outl(self.io + REG_QUEUE_PFN, pfn); }
+        Ok(q)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Split virtqueue (legacy layout: desc | avail | pad to 4K | used)
+// -----------------------------------------------------------------------------
+
+pub struct VirtQueue {
+    pub index: u16,
+    pub size: u16,
+    mem: *mut u8,
+    layout: Layout,
+    phys: u64,
+    avail_off: usize,
+    used_off: usize,
+
+    free_head: u16,
+    num_free: u16,
+    last_used: u16,
+}
+
+// The ring memory is owned exclusively by the queue; single core, no interrupts.
+unsafe impl Send for VirtQueue {}
+
+/// Frees the ring. The owner must reset the device first (`set_status(0)`),
+/// or it may keep writing into memory that has been handed out again.
+impl Drop for VirtQueue {
+    fn drop(&mut self) {
+        unsafe { alloc::alloc::dealloc(self.mem, self.layout); }
+    }
+}
+
+#[inline(always)]
+fn align_up(x: usize, a: usize) -> usize { (x + a - 1) & !(a - 1) }
+
+/// Physical address of a buffer handed to a device.
+pub fn phys_of(p: *const u8) -> u64 {
+    crate::bootinfo::virt_to_phys(p as u64)
+}
+
+impl VirtQueue {
+    fn alloc(index: u16, size: u16) -> Result<Self, VirtioError> {
+        let n = size as usize;
+        let desc_bytes = 16 * n;
+        let avail_bytes = 6 + 2 * n;
+        let used_off = align_up(desc_bytes + avail_bytes, QUEUE_ALIGN);
+        let used_bytes = 6 + 8 * n;
+        let total = align_up(used_off + used_bytes, QUEUE_ALIGN);
+
+        let layout = Layout::from_size_align(total, QUEUE_ALIGN).map_err(|_| VirtioError::NoQueue)?;
+        let mem = unsafe { alloc::alloc::alloc_zeroed(layout) };
+        if mem.is_null() { return Err(VirtioError::NoQueue); }
+
+        let phys = phys_of(mem);
+        // legacy QUEUE_PFN is 32 bits of 4K pages
+        if (phys >> 12) > (u32::MAX as u64) {
+            unsafe { alloc::alloc::dealloc(mem, layout); }
+            return Err(VirtioError::BadAddress);
+        }
+
+        let mut q = Self {
+            index,
+            size,
+            mem,
+            layout,
+            phys,
+            avail_off: desc_bytes,
+            used_off,
+            free_head: 0,
+            num_free: size,
+            last_used: 0,
+        };
+
+        // chain every descriptor into the free list
+        for i in 0..size {
+            q.write_desc(i, 0, 0, 0, i.wrapping_add(1));
+        }
+        Ok(q)
+    }
+
+    fn desc_ptr(&self, i: u16) -> *mut u8 {
+        unsafe { self.mem.add(16 * i as usize) }
+    }
+
+    fn write_desc(&mut self, i: u16, addr: u64, len: u32, flags: u16, next: u16) {
+        let p = self.desc_ptr(i);
+        unsafe {
+            ptr::write_volatile(p as *mut u64, addr);
+            ptr::write_volatile(p.add(8) as *mut u32, len);
+            ptr::write_volatile(p.add(12) as *mut u16, flags);
+            ptr::write_volatile(p.add(14) as *mut u16, next);
+        }
+    }
+
+    fn desc_flags(&self, i: u16) -> u16 {
+        unsafe { ptr::read_volatile(self.desc_ptr(i).add(12) as *const u16) }
+    }
+
+    fn desc_next(&self, i: u16) -> u16 {
+        unsafe { ptr::read_volatile(self.desc_ptr(i).add(14) as *const u16) }
+    }
+
+    pub fn num_free(&self) -> u16 { self.num_free }
+
+    /// Queue a descriptor chain. Each entry is (buffer, length, device_writes).
+    /// Returns the head descriptor id (echoed back by `pop_used`).
+    pub fn push(&mut self, bufs: &[(*const u8, usize, bool)]) -> Result<u16, VirtioError> {
+        if bufs.is_empty() || bufs.len() > self.num_free as usize {
+            return Err(VirtioError::QueueFull);
+        }
+
+        let head = self.free_head;
+        let mut cur = head;
+        for (i, &(p, len, writable)) in bufs.iter().enumerate() {
+            let next = self.desc_next(cur);
+            let mut flags = if writable { DESC_F_WRITE } else { 0 };
+            if i + 1 < bufs.len() { flags |= DESC_F_NEXT; }
+            self.write_desc(cur, phys_of(p), len as u32, flags, next);
+            if i + 1 < bufs.len() { cur = next; } else { self.free_head = next; }
+        }
+        self.num_free -= bufs.len() as u16;
+
+        // publish in the avail ring
+        unsafe {
+            let avail = self.mem.add(self.avail_off);
+            let idx = ptr::read_volatile(avail.add(2) as *const u16);
+            let slot = (idx % self.size) as usize;
+            ptr::write_volatile(avail.add(4 + 2 * slot) as *mut u16, head);
+            fence(Ordering::SeqCst);
+            ptr::write_volatile(avail.add(2) as *mut u16, idx.wrapping_add(1));
+        }
+        Ok(head)
+    }
+
+    /// Reclaim one completed chain: (head id, bytes written by the device).
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        fence(Ordering::SeqCst);
+        let used = unsafe { self.mem.add(self.used_off) };
+        let idx = unsafe { ptr::read_volatile(used.add(2) as *const u16) };
+        if idx == self.last_used { return None; }
+
+        let slot = (self.last_used % self.size) as usize;
+        let (id, len) = unsafe {
+            let e = used.add(4 + 8 * slot);
+            (ptr::read_volatile(e as *const u32) as u16, ptr::read_volatile(e.add(4) as *const u32))
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+
+        // return the chain to the free list
+        let mut cur = id;
+        let mut freed = 1u16;
+        while (self.desc_flags(cur) & DESC_F_NEXT) != 0 {
+            cur = self.desc_next(cur);
+            freed += 1;
+        }
+        let old_head = self.free_head;
+        let flags = self.desc_flags(cur) & !DESC_F_NEXT;
+        self.write_desc(cur, 0, 0, flags, old_head);
+        self.free_head = id;
+        self.num_free += freed;
+
+        Some((id, len))
+    }
+
+    /// Busy-wait for the chain `head` to complete.
+    pub fn wait_for(&mut self, head: u16, timeout_spins: u32) -> Result<u32, VirtioError> {
+        let mut spins: u32 = 0;
+        while spins < timeout_spins {
+            if let Some((id, len)) = self.pop_used() {
+                if id == head { return Ok(len); }
+                continue;
+            }
+            spins = spins.wrapping_add(1);
+            time::cpu_pause();
+        }
+        Err(VirtioError::Timeout)
+    }
+}
@@ -0,0 +1,177 @@
+#![allow(dead_code)]
+// src/x25519.rs
+// X25519 Diffie-Hellman (RFC 7748): Montgomery ladder over GF(2^255 - 19)
+// with five 51-bit limbs. Constant-time ladder (conditional swaps).
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// Field element: sum of limb[i] * 2^(51 * i).
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_bytes(b: &[u8; 32]) -> Fe {
+        let w = |i: usize| {
+            let mut x = [0u8; 8];
+            x.copy_from_slice(&b[i * 8..i * 8 + 8]);
+            u64::from_le_bytes(x)
+        };
+        let (w0, w1, w2, w3) = (w(0), w(1), w(2), w(3));
+        Fe([
+            w0 & MASK,
+            ((w0 >> 51) | (w1 << 13)) & MASK,
+            ((w1 >> 38) | (w2 << 26)) & MASK,
+            ((w2 >> 25) | (w3 << 39)) & MASK,
+            (w3 >> 12) & MASK,
+        ])
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        // two passes leave every limb below 2^51, so h < 2^255
+        let mut h = carry(carry(self.0.map(|x| x as u128)).0.map(|x| x as u128)).0;
+        // subtract p once if h >= p
+        let mut q = (h[0] + 19) >> 51;
+        q = (h[1] + q) >> 51;
+        q = (h[2] + q) >> 51;
+        q = (h[3] + q) >> 51;
+        q = (h[4] + q) >> 51;
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[4] &= MASK;
+
+        let words = [
+            h[0] | (h[1] << 51),
+            (h[1] >> 13) | (h[2] << 38),
+            (h[2] >> 26) | (h[3] << 25),
+            (h[3] >> 39) | (h[4] << 12),
+        ];
+        let mut out = [0u8; 32];
+        for (i, w) in words.iter().enumerate() {
+            out[i * 8..i * 8 + 8].copy_from_slice(&w.to_le_bytes());
+        }
+        out
+    }
+
+    fn add(self, b: Fe) -> Fe {
+        let a = self.0;
+        Fe([a[0] + b.0[0], a[1] + b.0[1], a[2] + b.0[2], a[3] + b.0[3], a[4] + b.0[4]])
+    }
+
+    fn sub(self, b: Fe) -> Fe {
+        // add 2p first so no limb goes negative
+        let a = self.0;
+        let b = b.0;
+        carry([
+            (a[0] + 0xF_FFFF_FFFF_FFDA - b[0]) as u128,
+            (a[1] + 0xF_FFFF_FFFF_FFFE - b[1]) as u128,
+            (a[2] + 0xF_FFFF_FFFF_FFFE - b[2]) as u128,
+            (a[3] + 0xF_FFFF_FFFF_FFFE - b[3]) as u128,
+            (a[4] + 0xF_FFFF_FFFF_FFFE - b[4]) as u128,
+        ])
+    }
+
+    fn mul(self, b: Fe) -> Fe {
+        let a = self.0.map(|x| x as u128);
+        let b = b.0.map(|x| x as u128);
+        let b19 = [b[0], b[1] * 19, b[2] * 19, b[3] * 19, b[4] * 19];
+        carry([
+            a[0] * b[0] + a[1] * b19[4] + a[2] * b19[3] + a[3] * b19[2] + a[4] * b19[1],
+            a[0] * b[1] + a[1] * b[0] + a[2] * b19[4] + a[3] * b19[3] + a[4] * b19[2],
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * b19[4] + a[4] * b19[3],
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * b19[4],
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ])
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    fn mul_small(self, k: u64) -> Fe {
+        carry(self.0.map(|x| x as u128 * k as u128))
+    }
+
+    /// self^(p - 2) = 1 / self.
+    fn invert(self) -> Fe {
+        // p - 2 = 2^255 - 21: bits 254..5 set, then 01011
+        let mut r = Fe::ONE;
+        for i in (0..255).rev() {
+            r = r.square();
+            let bit = if i >= 5 { 1 } else { (0b01011 >> i) & 1 };
+            if bit == 1 { r = r.mul(self); }
+        }
+        r
+    }
+}
+
+fn carry(mut t: [u128; 5]) -> Fe {
+    for i in 0..4 {
+        t[i + 1] += t[i] >> 51;
+        t[i] &= MASK as u128;
+    }
+    t[0] += 19 * (t[4] >> 51);
+    t[4] &= MASK as u128;
+    t[1] += t[0] >> 51;
+    t[0] &= MASK as u128;
+    Fe(t.map(|x| x as u64))
+}
+
+/// Swap `a` and `b` when `swap` is 1, without branching on it.
+fn cswap(swap: u64, a: &mut Fe, b: &mut Fe) {
+    let m = 0u64.wrapping_sub(swap);
+    for i in 0..5 {
+        let t = m & (a.0[i] ^ b.0[i]);
+        a.0[i] ^= t;
+        b.0[i] ^= t;
+    }
+}
+
+/// scalar * u (both 32-byte little-endian strings).
+pub fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let x1 = Fe::from_bytes(u);
+    let (mut x2, mut z2, mut x3, mut z3) = (Fe::ONE, Fe::ZERO, x1, Fe::ONE);
+    let mut swap = 0u64;
+    for t in (0..255).rev() {
+        let bit = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= bit;
+        cswap(swap, &mut x2, &mut x3);
+        cswap(swap, &mut z2, &mut z3);
+        swap = bit;
+
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let c = x3.add(z3);
+        let d = x3.sub(z3);
+        let da = d.mul(a);
+        let cb = c.mul(b);
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(e.mul_small(121_665)));
+    }
+    cswap(swap, &mut x2, &mut x3);
+    cswap(swap, &mut z2, &mut z3);
+
+    x2.mul(z2.invert()).to_bytes()
+}
+
+/// Public key for a private scalar (scalar * 9).
+pub fn x25519_base(scalar: &[u8; 32]) -> [u8; 32] {
+    let mut nine = [0u8; 32];
+    nine[0] = 9;
+    x25519(scalar, &nine)
+}
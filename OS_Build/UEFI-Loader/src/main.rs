@@ -8,7 +8,7 @@ use core::mem::size_of;
 use core::ptr;
 
 use uefi::boot::{self, AllocateType, MemoryType};
-use uefi::cstr16;
+use uefi::{cstr16, guid, Guid};
 use uefi::fs::FileSystem;
 use uefi::prelude::*;
 use uefi::proto::console::gop::GraphicsOutput;
@@ -40,6 +40,29 @@ struct BootKernelMapRaw {
 
 const BOOT_KERNEL_MAP_MAGIC: u32 = 0x4F54_484B; // 'OTHK'
 
+/// ACPI RSDP address, written after `BootKernelMapRaw` (offset 48). The
+/// kernel reads the FADT through it (e.g. the RTC century register).
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct BootAcpiRaw {
+    magic: u32,
+    _reserved: u32,
+    rsdp: u64,
+}
+
+const BOOT_ACPI_MAGIC: u32 = 0x4F54_4841; // 'OTHA'
+
+const ACPI2_TABLE_GUID: Guid = guid!("8868e871-e4f1-11d3-bc22-0080c73c8881");
+const ACPI1_TABLE_GUID: Guid = guid!("eb9d2d30-2d88-11d3-9a16-0090273fc14d");
+
+/// RSDP from the EFI configuration table, preferring the ACPI 2.0+ entry.
+fn find_rsdp() -> u64 {
+    uefi::system::with_config_table(|tables| {
+        let by_guid = |g: Guid| tables.iter().find(|t| t.guid == g).map(|t| t.address as u64);
+        by_guid(ACPI2_TABLE_GUID).or_else(|| by_guid(ACPI1_TABLE_GUID)).unwrap_or(0)
+    })
+}
+
 // --- Minimal ELF64 definitions (enough for PT_LOAD)
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -374,6 +397,12 @@ fn main() -> Status {
                 kernel_size: k_size,
             },
         );
+
+        // BootAcpiRaw at offset 48
+        ptr::write_unaligned(
+            bi_ptr_u8.add(48) as *mut BootAcpiRaw,
+            BootAcpiRaw { magic: BOOT_ACPI_MAGIC, _reserved: 0, rsdp: find_rsdp() },
+        );
     }
 
     // --- Build paging (identity 4GiB + kernel override) ---
@@ -1,5 +1,9 @@
 param(
-    [switch]$Debug
+    [switch]$Debug,
+    [ValidateSet("rtl8139", "e1000", "e1000e", "virtio-net-pci")]
+    [string]$Nic = "rtl8139",
+    # host port forwards in QEMU syntax, e.g. -HostFwd tcp::8080-:80
+    [string[]]$HostFwd = @()
 )
 
 $ErrorActionPreference = "Stop"
@@ -410,21 +414,26 @@ Write-Host "    BOOTX64.EFI => $efiBootDir\BOOTX64.EFI"
 Write-Host "    kernel.elf  => $osBuildRoot\efi_root\kernel.elf"
 
 Write-Host ""
-Write-Host "==> Launching QEMU with network device (RTL8139) and windowed display..."
+Write-Host "==> Launching QEMU with network device ($Nic) and windowed display..."
 
 if (-not $script:QemuExePath) {
     $script:QemuExePath = "qemu-system-x86_64.exe"
 }
 
 # Tip: for debugging reboot loops, add "-no-reboot" (and optionally -d int,cpu_reset)
+$netdev = "user,id=net1"
+foreach ($rule in $HostFwd) {
+    $netdev += ",hostfwd=$rule"
+}
+
 $qemuArgs = @(
     "-machine", "q35",
     "-m", "1024",
     "-drive", "if=pflash,format=raw,readonly=on,file=OVMF_CODE.fd",
     "-drive", "if=pflash,format=raw,file=OVMF_VARS.fd",
     "-drive", "file=fat:rw:efi_root,format=raw",
-    "-netdev", "user,id=net1",
-    "-device", "rtl8139,netdev=net1",
+    "-netdev", $netdev,
+    "-device", "$Nic,netdev=net1",
     "-serial", "stdio",
     "-no-reboot"
 )
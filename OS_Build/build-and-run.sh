@@ -3,14 +3,22 @@ set -euo pipefail
 
 DEBUG=0
 NO_QEMU=0
+SHARE_DIR="${SHARE_DIR:-}"
+NIC="${NIC:-rtl8139}"
+HOSTFWD=()
 
 usage() {
   cat <<'EOF'
-Usage: ./build_and_run.sh [--debug] [--no-qemu]
+Usage: ./build_and_run.sh [--debug] [--no-qemu] [--share DIR] [--nic MODEL] [--hostfwd RULE]...
 
 Options:
-  --debug     Build debug (no --release)
-  --no-qemu   Build artifacts but don't launch QEMU
+  --debug       Build debug (no --release)
+  --no-qemu     Build artifacts but don't launch QEMU
+  --share DIR   Expose DIR to the guest over virtio-9p (mounted at /host)
+  --nic MODEL   QEMU network card: rtl8139 (default), e1000, e1000e or virtio-net-pci
+  --hostfwd RULE
+                Forward a host port to the guest, QEMU syntax, e.g. tcp::8080-:80
+                (repeatable; reaches services behind TcpListener in the guest)
 EOF
 }
 
@@ -18,6 +26,9 @@ while [[ $# -gt 0 ]]; do
   case "$1" in
     --debug|-d) DEBUG=1; shift ;;
     --no-qemu)  NO_QEMU=1; shift ;;
+    --share)    [[ $# -ge 2 ]] || { usage; exit 2; }; SHARE_DIR="$2"; shift 2 ;;
+    --nic)      [[ $# -ge 2 ]] || { usage; exit 2; }; NIC="$2"; shift 2 ;;
+    --hostfwd)  [[ $# -ge 2 ]] || { usage; exit 2; }; HOSTFWD+=("$2"); shift 2 ;;
     -h|--help)  usage; exit 0 ;;
     *) echo "Unknown arg: $1"; usage; exit 2 ;;
   esac
@@ -353,13 +364,31 @@ fi
 [[ -n "$OVMF_CODE" && -f "$OVMF_CODE" ]] || die "OVMF_CODE not found. Install OVMF/edk2-ovmf or set OVMF_CODE=/path/to/code.fd"
 [[ -n "$OVMF_VARS" && -f "$OVMF_VARS" ]] || die "OVMF_VARS not found. Install OVMF/edk2-ovmf or set OVMF_VARS=/path/to/vars.fd"
 
+# Optional host share (virtio-9p). Othello mounts tag host0 at /host on boot.
+SHARE_ARGS=()
+if [[ -n "$SHARE_DIR" ]]; then
+  [[ -d "$SHARE_DIR" ]] || die "Share directory not found: $SHARE_DIR"
+  SHARE_ARGS=(-virtfs "local,path=$SHARE_DIR,mount_tag=host0,security_model=none,id=host0")
+fi
+
+case "$NIC" in
+  rtl8139|e1000|e1000e|virtio-net-pci) ;;
+  *) die "Unsupported NIC model: $NIC (expected rtl8139, e1000, e1000e or virtio-net-pci)" ;;
+esac
+
+NETDEV="user,id=net1"
+for rule in ${HOSTFWD[@]+"${HOSTFWD[@]}"}; do
+  NETDEV+=",hostfwd=$rule"
+done
+
 exec "$QEMU_EXE" \
   -machine q35 \
   -m 1024 \
   -drive "if=pflash,format=raw,readonly=on,file=$OVMF_CODE" \
   -drive "if=pflash,format=raw,file=$OVMF_VARS" \
   -drive "file=fat:rw:$OS_BUILD_ROOT/efi_root,format=raw" \
-  -netdev user,id=net1 \
-  -device rtl8139,netdev=net1 \
+  -netdev "$NETDEV" \
+  -device "$NIC,netdev=net1" \
+  ${SHARE_ARGS[@]+"${SHARE_ARGS[@]}"} \
   -serial stdio \
   -no-reboot